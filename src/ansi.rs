@@ -0,0 +1,170 @@
+use crate::parse::{parse_kv_list, parse_percentage};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// SGR codes lines are colored with: the 8 colors, bold and bright ones and
+/// some 256 color and true color codes
+const COLORS: &[&str] = &[
+    "31",
+    "32",
+    "33",
+    "34",
+    "35",
+    "36",
+    "37",
+    "1;31",
+    "1;33",
+    "91",
+    "92",
+    "93",
+    "38;5;208",
+    "38;5;45",
+    "38;2;255;105;180",
+    "41;37",
+    "4;36",
+];
+const RESET: &str = "\u{1b}[0m";
+
+/// how lines are messed up with terminal codes, see --inject-ansi
+#[derive(Debug, Clone)]
+pub struct InjectAnsi {
+    /// fraction of lines changed
+    pub rate: f64,
+    pub colors: bool,
+    /// control characters sprinkled in changed lines
+    pub controls: Vec<char>,
+}
+
+impl InjectAnsi {
+    /// parse "rate=1%[,colors=false][,controls=false][,nul=true]"
+    pub fn parse(v: &str) -> Result<InjectAnsi, String> {
+        let mut rate = None;
+        let mut colors = true;
+        let mut controls = true;
+        let mut nul = false;
+        let flag = |key: &str, value: &str| match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("{}={} should be true or false", key, value)),
+        };
+
+        for (key, value) in parse_kv_list(v)? {
+            match key.as_str() {
+                "rate" => rate = Some(parse_percentage(&value)?),
+                "colors" => colors = flag(&key, &value)?,
+                "controls" => controls = flag(&key, &value)?,
+                "nul" => nul = flag(&key, &value)?,
+                _ => return Err(format!("unknown ansi injection option {}", key)),
+            }
+        }
+
+        let mut chars = vec![];
+        if controls {
+            chars.extend(['\t', '\r']);
+        }
+        if nul {
+            chars.push('\0');
+        }
+        if !colors && chars.is_empty() {
+            return Err("ansi injection needs colors or control characters".to_string());
+        }
+        Ok(InjectAnsi {
+            rate: rate.ok_or_else(|| "ansi injection needs rate=PCT".to_string())?,
+            colors,
+            controls: chars,
+        })
+    }
+
+    /// `line` with colors and control characters, None for lines left alone
+    pub fn apply(&self, line: &str) -> Option<String> {
+        let mut rng = rand::thread_rng();
+        if self.rate <= 0.0 || !rng.gen_bool(self.rate) {
+            return None;
+        }
+
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let mut changed = content.to_string();
+        if !self.controls.is_empty() {
+            for _ in 0..rng.gen_range(1..=3) {
+                let boundaries: Vec<usize> = (0..=changed.len())
+                    .filter(|i| changed.is_char_boundary(*i))
+                    .collect();
+                let at = *boundaries.choose(&mut rng).unwrap_or(&0);
+                changed.insert(at, *self.controls.choose(&mut rng).unwrap());
+            }
+        }
+        if self.colors {
+            let color = COLORS.choose(&mut rng).unwrap();
+            // one word of it like a colored level, or the whole line
+            let words: Vec<(usize, &str)> = changed
+                .split(' ')
+                .scan(0, |start, word| {
+                    let at = *start;
+                    *start += word.len() + 1;
+                    Some((at, word))
+                })
+                .filter(|(_, word)| !word.is_empty())
+                .collect();
+            changed = match words.choose(&mut rng) {
+                Some((start, word)) if words.len() > 1 && rng.gen_bool(0.5) => format!(
+                    "{}\u{1b}[{}m{}{}{}",
+                    &changed[..*start],
+                    color,
+                    word,
+                    RESET,
+                    &changed[start + word.len()..]
+                ),
+                _ => format!("\u{1b}[{}m{}{}", color, changed, RESET),
+            };
+        }
+
+        changed.push_str(ending);
+        Some(changed)
+    }
+}
+
+pub fn is_inject_ansi(v: String) -> Result<(), String> {
+    InjectAnsi::parse(&v).map(|_| ())
+}
+
+/// `line` without ANSI escape sequences: CSI (colors, cursor moves), OSC
+/// (titles, links) and two byte escapes, None if it has none
+pub fn strip_ansi(line: &str) -> Option<String> {
+    if !line.contains('\u{1b}') {
+        return None;
+    }
+
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // parameters and intermediates up to a final byte from @ to ~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // up to BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' {
+                        break;
+                    }
+                    if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(stripped)
+}
@@ -0,0 +1,318 @@
+use crate::pacer::Pacer;
+use crate::parse::{is_valid_rate, MIN_RATE};
+use crate::rebalance::Rebalance;
+use crate::sink::Sink;
+use crate::stats::{FileStatus, Stats};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// a client that stops sending doesn't keep others waiting for longer
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// request bodies are small json objects
+const MAX_BODY: usize = 64 * 1024;
+/// for the request line and headers together
+const MAX_HEADER_BYTES: u64 = 16 * 1024;
+
+/// what the management api can see and change, see --api-addr
+#[derive(Debug)]
+pub struct Api {
+    /// clients must send it as `Authorization: Bearer TOKEN`
+    pub token: String,
+    pub stats: Arc<Stats>,
+    pub pacer: Arc<Pacer>,
+    pub rebalance: Arc<Rebalance>,
+    pub sink: Option<Arc<dyn Sink>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+    ready: bool,
+    uptime_secs: u64,
+    paused: bool,
+    /// lines per second for all files, null without a limit
+    rate: Option<f64>,
+    workers: usize,
+    files: usize,
+    lines: u64,
+    bytes: u64,
+    wraps: u64,
+    errors: u64,
+    throttled: u64,
+    sink_errors: u64,
+}
+
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+type Response = (u16, Value);
+
+/// serve the management api on `addr` from a background thread
+pub fn serve(addr: &str, api: Api) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    println!(
+        "management api listening on http://{}/api",
+        listener.local_addr()?
+    );
+
+    let api = Arc::new(api);
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    // a slow client must not hold up the requests of the others
+                    let api = api.clone();
+                    thread::spawn(move || {
+                        handle(stream, &api)
+                            .map_err(|err| eprintln!("Error in management api: {:?}", err))
+                            .ok();
+                    });
+                }
+                Err(error) => {
+                    eprintln!("Error accepting management api connection: {:?}", error);
+                }
+            }
+        }
+    }))
+}
+
+fn handle(stream: TcpStream, api: &Api) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let (code, body) = match read_request(&stream) {
+        Ok(request) if !authorized(&request, &api.token) => {
+            (401, json!({"error": "missing or wrong bearer token"}))
+        }
+        Ok(request) => route(&request, api),
+        Err(err)
+            if err
+                .get_ref()
+                .is_some_and(|inner| inner.is::<HeadersTooLarge>()) =>
+        {
+            (431, json!({"error": err.to_string()}))
+        }
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+            (400, json!({"error": err.to_string()}))
+        }
+        Err(err) => return Err(err),
+    };
+    respond(stream, code, &body)
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream).take(MAX_HEADER_BYTES);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut authorization = None;
+    let mut content_length = 0;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .parse()
+                    .map_err(|_| invalid(format!("bad content-length {}", value)))?;
+            }
+        }
+        header.clear();
+    }
+    // the limit ran out before the empty line that ends the headers
+    if reader.limit() == 0 && !header.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, HeadersTooLarge));
+    }
+
+    if content_length > MAX_BODY {
+        return Err(invalid(format!(
+            "body of {} bytes is over {}",
+            content_length, MAX_BODY
+        )));
+    }
+    let mut body = vec![0; content_length];
+    reader.into_inner().read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// the request line and headers are over MAX_HEADER_BYTES
+#[derive(Debug)]
+struct HeadersTooLarge;
+
+impl fmt::Display for HeadersTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request headers are over {} bytes", MAX_HEADER_BYTES)
+    }
+}
+
+impl std::error::Error for HeadersTooLarge {}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    match request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        Some(sent) => same_secret(sent.trim().as_bytes(), token.as_bytes()),
+        None => false,
+    }
+}
+
+/// compare without returning early, so the time taken doesn't tell how much
+/// of the token was right
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn route(request: &Request, api: &Api) -> Response {
+    let path = request.path.split('?').next().unwrap_or("");
+    match (request.method.as_str(), path) {
+        ("GET", "/api/stats") => (200, to_json(&summary(api))),
+        ("GET", "/api/files") => (200, to_json(&api.stats.status().files)),
+        ("GET", "/api/rate") => (200, rate(api)),
+        ("PUT", "/api/rate") => match field(&request.body, "rate") {
+            Ok(Value::Null) => {
+                api.pacer.set_rate(None);
+                (200, rate(api))
+            }
+            Ok(Value::Number(number))
+                if number
+                    .as_f64()
+                    .is_some_and(|rate| rate > 0.0 && is_valid_rate(rate)) =>
+            {
+                api.pacer.set_rate(number.as_f64());
+                (200, rate(api))
+            }
+            Ok(_) => bad_request(&format!(
+                "rate must be a number of at least {} or null",
+                MIN_RATE
+            )),
+            Err(message) => bad_request(&message),
+        },
+        ("GET", "/api/pause") => (200, paused(api)),
+        ("POST", "/api/pause") => {
+            api.pacer.set_paused(true);
+            (200, paused(api))
+        }
+        ("DELETE", "/api/pause") => {
+            api.pacer.set_paused(false);
+            (200, paused(api))
+        }
+        ("GET", "/api/parallelism") => (200, json!({"workers": api.rebalance.current()})),
+        ("PUT", "/api/parallelism") => match field(&request.body, "workers") {
+            Ok(Value::Number(number)) if number.as_u64().is_some_and(|workers| workers > 0) => {
+                let workers = number.as_u64().unwrap() as usize;
+                api.rebalance.request(workers);
+                (
+                    202,
+                    json!({"workers": api.rebalance.current(), "requested": workers}),
+                )
+            }
+            Ok(_) => bad_request("workers must be a number greater than 0"),
+            Err(message) => bad_request(&message),
+        },
+        (_, "/api/stats" | "/api/files" | "/api/rate" | "/api/pause" | "/api/parallelism") => {
+            (405, json!({"error": "method not allowed"}))
+        }
+        _ => (404, json!({"error": "not found"})),
+    }
+}
+
+fn bad_request(message: &str) -> Response {
+    (400, json!({ "error": message }))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or_else(|err| json!({"error": err.to_string()}))
+}
+
+/// `name` of a json object body
+fn field(body: &[u8], name: &str) -> Result<Value, String> {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(mut object)) => object
+            .remove(name)
+            .ok_or_else(|| format!("body needs a {} field", name)),
+        Ok(_) => Err("body must be a json object".to_string()),
+        Err(err) => Err(format!("body isn't json: {}", err)),
+    }
+}
+
+fn rate(api: &Api) -> Value {
+    json!({"rate": api.pacer.rate(), "cap": api.pacer.cap()})
+}
+
+fn paused(api: &Api) -> Value {
+    json!({"paused": api.pacer.is_paused()})
+}
+
+fn summary(api: &Api) -> Summary {
+    let status = api.stats.status();
+    let total = |count: fn(&FileStatus) -> u64| status.files.iter().map(count).sum();
+    Summary {
+        ready: status.ready,
+        uptime_secs: status.uptime_secs,
+        paused: api.pacer.is_paused(),
+        rate: api.pacer.limit(),
+        workers: api.rebalance.current(),
+        files: status.files.len(),
+        lines: total(|file| file.lines),
+        bytes: total(|file| file.bytes),
+        wraps: total(|file| file.wraps),
+        errors: total(|file| file.errors),
+        throttled: total(|file| file.throttled),
+        sink_errors: api.sink.as_ref().map_or(0, |sink| sink.errors()),
+    }
+}
+
+fn respond(mut stream: TcpStream, code: u16, body: &Value) -> io::Result<()> {
+    let reason = match code {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    };
+    let body = format!("{}\n", body);
+    let challenge = if code == 401 {
+        "WWW-Authenticate: Bearer\r\n"
+    } else {
+        ""
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        code,
+        reason,
+        body.len(),
+        challenge,
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,146 @@
+use crate::group::glob_match;
+use crate::parse::{parse_count, unknown_value};
+use crate::stats::FileStatus;
+use std::fmt;
+use std::path::Path;
+
+/// exit code when an assertion fails, 1 is for other errors and 3 for --error-budget
+pub const EXIT_CODE: i32 = 4;
+
+const METRICS: &[&str] = &[
+    "files",
+    "lines",
+    "bytes",
+    "wraps",
+    "fast_wraps",
+    "errors",
+    "throttled",
+    "dropped",
+];
+/// where two start at the same place the longest is used, so >= isn't read as >
+const OPERATORS: &[&str] = &[">=", "<=", "==", "!=", ">", "<"];
+
+/// a check on the totals of the generated files at the end of a run, see --assert
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    /// files the totals are summed over, all if None
+    pub glob: Option<String>,
+    pub metric: String,
+    pub operator: &'static str,
+    pub value: u64,
+}
+
+impl Assertion {
+    /// parse "[GLOB] METRIC OP VALUE" like "out/**/*.log lines>=1000" or "wraps==0"
+    pub fn parse(v: &str) -> Result<Assertion, String> {
+        let (pos, operator) = OPERATORS
+            .iter()
+            .filter_map(|op| v.find(op).map(|pos| (pos, *op)))
+            .min_by_key(|(pos, op)| (*pos, usize::MAX - op.len()))
+            .ok_or_else(|| {
+                format!(
+                    "{} isn't [GLOB] METRIC OP VALUE, for example 'wraps==0', OP is one of: {}",
+                    v,
+                    OPERATORS.join(" ")
+                )
+            })?;
+        let before = v[..pos].trim();
+        let (glob, metric) = match before.rsplit_once(char::is_whitespace) {
+            Some((glob, metric)) => (Some(glob.trim().to_string()), metric),
+            None => (None, before),
+        };
+        if !METRICS.contains(&metric) {
+            return Err(unknown_value("metric", metric, METRICS));
+        }
+        let value = parse_count(&v[pos + operator.len()..])?;
+
+        Ok(Assertion {
+            glob,
+            metric: metric.to_string(),
+            operator,
+            value,
+        })
+    }
+
+    /// true if the file at `rel_path` is counted, a glob without / matches file
+    /// names in any directory, other globs the path relative to the output
+    /// directory or with `out_dir` in front
+    fn includes(&self, out_dir: &Path, rel_path: &str) -> bool {
+        let glob = match &self.glob {
+            Some(glob) => glob,
+            None => return true,
+        };
+        if glob.contains('/') {
+            glob_match(glob, rel_path)
+                || glob_match(glob, &out_dir.join(rel_path).to_string_lossy())
+        } else {
+            Path::new(rel_path)
+                .file_name()
+                .is_some_and(|name| glob_match(glob, &name.to_string_lossy()))
+        }
+    }
+
+    /// total of the metric over the files it includes
+    fn actual(&self, out_dir: &Path, files: &[FileStatus]) -> u64 {
+        let included = files
+            .iter()
+            .filter(|file| self.includes(out_dir, &file.path_out));
+        let count: fn(&FileStatus) -> u64 = match self.metric.as_str() {
+            "files" => |_| 1,
+            "lines" => |file| file.lines,
+            "bytes" => |file| file.bytes,
+            "wraps" => |file| file.wraps,
+            "fast_wraps" => |file| file.fast_wraps,
+            "errors" => |file| file.errors,
+            "throttled" => |file| file.throttled,
+            _ => |file| file.dropped_on_full,
+        };
+        included.map(count).sum()
+    }
+
+    fn holds(&self, actual: u64) -> bool {
+        match self.operator {
+            ">=" => actual >= self.value,
+            "<=" => actual <= self.value,
+            "==" => actual == self.value,
+            "!=" => actual != self.value,
+            ">" => actual > self.value,
+            _ => actual < self.value,
+        }
+    }
+
+    /// the total if the assertion doesn't hold
+    pub fn check(&self, out_dir: &Path, files: &[FileStatus]) -> Option<u64> {
+        let actual = self.actual(out_dir, files);
+        if self.holds(actual) {
+            None
+        } else {
+            Some(actual)
+        }
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(glob) = &self.glob {
+            write!(f, "{} ", glob)?;
+        }
+        write!(f, "{}{}{}", self.metric, self.operator, self.value)
+    }
+}
+
+pub fn is_assertion(v: String) -> Result<(), String> {
+    Assertion::parse(&v).map(|_| ())
+}
+
+/// check every assertion printing the ones that failed, true if all hold
+pub fn check_all(assertions: &[Assertion], out_dir: &Path, files: &[FileStatus]) -> bool {
+    let mut passed = true;
+    for assertion in assertions {
+        if let Some(actual) = assertion.check(out_dir, files) {
+            eprintln!("Assertion failed: {}, was {}", assertion, actual);
+            passed = false;
+        }
+    }
+    passed
+}
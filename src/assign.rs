@@ -0,0 +1,118 @@
+use crate::parse::unknown_value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub enum Assign {
+    RoundRobin,
+    ByDir,
+    BySize,
+}
+
+impl FromStr for Assign {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<Assign, String> {
+        match v {
+            "round-robin" => Ok(Assign::RoundRobin),
+            "by-dir" => Ok(Assign::ByDir),
+            "by-size" => Ok(Assign::BySize),
+            _ => Err(unknown_value("assign strategy", v, Assign::NAMES)),
+        }
+    }
+}
+
+impl Assign {
+    pub const NAMES: &'static [&'static str] = &["round-robin", "by-dir", "by-size"];
+
+    /// worker index for each file given its (relative path, size), files keep
+    /// their relative order inside each worker
+    pub fn workers_for(&self, files: &[(&Path, u64)], workers: usize) -> Vec<usize> {
+        match self {
+            Assign::BySize => by_size(files, workers),
+            _ => {
+                let mut assigner = Assigner::new(self.clone(), workers);
+                files
+                    .iter()
+                    .map(|(path, size)| assigner.worker_for(path, *size))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// assigns files to workers one at a time as they're found, by-size can't
+/// sort them so each goes to the worker with the fewest bytes so far
+#[derive(Debug)]
+pub struct Assigner {
+    assign: Assign,
+    /// files for round-robin and by-dir, bytes for by-size
+    loads: Vec<u64>,
+    assigned: usize,
+    dir_worker: HashMap<PathBuf, usize>,
+}
+
+impl Assigner {
+    pub fn new(assign: Assign, workers: usize) -> Assigner {
+        Assigner {
+            assign,
+            loads: vec![0; workers],
+            assigned: 0,
+            dir_worker: HashMap::new(),
+        }
+    }
+
+    pub fn worker_for(&mut self, path: &Path, size: u64) -> usize {
+        let worker = match self.assign {
+            Assign::RoundRobin => self.assigned % self.loads.len(),
+            // all files in a directory go to the same worker, directories are
+            // given to the worker with fewer files so far
+            Assign::ByDir => {
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                match self.dir_worker.get(dir) {
+                    Some(worker) => *worker,
+                    None => {
+                        let worker = least_loaded(&self.loads);
+                        self.dir_worker.insert(dir.to_path_buf(), worker);
+                        worker
+                    }
+                }
+            }
+            Assign::BySize => least_loaded(&self.loads),
+        };
+        self.loads[worker] += match self.assign {
+            Assign::BySize => size,
+            _ => 1,
+        };
+        self.assigned += 1;
+        worker
+    }
+}
+
+fn least_loaded(loads: &[u64]) -> usize {
+    let mut index = 0;
+    for (i, load) in loads.iter().enumerate() {
+        if *load < loads[index] {
+            index = i;
+        }
+    }
+
+    index
+}
+
+/// biggest files first, each to the worker with the fewest total bytes so far
+fn by_size(files: &[(&Path, u64)], workers: usize) -> Vec<usize> {
+    let mut loads = vec![0u64; workers];
+    let mut result = vec![0; files.len()];
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by(|a, b| files[*b].1.cmp(&files[*a].1));
+
+    for i in order {
+        let worker = least_loaded(&loads);
+        loads[worker] += files[i].1;
+        result[i] = worker;
+    }
+
+    result
+}
@@ -0,0 +1,224 @@
+use crate::parse::unknown_value;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// lines kept in memory while the consumer isn't reading, with drop-new and drop-old
+pub const QUEUE_LINES: usize = 1024;
+/// time blocked is counted at least this often
+const BLOCKED_STEP: Duration = Duration::from_millis(100);
+
+/// what writing to a full pipe or socket does, see --block-on-full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhenFull {
+    /// wait until the consumer reads, slowing the generator down
+    Block,
+    /// queue lines and drop new ones once the queue is full
+    DropNew,
+    /// queue lines and drop the oldest once the queue is full
+    DropOld,
+}
+
+impl WhenFull {
+    pub const NAMES: &'static [&'static str] = &["block", "drop-new", "drop-old"];
+
+    pub fn parse(v: &str) -> Result<WhenFull, String> {
+        match v {
+            "block" => Ok(WhenFull::Block),
+            "drop-new" => Ok(WhenFull::DropNew),
+            "drop-old" => Ok(WhenFull::DropOld),
+            _ => Err(unknown_value(
+                "--block-on-full behavior",
+                v,
+                WhenFull::NAMES,
+            )),
+        }
+    }
+}
+
+/// lines dropped and time spent waiting because a consumer was full
+#[derive(Debug, Default)]
+pub struct FullCounters {
+    dropped: AtomicU64,
+    blocked_us: AtomicU64,
+}
+
+impl FullCounters {
+    fn dropped(&self, lines: u64) {
+        self.dropped.fetch_add(lines, Ordering::Relaxed);
+    }
+
+    fn blocked(&self, duration: Duration) {
+        self.blocked_us
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn dropped_lines(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn blocked_ms(&self) -> u64 {
+        self.blocked_us.load(Ordering::Relaxed) / 1000
+    }
+}
+
+pub fn set_nonblocking(fd: &impl AsRawFd) -> io::Result<()> {
+    let fd = fd.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// wait until `fd` can be written to for at most `timeout`, true if it can
+fn wait_writable(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+    let mut poll_fd = libc::pollfd {
+        fd,
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) } {
+        ready if ready >= 0 => Ok(ready > 0),
+        _ => {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                Ok(false)
+            } else {
+                Err(err)
+            }
+        }
+    }
+}
+
+/// writes to a non blocking pipe or socket, blocking, queueing or dropping
+/// lines while the consumer is full as `when_full` says
+#[derive(Debug)]
+pub struct FullQueue {
+    when_full: WhenFull,
+    counters: Arc<FullCounters>,
+    /// how long to block on a consumer that doesn't read, forever if None
+    timeout: Option<Duration>,
+    /// lines waiting for room, the first may be partly written
+    pending: VecDeque<Vec<u8>>,
+    /// bytes of the first pending line already written
+    written: usize,
+    /// the end of the last write, until its line ending comes
+    partial: Vec<u8>,
+}
+
+impl FullQueue {
+    pub fn new(when_full: WhenFull, counters: Arc<FullCounters>) -> FullQueue {
+        FullQueue {
+            when_full,
+            counters,
+            timeout: None,
+            pending: VecDeque::new(),
+            written: 0,
+            partial: vec![],
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> FullQueue {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// write `buf` to `out`, only whole lines are queued or dropped
+    pub fn write<W: Write + AsRawFd>(&mut self, out: &mut W, buf: &[u8]) -> io::Result<()> {
+        if self.when_full == WhenFull::Block {
+            return self.write_blocking(out, buf);
+        }
+
+        self.partial.extend_from_slice(buf);
+        while let Some(end) = self.partial.iter().position(|b| *b == b'\n') {
+            let line = self.partial.drain(..=end).collect();
+            self.push(line);
+        }
+        self.drain(out)
+    }
+
+    fn push(&mut self, line: Vec<u8>) {
+        if self.pending.len() >= QUEUE_LINES {
+            self.counters.dropped(1);
+            match self.when_full {
+                WhenFull::DropOld => {
+                    // a line that was partly written must be finished
+                    let oldest = if self.written > 0 { 1 } else { 0 };
+                    self.pending.remove(oldest);
+                }
+                _ => return,
+            }
+        }
+        self.pending.push_back(line);
+    }
+
+    /// write queued lines until the consumer is full
+    pub fn drain<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        while let Some(line) = self.pending.front() {
+            match out.write(&line[self.written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.written += written;
+                    if self.written == line.len() {
+                        self.pending.pop_front();
+                        self.written = 0;
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// write all of `buf`, counting the time waiting for the consumer
+    fn write_blocking<W: Write + AsRawFd>(&mut self, out: &mut W, buf: &[u8]) -> io::Result<()> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            match out.write(rest) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => rest = &rest[written..],
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    let started = Instant::now();
+                    // counted as it goes so a consumer that stopped reading shows up
+                    loop {
+                        let step = Instant::now();
+                        let ready = wait_writable(out.as_raw_fd(), BLOCKED_STEP)?;
+                        self.counters.blocked(step.elapsed());
+                        if ready {
+                            break;
+                        }
+                        if self
+                            .timeout
+                            .is_some_and(|timeout| started.elapsed() >= timeout)
+                        {
+                            return Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                "consumer didn't read in time",
+                            ));
+                        }
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FullQueue {
+    /// lines still queued when the output is closed never get out
+    fn drop(&mut self) {
+        let unfinished = !self.partial.is_empty() as usize;
+        self.counters
+            .dropped((self.pending.len() + unfinished) as u64);
+    }
+}
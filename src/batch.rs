@@ -0,0 +1,92 @@
+use crate::parse::{parse_duration, parse_kv_list, parse_size};
+use std::time::{Duration, Instant};
+
+/// when to write the lines buffered for an output, whichever is reached first
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    pub lines: Option<usize>,
+    pub bytes: Option<usize>,
+    pub time: Option<Duration>,
+}
+
+impl WriteBatch {
+    /// parse "lines=100,bytes=64KB,time=50", time without unit is in milliseconds
+    pub fn parse(v: &str) -> Result<WriteBatch, String> {
+        let mut batch = WriteBatch::default();
+        for (key, value) in parse_kv_list(v)? {
+            match key.as_str() {
+                "lines" => match value.parse::<usize>() {
+                    Ok(lines) if lines > 0 => batch.lines = Some(lines),
+                    _ => return Err(format!("{} isn't a valid line count", value)),
+                },
+                "bytes" => batch.bytes = Some(parse_size(&value)? as usize),
+                "time" => {
+                    batch.time = Some(match value.parse::<u64>() {
+                        Ok(ms) => Duration::from_millis(ms),
+                        Err(_) => parse_duration(&value)?,
+                    })
+                }
+                _ => return Err(format!("unknown write batch option {}", key)),
+            }
+        }
+
+        if batch.lines.is_none() && batch.bytes.is_none() && batch.time.is_none() {
+            return Err("write batch needs lines=N, bytes=K or time=ms".to_string());
+        }
+
+        Ok(batch)
+    }
+}
+
+pub fn is_write_batch(v: String) -> Result<(), String> {
+    WriteBatch::parse(&v).map(|_| ())
+}
+
+/// lines waiting to be written to an output in a single write
+#[derive(Debug)]
+pub struct Batch {
+    config: WriteBatch,
+    buffer: String,
+    lines: usize,
+    since: Option<Instant>,
+}
+
+impl Batch {
+    pub fn new(config: WriteBatch) -> Batch {
+        Batch {
+            config,
+            buffer: String::new(),
+            lines: 0,
+            since: None,
+        }
+    }
+
+    pub fn push(&mut self, line: &str) {
+        if self.since.is_none() {
+            self.since = Some(Instant::now());
+        }
+        self.buffer.push_str(line);
+        self.lines += 1;
+    }
+
+    pub fn is_due(&self) -> bool {
+        let since = match self.since {
+            Some(since) => since,
+            None => return false,
+        };
+
+        self.config.lines.is_some_and(|lines| self.lines >= lines)
+            || self
+                .config
+                .bytes
+                .is_some_and(|bytes| self.buffer.len() >= bytes)
+            || self.config.time.is_some_and(|time| since.elapsed() >= time)
+    }
+
+    /// buffered lines, leaving the batch empty
+    pub fn take(&mut self) -> String {
+        self.lines = 0;
+        self.since = None;
+        std::mem::take(&mut self.buffer)
+    }
+}
@@ -0,0 +1,38 @@
+use crate::stats::Status;
+use std::time::Duration;
+
+const MB: f64 = 1024.0 * 1024.0;
+
+/// print throughput per output file and in total for a bench run
+pub fn report(status: &Status, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    let mut total_lines = 0;
+    let mut total_bytes = 0;
+
+    println!(
+        "{:>12} {:>14} {:>10} {:>12}  path",
+        "lines", "bytes", "MB/s", "lines/s"
+    );
+
+    for file in status.files.iter() {
+        total_lines += file.lines;
+        total_bytes += file.bytes;
+        println!(
+            "{:>12} {:>14} {:>10.2} {:>12.0}  {}",
+            file.lines,
+            file.bytes,
+            file.bytes as f64 / MB / secs,
+            file.lines as f64 / secs,
+            file.path_out
+        );
+    }
+
+    println!(
+        "{:>12} {:>14} {:>10.2} {:>12.0}  total in {:.2}s",
+        total_lines,
+        total_bytes,
+        total_bytes as f64 / MB / secs,
+        total_lines as f64 / secs,
+        secs
+    );
+}
@@ -0,0 +1,89 @@
+use crate::parse::parse_kv_list;
+use crate::sink::Sink;
+use crate::stats::Stats;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep, JoinHandle};
+use std::time::Duration;
+
+/// how often errors are counted
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+/// exit code when the budget is exceeded, 1 is for other errors
+pub const EXIT_CODE: i32 = 3;
+
+/// errors tolerated before loggen gives up, see --error-budget
+#[derive(Debug, Clone, Default)]
+pub struct ErrorBudget {
+    /// errors reading samples or writing lines, to files or to a sink
+    pub writes: Option<u64>,
+    /// sends a sink retried or gave up on
+    pub network: Option<u64>,
+}
+
+impl ErrorBudget {
+    /// parse "writes=100,network=500", either can be left out
+    pub fn parse(v: &str) -> Result<ErrorBudget, String> {
+        let mut budget = ErrorBudget::default();
+        for (key, value) in parse_kv_list(v)? {
+            let count = value
+                .parse::<u64>()
+                .map_err(|_| format!("{}={} isn't an error count", key, value))?;
+            match key.as_str() {
+                "writes" => budget.writes = Some(count),
+                "network" => budget.network = Some(count),
+                _ => {
+                    return Err(format!(
+                        "unknown error budget {}, use writes or network",
+                        key
+                    ))
+                }
+            }
+        }
+
+        if budget.writes.is_none() && budget.network.is_none() {
+            return Err("the error budget needs writes=N or network=N".to_string());
+        }
+        Ok(budget)
+    }
+
+    /// what went over the budget, if anything
+    fn check(&self, writes: u64, network: u64) -> Option<String> {
+        match (self.writes, self.network) {
+            (Some(max), _) if writes > max => Some(format!(
+                "{} write errors, over the budget of {}",
+                writes, max
+            )),
+            (_, Some(max)) if network > max => Some(format!(
+                "{} network errors, over the budget of {}",
+                network, max
+            )),
+            _ => None,
+        }
+    }
+}
+
+pub fn is_error_budget(v: String) -> Result<(), String> {
+    ErrorBudget::parse(&v).map(|_| ())
+}
+
+/// stop everything once the errors go over `budget`, `exceeded` is set so
+/// loggen exits with EXIT_CODE
+pub fn watch(
+    budget: ErrorBudget,
+    stats: Arc<Stats>,
+    sink: Option<Arc<dyn Sink>>,
+    stop: Arc<AtomicBool>,
+    exceeded: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            sleep(CHECK_INTERVAL);
+            let network = sink.as_ref().map_or(0, |sink| sink.errors());
+            if let Some(reason) = budget.check(stats.errors(), network) {
+                eprintln!("Error: {}, stopping", reason);
+                exceeded.store(true, Ordering::Relaxed);
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+    })
+}
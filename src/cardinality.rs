@@ -0,0 +1,90 @@
+use rand::Rng;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// a template field with a controlled number of distinct values, new values
+/// become available at `growth` per second until `unique` is reached
+#[derive(Debug, Clone)]
+pub struct Cardinality {
+    pub field: String,
+    pub unique: u64,
+    pub growth: Option<f64>,
+    pub prefix: String,
+    /// when the first value was rendered, growth counts from there
+    started: OnceLock<Instant>,
+}
+
+/// the options of "field=user_id:unique=1000000", a part without = belongs
+/// to the value before it so prefixes can have colons, as in prefix=urn:id:
+fn options(v: &str) -> Result<Vec<(String, String)>, String> {
+    let mut options: Vec<(String, String)> = vec![];
+    for part in v.split(':') {
+        match (part.find('='), options.last_mut()) {
+            (Some(pos), _) => {
+                options.push((part[..pos].trim().to_string(), part[pos + 1..].to_string()))
+            }
+            (None, Some((_, value))) => {
+                value.push(':');
+                value.push_str(part);
+            }
+            (None, None) => return Err(format!("{} isn't a key=value pair", part)),
+        }
+    }
+    Ok(options)
+}
+
+impl Cardinality {
+    /// parse "field=user_id:unique=1000000[:growth=100][:prefix=user-]"
+    pub fn parse(v: &str) -> Result<Cardinality, String> {
+        let mut field = None;
+        let mut unique = None;
+        let mut growth = None;
+        let mut prefix = None;
+
+        for (key, value) in options(v)? {
+            match key.as_str() {
+                "field" if !value.trim().is_empty() => field = Some(value.trim().to_string()),
+                "unique" => match value.trim().parse::<u64>() {
+                    Ok(n) if n > 0 => unique = Some(n),
+                    _ => return Err(format!("{} isn't a valid unique count", value)),
+                },
+                "growth" => match value.trim().trim_end_matches("/s").parse::<f64>() {
+                    Ok(n) if n > 0.0 => growth = Some(n),
+                    _ => return Err(format!("{} isn't a valid growth per second", value)),
+                },
+                "prefix" => prefix = Some(value),
+                _ => return Err(format!("unknown cardinality option {}", key)),
+            }
+        }
+
+        let field = field.ok_or_else(|| "cardinality needs field=NAME".to_string())?;
+        Ok(Cardinality {
+            prefix: prefix.unwrap_or_else(|| format!("{}-", field)),
+            field,
+            unique: unique.ok_or_else(|| "cardinality needs unique=N".to_string())?,
+            growth,
+            started: OnceLock::new(),
+        })
+    }
+
+    /// distinct values available now
+    pub fn active(&self) -> u64 {
+        match self.growth {
+            Some(growth) => {
+                let started = self.started.get_or_init(Instant::now);
+                let grown = 1 + (started.elapsed().as_secs_f64() * growth) as u64;
+                grown.min(self.unique)
+            }
+            None => self.unique,
+        }
+    }
+
+    pub fn value(&self) -> String {
+        let n = rand::thread_rng().gen_range(0..self.active());
+        format!("{}{}", self.prefix, n)
+    }
+}
+
+pub fn is_cardinality(v: String) -> Result<(), String> {
+    Cardinality::parse(&v).map(|_| ())
+}
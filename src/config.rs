@@ -0,0 +1,385 @@
+use clap::{Arg, ArgSettings};
+use serde_json::Value;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+
+/// subcommands that take the generation options, config args go after their name
+const GEN_SUBCOMMANDS: &[&str] = &["bench", "shell"];
+
+/// value of --config in the command line args, if any
+fn config_path(args: &[OsString]) -> Option<String> {
+    let mut args = args.iter().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        // args after -- are for another loggen command, like service run
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            return args.next().map(|v| v.to_string());
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.to_string());
+        }
+    }
+
+    None
+}
+
+/// replace ${NAME} with the environment variable NAME and ${file:PATH} with the
+/// content of PATH (without trailing newlines), $${ is a literal ${
+pub fn interpolate(value: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("$${") {
+            out.push_str("${");
+            rest = &rest[3..];
+        } else if rest.starts_with("${") {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| format!("unclosed ${{ in {}", value))?;
+            let reference = &rest[2..end];
+            out.push_str(&resolve(reference)?);
+            rest = &rest[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn resolve(reference: &str) -> Result<String, String> {
+    if let Some(path) = reference.strip_prefix("file:") {
+        return fs::read_to_string(path)
+            .map(|v| v.trim_end_matches(&['\r', '\n'][..]).to_string())
+            .map_err(|err| format!("can't read secret file {}: {}", path, err));
+    }
+
+    if reference.is_empty() {
+        return Err("empty ${} reference".to_string());
+    }
+
+    env::var(reference).map_err(|_| format!("environment variable {} is not set", reference))
+}
+
+/// position of a problem in a config file, 0 when it isn't known
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+/// "path:line:column: message", without the position when it isn't known
+fn located(path: &str, pos: Position, message: &str) -> String {
+    if pos.line == 0 {
+        format!("{}: {}", path, message)
+    } else {
+        format!("{}:{}:{}: {}", path, pos.line, pos.column, message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// by extension, JSON if it isn't .toml, .yaml or .yml
+    fn of(path: &str) -> Format {
+        if path.ends_with(".toml") {
+            Format::Toml
+        } else if path.ends_with(".yaml") || path.ends_with(".yml") {
+            Format::Yaml
+        } else {
+            Format::Json
+        }
+    }
+
+    /// where the top level `key` is defined in `content`
+    fn key_position(&self, content: &str, key: &str) -> Position {
+        let quoted = format!("\"{}\"", key);
+        for (index, line) in content.lines().enumerate() {
+            let trimmed = line.trim_start();
+            let column = line.len() - trimmed.len();
+            let found = match self {
+                Format::Json => line
+                    .find(&quoted)
+                    .filter(|pos| line[pos + quoted.len()..].trim_start().starts_with(':')),
+                // top level keys start the line, TOML tables aren't supported
+                Format::Toml | Format::Yaml => {
+                    let separator = if *self == Format::Toml { '=' } else { ':' };
+                    let rest = trimmed
+                        .strip_prefix(&quoted)
+                        .or_else(|| trimmed.strip_prefix(key));
+                    let top_level = *self == Format::Toml || column == 0;
+                    match rest {
+                        Some(rest) if top_level && rest.trim_start().starts_with(separator) => {
+                            Some(column)
+                        }
+                        _ => None,
+                    }
+                }
+            };
+
+            if let Some(column) = found {
+                return Position {
+                    line: index + 1,
+                    column: column + 1,
+                };
+            }
+        }
+
+        Position::default()
+    }
+
+    fn parse(&self, content: &str) -> Result<Value, (Position, String)> {
+        match self {
+            Format::Json => serde_json::from_str(content).map_err(|err| {
+                let pos = Position {
+                    line: err.line(),
+                    column: err.column(),
+                };
+                (pos, err.to_string())
+            }),
+            Format::Toml => toml::from_str(content).map_err(|err| {
+                let pos = err
+                    .span()
+                    .map(|span| offset_position(content, span.start))
+                    .unwrap_or_default();
+                (pos, err.message().replace('\n', " "))
+            }),
+            Format::Yaml => serde_yaml::from_str(content).map_err(|err| {
+                let pos = err
+                    .location()
+                    .map(|location| Position {
+                        line: location.line(),
+                        column: location.column(),
+                    })
+                    .unwrap_or_default();
+                (pos, err.to_string())
+            }),
+        }
+    }
+}
+
+fn offset_position(content: &str, offset: usize) -> Position {
+    let before = &content[..offset.min(content.len())];
+    Position {
+        line: before.matches('\n').count() + 1,
+        column: before.len() - before.rfind('\n').map(|pos| pos + 1).unwrap_or(0) + 1,
+    }
+}
+
+/// number of single character edits to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn long_name<'a>(option: &'a Arg) -> Option<&'a str> {
+    option.s.long
+}
+
+/// check `value` of `option` the way the command line does, returns its args
+fn option_args(option: &Arg, key: &str, value: &Value) -> Result<Vec<OsString>, String> {
+    if !option.is_set(ArgSettings::TakesValue) {
+        return match value {
+            Value::Bool(true) => Ok(vec![OsString::from(format!("--{}", key))]),
+            Value::Bool(false) | Value::Null => Ok(vec![]),
+            _ => Err(format!("{} is a flag, expected true or false", key)),
+        };
+    }
+
+    let values = match value {
+        Value::Null => return Ok(vec![]),
+        Value::Array(_) if !option.is_set(ArgSettings::Multiple) => {
+            return Err(format!(
+                "{} can't be repeated, expected a single value",
+                key
+            ))
+        }
+        Value::Array(values) => values.iter().collect(),
+        _ => vec![value],
+    };
+
+    let mut args = vec![];
+    for value in values {
+        let value = match value {
+            Value::String(v) => interpolate(v).map_err(|err| format!("{}: {}", key, err))?,
+            Value::Number(v) => v.to_string(),
+            _ => return Err(format!("{}: expected a string or number", key)),
+        };
+
+        if let Some(possible) = &option.v.possible_vals {
+            if !possible.contains(&value.as_str()) {
+                return Err(format!(
+                    "{}: invalid value {:?}, expected one of: {}",
+                    key,
+                    value,
+                    possible.join(", ")
+                ));
+            }
+        }
+        if let Some(validator) = &option.v.validator {
+            validator(value.clone()).map_err(|err| format!("{}: {}", key, err))?;
+        }
+
+        args.push(OsString::from(format!("--{}={}", key, value)));
+    }
+
+    Ok(args)
+}
+
+/// translate a config object to command line args, keys are long names of
+/// `options` and values are checked like in the command line, every problem
+/// is reported with its line and column
+fn config_args(path: &str, options: &[Arg]) -> Result<Vec<OsString>, String> {
+    let content =
+        fs::read_to_string(path).map_err(|err| format!("can't read config {}: {}", path, err))?;
+    let format = Format::of(path);
+    let config = format
+        .parse(&content)
+        .map_err(|(pos, err)| located(path, pos, &format!("invalid config: {}", err)))?;
+    let entries = match config {
+        Value::Object(entries) => entries,
+        _ => return Err(format!("config {} must be an object of options", path)),
+    };
+
+    let mut args = vec![];
+    let mut errors = vec![];
+    for (key, value) in entries.iter() {
+        let pos = format.key_position(&content, key);
+        let option = options
+            .iter()
+            .find(|option| long_name(option) == Some(key.as_str()));
+        let result = match option {
+            _ if key == "config" => Err("config can't be set in a config file".to_string()),
+            Some(option) => option_args(option, key, value),
+            None => {
+                let suggestion = options
+                    .iter()
+                    .filter_map(long_name)
+                    .map(|name| (edit_distance(key, name), name))
+                    .filter(|(distance, _)| *distance <= 2)
+                    .min();
+                Err(match suggestion {
+                    Some((_, name)) => format!("unknown option {}, did you mean {}?", key, name),
+                    None => format!("unknown option {}, see loggen config-schema", key),
+                })
+            }
+        };
+
+        match result {
+            Ok(option_args) => args.extend(option_args),
+            Err(err) => errors.push((pos, err)),
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(args);
+    }
+
+    errors.sort();
+    Err(errors
+        .iter()
+        .map(|(pos, err)| located(path, *pos, err))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// JSON schema of config files with `options`
+pub fn schema(options: &[Arg]) -> Value {
+    let mut properties = serde_json::Map::new();
+    for option in options {
+        let name = match long_name(option) {
+            Some(name) if name != "config" => name,
+            _ => continue,
+        };
+
+        let mut property = serde_json::Map::new();
+        if let Some(help) = option.b.help {
+            property.insert("description".to_string(), Value::from(help));
+        }
+        if !option.is_set(ArgSettings::TakesValue) {
+            property.insert("type".to_string(), Value::from("boolean"));
+        } else {
+            let mut item = serde_json::Map::new();
+            match &option.v.possible_vals {
+                Some(possible) => {
+                    item.insert("enum".to_string(), Value::from(possible.clone()));
+                }
+                None => {
+                    item.insert("type".to_string(), Value::from(vec!["string", "number"]));
+                }
+            }
+            if option.is_set(ArgSettings::Multiple) {
+                let list = serde_json::json!({ "type": "array", "items": item.clone() });
+                property.insert(
+                    "oneOf".to_string(),
+                    Value::from(vec![Value::Object(item), list]),
+                );
+            } else {
+                property = property.into_iter().chain(item).collect();
+            }
+            if let Some(default) = option.v.default_val {
+                property.insert(
+                    "default".to_string(),
+                    Value::from(default.to_string_lossy().to_string()),
+                );
+            }
+        }
+
+        properties.insert(name.to_string(), Value::Object(property));
+    }
+
+    serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "loggen config",
+        "description": "options by long name, string values can use ${ENV_VAR} and ${file:PATH}",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// command line args with the options from --config added before the ones in
+/// the command line, which override them (repeatable options are combined),
+/// `options_for` returns the options the config can set, for the subcommand
+/// or the default command if None
+pub fn args_with_config(
+    args: Vec<OsString>,
+    options_for: fn(Option<&str>) -> Vec<Arg<'static, 'static>>,
+) -> Result<Vec<OsString>, String> {
+    let path = match config_path(&args) {
+        Some(path) => path,
+        None => return Ok(args),
+    };
+
+    let subcommand = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(name) if GEN_SUBCOMMANDS.contains(&name) => Some(name),
+        _ => None,
+    };
+    let config_args = config_args(&path, &options_for(subcommand))?;
+    let insert_at = if subcommand.is_some() { 2 } else { 1 };
+
+    let mut out = args;
+    out.splice(insert_at..insert_at, config_args);
+    Ok(out)
+}
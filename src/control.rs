@@ -0,0 +1,63 @@
+use crate::rebalance::Rebalance;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// accept commands on the unix socket at `path` from a background thread, a
+/// socket left there by a previous run is replaced
+pub fn serve(path: &Path, rebalance: Arc<Rebalance>) -> io::Result<JoinHandle<()>> {
+    if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    println!("control socket listening on {}", path.display());
+
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    handle(stream, &rebalance)
+                        .map_err(|err| eprintln!("Error in control socket: {:?}", err))
+                        .ok();
+                }
+                Err(error) => {
+                    eprintln!("Error accepting control connection: {:?}", error);
+                }
+            }
+        }
+    }))
+}
+
+/// answer every command line of a connection until it's closed
+fn handle(stream: UnixStream, rebalance: &Rebalance) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            // clients that close without reading every reply
+            Err(err) if err.kind() == io::ErrorKind::ConnectionReset => break,
+            Err(err) => return Err(err),
+        };
+        let reply = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => continue,
+            ["parallelism"] => format!("parallelism {}", rebalance.current()),
+            ["parallelism", workers] => match workers.parse::<usize>() {
+                Ok(workers) if workers > 0 => {
+                    rebalance.request(workers);
+                    format!("ok, rebalancing to {} workers", workers)
+                }
+                _ => format!("error: {} isn't a number greater than 0", workers),
+            },
+            [command, ..] => format!(
+                "error: unknown command {}, supported: parallelism [COUNT]",
+                command
+            ),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::enrich::add_fields;
+use crate::group::glob_match;
+use crate::parse::{parse_duration, parse_kv_list};
+use crate::template::random_hex;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// the id being injected and when it was generated
+#[derive(Debug)]
+struct CurrentId {
+    id: String,
+    started: Instant,
+}
+
+/// files matching a glob whose lines get the same trace id while they are
+/// written close in time, see --correlate
+#[derive(Debug, Clone)]
+pub struct Correlation {
+    pub pattern: String,
+    pub field: String,
+    /// a new id is generated after this long
+    pub window: Duration,
+    current: Arc<Mutex<CurrentId>>,
+}
+
+impl Correlation {
+    /// parse "files=GLOB[:window=DURATION][:field=NAME]"
+    pub fn parse(v: &str) -> Result<Correlation, String> {
+        let mut pattern = None;
+        let mut window = Duration::from_millis(200);
+        let mut field = "trace_id".to_string();
+
+        for (key, value) in parse_kv_list(&v.replace(':', ","))? {
+            match key.as_str() {
+                "files" if !value.is_empty() => pattern = Some(value),
+                "window" => match parse_duration(&value) {
+                    Ok(duration) if !duration.is_zero() => window = duration,
+                    _ => return Err(format!("{} isn't a valid window", value)),
+                },
+                "field" if !value.is_empty() && !value.contains(['"', ' ']) => field = value,
+                "field" => return Err(format!("{} isn't a valid field name", value)),
+                _ => return Err(format!("unknown correlate option {}", key)),
+            }
+        }
+
+        Ok(Correlation {
+            pattern: pattern.ok_or_else(|| "correlate needs files=GLOB".to_string())?,
+            field,
+            window,
+            current: Arc::new(Mutex::new(CurrentId {
+                id: random_hex(32),
+                started: Instant::now(),
+            })),
+        })
+    }
+
+    pub fn matches(&self, rel_path: &Path) -> bool {
+        glob_match(&self.pattern, &rel_path.to_string_lossy())
+    }
+
+    /// the id shared by all files now, a new one once the window passed
+    fn id(&self) -> String {
+        let mut current = self.current.lock().unwrap();
+        if current.started.elapsed() >= self.window {
+            current.id = random_hex(32);
+            current.started = Instant::now();
+        }
+        current.id.clone()
+    }
+
+    /// `line` with the current id added like --enrich adds fields
+    pub fn apply(&self, line: &str) -> String {
+        let id = self.id();
+        add_fields(
+            line,
+            &format!(" {}={}", self.field, id),
+            &format!(",\"{}\":\"{}\"", self.field, id),
+        )
+    }
+}
+
+pub fn is_correlation(v: String) -> Result<(), String> {
+    Correlation::parse(&v).map(|_| ())
+}
+
+/// the first correlation `rel_path` belongs to, if any
+pub fn correlation_for(correlations: &[Correlation], rel_path: &Path) -> Option<Correlation> {
+    correlations
+        .iter()
+        .find(|correlation| correlation.matches(rel_path))
+        .cloned()
+}
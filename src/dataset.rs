@@ -0,0 +1,298 @@
+use crate::manifest::{Checksum, Segment};
+use crate::source::{FileSource, Source};
+use crate::synth;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// file name of the manifest written in the dataset directory
+pub const MANIFEST: &str = "dataset.json";
+
+/// an output file of a dataset and where its lines come from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileLayout {
+    /// relative to the dataset directory
+    pub path: String,
+    /// sample whose lines are repeated in order, relative to the layout file
+    pub sample: Option<PathBuf>,
+    /// built in synthetic format, see synth::FORMATS
+    pub format: Option<String>,
+    /// weight of the file when splitting the total lines
+    pub share: Option<u64>,
+    /// exact number of lines, instead of a share of the total
+    pub lines: Option<u64>,
+}
+
+/// which files a dataset has, see `loggen build-dataset`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    pub files: Vec<FileLayout>,
+}
+
+/// what was written, a snapshot (see `loggen snapshot`) with the total
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetManifest {
+    pub total_lines: u64,
+    pub files: BTreeMap<String, Segment>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// read a layout from a YAML (or JSON) file, sample paths are made relative
+/// to its directory
+pub fn load_layout(path: &Path) -> io::Result<Layout> {
+    let content = fs::read_to_string(path)?;
+    let mut layout: Layout = serde_yaml::from_str(&content).map_err(|err| {
+        invalid(format!(
+            "{} isn't a dataset layout: {}",
+            path.display(),
+            err
+        ))
+    })?;
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    for file in layout.files.iter_mut() {
+        if let Some(sample) = &mut file.sample {
+            *sample = base.join(&sample);
+        }
+    }
+    check_layout(&layout)?;
+    Ok(layout)
+}
+
+fn check_layout(layout: &Layout) -> io::Result<()> {
+    if layout.files.is_empty() {
+        return Err(invalid("the layout has no files".to_string()));
+    }
+
+    let mut paths = vec![];
+    for file in &layout.files {
+        let rel_path = Path::new(&file.path);
+        if file.path.is_empty()
+            || rel_path
+                .components()
+                .any(|part| !matches!(part, Component::Normal(_)))
+        {
+            return Err(invalid(format!(
+                "{} must be a relative path inside the dataset directory",
+                file.path
+            )));
+        }
+        if file.path == MANIFEST {
+            return Err(invalid(format!("{} is the name of the manifest", MANIFEST)));
+        }
+        if paths.contains(&rel_path) {
+            return Err(invalid(format!("{} is in the layout twice", file.path)));
+        }
+        paths.push(rel_path);
+
+        match (&file.sample, &file.format) {
+            (Some(_), None) => {}
+            (None, Some(format)) if synth::FORMATS.contains(&format.as_str()) => {}
+            (None, Some(format)) => {
+                return Err(invalid(format!(
+                    "unknown format {} for {}, supported: {}",
+                    format,
+                    file.path,
+                    synth::FORMATS.join(", ")
+                )))
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "{} needs either a sample or a format",
+                    file.path
+                )))
+            }
+        }
+        if file.lines.is_some() && file.share.is_some() {
+            return Err(invalid(format!(
+                "{} has both lines and share, use one",
+                file.path
+            )));
+        }
+        if file.share == Some(0) {
+            return Err(invalid(format!("{} has a share of 0", file.path)));
+        }
+    }
+
+    Ok(())
+}
+
+/// lines of each file: files with `lines` get exactly that, the rest of
+/// `total` is split by share (1 by default), remainders go to the files with
+/// the largest ones
+pub fn plan(layout: &Layout, total: u64) -> io::Result<Vec<u64>> {
+    let exact: u64 = layout.files.iter().filter_map(|file| file.lines).sum();
+    if exact > total {
+        return Err(invalid(format!(
+            "files with exact lines add up to {}, more than the total of {}",
+            exact, total
+        )));
+    }
+    let shared = total - exact;
+    let shares: u128 = layout
+        .files
+        .iter()
+        .filter(|file| file.lines.is_none())
+        .map(|file| file.share.unwrap_or(1) as u128)
+        .sum();
+    if shares == 0 && shared > 0 {
+        return Err(invalid(format!(
+            "{} lines are left for files with a share but there are none",
+            shared
+        )));
+    }
+
+    let mut counts = vec![];
+    let mut remainders = vec![];
+    for (index, file) in layout.files.iter().enumerate() {
+        match file.lines {
+            Some(lines) => counts.push(lines),
+            None => {
+                let scaled = shared as u128 * file.share.unwrap_or(1) as u128;
+                counts.push((scaled / shares) as u64);
+                remainders.push((scaled % shares, index));
+            }
+        }
+    }
+
+    let mut left = total - counts.iter().sum::<u64>();
+    // largest remainder first, earlier files first on ties
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    for (_, index) in remainders {
+        if left == 0 {
+            break;
+        }
+        counts[index] += 1;
+        left -= 1;
+    }
+
+    Ok(counts)
+}
+
+fn open_source(file: &FileLayout) -> io::Result<Box<dyn Source>> {
+    match (&file.sample, &file.format) {
+        (Some(sample), _) => {
+            // fail before writing anything if the sample can't be read
+            File::open(sample).map_err(|err| {
+                io::Error::new(err.kind(), format!("{}: {}", sample.display(), err))
+            })?;
+            Ok(Box::new(FileSource::new(sample.clone())))
+        }
+        (None, Some(format)) => Ok(synth::open(format, &Default::default())?.0),
+        (None, None) => Err(invalid(format!("{} has no sample or format", file.path))),
+    }
+}
+
+/// write `lines` lines from `source` to `path`, repeating it as needed
+fn write_file(source: &mut dyn Source, path: &Path, lines: u64) -> io::Result<Segment> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut checksum = Checksum::new();
+    let mut bytes = 0;
+    let mut written = 0;
+    let mut rewound = true;
+
+    while written < lines {
+        let mut line = match source.read_line()? {
+            Some(line) => line,
+            None if rewound => {
+                return Err(invalid(format!(
+                    "the source of {} has no lines",
+                    path.display()
+                )))
+            }
+            None => {
+                source.rewind()?;
+                rewound = true;
+                continue;
+            }
+        };
+        rewound = false;
+        if !line.ends_with('\n') {
+            line.push('\n');
+        }
+
+        writer.write_all(line.as_bytes())?;
+        checksum.update(line.as_bytes());
+        bytes += line.len() as u64;
+        written += 1;
+    }
+    writer.flush()?;
+
+    Ok(Segment {
+        lines: written,
+        bytes,
+        checksum: checksum.hex(),
+    })
+}
+
+/// write every file of `layout` in `out_dir` with `threads` threads and the
+/// manifest next to them, `out_dir` must be empty or not exist
+pub fn build(
+    layout: &Layout,
+    total: u64,
+    out_dir: &Path,
+    threads: usize,
+) -> io::Result<DatasetManifest> {
+    let counts = plan(layout, total)?;
+    if out_dir.exists() && fs::read_dir(out_dir)?.next().is_some() {
+        return Err(invalid(format!(
+            "{} isn't empty, datasets are built in a new or empty directory",
+            out_dir.display()
+        )));
+    }
+    let mut sources = layout
+        .files
+        .iter()
+        .map(open_source)
+        .collect::<io::Result<Vec<_>>>()?;
+    fs::create_dir_all(out_dir)?;
+
+    let next = AtomicUsize::new(0);
+    let sources: Vec<Mutex<&mut Box<dyn Source>>> = sources.iter_mut().map(Mutex::new).collect();
+    let results: Vec<Mutex<Option<io::Result<Segment>>>> =
+        layout.files.iter().map(|_| Mutex::new(None)).collect();
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, layout.files.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= layout.files.len() {
+                    break;
+                }
+                let path = out_dir.join(&layout.files[index].path);
+                let mut source = sources[index].lock().unwrap();
+                let result = write_file(source.as_mut(), &path, counts[index]);
+                *results[index].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    let mut files = BTreeMap::new();
+    for (file, result) in layout.files.iter().zip(results) {
+        let segment =
+            result.into_inner().unwrap().unwrap().map_err(|err| {
+                io::Error::new(err.kind(), format!("writing {}: {}", file.path, err))
+            })?;
+        files.insert(file.path.clone(), segment);
+    }
+
+    let manifest = DatasetManifest {
+        total_lines: total,
+        files,
+    };
+    let content = serde_json::to_string_pretty(&manifest).map_err(io::Error::other)?;
+    fs::write(out_dir.join(MANIFEST), content + "\n")?;
+    Ok(manifest)
+}
@@ -0,0 +1,37 @@
+use rand::Rng;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// re-emits a fraction of written lines after a delay to exercise deduplication
+#[derive(Debug)]
+pub struct Duplicator {
+    rate: f64,
+    delay: Duration,
+    pending: VecDeque<(Instant, String)>,
+}
+
+impl Duplicator {
+    pub fn new(rate: f64, delay: Duration) -> Duplicator {
+        Duplicator {
+            rate,
+            delay,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// maybe schedule a duplicate of a line that was just written
+    pub fn record(&mut self, line: &str) {
+        if self.rate > 0.0 && rand::thread_rng().gen_bool(self.rate) {
+            self.pending
+                .push_back((Instant::now() + self.delay, line.to_string()));
+        }
+    }
+
+    /// next duplicate whose delay has elapsed, pending lines are queued in order
+    pub fn next_due(&mut self) -> Option<String> {
+        match self.pending.front() {
+            Some((due, _)) if *due <= Instant::now() => self.pending.pop_front().map(|(_, l)| l),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::parse::unknown_value;
+use std::ffi::CStr;
+use std::path::Path;
+use std::str::FromStr;
+
+/// metadata that can be added to generated lines, see --enrich
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    /// hostname of the machine running loggen
+    Host,
+    /// loggen's process id
+    Pid,
+    /// path of the sample the line was read from
+    File,
+    /// index of the generator thread that wrote the line
+    Worker,
+}
+
+impl Field {
+    pub const NAMES: &'static [&'static str] = &["host", "pid", "file", "worker"];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Field::Host => "host",
+            Field::Pid => "pid",
+            Field::File => "file",
+            Field::Worker => "worker",
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<Field, String> {
+        match v {
+            "host" => Ok(Field::Host),
+            "pid" => Ok(Field::Pid),
+            "file" => Ok(Field::File),
+            "worker" => Ok(Field::Worker),
+            _ => Err(unknown_value("enrich field", v, Field::NAMES)),
+        }
+    }
+}
+
+/// fields to add to every line, in the order they were given
+#[derive(Debug, Clone)]
+pub struct Enrich {
+    fields: Vec<Field>,
+}
+
+impl Enrich {
+    /// parse "host,pid,file,worker"
+    pub fn parse(v: &str) -> Result<Enrich, String> {
+        let mut fields = vec![];
+        for name in v.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let field = name.parse::<Field>()?;
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+
+        if fields.is_empty() {
+            return Err(format!("{} has no fields to add", v));
+        }
+        Ok(Enrich { fields })
+    }
+}
+
+pub fn is_enrich(v: String) -> Result<(), String> {
+    Enrich::parse(&v).map(|_| ())
+}
+
+pub fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) };
+    if result != 0 {
+        return "unknown".to_string();
+    }
+    // the name may be truncated without a terminating nul
+    buf[buf.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .to_string()
+}
+
+/// adds the fields to the lines of one file, appended as key=value to plain
+/// lines and as keys at the end of JSON objects so the line keeps its key order
+#[derive(Debug)]
+pub struct Enricher {
+    fields: Vec<Field>,
+    host: String,
+    file: String,
+    plain: String,
+    json: String,
+}
+
+impl Enricher {
+    pub fn new(enrich: &Enrich, path_in: &Path) -> Enricher {
+        let mut enricher = Enricher {
+            fields: enrich.fields.clone(),
+            host: hostname(),
+            file: path_in.display().to_string(),
+            plain: String::new(),
+            json: String::new(),
+        };
+        enricher.assigned(0);
+        enricher
+    }
+
+    /// the file is written by generator `worker`
+    pub fn assigned(&mut self, worker: usize) {
+        let mut plain = String::new();
+        let mut json = String::new();
+        for field in &self.fields {
+            let (text, quoted) = match field {
+                Field::Host => (self.host.clone(), true),
+                Field::Pid => (std::process::id().to_string(), false),
+                Field::File => (self.file.clone(), true),
+                Field::Worker => (worker.to_string(), false),
+            };
+
+            plain.push_str(&format!(" {}={}", field.name(), text.replace(' ', "_")));
+            let value = if quoted {
+                serde_json::Value::from(text).to_string()
+            } else {
+                text
+            };
+            json.push_str(&format!(",\"{}\":{}", field.name(), value));
+        }
+
+        self.plain = plain;
+        self.json = json;
+    }
+
+    /// `line` with the fields added, keeping its line ending
+    pub fn apply(&self, line: &str) -> String {
+        add_fields(line, &self.plain, &self.json)
+    }
+}
+
+/// `line` with `plain` (like " key=value") appended to plain lines or `json`
+/// (like ",\"key\":value") added at the end of JSON objects, keeping its line
+/// ending
+pub fn add_fields(line: &str, plain: &str, json: &str) -> String {
+    let content = line.trim_end_matches(['\r', '\n']);
+    let ending = &line[content.len()..];
+    let trimmed = content.trim_end();
+
+    let is_object = trimmed.trim_start().starts_with('{')
+        && matches!(
+            serde_json::from_str::<serde_json::Value>(trimmed),
+            Ok(serde_json::Value::Object(_))
+        );
+    if !is_object {
+        return format!("{}{}{}", content, plain, ending);
+    }
+
+    let body = trimmed[..trimmed.len() - 1].trim_end();
+    let fields = if body.ends_with('{') {
+        // no keys yet, skip the leading comma
+        &json[1..]
+    } else {
+        json
+    };
+    format!("{}{}}}{}", body, fields, ending)
+}
@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+
+/// file descriptors left for stdio, sockets and the like when the budget is
+/// derived from the process limit
+const RESERVED_FDS: usize = 64;
+
+/// max open files allowed by RLIMIT_NOFILE minus a reserve for other uses
+pub fn default_max_open_files() -> usize {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    let ok = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } == 0;
+    if ok && limit.rlim_cur != libc::RLIM_INFINITY {
+        (limit.rlim_cur as usize)
+            .saturating_sub(RESERVED_FDS)
+            .max(2)
+    } else {
+        // unknown or unlimited, 1024 is the common default soft limit
+        1024 - RESERVED_FDS
+    }
+}
+
+/// tracks which items of a worker are open and picks the least recently used
+/// one to close when opening another would exceed the capacity
+#[derive(Debug)]
+pub struct Lru {
+    capacity: usize,
+    open: VecDeque<usize>,
+}
+
+impl Lru {
+    pub fn new(capacity: usize) -> Lru {
+        Lru {
+            capacity: capacity.max(1),
+            open: VecDeque::new(),
+        }
+    }
+
+    /// mark `index` as used, returns the index that must be closed to stay
+    /// within capacity if any
+    pub fn touch(&mut self, index: usize) -> Option<usize> {
+        if let Some(pos) = self.open.iter().position(|i| *i == index) {
+            self.open.remove(pos);
+            self.open.push_back(index);
+            return None;
+        }
+
+        self.open.push_back(index);
+        if self.open.len() > self.capacity {
+            self.open.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// forget `index`, its item left the worker
+    pub fn remove(&mut self, index: usize) {
+        self.open.retain(|i| *i != index);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::pacer::Pacer;
+use crate::parse::parse_rate;
+use std::path::Path;
+use std::sync::Arc;
+
+/// true if `path` matches `pattern`, `*` matches any characters but /, `**`
+/// any characters including / and `?` a single character but /
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    matches_from(&pattern, &path)
+}
+
+fn matches_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            // a/**/b also matches a/b
+            let rest = &pattern[2..];
+            if rest.first() == Some(&'/') && matches_from(&rest[1..], path) {
+                return true;
+            }
+            (0..=path.len()).any(|skip| matches_from(rest, &path[skip..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            let segment = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=segment).any(|skip| matches_from(rest, &path[skip..]))
+        }
+        Some('?') => match path.first() {
+            Some(c) if *c != '/' => matches_from(&pattern[1..], &path[1..]),
+            _ => false,
+        },
+        Some(c) => path.first() == Some(c) && matches_from(&pattern[1..], &path[1..]),
+    }
+}
+
+/// files matching a glob that share a combined rate, see --group-rate
+#[derive(Debug, Clone)]
+pub struct RateGroup {
+    pub pattern: String,
+    pub rate: f64,
+    pub pacer: Arc<Pacer>,
+}
+
+impl RateGroup {
+    /// parse "GLOB=RATE" like "serviceA/**=1000lps"
+    pub fn parse(v: &str) -> Result<RateGroup, String> {
+        let (pattern, rate) = v
+            .rsplit_once('=')
+            .ok_or_else(|| format!("{} isn't GLOB=RATE, for example 'api/**=100lps'", v))?;
+        if pattern.is_empty() {
+            return Err(format!("{} has an empty glob", v));
+        }
+        let rate = parse_rate(rate)?;
+
+        Ok(RateGroup {
+            pattern: pattern.to_string(),
+            rate,
+            pacer: Arc::new(Pacer::new(Some(rate))),
+        })
+    }
+
+    pub fn matches(&self, rel_path: &Path) -> bool {
+        glob_match(&self.pattern, &rel_path.to_string_lossy())
+    }
+}
+
+pub fn is_group_rate(v: String) -> Result<(), String> {
+    RateGroup::parse(&v).map(|_| ())
+}
+
+/// pacer of the first group `rel_path` belongs to, if any
+pub fn pacer_for(groups: &[RateGroup], rel_path: &Path) -> Option<Arc<Pacer>> {
+    groups
+        .iter()
+        .find(|group| group.matches(rel_path))
+        .map(|group| group.pacer.clone())
+}
@@ -0,0 +1,48 @@
+use crate::template::{TemplateContext, TemplateState};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    /// rendered as a template, see --template
+    pub line: String,
+}
+
+/// writes a keepalive line into an output that had no lines for a while
+#[derive(Debug)]
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    template: TemplateState,
+    last_line: Instant,
+}
+
+impl Heartbeat {
+    pub fn new(config: HeartbeatConfig, context: TemplateContext) -> Heartbeat {
+        Heartbeat {
+            config,
+            template: TemplateState::new(context),
+            last_line: Instant::now(),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.config.interval
+    }
+
+    /// a regular line was written
+    pub fn written(&mut self) {
+        self.last_line = Instant::now();
+    }
+
+    /// heartbeat line if nothing was written for the interval
+    pub fn next_due(&mut self) -> Option<String> {
+        if self.last_line.elapsed() < self.config.interval {
+            return None;
+        }
+
+        self.last_line = Instant::now();
+        let mut line = self.template.render(&self.config.line);
+        line.push('\n');
+        Some(line)
+    }
+}
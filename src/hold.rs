@@ -0,0 +1,49 @@
+use crate::out_writer::OutWriter;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// keep writing to a file after it was rotated, like a process that didn't
+/// reopen its log yet, see --rotate-hold-open
+#[derive(Debug, Clone)]
+pub struct HoldConfig {
+    pub duration: Duration,
+    /// lines written to the rotated file at most, all lines during duration if None
+    pub lines: Option<u64>,
+}
+
+/// writer of a rotated file that is still written to
+#[derive(Debug)]
+pub struct Held {
+    writer: OutWriter,
+    until: Instant,
+    lines_left: Option<u64>,
+}
+
+impl Held {
+    pub fn new(writer: OutWriter, config: &HoldConfig) -> Held {
+        Held {
+            writer,
+            until: Instant::now() + config.duration,
+            lines_left: config.lines,
+        }
+    }
+
+    /// false once the hold time passed or the line limit was reached
+    pub fn is_active(&self) -> bool {
+        Instant::now() < self.until && self.lines_left != Some(0)
+    }
+
+    pub fn write(&mut self, line: &str) -> io::Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        if let Some(lines_left) = &mut self.lines_left {
+            *lines_left -= 1;
+        }
+
+        Ok(())
+    }
+
+    /// flush what was written, the file is closed when it's dropped
+    pub fn release(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,239 @@
+use crate::validate;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// candidate field separators, in order of preference when equally consistent
+const SEPARATORS: &[(char, &str)] = &[
+    ('\t', "tab"),
+    ('|', "pipe"),
+    (',', "comma"),
+    (';', "semicolon"),
+    (' ', "space"),
+];
+/// share of lines a classification or separator must hold for
+const MAJORITY: f64 = 0.9;
+
+#[derive(Debug, Serialize)]
+pub struct LineLengths {
+    pub min: usize,
+    pub max: usize,
+    pub mean: usize,
+    pub p50: usize,
+    pub p95: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimestampReport {
+    /// name as reported by validate-samples, like iso8601 or common-log
+    pub name: &'static str,
+    /// strftime format to parse or render it
+    pub format: &'static str,
+    /// percentage of sampled lines where it was found
+    pub pct: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeparatorReport {
+    pub name: &'static str,
+    /// most common number of fields per line
+    pub fields: usize,
+    /// percentage of sampled lines with that number of fields
+    pub pct: u64,
+}
+
+/// what was found in the first lines of a sample file
+#[derive(Debug, Serialize)]
+pub struct FileInspection {
+    pub path: String,
+    pub sampled_lines: usize,
+    /// json, plain, mixed (some lines are JSON objects), binary or empty
+    pub kind: &'static str,
+    pub line_length: Option<LineLengths>,
+    pub timestamp: Option<TimestampReport>,
+    /// only for plain lines
+    pub separator: Option<SeparatorReport>,
+    /// true if most plain lines have key=value pairs
+    pub key_value: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InspectReport {
+    pub files: Vec<FileInspection>,
+}
+
+fn percent(count: usize, total: usize) -> u64 {
+    (count * 100).checked_div(total).unwrap_or(0) as u64
+}
+
+fn line_lengths(lines: &[String]) -> Option<LineLengths> {
+    let mut lengths: Vec<usize> = lines.iter().map(|line| line.len()).collect();
+    lengths.sort_unstable();
+    let at = |pct: usize| lengths[(lengths.len() - 1) * pct / 100];
+    Some(LineLengths {
+        min: *lengths.first()?,
+        max: *lengths.last()?,
+        mean: lengths.iter().sum::<usize>() / lengths.len(),
+        p50: at(50),
+        p95: at(95),
+    })
+}
+
+fn timestamp(lines: &[String]) -> Option<TimestampReport> {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    for line in lines {
+        if let Some(name) = validate::detect_format(line) {
+            *counts.entry(name).or_insert(0) += 1;
+        }
+    }
+
+    let (name, count) = counts
+        .into_iter()
+        .max_by_key(|(name, count)| (*count, *name))?;
+    Some(TimestampReport {
+        name,
+        format: validate::strftime_of(name)?,
+        pct: percent(count, lines.len()),
+    })
+}
+
+/// separator that splits most lines into the same number of fields
+fn separator(lines: &[&String]) -> Option<SeparatorReport> {
+    let mut best: Option<SeparatorReport> = None;
+    for (sep, name) in SEPARATORS {
+        let mut by_fields: HashMap<usize, usize> = HashMap::new();
+        for line in lines {
+            *by_fields.entry(line.split(*sep).count()).or_insert(0) += 1;
+        }
+
+        let (fields, count) = match by_fields.into_iter().max_by_key(|(f, c)| (*c, *f)) {
+            Some(most_common) => most_common,
+            None => continue,
+        };
+        let pct = percent(count, lines.len());
+        if fields < 2 || (pct as f64) < MAJORITY * 100.0 {
+            continue;
+        }
+        if best.as_ref().is_none_or(|best| pct > best.pct) {
+            best = Some(SeparatorReport { name, fields, pct });
+        }
+    }
+
+    best
+}
+
+fn is_key_value(line: &str) -> bool {
+    line.split_whitespace()
+        .filter(|token| match token.find('=') {
+            Some(pos) => {
+                pos > 0
+                    && token[..pos]
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            }
+            None => false,
+        })
+        .count()
+        >= 2
+}
+
+fn is_json_object(line: &str) -> bool {
+    line.trim_start().starts_with('{')
+        && matches!(
+            serde_json::from_str::<serde_json::Value>(line),
+            Ok(serde_json::Value::Object(_))
+        )
+}
+
+/// inspect the first `sample_lines` lines of the file at `path`
+fn inspect_file(path: &Path, rel_path: &str, sample_lines: usize) -> io::Result<FileInspection> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut inspection = FileInspection {
+        path: rel_path.to_string(),
+        sampled_lines: 0,
+        kind: "empty",
+        line_length: None,
+        timestamp: None,
+        separator: None,
+        key_value: false,
+    };
+
+    let sample = reader.fill_buf()?;
+    if validate::is_binary(&sample[..sample.len().min(validate::BINARY_SAMPLE)]) {
+        inspection.kind = "binary";
+        return Ok(inspection);
+    }
+
+    let mut lines = vec![];
+    let mut buf = vec![];
+    while lines.len() < sample_lines {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buf);
+        lines.push(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+    if lines.is_empty() {
+        return Ok(inspection);
+    }
+
+    let (json, plain): (Vec<&String>, Vec<&String>) =
+        lines.iter().partition(|line| is_json_object(line));
+    let json_pct = json.len() as f64 / lines.len() as f64;
+    inspection.kind = if json_pct >= MAJORITY {
+        "json"
+    } else if json.is_empty() {
+        "plain"
+    } else {
+        "mixed"
+    };
+    inspection.sampled_lines = lines.len();
+    inspection.line_length = line_lengths(&lines);
+    inspection.timestamp = timestamp(&lines);
+    if !plain.is_empty() {
+        inspection.separator = separator(&plain);
+        let key_value = plain.iter().filter(|line| is_key_value(line)).count();
+        inspection.key_value = key_value as f64 >= plain.len() as f64 * MAJORITY;
+    }
+
+    Ok(inspection)
+}
+
+/// inspect every file under `in_dir`, files that can't be read are reported on
+/// stderr and left out
+pub fn inspect(in_dir: &Path, sample_lines: usize) -> io::Result<InspectReport> {
+    if !in_dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} isn't a directory", in_dir.display()),
+        ));
+    }
+
+    let mut files = vec![];
+    for entry in WalkDir::new(in_dir)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let rel_path = path
+            .strip_prefix(in_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string();
+        match inspect_file(path, &rel_path, sample_lines) {
+            Ok(inspection) => files.push(inspection),
+            Err(err) => eprintln!("Error inspecting {}: {}", rel_path, err),
+        }
+    }
+
+    Ok(InspectReport { files })
+}
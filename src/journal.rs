@@ -0,0 +1,186 @@
+use crate::enrich::hostname;
+use rand::Rng;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// what the output files are written as, see --out-format
+pub const OUT_FORMATS: &[&str] = &["lines", "journal-export"];
+
+/// fields shared by the records of every output, see --out-format journal-export
+#[derive(Debug, Clone)]
+pub struct JournalOptions {
+    /// random for each run, like a new boot
+    boot_id: String,
+    hostname: String,
+    pid: u32,
+    /// monotonic timestamps count from here
+    started: Instant,
+}
+
+impl JournalOptions {
+    /// a new boot id, monotonic timestamps start now
+    pub fn start() -> JournalOptions {
+        JournalOptions {
+            boot_id: format!("{:032x}", rand::thread_rng().gen::<u128>()),
+            hostname: hostname(),
+            pid: std::process::id(),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// writes lines as entries of the systemd journal export format, the
+/// sample line is the MESSAGE
+#[derive(Debug)]
+pub struct JournalExport {
+    options: JournalOptions,
+    /// SYSLOG_IDENTIFIER, the file name without extension
+    identifier: String,
+    seqnum: u64,
+}
+
+/// journald's priority for the level a line mentions, info if it has none
+fn priority_of(line: &str) -> u8 {
+    let upper = line.to_ascii_uppercase();
+    let has = |word: &str| {
+        upper
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|token| token == word)
+    };
+    if has("FATAL") || has("CRIT") || has("CRITICAL") {
+        2
+    } else if has("ERROR") || has("ERR") {
+        3
+    } else if has("WARN") || has("WARNING") {
+        4
+    } else if has("DEBUG") || has("TRACE") {
+        7
+    } else {
+        6
+    }
+}
+
+/// values with newlines, control characters or that aren't utf-8 are
+/// written in the binary form: the name, a newline and a little endian u64
+/// length before the raw value
+fn push_field(record: &mut Vec<u8>, name: &str, value: &[u8]) {
+    let printable = std::str::from_utf8(value)
+        .is_ok_and(|text| !text.chars().any(|c| c.is_control() && c != '\t'));
+    record.extend_from_slice(name.as_bytes());
+    if printable {
+        record.push(b'=');
+    } else {
+        record.push(b'\n');
+        record.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    }
+    record.extend_from_slice(value);
+    record.push(b'\n');
+}
+
+impl JournalExport {
+    pub fn new(options: &JournalOptions, rel_path: &Path) -> JournalExport {
+        JournalExport {
+            options: options.clone(),
+            identifier: rel_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "loggen".to_string()),
+            seqnum: 0,
+        }
+    }
+
+    /// the entry for `line`, ending with the empty line that separates entries
+    pub fn record(&mut self, line: &str) -> Vec<u8> {
+        self.seqnum += 1;
+        let message = line.trim_end_matches(['\r', '\n']);
+        let realtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_micros() as u64);
+        let monotonic = self.options.started.elapsed().as_micros() as u64;
+        let boot_id = &self.options.boot_id;
+
+        let mut record = Vec::with_capacity(message.len() + 512);
+        push_field(
+            &mut record,
+            "__CURSOR",
+            format!(
+                "s={};i={:x};b={};m={:x};t={:x};x={:016x}",
+                boot_id,
+                self.seqnum,
+                boot_id,
+                monotonic,
+                realtime,
+                self.seqnum ^ realtime
+            )
+            .as_bytes(),
+        );
+        push_field(
+            &mut record,
+            "__REALTIME_TIMESTAMP",
+            realtime.to_string().as_bytes(),
+        );
+        push_field(
+            &mut record,
+            "__MONOTONIC_TIMESTAMP",
+            monotonic.to_string().as_bytes(),
+        );
+        push_field(&mut record, "_BOOT_ID", boot_id.as_bytes());
+        push_field(&mut record, "_TRANSPORT", b"stdout");
+        push_field(
+            &mut record,
+            "PRIORITY",
+            priority_of(message).to_string().as_bytes(),
+        );
+        push_field(&mut record, "SYSLOG_IDENTIFIER", self.identifier.as_bytes());
+        push_field(&mut record, "_PID", self.options.pid.to_string().as_bytes());
+        push_field(&mut record, "_HOSTNAME", self.options.hostname.as_bytes());
+        push_field(&mut record, "MESSAGE", message.as_bytes());
+        record.push(b'\n');
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{push_field, JournalExport, JournalOptions};
+    use std::path::Path;
+
+    fn field(name: &str, value: &[u8]) -> Vec<u8> {
+        let mut record = vec![];
+        push_field(&mut record, name, value);
+        record
+    }
+
+    #[test]
+    fn text_values_are_name_equals_value() {
+        assert_eq!(field("MESSAGE", b"hi\tthere"), b"MESSAGE=hi\tthere\n");
+        assert_eq!(field("MESSAGE", b""), b"MESSAGE=\n");
+    }
+
+    #[test]
+    fn other_values_are_binary_with_their_length() {
+        assert_eq!(
+            field("MESSAGE", b"a\nb"),
+            b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n"
+        );
+        assert_eq!(
+            field("MESSAGE", b"\x1b[31mred"),
+            b"MESSAGE\n\x08\0\0\0\0\0\0\0\x1b[31mred\n"
+        );
+        assert_eq!(
+            field("DATA", b"\xff\xfe"),
+            b"DATA\n\x02\0\0\0\0\0\0\0\xff\xfe\n"
+        );
+    }
+
+    #[test]
+    fn records_end_with_an_empty_line() {
+        let mut export = JournalExport::new(&JournalOptions::start(), Path::new("dir/app.log"));
+        let record = export.record("ERROR bell\x07\n");
+        assert!(record.ends_with(b"MESSAGE\n\x0b\0\0\0\0\0\0\0ERROR bell\x07\n\n"));
+        let text = String::from_utf8_lossy(&record);
+        assert!(text.contains("\nPRIORITY=3\n"));
+        assert!(text.contains("\nSYSLOG_IDENTIFIER=app\n"));
+        assert!(text.starts_with("__CURSOR=s="));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::source::Source;
+use kafka::consumer::{Consumer, FetchOffset};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+
+/// parse "kafka://broker1:9092,broker2:9092/topic" into (brokers, topic)
+pub fn parse_url(url: &str) -> Result<(Vec<String>, String), String> {
+    let rest = match url.strip_prefix("kafka://") {
+        Some(rest) => rest,
+        None => return Err(format!("{} isn't a kafka:// url", url)),
+    };
+
+    match rest.find('/') {
+        Some(pos) if pos > 0 && pos + 1 < rest.len() => Ok((
+            rest[..pos].split(',').map(|v| v.to_string()).collect(),
+            rest[pos + 1..].to_string(),
+        )),
+        _ => Err(format!("{} should be kafka://broker[,broker]/topic", url)),
+    }
+}
+
+/// consumes a topic from the earliest offset, the end is reached when a poll
+/// returns no messages, rewinding consumes the topic again from the start
+pub struct KafkaSource {
+    brokers: Vec<String>,
+    topic: String,
+    consumer: Option<Consumer>,
+    pending: VecDeque<String>,
+}
+
+impl fmt::Debug for KafkaSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaSource")
+            .field("brokers", &self.brokers)
+            .field("topic", &self.topic)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+fn to_io_error(err: kafka::Error) -> io::Error {
+    io::Error::other(format!("kafka: {}", err))
+}
+
+impl KafkaSource {
+    pub fn new(brokers: Vec<String>, topic: String) -> io::Result<KafkaSource> {
+        let mut source = KafkaSource {
+            brokers,
+            topic,
+            consumer: None,
+            pending: VecDeque::new(),
+        };
+
+        // connect at startup so a wrong broker or topic is reported right away
+        source.consumer()?;
+        Ok(source)
+    }
+
+    fn consumer(&mut self) -> io::Result<&mut Consumer> {
+        if self.consumer.is_none() {
+            let consumer = Consumer::from_hosts(self.brokers.clone())
+                .with_topic(self.topic.clone())
+                .with_fallback_offset(FetchOffset::Earliest)
+                .with_client_id("loggen".to_string())
+                .create()
+                .map_err(to_io_error)?;
+            self.consumer = Some(consumer);
+        }
+
+        Ok(self.consumer.as_mut().unwrap())
+    }
+}
+
+impl Source for KafkaSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if self.pending.is_empty() {
+            let message_sets = self.consumer()?.poll().map_err(to_io_error)?;
+            for message_set in message_sets.iter() {
+                for message in message_set.messages() {
+                    let mut line = String::from_utf8_lossy(message.value).to_string();
+                    if !line.ends_with('\n') {
+                        line.push('\n');
+                    }
+                    self.pending.push_back(line);
+                }
+            }
+        }
+
+        Ok(self.pending.pop_front())
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        // without a consumer group offsets aren't stored, a new consumer starts at the earliest
+        self.consumer = None;
+        self.pending.clear();
+        Ok(())
+    }
+}
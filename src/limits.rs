@@ -0,0 +1,205 @@
+use crate::pacer::Pacer;
+use crate::stats::Stats;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// how often memory and cpu use are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// the rate is cut to this share of the measured one while over a limit
+const BACKOFF: f64 = 0.75;
+/// and raised by this factor each check while comfortably under the limits
+const RECOVER: f64 = 1.1;
+/// under this share of every limit is comfortably under
+const HEADROOM: f64 = 0.8;
+/// throttling never goes below this many lines per second
+const MIN_RATE: f64 = 1.0;
+/// share of a cgroup limit used when --max-memory or --max-cpu-pct go over it
+const CGROUP_SHARE: f64 = 0.9;
+
+/// resource use loggen keeps itself under by lowering its rate, see
+/// --max-memory and --max-cpu-pct
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// resident memory in bytes
+    pub max_memory: Option<u64>,
+    /// percentage of one core, 200 is two cores
+    pub max_cpu_pct: Option<f64>,
+}
+
+pub fn parse_cpu_pct(v: &str) -> Result<f64, String> {
+    match v.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(pct) if pct > 0.0 => Ok(pct),
+        _ => Err(format!("{} isn't a percentage greater than 0", v)),
+    }
+}
+
+pub fn is_cpu_pct(v: String) -> Result<(), String> {
+    parse_cpu_pct(&v).map(|_| ())
+}
+
+/// resident memory of this process
+#[cfg(target_os = "linux")]
+pub fn memory_used() -> Option<u64> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * page_size.max(0) as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn memory_used() -> Option<u64> {
+    None
+}
+
+/// user and system cpu time used by this process
+fn cpu_used() -> Duration {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+    let time = |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+    time(usage.ru_utime) + time(usage.ru_stime)
+}
+
+/// memory limit of the cgroup (v2 or v1) loggen runs in
+fn cgroup_memory() -> Option<u64> {
+    [
+        "/sys/fs/cgroup/memory.max",
+        "/sys/fs/cgroup/memory/memory.limit_in_bytes",
+    ]
+    .iter()
+    .filter_map(|path| fs::read_to_string(path).ok())
+    // "max" in v2 and a huge number in v1 mean no limit
+    .find_map(|v| v.trim().parse::<u64>().ok().filter(|v| *v < 1 << 60))
+}
+
+/// cpu quota of the cgroup (v2 or v1) loggen runs in, as a percentage of one core
+fn cgroup_cpu_pct() -> Option<f64> {
+    let quota = |quota: &str, period: &str| -> Option<f64> {
+        let quota = quota.trim().parse::<f64>().ok().filter(|v| *v > 0.0)?;
+        let period = period.trim().parse::<f64>().ok().filter(|v| *v > 0.0)?;
+        Some(quota / period * 100.0)
+    };
+
+    if let Ok(max) = fs::read_to_string("/sys/fs/cgroup/cpu.max") {
+        let mut parts = max.split_whitespace();
+        return quota(parts.next()?, parts.next()?);
+    }
+    quota(
+        &fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_quota_us").ok()?,
+        &fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_period_us").ok()?,
+    )
+}
+
+impl Limits {
+    pub fn is_set(&self) -> bool {
+        self.max_memory.is_some() || self.max_cpu_pct.is_some()
+    }
+
+    /// lower limits that are over what the cgroup allows, the kernel would
+    /// kill or throttle loggen before it reacts
+    pub fn fit_cgroup(mut self) -> Limits {
+        if let (Some(max), Some(cgroup)) = (self.max_memory, cgroup_memory()) {
+            let fitted = (cgroup as f64 * CGROUP_SHARE) as u64;
+            if max > fitted {
+                eprintln!(
+                    "Warning: --max-memory is over the cgroup limit of {} bytes, using {} bytes",
+                    cgroup, fitted
+                );
+                self.max_memory = Some(fitted);
+            }
+        }
+        if let (Some(max), Some(cgroup)) = (self.max_cpu_pct, cgroup_cpu_pct()) {
+            let fitted = cgroup * CGROUP_SHARE;
+            if max > fitted {
+                eprintln!(
+                    "Warning: --max-cpu-pct is over the cgroup quota of {:.0}%, using {:.0}%",
+                    cgroup, fitted
+                );
+                self.max_cpu_pct = Some(fitted);
+            }
+        }
+        if self.max_memory.is_some() && memory_used().is_none() {
+            eprintln!(
+                "Warning: memory use can't be read on this platform, --max-memory is ignored"
+            );
+            self.max_memory = None;
+        }
+
+        self
+    }
+
+    /// the limit that is exceeded, if any, and whether use is under the
+    /// headroom of every limit
+    fn check(&self, memory: Option<u64>, cpu_pct: f64) -> (Option<String>, bool) {
+        let mut over = None;
+        let mut under = true;
+        if let (Some(max), Some(memory)) = (self.max_memory, memory) {
+            if memory > max {
+                over = Some(format!("memory {} bytes over {}", memory, max));
+            }
+            under &= (memory as f64) < max as f64 * HEADROOM;
+        }
+        if let Some(max) = self.max_cpu_pct {
+            if cpu_pct > max {
+                over = Some(format!("cpu {:.0}% over {:.0}%", cpu_pct, max));
+            }
+            under &= cpu_pct < max * HEADROOM;
+        }
+        (over, under)
+    }
+}
+
+/// check resource use every second and cap the pacer's rate while it's over
+/// the limits, the cap is raised again gradually once it's back under
+pub fn watch(
+    limits: Limits,
+    pacer: Arc<Pacer>,
+    stats: Arc<Stats>,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_check = Instant::now();
+        let mut last_cpu = cpu_used();
+        let mut last_lines = stats.lines();
+        // rate measured when throttling started, the cap is lifted when it gets back to it
+        let mut throttled_from = 0.0;
+
+        while !stop.load(Ordering::Relaxed) {
+            sleep(CHECK_INTERVAL);
+            let now = Instant::now();
+            let elapsed = (now - last_check).as_secs_f64();
+            let cpu = cpu_used();
+            let lines = stats.lines();
+            let cpu_pct = (cpu - last_cpu).as_secs_f64() / elapsed * 100.0;
+            let rate = (lines - last_lines) as f64 / elapsed;
+            last_check = now;
+            last_cpu = cpu;
+            last_lines = lines;
+
+            match (limits.check(memory_used(), cpu_pct), pacer.cap()) {
+                ((Some(reason), _), None) => {
+                    throttled_from = rate;
+                    let cap = (rate * BACKOFF).max(MIN_RATE);
+                    eprintln!("Warning: {}, throttling to {:.0} lines/s", reason, cap);
+                    pacer.set_cap(Some(cap));
+                }
+                ((Some(_), _), Some(cap)) => {
+                    // the measured rate can be under the cap while buffers drain
+                    pacer.set_cap(Some((cap.min(rate) * BACKOFF).max(MIN_RATE)));
+                }
+                ((None, true), Some(cap)) => {
+                    let raised = cap * RECOVER;
+                    if raised >= throttled_from || pacer.rate().is_some_and(|rate| raised >= rate) {
+                        eprintln!("back under the limits, throttling stopped");
+                        pacer.set_cap(None);
+                    } else {
+                        pacer.set_cap(Some(raised));
+                    }
+                }
+                _ => {}
+            }
+        }
+    })
+}
@@ -0,0 +1,105 @@
+use crate::parse::{parse_percentage, unknown_value};
+use rand::Rng;
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// line ending of written lines, see --line-ending
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    /// whatever the sample line had
+    #[default]
+    Preserve,
+    /// this share of lines end with \r\n, the rest with \n
+    Mixed(f64),
+}
+
+impl LineEnding {
+    pub const NAMES: &'static [&'static str] = &["lf", "crlf", "preserve", "mixed:PCT"];
+
+    /// `line` with its ending replaced, lines without one are left as they are
+    pub fn apply<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let crlf = match self {
+            LineEnding::Preserve => return Cow::Borrowed(line),
+            LineEnding::Lf => false,
+            LineEnding::Crlf => true,
+            LineEnding::Mixed(share) => rand::thread_rng().gen_bool(*share),
+        };
+        let body = match line.strip_suffix('\n') {
+            Some(body) => body.strip_suffix('\r').unwrap_or(body),
+            None => return Cow::Borrowed(line),
+        };
+
+        let ending = if crlf { "\r\n" } else { "\n" };
+        if line.len() == body.len() + ending.len() {
+            // it already ends that way
+            return Cow::Borrowed(line);
+        }
+        let mut replaced = String::with_capacity(body.len() + ending.len());
+        replaced.push_str(body);
+        replaced.push_str(ending);
+        Cow::Owned(replaced)
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<LineEnding, String> {
+        match v {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            "preserve" => Ok(LineEnding::Preserve),
+            _ => match v.strip_prefix("mixed:") {
+                Some(pct) => parse_percentage(pct).map(LineEnding::Mixed),
+                None => Err(unknown_value("line ending", v, LineEnding::NAMES)),
+            },
+        }
+    }
+}
+
+pub fn is_line_ending(v: String) -> Result<(), String> {
+    v.parse::<LineEnding>().map(|_| ())
+}
+
+/// whether written lines end with a newline, see --trailing-newline
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TrailingNewline {
+    /// lines without one, like the last line of some samples, get one
+    Always,
+    /// as the sample has it
+    #[default]
+    Preserve,
+    /// the ending of each line is written before the next one, so outputs
+    /// always end with a partial line
+    Never,
+}
+
+impl TrailingNewline {
+    pub const NAMES: &'static [&'static str] = &["always", "preserve", "never"];
+}
+
+impl FromStr for TrailingNewline {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<TrailingNewline, String> {
+        match v {
+            "always" => Ok(TrailingNewline::Always),
+            "preserve" => Ok(TrailingNewline::Preserve),
+            "never" => Ok(TrailingNewline::Never),
+            _ => Err(unknown_value("trailing newline", v, TrailingNewline::NAMES)),
+        }
+    }
+}
+
+/// `line` without its \n or \r\n and the ending
+pub fn split_ending(line: &str) -> (&str, &'static str) {
+    match line.strip_suffix('\n') {
+        Some(body) => match body.strip_suffix('\r') {
+            Some(body) => (body, "\r\n"),
+            None => (body, "\n"),
+        },
+        None => (line, ""),
+    }
+}
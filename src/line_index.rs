@@ -0,0 +1,381 @@
+use crate::parse::unknown_value;
+use crate::source::{read_line, Source};
+use rand::Rng;
+use std::convert::TryInto;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"LGIDX1\0\0";
+/// magic, sample size, mtime seconds and nanoseconds, lines
+const HEADER_LEN: usize = 40;
+/// bytes read at a time while indexing
+const CHUNK: usize = 1 << 20;
+
+/// where the first pass of each sample starts, see --start-line and --start-pct
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartPoint {
+    /// counted from 1
+    Line(u64),
+    /// share of the lines, 0 to 1
+    Share(f64),
+}
+
+impl StartPoint {
+    /// the line to start at, counted from 0, past the end if the sample is shorter
+    fn line(&self, lines: u64) -> u64 {
+        match self {
+            StartPoint::Line(line) => line - 1,
+            StartPoint::Share(share) => (lines as f64 * share) as u64,
+        }
+    }
+}
+
+pub fn parse_start_line(v: &str) -> Result<u64, String> {
+    match v.parse::<u64>() {
+        Ok(line) if line > 0 => Ok(line),
+        _ => Err(format!("{} isn't a line number, they start at 1", v)),
+    }
+}
+
+pub fn is_start_line(v: String) -> Result<(), String> {
+    parse_start_line(&v).map(|_| ())
+}
+
+/// the order sample lines are replayed in, see --replay-order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayOrder {
+    Sequential,
+    /// any line each time, a pass is as many lines as the sample has
+    Random,
+}
+
+impl ReplayOrder {
+    pub const NAMES: &'static [&'static str] = &["sequential", "random"];
+}
+
+impl FromStr for ReplayOrder {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<ReplayOrder, String> {
+        match v {
+            "sequential" => Ok(ReplayOrder::Sequential),
+            "random" => Ok(ReplayOrder::Random),
+            _ => Err(unknown_value("replay order", v, ReplayOrder::NAMES)),
+        }
+    }
+}
+
+/// how samples read through their line index are replayed
+#[derive(Debug, Clone)]
+pub struct IndexOptions {
+    pub start: Option<StartPoint>,
+    pub order: ReplayOrder,
+    /// where indexes are cached
+    pub dir: PathBuf,
+}
+
+/// $XDG_CACHE_HOME/loggen/index or ~/.cache/loggen/index, see --index-dir
+pub fn default_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("loggen")
+        .join("index")
+}
+
+#[derive(Debug)]
+enum Offsets {
+    /// the cached index file, read as lines are looked up
+    Mapped(memmap2::Mmap),
+    /// when it couldn't be cached
+    Memory(Vec<u64>),
+}
+
+/// the offset of the start of every line of a sample, and its size after
+/// the last one
+#[derive(Debug)]
+pub struct LineIndex {
+    offsets: Offsets,
+    lines: u64,
+}
+
+/// size and modification time of a sample, a cached index is rebuilt when
+/// they change
+fn fingerprint(path: &Path) -> io::Result<[u64; 3]> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok([metadata.len(), mtime.as_secs(), mtime.subsec_nanos() as u64])
+}
+
+/// file in `dir` for the index of `path`, named after a hash of its full path
+fn cache_path(dir: &Path, path: &Path) -> PathBuf {
+    let full = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let digest = ring::digest::digest(&ring::digest::SHA256, full.to_string_lossy().as_bytes());
+    let name: String = digest.as_ref()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    dir.join(format!("{}.idx", name))
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// offsets of the lines of `path`, a last line without a newline counts
+fn scan(path: &Path, size: u64) -> io::Result<Vec<u64>> {
+    let mut file = File::open(path)?;
+    let mut offsets = vec![0];
+    let mut chunk = vec![0; CHUNK];
+    let mut at = 0;
+    loop {
+        let len = file.read(&mut chunk)?;
+        if len == 0 {
+            break;
+        }
+        for (pos, b) in chunk[..len].iter().enumerate() {
+            if *b == b'\n' {
+                offsets.push(at + pos as u64 + 1);
+            }
+        }
+        at += len as u64;
+    }
+    if *offsets.last().unwrap() != at {
+        offsets.push(at);
+    }
+    // the sample may have grown while it was read, the index is of what was read
+    if at != size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "changed while it was indexed",
+        ));
+    }
+    Ok(offsets)
+}
+
+impl LineIndex {
+    /// the index of `path` cached in `dir`, built and cached if it's missing
+    /// or the sample changed since
+    pub fn open(path: &Path, dir: &Path) -> io::Result<LineIndex> {
+        let fingerprint = fingerprint(path)?;
+        let cached = cache_path(dir, path);
+        if let Some(index) = LineIndex::load(&cached, &fingerprint) {
+            return Ok(index);
+        }
+
+        let offsets = scan(path, fingerprint[0])?;
+        let lines = offsets.len() as u64 - 1;
+        if let Err(err) = LineIndex::save(&cached, &fingerprint, &offsets) {
+            eprintln!(
+                "Warning: can't cache the line index of {:?} in {:?}: {}",
+                path, dir, err
+            );
+            return Ok(LineIndex {
+                offsets: Offsets::Memory(offsets),
+                lines,
+            });
+        }
+        LineIndex::load(&cached, &fingerprint).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the cached index {:?} can't be read", cached),
+            )
+        })
+    }
+
+    /// None if the file is missing, from another version of the sample or cut short
+    fn load(cached: &Path, fingerprint: &[u64; 3]) -> Option<LineIndex> {
+        let file = File::open(cached).ok()?;
+        // safety: index files are replaced with a rename, never written in place
+        let map = unsafe { memmap2::Mmap::map(&file) }.ok()?;
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC {
+            return None;
+        }
+        if (0..3).any(|i| read_u64(&map, 8 + i * 8) != fingerprint[i]) {
+            return None;
+        }
+        let lines = read_u64(&map, 32);
+        if map.len() as u64 != HEADER_LEN as u64 + (lines + 1) * 8 {
+            return None;
+        }
+        Some(LineIndex {
+            offsets: Offsets::Mapped(map),
+            lines,
+        })
+    }
+
+    fn save(cached: &Path, fingerprint: &[u64; 3], offsets: &[u64]) -> io::Result<()> {
+        fs::create_dir_all(cached.parent().unwrap())?;
+        let tmp = cached.with_extension(format!("tmp{}", std::process::id()));
+        let mut out = io::BufWriter::new(File::create(&tmp)?);
+        out.write_all(MAGIC)?;
+        for v in fingerprint {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        out.write_all(&(offsets.len() as u64 - 1).to_le_bytes())?;
+        for offset in offsets {
+            out.write_all(&offset.to_le_bytes())?;
+        }
+        out.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        // loggen processes indexing the same sample each replace it whole
+        fs::rename(&tmp, cached)
+    }
+
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// where line `n` starts, counted from 0, `lines()` gives the sample size
+    pub fn offset(&self, n: u64) -> u64 {
+        match &self.offsets {
+            Offsets::Mapped(map) => read_u64(map, HEADER_LEN + n as usize * 8),
+            Offsets::Memory(offsets) => offsets[n as usize],
+        }
+    }
+}
+
+/// sample file read through its line index, so the first pass can start at
+/// any line and lines can be picked at random without reading the ones before
+#[derive(Debug)]
+pub struct IndexedSource {
+    path: PathBuf,
+    options: IndexOptions,
+    /// built or loaded on the first read
+    index: Option<LineIndex>,
+    /// reader buffer size, the std default if None
+    capacity: Option<usize>,
+    reader: Option<BufReader<File>>,
+    /// next line to read in order, counted from 0
+    next: u64,
+    /// lines given this pass
+    given: u64,
+    /// number of the last line given, counted from 1
+    current: u64,
+    /// the start point only applies to the first pass
+    started: bool,
+}
+
+impl IndexedSource {
+    pub fn new(path: PathBuf, options: IndexOptions, capacity: Option<usize>) -> IndexedSource {
+        IndexedSource {
+            path,
+            options,
+            index: None,
+            capacity,
+            reader: None,
+            next: 0,
+            given: 0,
+            current: 0,
+            started: false,
+        }
+    }
+
+    fn index(&mut self) -> io::Result<&LineIndex> {
+        if self.index.is_none() {
+            self.index = Some(LineIndex::open(&self.path, &self.options.dir)?);
+        }
+        Ok(self.index.as_ref().unwrap())
+    }
+
+    /// reader at the start of line `next`, reopened there after close
+    fn reader(&mut self) -> io::Result<&mut BufReader<File>> {
+        if self.reader.is_none() {
+            let next = self.next;
+            let offset = self.index()?.offset(next);
+            let file = File::open(&self.path)?;
+            let mut reader = match self.capacity {
+                Some(capacity) => BufReader::with_capacity(capacity, file),
+                None => BufReader::new(file),
+            };
+            reader.seek(SeekFrom::Start(offset))?;
+            self.reader = Some(reader);
+        }
+        Ok(self.reader.as_mut().unwrap())
+    }
+
+    fn random_line(&mut self) -> io::Result<Option<String>> {
+        let given = self.given;
+        let index = self.index()?;
+        if given >= index.lines() {
+            return Ok(None);
+        }
+        let n = rand::thread_rng().gen_range(0..index.lines());
+        let (start, end) = (index.offset(n), index.offset(n + 1));
+        let mut buf = vec![0; (end - start) as usize];
+        if self.reader.is_none() {
+            let file = File::open(&self.path)?;
+            self.reader = Some(BufReader::new(file));
+        }
+        self.reader
+            .as_ref()
+            .unwrap()
+            .get_ref()
+            .read_exact_at(&mut buf, start)?;
+        self.current = n + 1;
+        String::from_utf8(buf).map(Some).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            )
+        })
+    }
+}
+
+impl Source for IndexedSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if !self.started {
+            self.started = true;
+            let lines = self.index()?.lines();
+            if let Some(start) = self.options.start {
+                self.next = start.line(lines).min(lines);
+                self.reader = None;
+            }
+        }
+
+        let line = match self.options.order {
+            ReplayOrder::Random => self.random_line()?,
+            ReplayOrder::Sequential => {
+                let line = read_line(self.reader()?)?;
+                if line.is_some() {
+                    self.next += 1;
+                    self.current = self.next;
+                }
+                line
+            }
+        };
+        if line.is_some() {
+            self.given += 1;
+        }
+        Ok(line)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.next = 0;
+        self.given = 0;
+        self.current = 0;
+        match &mut self.reader {
+            Some(reader) => reader.seek(SeekFrom::Start(0)).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn close(&mut self) {
+        self.reader = None;
+    }
+
+    fn line_number(&self) -> Option<u64> {
+        Some(self.current)
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Component, Path, PathBuf};
+
+/// name of a symlink to the file an output writes to now, see --link-latest,
+/// can have {stem}, {ext} and {name} of the output file
+#[derive(Debug, Clone)]
+pub struct LinkLatest {
+    template: String,
+}
+
+impl LinkLatest {
+    pub fn parse(v: &str) -> Result<LinkLatest, String> {
+        if v.is_empty() || v.contains('/') {
+            return Err(format!("{} should be a file name like current.log", v));
+        }
+        let mut rest = v;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in {}", v))?;
+            match &rest[start..start + end + 1] {
+                "{stem}" | "{ext}" | "{name}" => {}
+                placeholder => {
+                    return Err(format!(
+                        "unknown placeholder {}, use {{stem}}, {{ext}} or {{name}}",
+                        placeholder
+                    ))
+                }
+            }
+            rest = &rest[start + end + 1..];
+        }
+        Ok(LinkLatest {
+            template: v.to_string(),
+        })
+    }
+
+    /// where the link of the output of `rel_path` goes: in its directory in
+    /// `out_base`, outside date partitions
+    pub fn path(&self, out_base: &Path, rel_path: &Path) -> PathBuf {
+        let name = rel_path.file_name().unwrap_or_default().to_string_lossy();
+        let stem = rel_path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = rel_path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        let link_name = self
+            .template
+            .replace("{stem}", &stem)
+            .replace("{ext}", &ext)
+            .replace("{name}", &name);
+        out_base.join(rel_path).with_file_name(link_name)
+    }
+}
+
+pub fn is_link_latest(v: String) -> Result<(), String> {
+    LinkLatest::parse(&v).map(|_| ())
+}
+
+/// `target` relative to the directory `dir`, both relative to the same place
+fn relative(target: &Path, dir: &Path) -> PathBuf {
+    let target: Vec<Component> = target.components().collect();
+    let dir: Vec<Component> = dir.components().collect();
+    let common = target.iter().zip(&dir).take_while(|(a, b)| a == b).count();
+    let mut path = PathBuf::new();
+    for _ in common..dir.len() {
+        path.push("..");
+    }
+    for part in &target[common..] {
+        path.push(part);
+    }
+    path
+}
+
+/// make `link` point to `target`, replacing the old link at once so readers
+/// following it never find it missing
+pub fn point(link: &Path, target: &Path) -> io::Result<()> {
+    let dir = link.parent().unwrap_or_else(|| Path::new(""));
+    fs::create_dir_all(dir)?;
+    let tmp = link.with_file_name(format!(
+        ".{}.{}.tmp",
+        link.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id()
+    ));
+    match fs::remove_file(&tmp) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    symlink(relative(target, dir), &tmp)?;
+    fs::rename(&tmp, link)
+}
@@ -0,0 +1,66 @@
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone)]
+pub struct LogrotateOptions {
+    /// rotate when a file is bigger than this many bytes
+    pub size: u64,
+    /// rotated files to keep
+    pub rotate: u64,
+    pub compress: bool,
+}
+
+/// files loggen wrote to, skipping manifests and copies made by rotation
+/// (default --rotate-name and logrotate's numbered and compressed copies)
+fn is_output_file(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    if name.ends_with(".gz") {
+        return false;
+    }
+
+    match path.extension().and_then(|v| v.to_str()) {
+        Some("manifest") | Some("tmp") | Some("rotated") => false,
+        Some(extension) => !extension.chars().all(|c| c.is_ascii_digit()),
+        None => true,
+    }
+}
+
+/// output files under `out_dir` as absolute paths, sorted
+pub fn output_files(out_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let out_dir = out_dir.canonicalize()?;
+    let mut files: Vec<PathBuf> = WalkDir::new(&out_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .filter(|path| path.is_file() && is_output_file(path))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// logrotate config for `files`, loggen keeps its files open so they are
+/// copied and truncated instead of moved
+pub fn render(files: &[PathBuf], options: &LogrotateOptions) -> String {
+    let mut out = String::new();
+    writeln!(out, "# generated by loggen emit-logrotate, run with:").unwrap();
+    writeln!(out, "# logrotate --state logrotate.state this-file").unwrap();
+    for file in files {
+        // logrotate splits paths on whitespace unless they are quoted
+        writeln!(out, "\"{}\"", file.display()).unwrap();
+    }
+    writeln!(out, "{{").unwrap();
+    writeln!(out, "    size {}", options.size).unwrap();
+    writeln!(out, "    rotate {}", options.rotate).unwrap();
+    writeln!(out, "    copytruncate").unwrap();
+    writeln!(out, "    missingok").unwrap();
+    writeln!(out, "    notifempty").unwrap();
+    if options.compress {
+        writeln!(out, "    compress").unwrap();
+        // the copy made while loggen writes is compressed on the next run
+        writeln!(out, "    delaycompress").unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
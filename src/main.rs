@@ -1,12 +1,154 @@
-use clap::{App, Arg};
-use libc;
-use num_cpus;
+use chrono::Utc;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, BufRead, BufReader, LineWriter, Seek, SeekFrom, Write};
+use std::io::{self, Write};
+use std::os::unix::fs::FileTypeExt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, sleep, JoinHandle};
-use std::time::Duration;
-use walkdir::WalkDir;
+use std::time::{Duration, Instant};
+
+mod ansi;
+mod api;
+mod assertion;
+mod assign;
+mod backpressure;
+mod batch;
+mod bench;
+mod budget;
+mod cardinality;
+mod config;
+mod control;
+mod correlate;
+mod dataset;
+mod duplicate;
+mod enrich;
+mod fd_budget;
+mod group;
+mod heartbeat;
+mod hold;
+mod inspect;
+mod journal;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod limits;
+mod line_ending;
+mod line_index;
+mod link;
+mod logrotate;
+mod manifest;
+mod marker;
+mod merge;
+mod normalize;
+mod out_writer;
+mod pacer;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod parse;
+mod partition;
+mod perms;
+mod postmortem;
+mod profile;
+mod rebalance;
+mod record;
+mod regex;
+mod reload;
+mod reorder;
+mod resume;
+mod rewrite;
+mod rotate;
+mod route;
+mod scan;
+mod scenario;
+mod script;
+mod selftest;
+mod service;
+mod shell;
+mod silence;
+mod sink;
+mod slice;
+mod slow_write;
+mod snapshot;
+mod source;
+mod split;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod start;
+mod stats;
+mod statsd;
+mod status;
+mod steal;
+mod synth;
+mod tag;
+mod template;
+mod throttle;
+mod timestamp;
+mod touch;
+mod usage;
+mod validate;
+mod values;
+mod window;
+mod zero_copy;
+
+use ansi::InjectAnsi;
+use api::Api;
+use assertion::Assertion;
+use assign::{Assign, Assigner};
+use backpressure::{FullQueue, WhenFull};
+use batch::{Batch, WriteBatch};
+use budget::ErrorBudget;
+use cardinality::Cardinality;
+use correlate::Correlation;
+use duplicate::Duplicator;
+use enrich::{Enrich, Enricher};
+use group::RateGroup;
+use heartbeat::{Heartbeat, HeartbeatConfig};
+use hold::{Held, HoldConfig};
+use journal::{JournalExport, JournalOptions};
+use limits::Limits;
+use line_ending::{LineEnding, TrailingNewline};
+use line_index::{IndexOptions, IndexedSource, ReplayOrder, StartPoint};
+use link::LinkLatest;
+use manifest::ManifestTracker;
+use marker::Marker;
+use merge::MergeMode;
+use out_writer::{FlushPolicy, OutWriter, WriteBuffering};
+use pacer::Pacer;
+use partition::{OutFilePolicy, OutPartition};
+use perms::OutPerms;
+use postmortem::StateDumps;
+use profile::ProfiledFile;
+use rebalance::Rebalance;
+use record::{Framing, RecordSource};
+use reload::{Latest, Reloadable, Watched};
+use reorder::{ReorderConfig, Reorderer};
+use resume::{OutputState, ResumeState};
+use rewrite::Rewriter;
+use rotate::{RotateMethod, RotateName};
+use route::{Route, Router};
+use scan::Found;
+use script::{Script, ScriptState};
+use shell::Shell;
+use silence::Silence;
+use sink::{Balance, FailoverOptions, PoolOptions, Reconnect, Sink, SinkOptions, SinkOutput};
+use slice::{Slice, SliceSource};
+use slow_write::{SlowWrite, Staller};
+use source::{FileSource, Input, MmapSource, PipeSource, Source};
+use split::SplitConfig;
+use start::{StartAt, SyncMode};
+use stats::{FileStats, Stats};
+use steal::WorkQueue;
+use tag::{FileTag, LineTag, TagPosition};
+use template::{Counters, TemplateContext, TemplateState};
+use throttle::{Throttle, Throttled, Throttler};
+use timestamp::{TimestampFuzz, Timestamps};
+use touch::{TouchPolicy, Toucher};
+use usage::Usage;
+use window::{Verdict, Window, WindowState};
 
 #[derive(Debug, Clone)]
 pub enum WrapStrategy {
@@ -16,12 +158,22 @@ pub enum WrapStrategy {
 }
 
 impl WrapStrategy {
-    pub fn from_str(v: &str, default: WrapStrategy) -> WrapStrategy {
+    pub const NAMES: &'static [&'static str] = &["truncate", "append", "rotate"];
+}
+
+impl FromStr for WrapStrategy {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<WrapStrategy, String> {
         match v {
-            "truncate" => WrapStrategy::Truncate,
-            "append" => WrapStrategy::Append,
-            "rotate" => WrapStrategy::Rotate,
-            _ => default,
+            "truncate" => Ok(WrapStrategy::Truncate),
+            "append" => Ok(WrapStrategy::Append),
+            "rotate" => Ok(WrapStrategy::Rotate),
+            _ => Err(parse::unknown_value(
+                "wrap strategy",
+                v,
+                WrapStrategy::NAMES,
+            )),
         }
     }
 }
@@ -34,73 +186,878 @@ pub fn is_positive_number(v: String) -> Result<(), String> {
     Err(format!("{} isn't a positive number", &*v))
 }
 
+pub fn is_non_zero_number(v: String) -> Result<(), String> {
+    match v.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("{} isn't a number greater than 0", &*v)),
+    }
+}
+
+/// state shared by the workers and everything that observes or controls them
+#[derive(Debug, Clone)]
+pub struct Shared {
+    pub stats: Arc<Stats>,
+    pub stop: Arc<AtomicBool>,
+    pub pacer: Arc<Pacer>,
+    pub rebalance: Arc<Rebalance>,
+    /// SIGUSR1 signals received, every output is rotated when it changes
+    pub rotate_requests: Arc<AtomicU64>,
+    /// rotations of single files asked for in `loggen shell`, workers look for
+    /// them when it changes
+    pub file_rotations: Arc<AtomicU64>,
+    /// a sample found by the startup scan couldn't be read
+    pub scan_failed: Arc<AtomicBool>,
+    /// errors went over --error-budget
+    pub budget_exceeded: Arc<AtomicBool>,
+    /// cpu time and context switches of the workers, see --resource-report
+    pub usage: Arc<Usage>,
+}
+
+impl Shared {
+    fn new() -> Shared {
+        Shared {
+            stats: Arc::new(Stats::new()),
+            stop: Arc::new(AtomicBool::new(false)),
+            pacer: Arc::new(Pacer::new(None)),
+            rebalance: Arc::new(Rebalance::default()),
+            rotate_requests: Arc::new(AtomicU64::new(0)),
+            file_rotations: Arc::new(AtomicU64::new(0)),
+            scan_failed: Arc::new(AtomicBool::new(false)),
+            budget_exceeded: Arc::new(AtomicBool::new(false)),
+            usage: Arc::new(Usage::default()),
+        }
+    }
+}
+
+/// options that control how files are distributed and paced
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub interval: Duration,
+    pub parallelism: usize,
+    pub max_open_files: usize,
+    pub assign: Assign,
+    /// threads walking the input directory
+    pub scan_threads: usize,
+    /// idle workers take late files from busy ones
+    pub work_stealing: bool,
+    /// read directory samples from memory maps
+    pub mmap: bool,
+    /// buffer size of sample readers, the std default if None
+    pub read_buffer: Option<usize>,
+    /// options of the --format generators
+    pub synth: synth::SynthOptions,
+    /// wait until this time after setting up to start writing
+    pub start_at: Option<StartAt>,
+    /// lower the rate while memory or cpu use are over these
+    pub limits: Limits,
+    /// windows without output, counted from when writing starts
+    pub silence: Option<Silence>,
+    /// the worker count can be changed while running, see --control-socket
+    pub rebalance: bool,
+}
+
+/// options that apply to every generated file
+#[derive(Debug, Clone)]
+pub struct GenOptions {
+    pub wrap_strategy: WrapStrategy,
+    pub out_partition: OutPartition,
+    pub out_file_policy: OutFilePolicy,
+    pub duplicate_rate: f64,
+    pub duplicate_delay: Duration,
+    pub reorder: Option<ReorderConfig>,
+    pub rotate_name: RotateName,
+    pub rotate_method: RotateMethod,
+    /// symlink to the file each output writes to now
+    pub link_latest: Option<LinkLatest>,
+    /// keep writing to rotated files for a while
+    pub rotate_hold: Option<HoldConfig>,
+    /// rotate outputs after this many lines
+    pub rotate_lines: Option<u64>,
+    /// render sample lines as templates, the context is shared by all files
+    pub template: Option<TemplateContext>,
+    /// zone and locale of generated timestamps, for heartbeats without --template
+    pub timestamps: Timestamps,
+    pub write_batch: Option<WriteBatch>,
+    /// delay some writes to emulate a slow disk
+    pub slow_write: Option<SlowWrite>,
+    /// keep a <file>.manifest next to each output file
+    pub manifest: bool,
+    pub touch_policy: TouchPolicy,
+    pub out_perms: OutPerms,
+    pub split_long_lines: Option<SplitConfig>,
+    /// add generator metadata to every line
+    pub enrich: Option<Enrich>,
+    /// groups of files that share a rate
+    pub rate_groups: Vec<RateGroup>,
+    /// groups of files whose lines share trace ids
+    pub correlations: Vec<Correlation>,
+    /// wrap some lines in color codes and add control characters to them
+    pub inject_ansi: Option<InjectAnsi>,
+    /// remove ANSI escape sequences from sample lines
+    pub strip_ansi: bool,
+    /// send lines to other outputs by content, in order
+    pub routes: Arc<Vec<Route>>,
+    /// drop or delay a share of the lines by content, in order
+    pub throttles: Arc<Vec<Throttle>>,
+    pub line_ending: LineEnding,
+    pub trailing_newline: TrailingNewline,
+    /// write line endings this long after the rest of the line
+    pub partial_line_flush: Option<Duration>,
+    /// only replay sample lines with timestamps in this window
+    pub window: Option<Window>,
+    /// only replay these lines of each sample file
+    pub slice: Option<Slice>,
+    /// read samples through their line index, for --start-line, --start-pct
+    /// and --replay-order random
+    pub index: Option<IndexOptions>,
+    /// read and write samples as framed binary records instead of lines
+    pub records: Option<Framing>,
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// count write syscalls, flushes and time blocked per output
+    pub profile_io: bool,
+    /// transform lines with a user script before writing them
+    pub script: Option<Arc<Reloadable<Script>>>,
+    /// rewrite the content of lines with regex rules before the script
+    pub rewrite: Option<Arc<Reloadable<Rewriter>>>,
+    /// inject a marker line into every output this often
+    pub marker_interval: Option<Duration>,
+    /// send lines here instead of writing files in the output directory
+    pub sink: Option<Arc<dyn Sink>>,
+    /// what writes to full pipes and sockets do
+    pub block_on_full: Option<WhenFull>,
+    /// continue the outputs of previous runs
+    pub resume: Option<Arc<ResumeState>>,
+    /// add where each line was read from to it
+    pub tag_lines: Option<LineTag>,
+    /// copy whole passes of samples file to file instead of line by line, when
+    /// lines are written as they are read without pacing
+    pub zero_copy: bool,
+    /// warn when a paced sample wraps after less than this
+    pub fast_wrap: Option<Duration>,
+    pub write_buffering: WriteBuffering,
+    /// write journal export entries instead of lines, see --out-format
+    pub journal: Option<JournalOptions>,
+}
+
+impl GenOptions {
+    /// lines are written as they are read, nothing transforms, adds, drops,
+    /// delays or counts them
+    fn writes_as_read(&self) -> bool {
+        self.duplicate_rate == 0.0
+            && self.reorder.is_none()
+            && self.rotate_hold.is_none()
+            && self.rotate_lines.is_none()
+            && self.template.is_none()
+            && self.write_batch.is_none()
+            && self.slow_write.is_none()
+            && !self.manifest
+            && matches!(self.touch_policy, TouchPolicy::Natural)
+            && self.split_long_lines.is_none()
+            && self.enrich.is_none()
+            && self.rate_groups.is_empty()
+            && self.correlations.is_empty()
+            && self.inject_ansi.is_none()
+            && !self.strip_ansi
+            && self.routes.is_empty()
+            && self.throttles.is_empty()
+            && self.line_ending == LineEnding::Preserve
+            && self.trailing_newline == TrailingNewline::Preserve
+            && self.partial_line_flush.is_none()
+            && self.window.is_none()
+            && self.slice.is_none()
+            && self.index.is_none()
+            && self.records.is_none()
+            && self.heartbeat.is_none()
+            && !self.profile_io
+            && self.script.is_none()
+            && self.rewrite.is_none()
+            && self.marker_interval.is_none()
+            && self.sink.is_none()
+            && self.tag_lines.is_none()
+            && self.journal.is_none()
+    }
+}
+
+/// what was read from a sample
+#[derive(Debug)]
+enum Sample {
+    Line(String),
+    /// a binary record with its framing
+    Record(Vec<u8>),
+}
+
 #[derive(Debug)]
 struct GenInput {
     path_in: PathBuf,
+    out_base: PathBuf,
+    rel_path: PathBuf,
+    partition: OutPartition,
+    // number of the current output file with --out-file-policy new-per-wrap
+    file_number: Option<u64>,
     path_out: PathBuf,
-    reader: BufReader<File>,
-    writer: LineWriter<File>,
+    // source and writer are opened lazily and may be closed to stay within the fd budget
+    source: Box<dyn Source>,
+    writer: Option<OutWriter>,
+    duplicator: Duplicator,
+    reorderer: Option<Reorderer>,
+    stats: Arc<FileStats>,
+    rotate_name: RotateName,
+    rotate_method: RotateMethod,
+    rotations: u64,
+    /// the --link-latest symlink and the file it points to, if it was updated
+    latest: Option<(PathBuf, Option<PathBuf>)>,
+    rotate_hold: Option<HoldConfig>,
+    rotate_lines: Option<u64>,
+    // lines in path_out since it was created or rotated, counted on first use
+    lines_to_rotate: Option<u64>,
+    // the previous file while rotate_hold is active
+    held: Option<Held>,
+    template: Option<TemplateState>,
+    batch: Option<Batch>,
+    staller: Option<Staller>,
+    sink: Option<Box<dyn SinkOutput>>,
+    write_manifest: bool,
+    // created when path_out is first written, recreated when the partition changes
+    manifest: Option<ManifestTracker>,
+    toucher: Toucher,
+    marker: Option<Marker>,
+    out_perms: OutPerms,
+    split_long_lines: Option<SplitConfig>,
+    enricher: Option<Enricher>,
+    correlation: Option<Correlation>,
+    inject_ansi: Option<InjectAnsi>,
+    strip_ansi: bool,
+    // the rate of the group the file belongs to, if any
+    group_pacer: Option<Arc<Pacer>>,
+    router: Option<Router>,
+    throttler: Option<Throttler>,
+    line_ending: LineEnding,
+    trailing_newline: TrailingNewline,
+    partial_line_flush: Option<Duration>,
+    /// ending of the last line written, held back by --trailing-newline never
+    /// until the next line or by --partial-line-flush until it's due
+    pending_ending: Option<(&'static str, Option<Instant>)>,
+    window: Option<WindowState>,
+    framing: Option<Framing>,
+    heartbeat: Option<Heartbeat>,
+    script: Option<ScriptState>,
+    rewriter: Option<Latest<Rewriter>>,
+    profile_io: bool,
+    block_on_full: Option<WhenFull>,
+    /// copy whole passes, only for samples that are regular files
+    zero_copy: bool,
+    /// size of the sample when it was last measured for zero_copy
+    pass: Option<zero_copy::Pass>,
+    fast_wrap: Option<Duration>,
+    warned_fast_wrap: bool,
+    write_buffering: WriteBuffering,
+    /// first line of the output left by the last run, the sample's first line
+    /// isn't written again if it's the same, see --resume-output
+    resumed_header: Option<String>,
+    tag: Option<FileTag>,
+    /// lines read from the sample this pass
+    sample_line: u64,
+    /// the source's header goes before the next line, set for every new
+    /// output file, see Source::header
+    header_due: bool,
+    journal: Option<JournalExport>,
+}
+
+/// where to write `rel_path` now
+fn out_path(
+    partition: &OutPartition,
+    out_base: &Path,
+    rel_path: &Path,
+    file_number: Option<u64>,
+) -> PathBuf {
+    let path = partition.path_for(out_base, rel_path, Utc::now());
+    match file_number {
+        Some(n) => partition::numbered_path(&path, n),
+        None => path,
+    }
+}
+
+fn open_append(path: &Path, out_perms: &OutPerms) -> io::Result<File> {
+    if let Some(dir_to_create) = path.parent() {
+        fs::create_dir_all(dir_to_create)?;
+    }
+
+    let created = !path.exists();
+    let write_file = OpenOptions::new().create(true).append(true).open(path)?;
+    if created {
+        out_perms.apply(path)?;
+    }
+
+    Ok(write_file)
 }
 
 impl GenInput {
-    fn new(path_in: PathBuf, path_out: PathBuf) -> io::Result<GenInput> {
-        let read_file = File::open(&path_in)?;
-        let write_file = OpenOptions::new()
+    fn new(
+        source: Box<dyn Source>,
+        path_in: PathBuf,
+        size: u64,
+        out_base: PathBuf,
+        rel_path: PathBuf,
+        options: &GenOptions,
+        stats: &Stats,
+    ) -> GenInput {
+        let partition = options.out_partition.clone();
+        let stats = stats.register(FileStats::new(path_in.clone(), rel_path.clone(), size));
+        let file_number = match options.out_file_policy {
+            OutFilePolicy::Reuse => None,
+            OutFilePolicy::NewPerWrap => Some(1),
+        };
+        let path_out = out_path(&partition, &out_base, &rel_path, file_number);
+        let sink = options.sink.as_ref().map(|sink| {
+            let mut output = sink.output(&rel_path);
+            output.count_full(stats.full.clone());
+            output
+        });
+        let enricher = options
+            .enrich
+            .as_ref()
+            .map(|enrich| Enricher::new(enrich, &path_in));
+        let router = if options.routes.is_empty() {
+            None
+        } else {
+            Some(Router::new(
+                options.routes.clone(),
+                &out_base,
+                &rel_path,
+                options.sink.as_ref(),
+                &options.out_perms,
+            ))
+        };
+        let tag = options
+            .tag_lines
+            .as_ref()
+            .map(|tag| tag.for_file(&path_in, &rel_path));
+        let latest = options
+            .link_latest
+            .as_ref()
+            .map(|link| link.path(&out_base, &rel_path))
+            .filter(|link| *link != path_out)
+            .map(|link| (link, None));
+        let mut gen_input = GenInput {
+            source,
+            writer: None,
+            path_in,
+            out_base,
+            partition,
+            file_number,
+            path_out,
+            duplicator: Duplicator::new(options.duplicate_rate, options.duplicate_delay),
+            reorderer: options.reorder.clone().map(Reorderer::new),
+            stats,
+            rotate_name: options.rotate_name.clone(),
+            rotate_method: options.rotate_method,
+            rotations: 0,
+            latest,
+            rotate_hold: options.rotate_hold.clone(),
+            rotate_lines: options.rotate_lines,
+            lines_to_rotate: None,
+            held: None,
+            template: options.template.clone().map(TemplateState::new),
+            batch: options.write_batch.clone().map(Batch::new),
+            staller: options.slow_write.clone().map(Staller::new),
+            sink,
+            write_manifest: options.manifest,
+            manifest: None,
+            toucher: Toucher::new(options.touch_policy.clone()),
+            out_perms: options.out_perms.clone(),
+            split_long_lines: options.split_long_lines.clone(),
+            enricher,
+            correlation: correlate::correlation_for(&options.correlations, &rel_path),
+            inject_ansi: options.inject_ansi.clone(),
+            strip_ansi: options.strip_ansi,
+            group_pacer: group::pacer_for(&options.rate_groups, &rel_path),
+            router,
+            throttler: if options.throttles.is_empty() {
+                None
+            } else {
+                Some(Throttler::new(options.throttles.clone()))
+            },
+            line_ending: options.line_ending,
+            trailing_newline: options.trailing_newline,
+            partial_line_flush: options.partial_line_flush,
+            pending_ending: None,
+            window: options.window.clone().map(WindowState::new),
+            framing: options.records,
+            heartbeat: options.heartbeat.clone().map(|config| {
+                let context = options.template.clone().unwrap_or_else(|| TemplateContext {
+                    timestamps: options.timestamps.clone(),
+                    ..TemplateContext::default()
+                });
+                Heartbeat::new(config, context)
+            }),
+            script: options
+                .script
+                .clone()
+                .map(|script| ScriptState::new(script, &rel_path)),
+            rewriter: options.rewrite.clone().map(Latest::new),
+            profile_io: options.profile_io,
+            block_on_full: options.block_on_full,
+            zero_copy: false,
+            pass: None,
+            fast_wrap: options.fast_wrap,
+            warned_fast_wrap: false,
+            write_buffering: options.write_buffering,
+            marker: options
+                .marker_interval
+                .map(|interval| Marker::new(interval, &rel_path)),
+            resumed_header: None,
+            tag,
+            sample_line: 0,
+            header_due: true,
+            journal: options
+                .journal
+                .as_ref()
+                .map(|journal| JournalExport::new(journal, &rel_path)),
+            rel_path,
+        };
+        if let Some(resume) = &options.resume {
+            gen_input.resume(resume);
+        }
+
+        gen_input
+    }
+
+    /// continue numbering rotations, files and script lines where the last run
+    /// left the output
+    fn resume(&mut self, resume: &ResumeState) {
+        let state = resume.output(&self.rel_path).unwrap_or_default();
+        let bytes = fs::metadata(&self.path_out).map_or(0, |metadata| metadata.len());
+        if state.path == self.path_out.display().to_string() && state.bytes != bytes {
+            eprintln!(
+                "Warning: {:?} has {} bytes but the last run left {}, it was changed since",
+                self.path_out, bytes, state.bytes
+            );
+        }
+
+        self.rotations = state.rotations;
+        if let (Some(n), Some(_)) = (state.file_number, self.file_number) {
+            // the last file is left as it was, like after a wrap
+            self.file_number = Some(n + 1);
+            self.path_out = out_path(
+                &self.partition,
+                &self.out_base,
+                &self.rel_path,
+                self.file_number,
+            );
+        }
+        if let Some(script) = &mut self.script {
+            script.continue_from(state.script_seq);
+        }
+        if self.framing.is_none() {
+            self.resumed_header = resume::first_line(&self.path_out);
+        }
+    }
+
+    /// where this run left the output, for the next --resume-output
+    fn resume_state(&self) -> OutputState {
+        OutputState {
+            path: self.path_out.display().to_string(),
+            bytes: fs::metadata(&self.path_out).map_or(0, |metadata| metadata.len()),
+            rotations: self.rotations,
+            file_number: self.file_number,
+            script_seq: self.script.as_ref().map_or(0, |script| script.seq()),
+        }
+    }
+
+    fn line_writer(&self, file: File) -> OutWriter {
+        let stats = if self.profile_io {
+            Some(self.stats.clone())
+        } else {
+            None
+        };
+        let is_pipe = file
+            .metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_fifo());
+        let mut file = ProfiledFile::new(file, stats);
+        if let Some(when_full) = self.block_on_full.filter(|_| is_pipe) {
+            match backpressure::set_nonblocking(file.file()) {
+                Ok(()) => {
+                    file = file.with_full_queue(FullQueue::new(when_full, self.stats.full.clone()))
+                }
+                Err(err) => self.stats.error(format!(
+                    "Error making {:?} non blocking: {:?}",
+                    self.path_out, err
+                )),
+            }
+        }
+        OutWriter::new(file, &self.write_buffering)
+    }
+
+    fn writer(&mut self) -> io::Result<&mut OutWriter> {
+        if self.writer.is_none() {
+            self.toucher.opening(&self.path_out);
+            let file = open_append(&self.path_out, &self.out_perms)?;
+            self.writer = Some(self.line_writer(file));
+            self.link_latest()?;
+        }
+
+        Ok(self.writer.as_mut().unwrap())
+    }
+
+    /// point the --link-latest symlink to the current output file once it exists
+    fn link_latest(&mut self) -> io::Result<()> {
+        if let Some((link, target)) = &mut self.latest {
+            if target.as_ref() != Some(&self.path_out) {
+                link::point(link, &self.path_out)?;
+                *target = Some(self.path_out.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn manifest(&mut self) -> io::Result<Option<&mut ManifestTracker>> {
+        if self.write_manifest && self.manifest.is_none() {
+            self.manifest = Some(ManifestTracker::open(&self.path_out)?);
+        }
+
+        Ok(self.manifest.as_mut())
+    }
+
+    fn save_manifest(&mut self) -> io::Result<()> {
+        match &mut self.manifest {
+            Some(manifest) => manifest.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// write to the output file, all writes to it go through here to keep the manifest right
+    fn write_file(&mut self, data: &str) -> io::Result<()> {
+        // the manifest counts what's already in the file so it's opened first
+        self.manifest()?;
+        self.write_bytes(data.as_bytes())?;
+        if let Some(manifest) = &mut self.manifest {
+            manifest.written(data)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+        match self
+            .staller
+            .as_mut()
+            .and_then(|staller| staller.next_pause())
+        {
+            Some(pause) => {
+                // stall in the middle so readers see the first half alone
+                let (first, rest) = data.split_at(data.len() / 2);
+                let writer = self.writer()?;
+                writer.write_all(first)?;
+                writer.flush()?;
+                sleep(pause);
+                writer.write_all(rest)?;
+            }
+            None => self.writer()?.write_all(data)?,
+        }
+        if let (false, Some(writer)) = (self.toucher.is_natural(), &mut self.writer) {
+            // times are set on the file so buffered data must be written first
+            writer.flush()?;
+            self.toucher.touch(writer.get_ref().file())?;
+        }
+
+        Ok(())
+    }
+
+    /// next record of a binary sample with its framing, ready to write
+    fn read_record(&mut self, framing: Framing) -> io::Result<Option<Vec<u8>>> {
+        let framed = self
+            .source
+            .read_record()?
+            .map(|record| framing.frame(&record));
+        if let Some(framed) = &framed {
+            self.stats.read(framed.len() as u64);
+        }
+
+        Ok(framed)
+    }
+
+    /// copy a whole pass of the sample to the output at once and wrap, see
+    /// GenOptions::zero_copy, false if the output isn't a regular file and
+    /// lines have to be written one by one instead
+    fn copy_pass(&mut self, wrap_strategy: &WrapStrategy) -> io::Result<bool> {
+        self.check_partition()?;
+        let size = fs::metadata(&self.path_in)?.len();
+        let pass = match self.pass {
+            Some(pass) if pass.bytes == size => pass,
+            _ => zero_copy::measure(&self.path_in)?,
+        };
+        self.pass = Some(pass);
+
+        if let Some(dir) = self.path_out.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // pipes and devices can't be written at an offset, they are checked
+        // before opening them so readers don't see the writer close
+        if let Ok(metadata) = fs::metadata(&self.path_out) {
+            if !metadata.file_type().is_file() {
+                self.zero_copy = false;
+                return Ok(false);
+            }
+        }
+        let created = !self.path_out.exists();
+        // copy_file_range can't write to files opened for appending
+        let out = OpenOptions::new()
             .write(true)
             .create(true)
-            .append(true)
-            .open(&path_out)?;
-
-        let reader = BufReader::new(read_file);
-        let writer = LineWriter::new(write_file);
-        Ok(GenInput {
-            reader,
-            writer,
-            path_in: path_in,
-            path_out: path_out,
-        })
+            .truncate(false)
+            .open(&self.path_out)?;
+        if created {
+            self.out_perms.apply(&self.path_out)?;
+        }
+        self.link_latest()?;
+        match zero_copy::append(&File::open(&self.path_in)?, &out, pass.bytes) {
+            Err(err) if zero_copy::is_unsupported(&err) => {
+                self.zero_copy = false;
+                return Ok(false);
+            }
+            result => result?,
+        }
+        self.stats.read(pass.bytes);
+        self.stats.written_pass(pass.lines, pass.bytes);
+
+        self.wrap(wrap_strategy).map(|_| true)
+    }
+
+    /// write a framed record, it's flushed so readers never see part of one
+    fn write_record(&mut self, framed: &[u8]) -> io::Result<()> {
+        self.check_partition()?;
+        self.write_bytes(framed)?;
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+        }
+        self.stats.written(framed.len() as u64);
+        Ok(())
+    }
+
+    /// close source and writer, they are reopened on next use
+    fn close(&mut self) -> io::Result<()> {
+        if self.ending_due().is_some() {
+            self.finish_ending()?;
+        }
+        self.flush_batch()?;
+        self.release_held()?;
+        if let Some(router) = &mut self.router {
+            router.close()?;
+        }
+        self.source.close();
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+
+        self.save_manifest()
+    }
+
+    /// switch to a new partition directory if we crossed a boundary since the last write
+    fn check_partition(&mut self) -> io::Result<()> {
+        let path_out = out_path(
+            &self.partition,
+            &self.out_base,
+            &self.rel_path,
+            self.file_number,
+        );
+
+        if path_out != self.path_out {
+            self.switch_to(path_out)?;
+        }
+
+        Ok(())
+    }
+
+    /// close the current output file and write to `path_out` from now on
+    fn switch_to(&mut self, path_out: PathBuf) -> io::Result<()> {
+        self.finish_ending()?;
+        self.flush_batch()?;
+        self.save_manifest()?;
+        self.manifest = None;
+        self.lines_to_rotate = None;
+        self.header_due = true;
+        self.toucher.reset();
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        self.path_out = path_out;
+        Ok(())
+    }
+
+    /// close the output file and start the next numbered one
+    fn next_file(&mut self, n: u64) -> io::Result<()> {
+        self.file_number = Some(n + 1);
+        let path_out = out_path(
+            &self.partition,
+            &self.out_base,
+            &self.rel_path,
+            self.file_number,
+        );
+        self.switch_to(path_out)
     }
 
     fn truncate(&mut self) -> io::Result<()> {
+        self.pending_ending = None;
+        self.flush_batch()?;
+        // buffered lines of the pass go in before the file is truncated, not after
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        if let Some(manifest) = self.manifest()? {
+            manifest.truncated()?;
+        }
+        self.toucher.opening(&self.path_out);
+        if let Some(dir_to_create) = self.path_out.parent() {
+            fs::create_dir_all(dir_to_create)?;
+        }
+        let created = !self.path_out.exists();
         let write_file = OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
             .open(&self.path_out)?;
+        if created {
+            self.out_perms.apply(&self.path_out)?;
+        }
 
-        self.writer = LineWriter::new(write_file);
+        self.writer = Some(self.line_writer(write_file));
+        self.link_latest()?;
+        self.lines_to_rotate = Some(0);
+        self.header_due = true;
 
         Ok(())
     }
 
     fn rotate(&mut self) -> io::Result<()> {
-        std::fs::rename(&self.path_out, self.path_out.with_extension("rotated"))?;
-        let write_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .append(true)
-            .open(&self.path_out)?;
+        self.finish_ending()?;
+        self.flush_batch()?;
+        self.rotations += 1;
+        let rotated = self
+            .rotate_name
+            .render(&self.path_out, self.rotations, Utc::now());
+        match self.rotate_method {
+            RotateMethod::Rename => std::fs::rename(&self.path_out, &rotated)?,
+            RotateMethod::Link => {
+                rotate::link_and_replace(&self.path_out, &rotated, &self.out_perms)?
+            }
+            RotateMethod::CopyTruncate => {
+                if let Some(writer) = &mut self.writer {
+                    writer.flush()?;
+                }
+                rotate::copy_and_truncate(&self.path_out, &rotated)?;
+            }
+        }
+        if let Some(manifest) = self.manifest()? {
+            manifest.rotated(&rotated)?;
+        }
+        // the writer appends to the same file, now empty
+        if self.rotate_method != RotateMethod::CopyTruncate {
+            self.release_held()?;
+            if let (Some(config), Some(writer)) = (&self.rotate_hold, self.writer.take()) {
+                self.held = Some(Held::new(writer, config));
+            }
+            let file = open_append(&self.path_out, &self.out_perms)?;
+            self.writer = Some(self.line_writer(file));
+        }
+        self.lines_to_rotate = Some(0);
+        self.header_due = true;
 
-        self.writer = LineWriter::new(write_file);
+        Ok(())
+    }
 
+    /// rotate now whatever the read position, see SIGUSR1
+    fn force_rotate(&mut self) -> io::Result<()> {
+        if self.sink.is_some() || !self.path_out.exists() {
+            return Ok(());
+        }
+        let was_open = self.writer.is_some();
+        self.rotate()?;
+        if !was_open {
+            // closed for the open files budget, the next write opens it again
+            self.writer = None;
+        }
         Ok(())
     }
 
     fn read(&mut self) -> io::Result<Option<String>> {
-        let mut buf = String::new();
-        match self.reader.read_line(&mut buf) {
-            Ok(len) => {
-                if len == 0 {
-                    Ok(None)
-                } else {
-                    Ok(Some(buf))
-                }
+        let source = &mut self.source;
+        let stats = &self.stats;
+        let template = &mut self.template;
+        let window = &mut self.window;
+        let strip_ansi = self.strip_ansi;
+        let resumed_header = &mut self.resumed_header;
+        let tag = &self.tag;
+        let sample_line = &mut self.sample_line;
+        // templates are rendered as lines are read so reordered lines keep their timestamps
+        let mut read = || {
+            if window.as_ref().is_some_and(|window| window.is_empty()) {
+                return Ok(None);
             }
-            Err(err) => Err(err),
+            let line = loop {
+                let line = match source.read_line()? {
+                    Some(line) => line,
+                    None => break None,
+                };
+                *sample_line += 1;
+                stats.read(line.len() as u64);
+                let line = match strip_ansi.then(|| ansi::strip_ansi(&line)).flatten() {
+                    Some(stripped) => stripped,
+                    None => line,
+                };
+                if resumed_header
+                    .take()
+                    .is_some_and(|header| header == line.trim_end_matches(['\r', '\n']))
+                {
+                    // the output already starts with it
+                    continue;
+                }
+                match window.as_mut().map(|window| window.check(&line)) {
+                    None | Some(Verdict::Keep) => break Some(line),
+                    Some(Verdict::Skip) => continue,
+                    Some(Verdict::End) => break None,
+                }
+            };
+            let line = match template {
+                Some(template) => line.map(|line| template.render(&line)),
+                None => line,
+            };
+            Ok(match tag {
+                Some(tag) => {
+                    let lineno = source.line_number().unwrap_or(*sample_line);
+                    line.map(|line| tag.tag(&line, lineno))
+                }
+                None => line,
+            })
+        };
+
+        match &mut self.reorderer {
+            Some(reorderer) => reorderer.next(read),
+            None => read(),
         }
     }
 
     fn wrap(&mut self, wrap_strategy: &WrapStrategy) -> io::Result<()> {
+        self.sample_line = 0;
+        if let Some(window) = &mut self.window {
+            if window.rewound() {
+                eprintln!(
+                    "Warning: no lines of {:?} are between --since and --until",
+                    self.path_in
+                );
+            }
+        }
+        if self.sink.is_some() {
+            // there's no output file to truncate or rotate
+            self.wrapped();
+            return self.source.rewind();
+        }
+
+        if let Some(n) = self.file_number {
+            self.next_file(n)?;
+            self.wrapped();
+            return self.source.rewind();
+        }
+
         match wrap_strategy {
             WrapStrategy::Truncate => {
                 self.truncate()?;
@@ -113,179 +1070,3459 @@ impl GenInput {
             }
         }
 
-        self.reader.seek(SeekFrom::Start(0)).map(|_| ())
+        self.wrapped();
+        self.source.rewind()
     }
 
-    fn write(&mut self, line: &str) -> io::Result<()> {
-        self.writer.write_all(line.as_bytes())
+    /// count the wrap, warns the first time a pass was shorter than --warn-fast-wrap
+    fn wrapped(&mut self) {
+        let pass = self.stats.wrapped();
+        let threshold = match self.fast_wrap {
+            Some(threshold) if pass.duration < threshold => threshold,
+            _ => return,
+        };
+        self.stats.fast_wrap();
+        if !self.warned_fast_wrap {
+            self.warned_fast_wrap = true;
+            eprintln!(
+                "Warning: {:?} wrapped after {:?} ({} lines), faster than --warn-fast-wrap {:?}, \
+                 use a bigger sample or a longer --interval if that's not intended",
+                self.path_in, pass.duration, pass.lines, threshold
+            );
+        }
     }
-}
 
-fn generate(mut items: Vec<GenInput>, interval: Duration, wrap_strategy: &WrapStrategy) {
-    loop {
-        for item in items.iter_mut() {
-            match item.read() {
-                Ok(Some(line)) => {
-                    item.write(&line)
-                        .map_err(|err| eprintln!("Error: {:?}", err))
-                        .ok();
-                }
-                Ok(None) => {
-                    item.wrap(wrap_strategy)
-                        .map_err(|err| eprintln!("Error: {:?}", err))
-                        .ok();
-                }
-                Err(error) => {
-                    eprintln!("Error reading: {:?}", error);
+    /// write buffered lines to the output in a single write
+    fn flush_batch(&mut self) -> io::Result<()> {
+        if let Some(batch) = &mut self.batch {
+            let data = batch.take();
+            if !data.is_empty() {
+                self.write_file(&data)?;
+                if self.holds_endings() {
+                    // the line writer keeps the partial line otherwise
+                    self.writer()?.flush()?;
                 }
             }
+        }
+
+        Ok(())
+    }
 
-            sleep(interval);
+    /// flush buffered lines if the batch time limit or the flush interval of
+    /// --buffered-writes was reached
+    fn flush_due(&mut self) -> io::Result<()> {
+        if let Some(batch) = &self.batch {
+            if batch.is_due() {
+                self.flush_batch()?;
+            }
+        }
+        match &mut self.writer {
+            Some(writer) => writer.flush_due(),
+            None => Ok(()),
         }
     }
-}
 
-fn run(
-    in_dir: &str,
-    out_dir: &str,
-    interval: Duration,
-    parallelism_num: usize,
-    wrap_strategy: WrapStrategy,
-) -> io::Result<Vec<JoinHandle<()>>> {
-    let in_path = Path::new(in_dir);
-    let out_path = Path::new(out_dir);
-    let mut workers_data: Vec<Vec<GenInput>> = Vec::with_capacity(parallelism_num);
-    let mut counter: usize = 0;
+    /// close the rotated file that was still written to, if any
+    fn release_held(&mut self) -> io::Result<()> {
+        match self.held.take() {
+            Some(held) => held.release(),
+            None => Ok(()),
+        }
+    }
 
-    for _i in 0..parallelism_num {
-        workers_data.push(vec![]);
+    /// write to the rotated file instead of the current one if it's still held open
+    fn write_held(&mut self, line: &str) -> io::Result<bool> {
+        match &mut self.held {
+            Some(held) if held.is_active() => {
+                held.write(line)?;
+                Ok(true)
+            }
+            Some(_) => self.release_held().map(|_| false),
+            None => Ok(false),
+        }
     }
 
-    println!(
-        "{} -> {} (threads: {}, interval: {:?}, wrap: {:?})",
-        in_dir, out_dir, parallelism_num, interval, wrap_strategy
-    );
+    fn write_out(&mut self, line: &str) -> io::Result<()> {
+        if let Some(journal) = &mut self.journal {
+            let record = journal.record(line);
+            return self.write_record(&record);
+        }
+        let terminated;
+        let line = if self.trailing_newline == TrailingNewline::Always && !line.ends_with('\n') {
+            terminated = format!("{}\n", line);
+            &terminated
+        } else {
+            line
+        };
+        let line_ending = self.line_ending;
+        let line = &*line_ending.apply(line);
+        if let Some(router) = &mut self.router {
+            if router.route(line)? {
+                self.stats.written(line.len() as u64);
+                return Ok(());
+            }
+        }
 
-    for entry in WalkDir::new(in_dir).into_iter().filter_map(|e| e.ok()) {
-        let path_in = entry.path();
-        if path_in.is_file() {
-            if let Ok(rel_dir) = path_in.strip_prefix(in_path) {
-                let path_out = out_path.join(rel_dir);
-                let dir_to_create = path_out.parent().unwrap();
-                fs::create_dir_all(dir_to_create)?;
-                let index: usize = counter % parallelism_num;
-                let gen_input = GenInput::new(path_in.to_path_buf(), path_out)?;
-                workers_data[index].push(gen_input);
+        if let Some(sink) = &mut self.sink {
+            if let Some(pause) = self
+                .staller
+                .as_mut()
+                .and_then(|staller| staller.next_pause())
+            {
+                sleep(pause);
+            }
+            if self.profile_io {
+                let started = Instant::now();
+                sink.write(line)?;
+                self.stats.io.called(line.len(), started.elapsed());
+            } else {
+                sink.write(line)?;
+            }
+            self.stats.written(line.len() as u64);
+            return Ok(());
+        }
+
+        self.check_partition()?;
+        if self.write_held(line)? {
+            self.stats.written(line.len() as u64);
+            return Ok(());
+        }
+        self.write_header()?;
+
+        if self.holds_endings() {
+            self.write_holding_ending(line)?;
+            self.stats.written(line.len() as u64);
+            return self.count_rotate_lines();
+        }
 
-                counter += 1;
+        match &mut self.batch {
+            Some(batch) => {
+                batch.push(line);
+                if batch.is_due() {
+                    self.flush_batch()?;
+                }
             }
+            None => self.write_file(line)?,
         }
+        self.stats.written(line.len() as u64);
+        self.count_rotate_lines()
     }
 
-    let mut join_handles = vec![];
-    for worker_data in workers_data.into_iter() {
-        if worker_data.len() > 0 {
-            let my_wrap_strategy = wrap_strategy.clone();
-            join_handles.push(thread::spawn(move || {
-                generate(worker_data, interval, &my_wrap_strategy);
-            }));
+    /// write the source's header if the output file is new, it isn't counted
+    /// as lines or for --rotate-lines
+    fn write_header(&mut self) -> io::Result<()> {
+        if !std::mem::take(&mut self.header_due) {
+            return Ok(());
+        }
+        match self.source.header() {
+            Some(header) => {
+                let header: String = header
+                    .split_inclusive('\n')
+                    .map(|line| self.line_ending.apply(line))
+                    .collect();
+                self.write_file(&header)
+            }
+            None => Ok(()),
         }
     }
 
-    Ok(join_handles)
-}
+    /// rotate once --rotate-lines lines went to the output file
+    fn count_rotate_lines(&mut self) -> io::Result<()> {
+        let max = match self.rotate_lines {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let lines = match self.lines_to_rotate {
+            Some(lines) => lines + 1,
+            // the output may have lines from before, this one is buffered or written already
+            None => {
+                self.flush_batch()?;
+                if let Some(writer) = &mut self.writer {
+                    writer.flush()?;
+                }
+                rotate::lines_in(&self.path_out)?
+            }
+        };
+        self.lines_to_rotate = Some(lines);
+        if lines >= max {
+            self.rotate()?;
+        }
 
-fn main() {
-    unsafe {
-        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        Ok(())
     }
 
-    let matches = App::new("loggen")
-        .version("0.2.0")
-        .author("Mariano Guerra <mariano@marianoguerra.org>")
-        .about("Generate logs from a directory tree of sample logs")
-        .arg(
-            Arg::with_name("in-base-dir")
-                .short("i")
-                .long("in-base-dir")
-                .value_name("FILE")
-                .required(true)
-                .help("Input base directory")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("out-base-dir")
-                .short("o")
-                .long("out-base-dir")
-                .value_name("FILE")
-                .required(true)
-                .help("Output base directory")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("interval")
-                .short("t")
-                .long("interval")
-                .value_name("MS")
-                .help("Time in milliseconds between reads")
-                .validator(is_positive_number)
-                .default_value("250")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("wrap-strategy")
-                .short("w")
-                .long("wrap-strategy")
-                .value_name("STRATEGY")
-                .help("What to do when sample log reaches the end")
-                .default_value("append")
-                .possible_values(&["truncate", "append", "rotate"])
-                .required(true),
-        )
-        .arg(
-            Arg::with_name("parallelism")
-                .short("p")
-                .long("parallelism")
-                .value_name("COUNT")
-                .help("Number of parallel generators")
-                .validator(is_positive_number)
-                .default_value("2")
-                .takes_value(true),
-        )
-        .get_matches();
+    fn holds_endings(&self) -> bool {
+        self.trailing_newline == TrailingNewline::Never || self.partial_line_flush.is_some()
+    }
 
-    let in_dir = matches.value_of("in-base-dir").unwrap();
-    let out_dir = matches.value_of("out-base-dir").unwrap();
-    let wrap_strategy = matches.value_of("wrap-strategy").unwrap();
-    let interval_str = matches.value_of("interval").unwrap_or("0");
-    let interval_num = interval_str.parse::<u64>().unwrap();
-    let parallelism_str = matches.value_of("parallelism").unwrap_or("0");
-    let parallelism_num_0 = parallelism_str.parse::<usize>().unwrap();
-    let parallelism_num = if parallelism_num_0 == 0 {
-        num_cpus::get() as usize
-    } else {
-        parallelism_num_0
-    };
+    /// write `line` without its ending, after the ending held from the line
+    /// before, and hold its own, what's written is flushed so readers see the
+    /// partial line
+    fn write_holding_ending(&mut self, line: &str) -> io::Result<()> {
+        let (body, ending) = match line_ending::split_ending(line) {
+            // lines are still separated, only the last one isn't terminated
+            (body, "") if self.trailing_newline == TrailingNewline::Never => (body, "\n"),
+            split => split,
+        };
+        let data = match self.pending_ending.take() {
+            Some((pending, _)) => format!("{}{}", pending, body),
+            None => body.to_string(),
+        };
+        if !ending.is_empty() {
+            let due = self.partial_line_flush.map(|delay| Instant::now() + delay);
+            self.pending_ending = Some((ending, due));
+        }
 
-    match run(
-        in_dir,
-        out_dir,
-        Duration::from_millis(interval_num),
-        parallelism_num,
-        WrapStrategy::from_str(wrap_strategy, WrapStrategy::Append),
-    ) {
-        Ok(join_handles) => {
-            for join_handle in join_handles {
-                match join_handle.join() {
-                    Ok(_) => {}
-                    Err(error) => {
-                        eprintln!("Error in thread: {:?}", error);
-                    }
+        match &mut self.batch {
+            Some(batch) => {
+                batch.push(&data);
+                if batch.is_due() {
+                    self.flush_batch()?;
                 }
+                Ok(())
+            }
+            None => {
+                self.write_file(&data)?;
+                self.writer()?.flush()
             }
         }
-        Err(error) => {
-            eprintln!("Error: {}", error);
+    }
+
+    /// when the held ending of the last line is due, with --partial-line-flush
+    fn ending_due(&self) -> Option<Instant> {
+        self.pending_ending.and_then(|(_, due)| due)
+    }
+
+    /// write the held ending of the last line if it's due
+    fn write_due_ending(&mut self) -> io::Result<()> {
+        match self.pending_ending {
+            Some((ending, Some(due))) if due <= Instant::now() => {
+                self.pending_ending = None;
+                self.flush_batch()?;
+                self.write_file(ending)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// the output file ends: write the held ending with --partial-line-flush,
+    /// with --trailing-newline never the file is left with a partial line
+    fn finish_ending(&mut self) -> io::Result<()> {
+        match self.pending_ending.take() {
+            Some((ending, Some(_))) => {
+                self.flush_batch()?;
+                self.write_file(ending)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let rewritten;
+        let line = match &mut self.rewriter {
+            Some(rewriter) => {
+                rewritten = rewriter.get().rewrite(line);
+                rewritten.as_str()
+            }
+            None => line,
+        };
+        if let Some(script) = &mut self.script {
+            for line in script.transform(line)? {
+                self.write_line(&line)?;
+            }
+            return Ok(());
+        }
+
+        self.write_line(line)
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        match self
+            .throttler
+            .as_mut()
+            .map(|throttler| throttler.check(line))
+        {
+            Some(Throttled::Dropped) => {
+                self.stats.throttled();
+                Ok(())
+            }
+            Some(Throttled::Delayed) => Ok(()),
+            Some(Throttled::Write) | None => self.write_kept(line),
         }
     }
+
+    /// write a line that wasn't dropped or delayed by --throttle
+    fn write_kept(&mut self, line: &str) -> io::Result<()> {
+        if let Some(heartbeat) = &mut self.heartbeat {
+            heartbeat.written();
+        }
+
+        let enriched;
+        let line = match &self.enricher {
+            Some(enricher) => {
+                enriched = enricher.apply(line);
+                enriched.as_str()
+            }
+            None => line,
+        };
+        let correlated;
+        let line = match &self.correlation {
+            Some(correlation) => {
+                correlated = correlation.apply(line);
+                correlated.as_str()
+            }
+            None => line,
+        };
+        let injected;
+        let line = match self
+            .inject_ansi
+            .as_ref()
+            .and_then(|inject| inject.apply(line))
+        {
+            Some(changed) => {
+                injected = changed;
+                injected.as_str()
+            }
+            None => line,
+        };
+
+        if let Some(split) = &self.split_long_lines {
+            for piece in split.split(line) {
+                self.write_out(&piece)?;
+                self.duplicator.record(&piece);
+            }
+            return Ok(());
+        }
+
+        self.write_out(line)?;
+        self.duplicator.record(line);
+        Ok(())
+    }
+
+    /// write a heartbeat line if no line was written for its interval
+    fn write_heartbeat(&mut self) -> io::Result<()> {
+        match self
+            .heartbeat
+            .as_mut()
+            .and_then(|heartbeat| heartbeat.next_due())
+        {
+            Some(line) => self.write_out(&line),
+            None => Ok(()),
+        }
+    }
+
+    /// write a marker line if it's time for one
+    fn write_marker(&mut self) -> io::Result<()> {
+        match self.marker.as_mut().and_then(|marker| marker.next_due()) {
+            Some(line) => self.write_out(&line),
+            None => Ok(()),
+        }
+    }
+
+    /// write duplicated lines whose delay has elapsed
+    fn write_duplicates(&mut self) -> io::Result<()> {
+        while let Some(line) = self.duplicator.next_due() {
+            self.write_out(&line)?;
+        }
+
+        Ok(())
+    }
+
+    /// write lines delayed by --throttle whose delay has elapsed
+    fn write_throttled(&mut self) -> io::Result<()> {
+        while let Some(line) = self.throttler.as_mut().and_then(|t| t.next_due()) {
+            self.write_kept(&line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// longest a worker waits for files from the startup scan before reading again
+const SCAN_POLL: Duration = Duration::from_millis(100);
+/// longest an idle worker waits before looking for late files of others
+const STEAL_POLL: Duration = Duration::from_millis(10);
+/// files are taken from a worker once they're this late, or an interval
+const STEAL_BEHIND: Duration = Duration::from_millis(100);
+
+/// files sent to a worker by the startup scan, waits up to `wait` for the
+/// first one, `inbox` is set to None once the scan is done
+fn receive(inbox: &mut Option<Receiver<GenInput>>, wait: Duration) -> Vec<GenInput> {
+    let mut found = vec![];
+    if let Some(receiver) = inbox {
+        let mut next = receiver.recv_timeout(wait);
+        loop {
+            match next {
+                Ok(item) => found.push(item),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    *inbox = None;
+                    break;
+                }
+            }
+            next = receiver.recv_timeout(Duration::ZERO);
+        }
+    }
+    found
+}
+
+/// how a worker waits, depends on its files
+#[derive(Debug, Default)]
+struct Pacing {
+    /// don't block on the pacer for long so heartbeats are written while it's silent
+    pacer_timeout: Option<Duration>,
+    /// endings are held by --partial-line-flush
+    partial_line_flush: bool,
+}
+
+impl Pacing {
+    /// `items` joined the worker
+    fn joined<'a>(&mut self, items: impl Iterator<Item = &'a GenInput>) {
+        for item in items {
+            if let Some(heartbeat) = &item.heartbeat {
+                let wait = heartbeat.interval() / 4;
+                self.pacer_timeout = Some(self.pacer_timeout.map_or(wait, |w| w.min(wait)));
+            }
+            self.partial_line_flush |= item.partial_line_flush.is_some();
+        }
+    }
+}
+
+/// read and write once for `item`, or do its timed writes if the pacer
+/// doesn't let it write yet
+fn generate_step(
+    item: &mut GenInput,
+    wrap_strategy: &WrapStrategy,
+    pacer_timeout: Option<Duration>,
+    shared: &Shared,
+) {
+    let stats = item.stats.clone();
+    item.write_duplicates()
+        .and_then(|_| item.write_throttled())
+        .and_then(|_| item.write_marker())
+        .and_then(|_| item.write_heartbeat())
+        .and_then(|_| item.flush_due())
+        .and_then(|_| item.write_due_ending())
+        .map_err(|err| stats.error(format!("Error: {:?}", err)))
+        .ok();
+
+    let refund = |item: &GenInput| {
+        shared.pacer.refund();
+        if let Some(pacer) = &item.group_pacer {
+            pacer.refund();
+        }
+        item.stats.control.refund();
+    };
+    let may_write = shared.pacer.wait_for(&shared.stop, pacer_timeout);
+    if !may_write || shared.stop.load(Ordering::Relaxed) {
+        if let Some(pacer) = &item.group_pacer {
+            pacer.refund();
+        }
+        item.stats.control.refund();
+        return;
+    }
+
+    if item.zero_copy {
+        match item.copy_pass(wrap_strategy) {
+            Ok(false) => {}
+            Ok(true) => return,
+            Err(err) => {
+                stats.error(format!("Error: {:?}", err));
+                return;
+            }
+        }
+    }
+
+    let read = match item.framing {
+        Some(framing) => item.read_record(framing).map(|r| r.map(Sample::Record)),
+        None => item.read().map(|line| line.map(Sample::Line)),
+    };
+    match read {
+        Ok(Some(sample)) => {
+            match &sample {
+                Sample::Line(line) => item.write(line),
+                Sample::Record(framed) => item.write_record(framed),
+            }
+            .map_err(|err| stats.error(format!("Error: {:?}", err)))
+            .ok();
+        }
+        Ok(None) if item.source.is_stream() => {
+            // nothing arrived yet, streams aren't wrapped
+            refund(item);
+        }
+        Ok(None) => {
+            refund(item);
+            item.wrap(wrap_strategy)
+                .map_err(|err| stats.error(format!("Error: {:?}", err)))
+                .ok();
+        }
+        Err(error) => {
+            stats.error(format!("Error reading {:?}: {:?}", item.path_in, error));
+        }
+    }
+}
+
+fn generate(
+    me: usize,
+    queues: Arc<Vec<Mutex<WorkQueue<GenInput>>>>,
+    mut inbox: Option<Receiver<GenInput>>,
+    run_options: &RunOptions,
+    wrap_strategy: &WrapStrategy,
+    silence: Option<&Silence>,
+    shared: &Shared,
+) -> Vec<GenInput> {
+    let queue = &queues[me];
+    let interval = run_options.interval;
+    let work_stealing = run_options.work_stealing;
+    let mut pacing = Pacing::default();
+    pacing.joined(queue.lock().unwrap().items_mut().map(|item| &*item));
+    // files are taken from workers that are this late
+    let steal_behind = interval.max(STEAL_BEHIND);
+    let mut rotate_requests = shared.rotate_requests.load(Ordering::Relaxed);
+    let mut file_rotations = shared.file_rotations.load(Ordering::Relaxed);
+    while !shared.stop.load(Ordering::Relaxed) && !shared.rebalance.is_yielding() {
+        // files found by the startup scan are spread over an interval from when they arrive
+        if inbox.is_some() {
+            let next = queue.lock().unwrap().next().map(|(deadline, _)| deadline);
+            let wait = next.map_or(SCAN_POLL, |next| {
+                next.saturating_duration_since(Instant::now())
+                    .min(SCAN_POLL)
+            });
+            let found = receive(&mut inbox, wait);
+            pacing.joined(found.iter());
+            let now = Instant::now();
+            let count = found.len() as u32;
+            let mut queue = queue.lock().unwrap();
+            for (i, item) in found.into_iter().enumerate() {
+                queue.add(item, now + interval * i as u32 / count);
+            }
+        }
+
+        let next = queue.lock().unwrap().next();
+        // with nothing due, files that other workers fell behind on are read here
+        if work_stealing && next.is_none_or(|(deadline, _)| deadline > Instant::now()) {
+            let stolen = steal::steal(me, &queues, steal_behind);
+            if !stolen.is_empty() {
+                pacing.joined(stolen.iter());
+                let now = Instant::now();
+                let mut queue = queue.lock().unwrap();
+                for item in stolen {
+                    queue.add(item, now);
+                }
+                continue;
+            }
+        }
+        let deadline = match next {
+            Some((deadline, _)) => deadline,
+            None if inbox.is_some() => continue,
+            // others may fall behind later, until they have nothing left either
+            None if work_stealing
+                && queues.iter().any(|queue| !queue.lock().unwrap().is_empty()) =>
+            {
+                sleep(STEAL_POLL);
+                continue;
+            }
+            None => break,
+        };
+        // endings held by --partial-line-flush are written on time between reads
+        let ending_due = if pacing.partial_line_flush {
+            queue
+                .lock()
+                .unwrap()
+                .items_mut()
+                .filter_map(|item| item.ending_due())
+                .min()
+        } else {
+            None
+        };
+        if let Some(due) = ending_due.filter(|due| *due < deadline) {
+            sleep(due.saturating_duration_since(Instant::now()));
+            for item in queue.lock().unwrap().items_mut() {
+                if let Err(err) = item.write_due_ending() {
+                    item.stats
+                        .error(format!("Error writing to {:?}: {:?}", item.path_out, err));
+                }
+            }
+            continue;
+        }
+        let now = Instant::now();
+        if deadline > now {
+            // woken up early to take files others fell behind on or that were found
+            match (work_stealing, inbox.is_some()) {
+                (true, _) => sleep((deadline - now).min(STEAL_POLL)),
+                (false, true) => {}
+                (false, false) => sleep(deadline - now),
+            }
+            continue;
+        }
+
+        if shared.rotate_requests.load(Ordering::Relaxed) != rotate_requests {
+            rotate_requests = shared.rotate_requests.load(Ordering::Relaxed);
+            for item in queue.lock().unwrap().items_mut() {
+                if let Err(err) = item.force_rotate() {
+                    item.stats
+                        .error(format!("Error rotating {:?}: {:?}", item.path_out, err));
+                }
+            }
+        }
+        if shared.file_rotations.load(Ordering::Relaxed) != file_rotations {
+            file_rotations = shared.file_rotations.load(Ordering::Relaxed);
+            for item in queue.lock().unwrap().items_mut() {
+                if !item.stats.control.take_rotate() {
+                    continue;
+                }
+                if let Err(err) = item.force_rotate() {
+                    item.stats
+                        .error(format!("Error rotating {:?}: {:?}", item.path_out, err));
+                }
+            }
+        }
+
+        if let Some(left) = silence.and_then(|silence| silence.remaining()) {
+            // buffered lines go out before the silence so it starts for downstream too
+            for item in queue.lock().unwrap().items_mut() {
+                if let Err(err) = item.flush_batch() {
+                    item.stats
+                        .error(format!("Error flushing {:?}: {:?}", item.path_out, err));
+                }
+            }
+            let end = Instant::now() + left;
+            while !shared.stop.load(Ordering::Relaxed) && Instant::now() < end {
+                sleep((end - Instant::now()).min(Duration::from_millis(100)));
+            }
+            continue;
+        }
+
+        let (index, mut item, deadline) = {
+            let mut queue = queue.lock().unwrap();
+            // another worker may have taken it since it was looked at
+            let (deadline, index) = match queue.next() {
+                Some((deadline, index)) if deadline <= now => (deadline, index),
+                _ => continue,
+            };
+            // paused files and files of a rate group wait without holding up the others
+            let control = queue.get(index).map(|item| &item.stats.control);
+            if let Some(wait) = control.and_then(|control| control.wait()) {
+                queue.postpone(Instant::now() + wait);
+                continue;
+            }
+            let group_wait = queue
+                .get(index)
+                .and_then(|item| item.group_pacer.as_ref())
+                .and_then(|pacer| pacer.try_take().err());
+            if let Some(wait) = group_wait {
+                if let Some(control) = control {
+                    control.refund();
+                }
+                queue.postpone(Instant::now() + wait);
+                continue;
+            }
+
+            let (index, item, evicted) = match queue.take() {
+                Some(taken) => taken,
+                None => continue,
+            };
+            if let Some(evicted_item) = evicted {
+                if let Err(err) = evicted_item.close() {
+                    evicted_item.stats.error(format!(
+                        "Error closing {:?}: {:?}",
+                        evicted_item.path_out, err
+                    ));
+                }
+            }
+            (index, item, deadline)
+        };
+        // an item that fell behind reads once now instead of catching up in a burst
+        let next_read = (deadline + interval).max(Instant::now());
+        item.stats
+            .scheduled(me, now.saturating_duration_since(deadline));
+        // the queue isn't locked while working so others can take its late files
+        generate_step(&mut item, wrap_strategy, pacing.pacer_timeout, shared);
+        queue.lock().unwrap().put_back(index, item, next_read);
+    }
+
+    // closed like evicted items, they may be handed to another worker
+    let mut items = queue.lock().unwrap().drain();
+    for item in items.iter_mut() {
+        if let Err(err) = item.close() {
+            item.stats
+                .error(format!("Error closing {:?}: {:?}", item.path_out, err));
+        }
+    }
+    items
+}
+
+/// distribute `gen_inputs` to `parallelism` workers and start them
+fn spawn_workers(
+    gen_inputs: Vec<GenInput>,
+    parallelism: usize,
+    run_options: &RunOptions,
+    wrap_strategy: &WrapStrategy,
+    silence: &Option<Silence>,
+    shared: &Shared,
+) -> Vec<JoinHandle<Vec<GenInput>>> {
+    let files: Vec<(&Path, u64)> = gen_inputs
+        .iter()
+        .map(|item| (item.rel_path.as_path(), item.stats.size))
+        .collect();
+    let indexes = run_options.assign.workers_for(&files, parallelism);
+    let mut workers_data: Vec<Vec<GenInput>> = (0..parallelism).map(|_| vec![]).collect();
+    for (mut gen_input, index) in gen_inputs.into_iter().zip(indexes) {
+        if let Some(enricher) = &mut gen_input.enricher {
+            enricher.assigned(index);
+        }
+        workers_data[index].push(gen_input);
+    }
+
+    start_workers(
+        workers_data
+            .into_iter()
+            // idle workers are only useful to take files from busy ones
+            .filter(|worker_data| run_options.work_stealing || !worker_data.is_empty())
+            .map(|worker_data| (worker_data, None))
+            .collect(),
+        parallelism,
+        run_options,
+        wrap_strategy,
+        silence,
+        shared,
+    )
+}
+
+/// start a worker for each set of files, workers with an inbox also generate
+/// the files sent to it until it's closed
+fn start_workers(
+    workers: Vec<(Vec<GenInput>, Option<Receiver<GenInput>>)>,
+    parallelism: usize,
+    run_options: &RunOptions,
+    wrap_strategy: &WrapStrategy,
+    silence: &Option<Silence>,
+    shared: &Shared,
+) -> Vec<JoinHandle<Vec<GenInput>>> {
+    // each open item uses two descriptors, one for the sample and one for the output
+    let max_open_items = run_options.max_open_files / 2 / parallelism;
+    let interval = run_options.interval;
+    let started = Instant::now();
+    let mut queues = vec![];
+    let mut inboxes = vec![];
+    for (worker_data, inbox) in workers.into_iter() {
+        // every item reads once per interval on its own, first reads are spread over
+        // an interval so they don't all happen at once
+        let mut queue = WorkQueue::new(max_open_items);
+        let count = worker_data.len() as u32;
+        for (index, item) in worker_data.into_iter().enumerate() {
+            queue.add(item, started + interval * index as u32 / count);
+        }
+        queues.push(Mutex::new(queue));
+        inboxes.push(inbox);
+    }
+
+    let queues = Arc::new(queues);
+    let mut join_handles = vec![];
+    for (me, inbox) in inboxes.into_iter().enumerate() {
+        let my_queues = queues.clone();
+        let my_run_options = run_options.clone();
+        let my_wrap_strategy = wrap_strategy.clone();
+        let my_shared = shared.clone();
+        let my_silence = silence.clone();
+        join_handles.push(thread::spawn(move || {
+            let _usage = Usage::enter(&my_shared.usage, format!("worker {}", me));
+            generate(
+                me,
+                my_queues,
+                inbox,
+                &my_run_options,
+                &my_wrap_strategy,
+                my_silence.as_ref(),
+                &my_shared,
+            )
+        }));
+    }
+    shared.rebalance.set_current(parallelism);
+    join_handles
+}
+
+/// wait for the workers, when another worker count is asked for they hand
+/// their files back and new workers start with them distributed again
+fn rebalance_workers(
+    mut join_handles: Vec<JoinHandle<Vec<GenInput>>>,
+    run_options: &RunOptions,
+    wrap_strategy: &WrapStrategy,
+    silence: &Option<Silence>,
+    shared: &Shared,
+) -> Vec<GenInput> {
+    loop {
+        let stopping = shared.stop.load(Ordering::Relaxed);
+        // files found by the startup scan go to the workers it started with
+        let requested = if shared.stats.is_ready() {
+            shared.rebalance.take_request()
+        } else {
+            None
+        };
+        if stopping || requested.is_some() {
+            shared.rebalance.set_yielding(true);
+            let mut gen_inputs = vec![];
+            for join_handle in join_handles.drain(..) {
+                match join_handle.join() {
+                    Ok(items) => gen_inputs.extend(items),
+                    Err(error) => eprintln!("Error in thread: {:?}", error),
+                }
+            }
+            shared.rebalance.set_yielding(false);
+            match requested {
+                Some(parallelism) if !shared.stop.load(Ordering::Relaxed) => {
+                    // in path order so the assignment doesn't depend on the previous one
+                    gen_inputs.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+                    println!(
+                        "rebalancing {} files to {} workers",
+                        gen_inputs.len(),
+                        parallelism
+                    );
+                    join_handles = spawn_workers(
+                        gen_inputs,
+                        parallelism,
+                        run_options,
+                        wrap_strategy,
+                        silence,
+                        shared,
+                    );
+                }
+                _ => return gen_inputs,
+            }
+        }
+        sleep(Duration::from_millis(100));
+    }
+}
+
+fn print_rate_groups(groups: &[RateGroup], gen_inputs: &[GenInput]) {
+    for group in groups {
+        let count = gen_inputs
+            .iter()
+            .filter(|item| group.matches(&item.rel_path))
+            .count();
+        println!(
+            "group {} ({} files) at {}lps",
+            group.pattern, count, group.rate
+        );
+    }
+}
+
+/// the generator of a sample found in `in_path`
+fn sample_input(
+    found: &Found,
+    in_path: &Path,
+    out_path: &Path,
+    run_options: &RunOptions,
+    options: &GenOptions,
+    stats: &Arc<Stats>,
+) -> io::Result<GenInput> {
+    let path_in = found.path.as_path();
+    let is_pipe = found.is_pipe;
+    let rel_dir = path_in.strip_prefix(in_path).unwrap_or(path_in);
+    // opening a pipe blocks until there's a writer, it's opened when first read
+    let size = if is_pipe {
+        0
+    } else {
+        // fail if the sample can't be read, it's opened again when used
+        File::open(path_in)?.metadata()?.len()
+    };
+    let source: Box<dyn Source> = if let Some(framing) = options.records {
+        if is_pipe {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "named pipes can't be read as binary records: {}",
+                    path_in.display()
+                ),
+            ));
+        }
+        Box::new(RecordSource::new(
+            path_in.to_path_buf(),
+            framing,
+            run_options.read_buffer,
+        ))
+    } else if is_pipe {
+        Box::new(PipeSource::new(path_in.to_path_buf()))
+    } else if let Some(index) = &options.index {
+        Box::new(IndexedSource::new(
+            path_in.to_path_buf(),
+            index.clone(),
+            run_options.read_buffer,
+        ))
+    } else if run_options.mmap {
+        Box::new(MmapSource::new(path_in.to_path_buf()))
+    } else {
+        Box::new(FileSource::with_capacity(
+            path_in.to_path_buf(),
+            run_options.read_buffer,
+        ))
+    };
+    // pipes never end, they're replayed whole
+    let source = match options.slice {
+        Some(slice) if !is_pipe => Box::new(SliceSource::new(source, slice, path_in.to_path_buf())),
+        _ => source,
+    };
+    let mut gen_input = GenInput::new(
+        source,
+        path_in.to_path_buf(),
+        size,
+        out_path.to_path_buf(),
+        rel_dir.to_path_buf(),
+        options,
+        stats,
+    );
+    gen_input.zero_copy = options.zero_copy
+        && run_options.interval.is_zero()
+        && options.writes_as_read()
+        && !is_pipe
+        && gen_input.resumed_header.is_none();
+    Ok(gen_input)
+}
+
+/// walk `in_path` sending a generator for each sample to a worker's inbox as
+/// they're found, a sample that can't be read stops everything
+fn scan_samples(
+    in_path: &Path,
+    out_path: &Path,
+    run_options: &RunOptions,
+    options: &GenOptions,
+    inboxes: Vec<Sender<GenInput>>,
+    shared: &Shared,
+) {
+    let (found_sender, found) = mpsc::channel();
+    let walker = {
+        let in_path = in_path.to_path_buf();
+        let threads = run_options.scan_threads;
+        let stop = shared.stop.clone();
+        thread::spawn(move || scan::walk(&in_path, threads, found_sender, &stop))
+    };
+
+    let mut assigner = Assigner::new(run_options.assign.clone(), inboxes.len());
+    let mut group_counts = vec![0; options.rate_groups.len()];
+    let mut count = 0;
+    for found in found.iter() {
+        let mut gen_input = match sample_input(
+            &found,
+            in_path,
+            out_path,
+            run_options,
+            options,
+            &shared.stats,
+        ) {
+            Ok(gen_input) => gen_input,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                shared.scan_failed.store(true, Ordering::Relaxed);
+                shared.stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        };
+        let index = assigner.worker_for(&gen_input.rel_path, gen_input.stats.size);
+        if let Some(enricher) = &mut gen_input.enricher {
+            enricher.assigned(index);
+        }
+        for (group, group_count) in options.rate_groups.iter().zip(group_counts.iter_mut()) {
+            if group.matches(&gen_input.rel_path) {
+                *group_count += 1;
+            }
+        }
+        // the worker is gone when stopping
+        inboxes[index].send(gen_input).ok();
+        count += 1;
+    }
+    drop(found);
+    if walker.join().is_err() {
+        eprintln!("Error in thread scanning {:?}", in_path);
+    }
+    if shared.stop.load(Ordering::Relaxed) {
+        return;
+    }
+
+    println!("found {} files in {:?}", count, in_path);
+    for (group, count) in options.rate_groups.iter().zip(group_counts) {
+        println!(
+            "group {} ({} files) at {}lps",
+            group.pattern, count, group.rate
+        );
+    }
+    shared.stats.set_ready();
+}
+
+fn run(
+    input: Input,
+    message_column: &str,
+    out_dir: &str,
+    run_options: RunOptions,
+    options: GenOptions,
+    shared: &Shared,
+) -> io::Result<Vec<JoinHandle<Vec<GenInput>>>> {
+    let stats = &shared.stats;
+    let out_path = Path::new(out_dir);
+    let interval = run_options.interval;
+    let parallelism_num = run_options.parallelism;
+    let mut gen_inputs = vec![];
+    // directories are scanned while generating, workers are sent files as they're found
+    let mut scanner = None;
+    let mut worker_inboxes = vec![];
+
+    println!(
+        "{} -> {} (threads: {}, interval: {:?}, wrap: {:?}, partition: {:?}, assign: {:?})",
+        match input {
+            Input::Dir(in_dir) => in_dir,
+            Input::Url(url) => url,
+            Input::Format(format) => format,
+        },
+        out_dir,
+        parallelism_num,
+        interval,
+        options.wrap_strategy,
+        options.out_partition,
+        run_options.assign
+    );
+
+    match input {
+        Input::Dir(in_dir) => {
+            let (inboxes, receivers): (Vec<_>, Vec<_>) =
+                (0..parallelism_num).map(|_| mpsc::channel()).unzip();
+            let in_path = PathBuf::from(in_dir);
+            let out_path = out_path.to_path_buf();
+            let my_run_options = run_options.clone();
+            let my_options = options.clone();
+            let my_shared = shared.clone();
+            scanner = Some(thread::spawn(move || {
+                scan_samples(
+                    &in_path,
+                    &out_path,
+                    &my_run_options,
+                    &my_options,
+                    inboxes,
+                    &my_shared,
+                );
+                vec![]
+            }));
+            worker_inboxes = receivers;
+        }
+        Input::Url(url) | Input::Format(url) => {
+            let (source, rel_path) = match input {
+                Input::Format(format) => synth::open(format, &run_options.synth)?,
+                _ => source::open_url(url, message_column)?,
+            };
+            gen_inputs.push(GenInput::new(
+                source,
+                PathBuf::from(url),
+                0,
+                out_path.to_path_buf(),
+                rel_path,
+                &options,
+                stats,
+            ));
+        }
+    }
+
+    if scanner.is_none() {
+        print_rate_groups(&options.rate_groups, &gen_inputs);
+    }
+
+    if let Some(start_at) = &run_options.start_at {
+        start_at.wait(&shared.stop);
+    }
+    let silence = run_options.silence.clone().map(|silence| Silence {
+        started: Instant::now(),
+        ..silence
+    });
+    let mut join_handles = if scanner.is_some() {
+        start_workers(
+            worker_inboxes
+                .into_iter()
+                .map(|inbox| (vec![], Some(inbox)))
+                .collect(),
+            parallelism_num,
+            &run_options,
+            &options.wrap_strategy,
+            &silence,
+            shared,
+        )
+    } else {
+        spawn_workers(
+            gen_inputs,
+            parallelism_num,
+            &run_options,
+            &options.wrap_strategy,
+            &silence,
+            shared,
+        )
+    };
+    if run_options.rebalance {
+        let my_run_options = run_options.clone();
+        let my_wrap_strategy = options.wrap_strategy.clone();
+        let my_shared = shared.clone();
+        join_handles = vec![thread::spawn(move || {
+            rebalance_workers(
+                join_handles,
+                &my_run_options,
+                &my_wrap_strategy,
+                &silence,
+                &my_shared,
+            )
+        })];
+    }
+
+    match scanner {
+        // ready once every file was found
+        Some(scanner) => join_handles.push(scanner),
+        None => stats.set_ready(),
+    }
+    if run_options.limits.is_set() {
+        limits::watch(
+            run_options.limits.clone(),
+            shared.pacer.clone(),
+            shared.stats.clone(),
+            shared.stop.clone(),
+        );
+    }
+
+    Ok(join_handles)
+}
+
+/// arguments shared by the default command and subcommands that generate logs
+fn gen_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("config")
+            .long("config")
+            .value_name("FILE")
+            .help("JSON object with options by long name, values can use ${ENV_VAR} and ${file:PATH}, command line options override it")
+            .takes_value(true),
+        Arg::with_name("in-base-dir")
+            .short("i")
+            .long("in-base-dir")
+            .value_name("FILE")
+            .required_unless_one(&["input", "format"])
+            .conflicts_with_all(&["input", "format"])
+            .help("Input base directory")
+            .takes_value(true),
+        Arg::with_name("input")
+            .long("input")
+            .value_name("URL")
+            .help("Generate from a source instead of a directory: kafka://broker[,broker]/topic, sqlite:path?table=name or parquet:path")
+            .takes_value(true),
+        Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .help("Generate synthetic lines instead of reading samples")
+            .possible_values(synth::FORMATS)
+            .conflicts_with("input")
+            .takes_value(true),
+        Arg::with_name("sessions")
+            .long("sessions")
+            .value_name("OPTIONS")
+            .help("Sessions for --format sessions: concurrency=N,actions=MIN..MAX,think=MIN..MAX [default: concurrency=10,actions=3..10,think=500ms..5s]")
+            .validator(synth::is_session_config)
+            .requires("format")
+            .takes_value(true),
+        Arg::with_name("synth-fields")
+            .long("synth-fields")
+            .value_name("FIELDS")
+            .help("Fields of --format kv and xml-event lines in order, a kind or NAME=KIND, kinds: timestamp, level, host, service, user, ip, method, path, status, duration_ms, bytes, trace_id, message [default: timestamp,level,host,service,message]")
+            .validator(synth::is_field_set)
+            .requires("format")
+            .takes_value(true),
+        Arg::with_name("start-at")
+            .long("start-at")
+            .value_name("TIME")
+            .help("Start writing at this RFC 3339 time, like 2024-05-01T12:00:00Z, to start loggen on several hosts at the same instant")
+            .validator(start::is_start_at)
+            .takes_value(true),
+        Arg::with_name("sync")
+            .long("sync")
+            .value_name("MODE")
+            .help("How to wait for --start-at: monotonic reads the wall clock once, ntp-tolerant follows adjustments made to it while waiting [default: ntp-tolerant]")
+            .possible_values(SyncMode::NAMES)
+            .requires("start-at")
+            .takes_value(true),
+        Arg::with_name("silence")
+            .long("silence")
+            .value_name("OPTIONS")
+            .help("Stop writing to every output for a while once per period, for example --silence every=10m for=30s, to test no data alerts and idle flushes")
+            .validator(silence::is_silence_part)
+            // multiple so it doesn't override itself, later values win
+            .multiple(true)
+            .takes_value(true),
+        Arg::with_name("max-memory")
+            .long("max-memory")
+            .value_name("SIZE")
+            .help("Lower the rate while loggen's resident memory is over SIZE, for example 512MB, it's lowered to the cgroup limit if that's smaller")
+            .validator(parse::is_size)
+            .takes_value(true),
+        Arg::with_name("max-cpu-pct")
+            .long("max-cpu-pct")
+            .value_name("PCT")
+            .help("Lower the rate while loggen uses more than PCT percent of a core, 200 is two cores, it's lowered to the cgroup quota if that's smaller")
+            .validator(limits::is_cpu_pct)
+            .takes_value(true),
+        Arg::with_name("records")
+            .long("records")
+            .value_name("FRAMING")
+            .help("Read samples as binary records with a length prefix instead of lines and write them with the same framing: u32be or u32le (4 byte length) or varint (protobuf delimited messages), line transformations can't be used with it")
+            .possible_values(Framing::NAMES)
+            .conflicts_with_all(&[
+                "input",
+                "format",
+                "mmap",
+                "sink",
+                "sink-failover",
+                "template",
+                "script",
+                "rewrite-rules",
+                "enrich",
+                "correlate",
+                "inject-ansi",
+                "strip-ansi",
+                "split-long-lines",
+                "duplicate-rate",
+                "reorder",
+                "heartbeat",
+                "marker-interval",
+                "write-batch",
+                "manifest",
+                "rotate-hold-open",
+                "route",
+                "throttle",
+                "since",
+                "until",
+                "line-ending",
+                "trailing-newline",
+                "partial-line-flush",
+            ])
+            .takes_value(true),
+        Arg::with_name("mmap")
+            .long("mmap")
+            .help("Memory map sample files instead of reading them, avoids read syscalls and copies for big samples"),
+        Arg::with_name("read-buffer")
+            .long("read-buffer")
+            .value_name("SIZE")
+            .help("Buffer size of sample file readers, like 64KB or 4MB, bigger reads fewer times for huge lines [default: 8KB]")
+            .validator(parse::is_buffer_size)
+            .conflicts_with("mmap")
+            .takes_value(true),
+        Arg::with_name("write-buffer")
+            .long("write-buffer")
+            .value_name("SIZE")
+            .help("Buffer size of output file writers, like 64KB or 4MB, lines longer than it are written with more than one syscall [default: 1KB, 8KB with --buffered-writes]")
+            .validator(parse::is_buffer_size)
+            .takes_value(true),
+        Arg::with_name("buffered-writes")
+            .long("buffered-writes")
+            .value_name("POLICY")
+            .help("Write outputs a full buffer at a time instead of flushing at each line ending, POLICY is full (only when the buffer fills, the output changes or loggen stops) or a duration like 100ms to also flush that long after the first unflushed write")
+            .validator(out_writer::is_flush_policy)
+            .takes_value(true),
+        Arg::with_name("no-zero-copy")
+            .long("no-zero-copy")
+            .help("Write line by line even when whole passes could be copied file to file, see README"),
+        Arg::with_name("warn-fast-wrap")
+            .long("warn-fast-wrap")
+            .value_name("DURATION")
+            .help("Warn once per file when a pass over its sample takes less than DURATION, like 10s, the passes are counted as fast_wraps in the stats, not checked without pacing")
+            .validator(parse::is_duration)
+            .takes_value(true),
+        Arg::with_name("message-column")
+            .long("message-column")
+            .value_name("NAME")
+            .help("Column with the line for sqlite: and parquet: inputs")
+            .default_value("message")
+            .takes_value(true),
+        Arg::with_name("out-base-dir")
+            .short("o")
+            .long("out-base-dir")
+            .value_name("FILE")
+            .required(true)
+            .help("Output base directory")
+            .takes_value(true),
+        Arg::with_name("interval")
+            .short("t")
+            .long("interval")
+            .value_name("MS")
+            .help("Time in milliseconds between reads of each file, files are read on their own schedules")
+            .validator(is_positive_number)
+            .default_value("250")
+            .takes_value(true),
+        Arg::with_name("wrap-strategy")
+            .short("w")
+            .long("wrap-strategy")
+            .value_name("STRATEGY")
+            .help("What to do when sample log reaches the end")
+            .default_value("append")
+            .possible_values(WrapStrategy::NAMES)
+            .required(true),
+        Arg::with_name("line-ending")
+            .long("line-ending")
+            .value_name("ENDING")
+            .help("Line ending of written lines: lf, crlf, preserve (what the sample has) or mixed:PCT (PCT percent crlf, the rest lf)")
+            .validator(line_ending::is_line_ending)
+            .default_value("preserve")
+            .takes_value(true),
+        Arg::with_name("trailing-newline")
+            .long("trailing-newline")
+            .value_name("MODE")
+            .help("Whether written lines end with a newline: always adds it to lines without one, preserve writes them as the sample has them, never writes each line's newline just before the next line so outputs always end with a partial line")
+            .possible_values(TrailingNewline::NAMES)
+            .default_value("preserve")
+            .takes_value(true),
+        Arg::with_name("partial-line-flush")
+            .long("partial-line-flush")
+            .value_name("MS")
+            .help("Write each line in two chunks, the newline MS milliseconds after the rest of the line (or before the next line if that comes first)")
+            .validator(is_non_zero_number)
+            .conflicts_with("write-batch")
+            .takes_value(true),
+        Arg::with_name("parallelism")
+            .short("p")
+            .long("parallelism")
+            .value_name("COUNT")
+            .help("Number of parallel generators")
+            .validator(is_positive_number)
+            .default_value("2")
+            .takes_value(true),
+        Arg::with_name("assign")
+            .long("assign")
+            .value_name("STRATEGY")
+            .help("How to distribute files to generators, by-dir keeps files of a directory together, by-size balances total bytes")
+            .default_value("round-robin")
+            .possible_values(Assign::NAMES),
+        Arg::with_name("no-work-stealing")
+            .long("no-work-stealing")
+            .help("Keep files on the worker they were assigned to instead of letting idle workers take the files busy ones fell behind on, always the case with --assign by-dir"),
+        Arg::with_name("scan-threads")
+            .long("scan-threads")
+            .value_name("N")
+            .help("Threads walking the input directory at startup, files start generating as they're found")
+            .default_value("1")
+            .validator(is_non_zero_number),
+        Arg::with_name("out-partition")
+            .long("out-partition")
+            .value_name("PARTITION")
+            .help("Write outputs into date partitioned subdirectories (UTC)")
+            .default_value("none")
+            .possible_values(OutPartition::NAMES),
+        Arg::with_name("out-file-policy")
+            .long("out-file-policy")
+            .value_name("POLICY")
+            .help("What to do with the output file when its sample wraps: reuse it as --wrap-strategy says or close it and write a new one with the next number (name.1.log, name.2.log...), for pipelines that treat files as immutable batches")
+            .default_value("reuse")
+            .possible_values(OutFilePolicy::NAMES),
+        Arg::with_name("out-format")
+            .long("out-format")
+            .value_name("FORMAT")
+            .help("Write outputs as lines (the default) or as systemd journal export entries with the line as MESSAGE, for tools that import journal exports")
+            .possible_values(journal::OUT_FORMATS)
+            .conflicts_with_all(&["sink", "sink-failover", "route", "records", "write-batch", "rotate-lines", "rotate-hold-open", "manifest", "line-ending", "trailing-newline", "partial-line-flush"])
+            .takes_value(true),
+        Arg::with_name("duplicate-rate")
+            .long("duplicate-rate")
+            .value_name("PCT")
+            .help("Percentage of written lines to emit again later, for example 0.5%")
+            .validator(parse::is_percentage)
+            .default_value("0")
+            .takes_value(true),
+        Arg::with_name("duplicate-delay")
+            .long("duplicate-delay")
+            .value_name("MS")
+            .help("Time in milliseconds to wait before emitting a duplicated line")
+            .validator(is_positive_number)
+            .default_value("1000")
+            .takes_value(true),
+        Arg::with_name("reorder")
+            .long("reorder")
+            .value_name("OPTIONS")
+            .help("Emit some lines out of order within a sliding window, for example window=50,rate=2%")
+            .validator(reorder::is_reorder_config)
+            .takes_value(true),
+        Arg::with_name("rotate-name")
+            .long("rotate-name")
+            .value_name("TEMPLATE")
+            .help("Name for rotated files, placeholders: {stem} {ext} {name} {n} {date} {date:FORMAT}")
+            .validator(rotate::is_rotate_name)
+            .default_value("{stem}.rotated")
+            .takes_value(true),
+        Arg::with_name("rotate-method")
+            .long("rotate-method")
+            .value_name("METHOD")
+            .help("How files are rotated, link and copytruncate never leave the output path missing")
+            .possible_values(RotateMethod::NAMES)
+            .default_value("rename")
+            .takes_value(true),
+        Arg::with_name("rotate-lines")
+            .long("rotate-lines")
+            .value_name("COUNT")
+            .help("Rotate each output after exactly COUNT lines were written to it, lines already in it count, independently of --wrap-strategy")
+            .validator(is_non_zero_number)
+            .conflicts_with_all(&["sink", "sink-failover", "records"])
+            .takes_value(true),
+        Arg::with_name("rotate-hold-open")
+            .long("rotate-hold-open")
+            .value_name("MS")
+            .help("With --wrap-strategy rotate keep writing to the rotated file for this many milliseconds before switching to the new one")
+            .validator(is_non_zero_number)
+            .takes_value(true),
+        Arg::with_name("rotate-hold-lines")
+            .long("rotate-hold-lines")
+            .value_name("COUNT")
+            .help("Lines to write to the rotated file at most while it's held open [default: all in --rotate-hold-open]")
+            .validator(is_non_zero_number)
+            .requires("rotate-hold-open")
+            .takes_value(true),
+        Arg::with_name("link-latest")
+            .long("link-latest")
+            .value_name("NAME")
+            .help("Keep a symlink NAME next to each output pointing to the file written now, placeholders: {stem} {ext} {name}")
+            .validator(link::is_link_latest)
+            .conflicts_with_all(&["sink", "sink-failover"])
+            .takes_value(true),
+        Arg::with_name("template")
+            .long("template")
+            .help("Render {{...}} placeholders in sample lines, see README for available functions"),
+        Arg::with_name("timestamp-tz")
+            .long("timestamp-tz")
+            .value_name("ZONE")
+            .help("Time zone of generated timestamps ({{timestamp}}, heartbeats and synthetic formats): utc, local, an offset like +05:30 or a tz database zone like Europe/Berlin, whose offset changes with daylight saving time")
+            .validator(timestamp::is_timestamp_tz)
+            .default_value("utc")
+            .takes_value(true),
+        Arg::with_name("timestamp-locale")
+            .long("timestamp-locale")
+            .value_name("LOCALE")
+            .help("Language of month and day names (%b, %B, %a, %A) in generated timestamps: en, de, es, fr, it, nl or pt, also as de_DE.UTF-8")
+            .validator(timestamp::is_timestamp_locale)
+            .default_value("en")
+            .takes_value(true),
+        Arg::with_name("timestamp-fuzz")
+            .long("timestamp-fuzz")
+            .value_name("KIND=PCT,...")
+            .help("Replace some generated timestamps with extreme ones: past (1900 to 1970), future (2038 to 9999), epoch (1970-01-01T00:00:00Z) or leap (second 60 where there's no leap second), for example past=1%,epoch=0.1%,leap=0.5%")
+            .validator(timestamp::is_timestamp_fuzz)
+            .takes_value(true),
+        Arg::with_name("scenario")
+            .long("scenario")
+            .value_name("FILE")
+            .help("Run the phases in FILE in order, one per line like 'spike 30s at 50000lps' or 'silence 5m', and exit at the end")
+            .validator(scenario::is_scenario)
+            .takes_value(true),
+        Arg::with_name("sink")
+            .long("sink")
+            .value_name("URL")
+            .help("Send lines to a sink instead of files in the output directory: loki://host:3100 (lokis:// for https), grpc://host:port, redis://host/stream, nats://host:4222?subject=logs, amqp://host/vhost?exchange=logs, clickhouse://host:8123/db.table, parquet:dir?columns=a,b, syslog://host:514?app_name={stem} (syslog+tls:// for RFC 5425), pubsub://project/topic, gcp-logging://project, cloudwatch://group/stream, kinesis://stream, eventhubs://namespace/hub, tcp://host:port or file:dir, several comma separated urls make a pool, see --balance")
+            .validator(sink::is_sink_url)
+            .takes_value(true),
+        Arg::with_name("sink-failover")
+            .long("sink-failover")
+            .value_name("PRIMARY,SECONDARY")
+            .help("Send lines to the PRIMARY sink and to SECONDARY while the primary fails, for example loki://host:3100,file:fallback-dir, fails back when the primary answers again")
+            .validator(sink::is_failover)
+            .conflicts_with("sink")
+            .takes_value(true),
+        Arg::with_name("balance")
+            .long("balance")
+            .value_name("STRATEGY")
+            .help("How to spread lines over the endpoints of a --sink with several urls: round-robin sends each line to the next one, hash-by-file all lines of a file to the same one")
+            .possible_values(Balance::NAMES)
+            .default_value("round-robin")
+            .takes_value(true),
+        Arg::with_name("connections")
+            .long("connections")
+            .value_name("COUNT")
+            .help("Connections each generated file opens to a tcp:// sink, lines go to each in turn")
+            .validator(is_non_zero_number)
+            .default_value("1")
+            .takes_value(true),
+        Arg::with_name("reconnect-every")
+            .long("reconnect-every")
+            .value_name("LIMITS")
+            .help("Close tcp:// sink connections and open new ones, from new source ports, after lines=COUNT lines or secs=SECONDS seconds, or both like lines=1000,secs=30")
+            .validator(sink::is_reconnect)
+            .takes_value(true),
+        Arg::with_name("tls-rotate-certs")
+            .long("tls-rotate-certs")
+            .value_name("OPTIONS")
+            .help("Open new syslog+tls:// sink connections with the next client certificate of the url every DURATION, like every=10m, the files are read again so replaced ones are picked up")
+            .validator(sink::is_rotate)
+            .takes_value(true),
+        Arg::with_name("block-on-full")
+            .long("block-on-full")
+            .value_name("BEHAVIOR")
+            .help("What writes to outputs that are named pipes and to tcp:// sinks do while the consumer isn't reading: block waits for it, drop-new and drop-old queue up to 1024 lines and then drop the newest or the oldest, dropped lines and time blocked are in /status")
+            .possible_values(WhenFull::NAMES)
+            .takes_value(true),
+        Arg::with_name("sink-failover-errors")
+            .long("sink-failover-errors")
+            .value_name("COUNT")
+            .help("Failed sends in a row of the primary sink before failing over, or of a --sink endpoint before it's taken out of the pool")
+            .validator(is_non_zero_number)
+            .default_value("5")
+            .takes_value(true),
+        Arg::with_name("sink-failover-retry")
+            .long("sink-failover-retry")
+            .value_name("DURATION")
+            .help("How often the primary sink is checked while failed over, and --sink endpoints taken out of the pool")
+            .validator(parse::is_duration)
+            .default_value("10s")
+            .takes_value(true),
+        Arg::with_name("sink-label")
+            .long("sink-label")
+            .value_name("NAME=VALUE")
+            .help("Static label added to every line sent to the sink, can be repeated")
+            .validator(sink::is_label)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("sink-concurrency")
+            .long("sink-concurrency")
+            .value_name("COUNT")
+            .help("Requests to send to the sink at the same time")
+            .validator(is_non_zero_number)
+            .default_value("1")
+            .takes_value(true),
+        Arg::with_name("sink-inflight")
+            .long("sink-inflight")
+            .value_name("COUNT")
+            .help("Batches waiting or being sent to the sink before generators wait [default: sink concurrency]")
+            .validator(is_non_zero_number)
+            .takes_value(true),
+        Arg::with_name("write-batch")
+            .long("write-batch")
+            .value_name("LIMITS")
+            .help("Buffer lines and write them at once when any limit is reached, for example lines=100,bytes=64KB,time=50 (ms)")
+            .validator(batch::is_write_batch)
+            .takes_value(true),
+        Arg::with_name("slow-write")
+            .long("slow-write")
+            .value_name("OPTIONS")
+            .help("Delay writes to emulate a slow disk, for example delay=5ms,jitter=2ms,every=100 pauses one write in 100 for 3 to 7ms after writing half of it")
+            .validator(slow_write::is_slow_write)
+            .takes_value(true),
+        Arg::with_name("resume-output")
+            .long("resume-output")
+            .help("Continue outputs left by previous runs: rotation and file numbers, template counters and script line numbers go on from a .loggen-state file in the output directory and a sample's first line isn't written again when the output already starts with it")
+            .conflicts_with_all(&["sink", "sink-failover"]),
+        Arg::with_name("manifest")
+            .long("manifest")
+            .help("Keep a <file>.manifest next to each output file with its line count, byte count, checksum and rotations, check them with verify-output"),
+        Arg::with_name("touch-policy")
+            .long("touch-policy")
+            .value_name("POLICY")
+            .help("Output file modification times: natural (set by the OS), backdate (write time minus --backdate-by) or freeze (keep the time the file had when first opened)")
+            .possible_values(TouchPolicy::NAMES)
+            .default_value("natural")
+            .takes_value(true),
+        Arg::with_name("backdate-by")
+            .long("backdate-by")
+            .value_name("DURATION")
+            .help("How far back to set modification times with --touch-policy backdate, for example 2h or 7d")
+            .validator(parse::is_duration)
+            .default_value("1d")
+            .takes_value(true),
+        Arg::with_name("out-mode")
+            .long("out-mode")
+            .value_name("MODE")
+            .help("Permissions (octal) of created output files, for example 0640")
+            .validator(perms::is_mode)
+            .takes_value(true),
+        Arg::with_name("out-owner")
+            .long("out-owner")
+            .value_name("USER:GROUP")
+            .help("Owner of created output files: user:group, user or :group, changing the user needs root or CAP_CHOWN")
+            .validator(perms::is_owner)
+            .takes_value(true),
+        Arg::with_name("split-long-lines")
+            .long("split-long-lines")
+            .value_name("OPTIONS")
+            .help("Split lines longer than max into several lines ending with marker: max=SIZE[,strategy=hard|word][,marker=TEXT], marker defaults to \\")
+            .validator(split::is_split_config)
+            .takes_value(true),
+        Arg::with_name("enrich")
+            .long("enrich")
+            .value_name("FIELDS")
+            .help("Add generator metadata to every line: host, pid, file (the sample path) and worker, comma separated, appended as key=value or added at the end of JSON objects")
+            .validator(enrich::is_enrich)
+            .takes_value(true),
+        Arg::with_name("tag-lines")
+            .long("tag-lines")
+            .value_name("TEMPLATE")
+            .help("Add where each line comes from to it, for example '# src={path}:{lineno}', placeholders: {path} (the sample path), {file} (relative to the input directory) and {lineno} (from 1)")
+            .validator(tag::is_line_tag)
+            .conflicts_with("records")
+            .takes_value(true),
+        Arg::with_name("tag-position")
+            .long("tag-position")
+            .value_name("POSITION")
+            .help("Put the --tag-lines tag after the line or before it, separated by a space")
+            .possible_values(TagPosition::NAMES)
+            .default_value("append")
+            .takes_value(true),
+        Arg::with_name("group-rate")
+            .long("group-rate")
+            .value_name("GLOB=RATE")
+            .help("Lines per second shared by the files matching GLOB (relative to the input directory, * within a directory, ** across them), for example 'api/**=1000lps', files read at most once per --interval, a file is in the first group it matches, can be repeated")
+            .validator(group::is_group_rate)
+            .multiple(true)
+            .takes_value(true),
+        Arg::with_name("correlate")
+            .long("correlate")
+            .value_name("GROUP")
+            .help("Add the same trace id to lines written close in time to the files matching GLOB: files=GLOB[:window=DURATION][:field=NAME], a new id every window (default 200ms), field defaults to trace_id, can be repeated")
+            .validator(correlate::is_correlation)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("inject-ansi")
+            .long("inject-ansi")
+            .value_name("OPTIONS")
+            .help("Wrap some lines or a word of them in ANSI color codes and add tabs and carriage returns to them: rate=PCT[,colors=false][,controls=false][,nul=true], nul=true also adds NUL bytes")
+            .validator(ansi::is_inject_ansi)
+            .takes_value(true),
+        Arg::with_name("strip-ansi")
+            .long("strip-ansi")
+            .help("Remove ANSI escape sequences (colors, cursor moves, titles) from sample lines as they're read"),
+        Arg::with_name("route")
+            .long("route")
+            .value_name("RULE")
+            .help("Move lines matching FIELD=VALUE (JSON field or key=value pair), /REGEX/ or * to another output, for example 'level=ERROR -> errors/{stem}.log', '+>' copies them instead, a percentage before the arrow routes only that share, rules are checked in order, can be repeated")
+            .validator(route::is_route)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("throttle")
+            .long("throttle")
+            .value_name("RULE")
+            .help("Keep a share of the lines matching FIELD=VALUE, /REGEX/ or * and drop the rest, like 'level=DEBUG:10%', with ':delay=DURATION' the rest is written that late instead, rules are checked in order, can be repeated")
+            .validator(throttle::is_throttle)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("since")
+            .long("since")
+            .value_name("TIME")
+            .help("Only replay sample lines with a timestamp at or after TIME, for example 2024-03-01T10:00:00 (UTC if it has no offset), lines without one go with the line before")
+            .validator(window::is_time)
+            .takes_value(true),
+        Arg::with_name("until")
+            .long("until")
+            .value_name("TIME")
+            .help("Only replay sample lines with a timestamp up to TIME, the rest of a sample is skipped after the first line past it")
+            .validator(window::is_time)
+            .takes_value(true),
+        Arg::with_name("head")
+            .long("head")
+            .value_name("LINES")
+            .help("Only replay the first LINES lines of each sample file each pass")
+            .validator(slice::is_line_count)
+            .conflicts_with_all(&["tail", "lines-range"])
+            .takes_value(true),
+        Arg::with_name("tail")
+            .long("tail")
+            .value_name("LINES")
+            .help("Only replay the last LINES lines of each sample file each pass, samples are counted once when first read")
+            .validator(slice::is_line_count)
+            .conflicts_with("lines-range")
+            .takes_value(true),
+        Arg::with_name("lines-range")
+            .long("lines-range")
+            .value_name("FIRST..LAST")
+            .help("Only replay lines FIRST to LAST of each sample file each pass, counted from 1 and both included, 1000.. goes to the end")
+            .validator(slice::is_lines_range)
+            .takes_value(true),
+        Arg::with_name("start-line")
+            .long("start-line")
+            .value_name("LINE")
+            .help("Start the first pass of each sample file at line LINE, counted from 1, later passes start from the top, lines before it aren't read, see --index-dir")
+            .validator(line_index::is_start_line)
+            .conflicts_with_all(&["start-pct", "head", "tail", "lines-range", "records", "mmap"])
+            .takes_value(true),
+        Arg::with_name("start-pct")
+            .long("start-pct")
+            .value_name("PCT")
+            .help("Start the first pass of each sample file PCT percent of its lines in, like 50, later passes start from the top")
+            .validator(parse::is_percentage)
+            .conflicts_with_all(&["head", "tail", "lines-range", "records", "mmap"])
+            .takes_value(true),
+        Arg::with_name("replay-order")
+            .long("replay-order")
+            .value_name("ORDER")
+            .help("Order sample lines are replayed in: sequential, or random to pick any line of the sample each time, a pass is as many lines as the sample has [default: sequential]")
+            .possible_values(ReplayOrder::NAMES)
+            .conflicts_with_all(&["head", "tail", "lines-range", "records", "mmap"])
+            .takes_value(true),
+        Arg::with_name("index-dir")
+            .long("index-dir")
+            .value_name("DIR")
+            .help("Where the line offset indexes of samples used by --start-line, --start-pct and --replay-order random are cached, an index is built again when its sample changes [default: ~/.cache/loggen/index]")
+            .takes_value(true),
+        Arg::with_name("heartbeat")
+            .long("heartbeat")
+            .value_names(&["INTERVAL", "LINE"])
+            .help("Write LINE (a template) to outputs with no lines for INTERVAL, for example --heartbeat 30s 'HEARTBEAT {{timestamp}}'")
+            // multiple so it doesn't override itself, the last one given is used
+            .multiple(true)
+            .number_of_values(2)
+            .takes_value(true),
+        Arg::with_name("profile-io")
+            .long("profile-io")
+            .help("Count write syscalls, bytes per write, flushes and time blocked writing per output and print them at exit"),
+        Arg::with_name("resource-report")
+            .long("resource-report")
+            .help("Print loggen's own cpu time, resident memory, open file descriptors and context switches per worker thread at exit"),
+        Arg::with_name("resource-report-every")
+            .long("resource-report-every")
+            .value_name("DURATION")
+            .help("Also print the --resource-report every DURATION while running, like 30s")
+            .validator(parse::is_duration)
+            .requires("resource-report")
+            .takes_value(true),
+        Arg::with_name("script")
+            .long("script")
+            .value_name("FILE")
+            .help("Rhai script with a transform(line, meta) function that returns the line to write, an array of lines or () to drop it, loaded again when it changes or on SIGUSR2")
+            .takes_value(true),
+        Arg::with_name("rewrite-rules")
+            .long("rewrite-rules")
+            .value_name("FILE")
+            .help("Rewrite lines with the rules in FILE, one per line like '/password=\\S+/ -> password=***', applied in order before --script, loaded again when it changes or on SIGUSR2")
+            .takes_value(true),
+        Arg::with_name("marker-interval")
+            .long("marker-interval")
+            .value_name("DURATION")
+            .help("Write a marker line with its send time to every output this often, measure end to end latency with await-marker")
+            .validator(parse::is_duration)
+            .takes_value(true),
+        Arg::with_name("cardinality")
+            .long("cardinality")
+            .value_name("FIELD")
+            .help("Template field {{NAME}} with a controlled number of distinct values: field=NAME:unique=N[:growth=PER_SEC][:prefix=TEXT], can be repeated")
+            .validator(cardinality::is_cardinality)
+            .requires("template")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("max-open-files")
+            .long("max-open-files")
+            .value_name("COUNT")
+            .help("Max files to keep open, least recently used files are closed and reopened when needed [default: open files limit minus 64]")
+            .validator(is_positive_number)
+            .takes_value(true),
+        Arg::with_name("status-addr")
+            .long("status-addr")
+            .value_name("ADDR")
+            .help("Serve /healthz, /readyz and /status (JSON) over HTTP on this address, for example 127.0.0.1:8080")
+            .takes_value(true),
+        Arg::with_name("control-socket")
+            .long("control-socket")
+            .value_name("PATH")
+            .help("Accept commands on this unix socket while running, parallelism COUNT changes the number of workers, see README")
+            .takes_value(true),
+        Arg::with_name("api-addr")
+            .long("api-addr")
+            .value_name("ADDR")
+            .help("Serve a management API over HTTP on this address: /api/stats, /api/files, /api/rate, /api/pause and /api/parallelism, needs --api-token, see README")
+            .requires("api-token")
+            .takes_value(true),
+        Arg::with_name("api-token")
+            .long("api-token")
+            .value_name("TOKEN")
+            .help("Bearer token clients of --api-addr must send, use ${VAR} or ${file:PATH} in a config file to keep it off the command line")
+            .requires("api-addr")
+            .takes_value(true),
+        Arg::with_name("assert")
+            .long("assert")
+            .value_name("CHECK")
+            .help("Check the totals when a bounded run ends and exit with code 4 if it fails: [GLOB] METRIC OP VALUE like 'out/**/*.log lines>=1000' or 'wraps==0', METRIC is files, lines, bytes, wraps, fast_wraps, errors, throttled or dropped, can be repeated")
+            .validator(assertion::is_assertion)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("error-budget")
+            .long("error-budget")
+            .value_name("LIMITS")
+            .help("Stop and exit with code 3 once errors go over these counts: writes=N (reading samples or writing lines) and network=N (failed sink sends), for example writes=100,network=500")
+            .validator(budget::is_error_budget)
+            .takes_value(true),
+        Arg::with_name("statsd")
+            .long("statsd")
+            .value_name("ADDR")
+            .help("Push lines, bytes, errors, wraps and fast wraps counters per file to this statsd (DogStatsD) server, for example 127.0.0.1:8125")
+            .takes_value(true),
+        Arg::with_name("state-dir")
+            .long("state-dir")
+            .value_name("DIR")
+            .help("Dump the state of the generators (offsets, rates, how far behind workers are, last errors) to a ring of JSON files in DIR, see README")
+            .takes_value(true),
+        Arg::with_name("state-interval")
+            .long("state-interval")
+            .value_name("DURATION")
+            .help("How often to dump state to --state-dir")
+            .validator(parse::is_duration)
+            .default_value("10s")
+            .takes_value(true),
+        Arg::with_name("state-keep")
+            .long("state-keep")
+            .value_name("COUNT")
+            .help("State files to keep in --state-dir, the oldest is replaced by the next dump")
+            .validator(is_non_zero_number)
+            .default_value("30")
+            .takes_value(true),
+        Arg::with_name("statsd-interval")
+            .long("statsd-interval")
+            .value_name("DURATION")
+            .help("How often to push counters to --statsd")
+            .validator(parse::is_duration)
+            .default_value("10s")
+            .takes_value(true),
+        Arg::with_name("statsd-prefix")
+            .long("statsd-prefix")
+            .value_name("PREFIX")
+            .help("Prefix of the metric names pushed to --statsd")
+            .default_value("loggen")
+            .takes_value(true),
+    ]
+}
+
+fn bench_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("duration")
+        .short("d")
+        .long("duration")
+        .value_name("DURATION")
+        .help("How long to run, for example 500ms, 30s or 5m")
+        .validator(parse::is_duration)
+        .default_value("10s")
+        .takes_value(true)]
+}
+
+/// options a --config file can set for `subcommand`, None for the default command
+fn config_options(subcommand: Option<&str>) -> Vec<Arg<'static, 'static>> {
+    let mut options = gen_args();
+    // shell takes only the generation options, like the default command
+    if subcommand == Some("bench") {
+        options.extend(bench_args());
+    }
+    options
+}
+
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_stop_signal(signal: libc::c_int) {
+    SIGNALLED.store(true, Ordering::Relaxed);
+    // a second signal kills the process as usual
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// SIGUSR1 signals received so far
+static ROTATE_SIGNALS: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn on_rotate_signal(_signal: libc::c_int) {
+    ROTATE_SIGNALS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// count SIGUSR1 signals in `requests` so workers rotate every output
+fn rotate_on_signal(requests: Arc<AtomicU64>) {
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            on_rotate_signal as *const () as libc::sighandler_t,
+        );
+    }
+
+    thread::spawn(move || loop {
+        requests.store(ROTATE_SIGNALS.load(Ordering::Relaxed), Ordering::Relaxed);
+        sleep(Duration::from_millis(100));
+    });
+}
+
+/// set `stop` on SIGINT or SIGTERM so outputs are flushed and closed before exiting
+fn stop_on_signal(stop: Arc<AtomicBool>) {
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_stop_signal as *const () as libc::sighandler_t,
+        );
+        libc::signal(
+            libc::SIGTERM,
+            on_stop_signal as *const () as libc::sighandler_t,
+        );
+    }
+
+    thread::spawn(move || {
+        while !SIGNALLED.load(Ordering::Relaxed) {
+            sleep(Duration::from_millis(100));
+        }
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+/// run and wait for the generators to finish, they only finish when `stop` is set
+fn run_and_wait(
+    matches: &ArgMatches,
+    run_options: RunOptions,
+    mut options: GenOptions,
+    shared: &Shared,
+) -> bool {
+    let input = match (matches.value_of("input"), matches.value_of("format")) {
+        (Some(url), _) => Input::Url(url),
+        (_, Some(format)) => Input::Format(format),
+        _ => Input::Dir(matches.value_of("in-base-dir").unwrap()),
+    };
+    let out_dir = matches.value_of("out-base-dir").unwrap();
+
+    if let Some(status_addr) = matches.value_of("status-addr") {
+        if let Err(error) = status::serve(status_addr, shared.stats.clone()) {
+            eprintln!(
+                "Error starting status endpoint on {}: {}",
+                status_addr, error
+            );
+            return false;
+        }
+    }
+
+    if let Some(path) = matches.value_of("control-socket") {
+        if let Err(error) = control::serve(Path::new(path), shared.rebalance.clone()) {
+            eprintln!("Error starting control socket on {}: {}", path, error);
+            return false;
+        }
+    }
+
+    let statsd = match matches.value_of("statsd") {
+        Some(addr) => {
+            let interval =
+                parse::parse_duration(matches.value_of("statsd-interval").unwrap()).unwrap();
+            let prefix = matches.value_of("statsd-prefix").unwrap();
+            match statsd::start(addr, prefix, interval, shared.stats.clone()) {
+                Ok(pusher) => Some(pusher),
+                Err(error) => {
+                    eprintln!("Error starting statsd pushes to {}: {}", addr, error);
+                    return false;
+                }
+            }
+        }
+        None => None,
+    };
+
+    // reloaded by a watcher started with the generators
+    let mut watched: Vec<Arc<dyn Watched>> = vec![];
+    if let Some(path) = matches.value_of("script") {
+        match Reloadable::load(Path::new(path), "script", Script::load) {
+            Ok(script) => {
+                watched.push(script.clone());
+                options.script = Some(script);
+            }
+            Err(error) => {
+                eprintln!("Error loading script {}: {}", path, error);
+                return false;
+            }
+        }
+    }
+    if let Some(path) = matches.value_of("rewrite-rules") {
+        match Reloadable::load(Path::new(path), "rewrite rules", Rewriter::load) {
+            Ok(rewrite) => {
+                watched.push(rewrite.clone());
+                options.rewrite = Some(rewrite);
+            }
+            Err(error) => {
+                eprintln!("Error loading rewrite rules {}: {}", path, error);
+                return false;
+            }
+        }
+    }
+
+    if let Some(url) = matches
+        .value_of("sink")
+        .or_else(|| matches.value_of("sink-failover"))
+    {
+        let concurrency = matches
+            .value_of("sink-concurrency")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        let errors = matches
+            .value_of("sink-failover-errors")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+        let retry =
+            parse::parse_duration(matches.value_of("sink-failover-retry").unwrap()).unwrap();
+        let sink_options = SinkOptions {
+            labels: matches
+                .values_of("sink-label")
+                .map(|values| {
+                    values
+                        .map(|v| {
+                            let pos = v.find('=').unwrap();
+                            (v[..pos].to_string(), v[pos + 1..].to_string())
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            concurrency,
+            inflight: matches
+                .value_of("sink-inflight")
+                .map(|v| v.parse::<usize>().unwrap())
+                .unwrap_or(concurrency),
+            pool: PoolOptions {
+                balance: Balance::parse(matches.value_of("balance").unwrap()),
+                errors,
+                retry,
+            },
+            block_on_full: options.block_on_full,
+            connections: matches.value_of("connections").unwrap().parse().unwrap(),
+            reconnect: matches
+                .value_of("reconnect-every")
+                .map(|v| Reconnect::parse(v).unwrap())
+                .unwrap_or_default(),
+            tls_rotate: matches
+                .value_of("tls-rotate-certs")
+                .map(|v| sink::parse_rotate(v).unwrap()),
+        };
+
+        let opened = match matches.value_of("sink-failover") {
+            Some(urls) => {
+                let (primary, secondary) = sink::split_failover(urls).unwrap();
+                let failover = FailoverOptions { errors, retry };
+                sink::open_failover(primary, secondary, &sink_options, failover)
+            }
+            None => sink::open(url, &sink_options),
+        };
+        match opened {
+            Ok(sink) => options.sink = Some(sink),
+            Err(error) => {
+                eprintln!("Error opening sink {}: {}", url, error);
+                return false;
+            }
+        }
+    }
+    let sink = options.sink.clone();
+
+    if matches.is_present("resume-output") {
+        match ResumeState::load(Path::new(out_dir)) {
+            Ok(resume) => {
+                if let Some(template) = &mut options.template {
+                    template.counters = Counters::starting_at(resume.counters());
+                }
+                options.resume = Some(Arc::new(resume));
+            }
+            Err(error) => {
+                eprintln!("Error loading the state of {}: {}", out_dir, error);
+                return false;
+            }
+        }
+    }
+    let resume = options.resume.clone();
+    let counters = options
+        .template
+        .as_ref()
+        .map(|template| template.counters.clone());
+
+    if matches.is_present("scenario") {
+        // hold generation until the first phase starts
+        shared.pacer.set_rate(Some(0.0));
+    }
+
+    if sink.is_none() {
+        if let Err(error) = options.out_perms.check(Path::new(out_dir)) {
+            eprintln!("Error: {}", error);
+            return false;
+        }
+    }
+
+    if let Some(addr) = matches.value_of("api-addr") {
+        let api = Api {
+            token: matches.value_of("api-token").unwrap().to_string(),
+            stats: shared.stats.clone(),
+            pacer: shared.pacer.clone(),
+            rebalance: shared.rebalance.clone(),
+            sink: sink.clone(),
+        };
+        if let Err(error) = api::serve(addr, api) {
+            eprintln!("Error starting management api on {}: {}", addr, error);
+            return false;
+        }
+    }
+
+    let state_writer = match matches.value_of("state-dir") {
+        Some(dir) => {
+            let config = StateDumps {
+                dir: PathBuf::from(dir),
+                interval: parse::parse_duration(matches.value_of("state-interval").unwrap())
+                    .unwrap(),
+                keep: matches.value_of("state-keep").unwrap().parse().unwrap(),
+            };
+            match postmortem::start(
+                config,
+                shared.stats.clone(),
+                sink.clone(),
+                shared.pacer.clone(),
+            ) {
+                Ok(state_writer) => Some(state_writer),
+                Err(error) => {
+                    eprintln!("Error writing state to {}: {}", dir, error);
+                    return false;
+                }
+            }
+        }
+        None => None,
+    };
+
+    if let Some(budget) = matches.value_of("error-budget") {
+        budget::watch(
+            ErrorBudget::parse(budget).unwrap(),
+            shared.stats.clone(),
+            sink.clone(),
+            shared.stop.clone(),
+            shared.budget_exceeded.clone(),
+        );
+    }
+
+    let assertions: Vec<Assertion> = matches
+        .values_of("assert")
+        .map(|values| values.map(|v| Assertion::parse(v).unwrap()).collect())
+        .unwrap_or_default();
+
+    stop_on_signal(shared.stop.clone());
+    rotate_on_signal(shared.rotate_requests.clone());
+    if !watched.is_empty() {
+        reload::watch(watched, shared.stop.clone());
+    }
+    let message_column = matches.value_of("message-column").unwrap();
+    let profile_io = options.profile_io;
+    let started = Instant::now();
+    if let Some(interval) = matches.value_of("resource-report-every") {
+        usage::watch(
+            shared.usage.clone(),
+            parse::parse_duration(interval).unwrap(),
+            started,
+            shared.stop.clone(),
+        );
+    }
+    match run(input, message_column, out_dir, run_options, options, shared) {
+        Ok(join_handles) => {
+            if let Some(scenario_path) = matches.value_of("scenario") {
+                let phases = scenario::load(scenario_path).unwrap();
+                scenario::start(phases, shared.pacer.clone(), shared.stop.clone());
+            }
+
+            let mut gen_inputs = vec![];
+            for join_handle in join_handles {
+                match join_handle.join() {
+                    Ok(items) => gen_inputs.extend(items),
+                    Err(error) => {
+                        eprintln!("Error in thread: {:?}", error);
+                    }
+                }
+            }
+
+            if let Some(resume) = resume {
+                for item in &gen_inputs {
+                    resume.update(&item.rel_path, item.resume_state());
+                }
+                let counters = counters.map(|counters| counters.values());
+                if let Err(error) = resume.save(counters.unwrap_or_default()) {
+                    eprintln!("Error saving the state of {}: {}", out_dir, error);
+                }
+            }
+
+            if let Some(statsd) = statsd {
+                statsd.finish();
+            }
+            if let Some(state_writer) = state_writer {
+                state_writer.finish();
+            }
+
+            let has_sink = sink.is_some();
+            if let Some(sink) = sink {
+                if let Err(error) = sink.close() {
+                    eprintln!("Error closing sink: {}", error);
+                }
+            }
+
+            if profile_io {
+                profile::report(&shared.stats.files(), started.elapsed(), has_sink);
+            }
+            if matches.is_present("resource-report") {
+                shared.usage.report(started.elapsed());
+            }
+            if shared.budget_exceeded.load(Ordering::Relaxed) {
+                std::process::exit(budget::EXIT_CODE);
+            }
+            if !assertion::check_all(
+                &assertions,
+                Path::new(out_dir),
+                &shared.stats.status().files,
+            ) {
+                std::process::exit(assertion::EXIT_CODE);
+            }
+            !shared.scan_failed.load(Ordering::Relaxed)
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            false
+        }
+    }
+}
+
+/// --since and --until, exits if the window is empty
+fn window_from_matches(matches: &ArgMatches) -> Option<Window> {
+    let window = Window {
+        since: matches
+            .value_of("since")
+            .map(|v| window::parse_time(v).unwrap()),
+        until: matches
+            .value_of("until")
+            .map(|v| window::parse_time(v).unwrap()),
+    };
+    match (window.since, window.until) {
+        (None, None) => None,
+        (Some(since), Some(until)) if since > until => clap::Error::with_description(
+            &format!("--since {} is after --until {}", since, until),
+            clap::ErrorKind::InvalidValue,
+        )
+        .exit(),
+        _ => Some(window),
+    }
+}
+
+/// --start-line, --start-pct and --replay-order, None if samples are read
+/// in order from the top
+fn index_from_matches(matches: &ArgMatches) -> Option<IndexOptions> {
+    let start = if let Some(v) = matches.value_of("start-line") {
+        Some(StartPoint::Line(line_index::parse_start_line(v).unwrap()))
+    } else {
+        matches
+            .value_of("start-pct")
+            .map(|v| StartPoint::Share(parse::parse_percentage(v).unwrap()))
+    };
+    let order = matches
+        .value_of("replay-order")
+        .map_or(ReplayOrder::Sequential, |v| v.parse().unwrap());
+    if start.is_none() && order == ReplayOrder::Sequential {
+        return None;
+    }
+    Some(IndexOptions {
+        start,
+        order,
+        dir: matches
+            .value_of("index-dir")
+            .map_or_else(line_index::default_dir, PathBuf::from),
+    })
+}
+
+/// --head, --tail or --lines-range
+fn slice_from_matches(matches: &ArgMatches) -> Option<Slice> {
+    if let Some(v) = matches.value_of("head") {
+        Some(Slice::head(slice::parse_line_count(v).unwrap()))
+    } else if let Some(v) = matches.value_of("tail") {
+        Some(Slice::Tail(slice::parse_line_count(v).unwrap()))
+    } else {
+        matches
+            .value_of("lines-range")
+            .map(|v| Slice::parse_range(v).unwrap())
+    }
+}
+
+fn options_from_matches(matches: &ArgMatches) -> (RunOptions, GenOptions) {
+    let wrap_strategy = matches.value_of("wrap-strategy").unwrap();
+    let out_partition = matches.value_of("out-partition").unwrap();
+    let assign = matches.value_of("assign").unwrap();
+    let duplicate_rate_str = matches.value_of("duplicate-rate").unwrap_or("0");
+    let duplicate_rate = parse::parse_percentage(duplicate_rate_str).unwrap();
+    let duplicate_delay_str = matches.value_of("duplicate-delay").unwrap_or("0");
+    let duplicate_delay_num = duplicate_delay_str.parse::<u64>().unwrap();
+    let max_open_files = matches
+        .value_of("max-open-files")
+        .map(|v| v.parse::<usize>().unwrap())
+        .unwrap_or_else(fd_budget::default_max_open_files);
+    let cardinality = matches
+        .values_of("cardinality")
+        .map(|values| {
+            values
+                .map(|v| Cardinality::parse(v).unwrap())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let rotate_name = matches
+        .value_of("rotate-name")
+        .map(|v| RotateName::parse(v).unwrap())
+        .unwrap_or_default();
+    let reorder = matches
+        .value_of("reorder")
+        .map(|v| ReorderConfig::parse(v).unwrap());
+    let interval_str = matches.value_of("interval").unwrap_or("0");
+    // a scenario sets the rate for all files, reads aren't paced by the interval
+    let interval_num = if matches.is_present("scenario") {
+        0
+    } else {
+        interval_str.parse::<u64>().unwrap()
+    };
+    let timestamps = Timestamps {
+        tz: matches
+            .value_of("timestamp-tz")
+            .map(|v| v.parse().unwrap())
+            .unwrap_or_default(),
+        locale: matches
+            .value_of("timestamp-locale")
+            .map(|v| v.parse().unwrap())
+            .unwrap_or_default(),
+        fuzz: matches
+            .value_of("timestamp-fuzz")
+            .map(|v| TimestampFuzz::parse(v).unwrap()),
+    };
+    timestamps.tz.activate();
+    let trailing_newline = matches
+        .value_of("trailing-newline")
+        .map(|v| v.parse().unwrap())
+        .unwrap_or_default();
+    let partial_line_flush = matches
+        .value_of("partial-line-flush")
+        .map(|v| Duration::from_millis(v.parse().unwrap()));
+    if trailing_newline == TrailingNewline::Never && partial_line_flush.is_some() {
+        clap::Error::with_description(
+            "--partial-line-flush writes newlines that --trailing-newline never holds back, use one of them",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+    let parallelism_str = matches.value_of("parallelism").unwrap_or("0");
+    let parallelism_num_0 = parallelism_str.parse::<usize>().unwrap();
+    let parallelism_num = if parallelism_num_0 == 0 {
+        num_cpus::get() as usize
+    } else {
+        parallelism_num_0
+    };
+
+    let (run_options, mut options) = (
+        RunOptions {
+            interval: Duration::from_millis(interval_num),
+            parallelism: parallelism_num,
+            max_open_files,
+            assign: assign.parse().unwrap(),
+            // files of a directory stay on their worker to keep their relative order
+            work_stealing: !matches.is_present("no-work-stealing") && assign != "by-dir",
+            scan_threads: matches
+                .value_of("scan-threads")
+                .map(|v| v.parse::<usize>().unwrap())
+                .unwrap_or(1),
+            mmap: matches.is_present("mmap"),
+            read_buffer: matches
+                .value_of("read-buffer")
+                .map(|v| parse::parse_size(v).unwrap() as usize),
+            synth: synth::SynthOptions {
+                sessions: matches
+                    .value_of("sessions")
+                    .map(|v| synth::SessionConfig::parse(v).unwrap())
+                    .unwrap_or_default(),
+                fields: matches
+                    .value_of("synth-fields")
+                    .map(|v| synth::FieldSet::parse(v).unwrap())
+                    .unwrap_or_default(),
+                timestamps: timestamps.clone(),
+            },
+            start_at: matches.value_of("start-at").map(|v| StartAt {
+                at: start::parse_start_at(v).unwrap(),
+                sync: matches
+                    .value_of("sync")
+                    .map(|v| v.parse().unwrap())
+                    .unwrap_or(SyncMode::NtpTolerant),
+            }),
+            silence: matches.values_of("silence").map(|values| {
+                let v = values.collect::<Vec<_>>().join(",");
+                Silence::parse(&v).unwrap_or_else(|err| {
+                    clap::Error::with_description(
+                        &format!("Invalid value for '--silence <OPTIONS>': {}", err),
+                        clap::ErrorKind::InvalidValue,
+                    )
+                    .exit()
+                })
+            }),
+            limits: Limits {
+                max_memory: matches
+                    .value_of("max-memory")
+                    .map(|v| parse::parse_size(v).unwrap()),
+                max_cpu_pct: matches
+                    .value_of("max-cpu-pct")
+                    .map(|v| limits::parse_cpu_pct(v).unwrap()),
+            }
+            .fit_cgroup(),
+            rebalance: matches.is_present("control-socket") || matches.is_present("api-addr"),
+        },
+        GenOptions {
+            wrap_strategy: wrap_strategy.parse().unwrap(),
+            line_ending: matches.value_of("line-ending").unwrap().parse().unwrap(),
+            trailing_newline,
+            partial_line_flush,
+            out_partition: out_partition.parse().unwrap(),
+            out_file_policy: matches
+                .value_of("out-file-policy")
+                .unwrap()
+                .parse()
+                .unwrap(),
+            duplicate_rate,
+            duplicate_delay: Duration::from_millis(duplicate_delay_num),
+            reorder,
+            rotate_name,
+            rotate_method: matches.value_of("rotate-method").unwrap().parse().unwrap(),
+            link_latest: matches
+                .value_of("link-latest")
+                .map(|v| LinkLatest::parse(v).unwrap()),
+            rotate_lines: matches
+                .value_of("rotate-lines")
+                .map(|v| v.parse::<u64>().unwrap()),
+            rotate_hold: matches.value_of("rotate-hold-open").map(|v| HoldConfig {
+                duration: Duration::from_millis(v.parse::<u64>().unwrap()),
+                lines: matches
+                    .value_of("rotate-hold-lines")
+                    .map(|v| v.parse::<u64>().unwrap()),
+            }),
+            sink: None,
+            block_on_full: matches
+                .value_of("block-on-full")
+                .map(|v| WhenFull::parse(v).unwrap()),
+            script: None,
+            rewrite: None,
+            profile_io: matches.is_present("profile-io"),
+            manifest: matches.is_present("manifest"),
+            out_perms: {
+                let (uid, gid) = matches
+                    .value_of("out-owner")
+                    .map(|v| perms::parse_owner(v).unwrap())
+                    .unwrap_or((None, None));
+                OutPerms {
+                    mode: matches
+                        .value_of("out-mode")
+                        .map(|v| perms::parse_mode(v).unwrap()),
+                    uid,
+                    gid,
+                }
+            },
+            heartbeat: matches.values_of("heartbeat").map(|values| {
+                let values: Vec<&str> = values.collect();
+                let (interval, line) = (values[values.len() - 2], values[values.len() - 1]);
+                HeartbeatConfig {
+                    interval: parse::parse_duration(interval).unwrap_or_else(|err| {
+                        clap::Error::with_description(
+                            &format!("Invalid value for '--heartbeat <INTERVAL> <LINE>': {}", err),
+                            clap::ErrorKind::InvalidValue,
+                        )
+                        .exit()
+                    }),
+                    line: line.to_string(),
+                }
+            }),
+            split_long_lines: matches
+                .value_of("split-long-lines")
+                .map(|v| SplitConfig::parse(v).unwrap()),
+            enrich: matches
+                .value_of("enrich")
+                .map(|v| Enrich::parse(v).unwrap()),
+            records: matches.value_of("records").map(|v| v.parse().unwrap()),
+            inject_ansi: matches
+                .value_of("inject-ansi")
+                .map(|v| InjectAnsi::parse(v).unwrap()),
+            strip_ansi: matches.is_present("strip-ansi"),
+            correlations: matches
+                .values_of("correlate")
+                .map(|values| values.map(|v| Correlation::parse(v).unwrap()).collect())
+                .unwrap_or_default(),
+            rate_groups: matches
+                .values_of("group-rate")
+                .map(|values| values.map(|v| RateGroup::parse(v).unwrap()).collect())
+                .unwrap_or_default(),
+            routes: Arc::new(
+                matches
+                    .values_of("route")
+                    .map(|values| values.map(|v| Route::parse(v).unwrap()).collect())
+                    .unwrap_or_default(),
+            ),
+            throttles: Arc::new(
+                matches
+                    .values_of("throttle")
+                    .map(|values| values.map(|v| Throttle::parse(v).unwrap()).collect())
+                    .unwrap_or_default(),
+            ),
+            window: window_from_matches(matches),
+            slice: slice_from_matches(matches),
+            index: index_from_matches(matches),
+            resume: None,
+            tag_lines: matches.value_of("tag-lines").map(|v| {
+                LineTag::parse(
+                    v,
+                    TagPosition::parse(matches.value_of("tag-position").unwrap()),
+                )
+                .unwrap()
+            }),
+            marker_interval: matches
+                .value_of("marker-interval")
+                .map(|v| parse::parse_duration(v).unwrap()),
+            touch_policy: TouchPolicy::parse(
+                matches.value_of("touch-policy").unwrap(),
+                parse::parse_duration(matches.value_of("backdate-by").unwrap()).unwrap(),
+            )
+            .unwrap(),
+            write_batch: matches
+                .value_of("write-batch")
+                .map(|v| WriteBatch::parse(v).unwrap()),
+            slow_write: matches
+                .value_of("slow-write")
+                .map(|v| SlowWrite::parse(v).unwrap()),
+            template: if matches.is_present("template") {
+                Some(TemplateContext {
+                    fields: Arc::new(cardinality),
+                    timestamps: timestamps.clone(),
+                    ..TemplateContext::default()
+                })
+            } else {
+                None
+            },
+            timestamps,
+            zero_copy: false,
+            fast_wrap: None,
+            write_buffering: WriteBuffering {
+                capacity: matches
+                    .value_of("write-buffer")
+                    .map(|v| parse::parse_size(v).unwrap() as usize),
+                flush: matches
+                    .value_of("buffered-writes")
+                    .map(|v| FlushPolicy::parse(v).unwrap()),
+            },
+            journal: (matches.value_of("out-format") == Some("journal-export"))
+                .then(JournalOptions::start),
+        },
+    );
+    // paced or limited runs write line by line, the rest is checked per file in run
+    // unpaced runs wrap as fast as they can on purpose
+    if matches.is_present("scenario") || !run_options.interval.is_zero() {
+        options.fast_wrap = matches
+            .value_of("warn-fast-wrap")
+            .map(|v| parse::parse_duration(v).unwrap())
+            .filter(|threshold| !threshold.is_zero());
+    }
+    // a pass is copied for each line allowed, so rates that can be set while
+    // running need lines written one by one
+    options.zero_copy = !matches.is_present("scenario")
+        && !matches.is_present("api-addr")
+        && !matches.is_present("no-zero-copy")
+        && run_options.limits.max_memory.is_none()
+        && run_options.limits.max_cpu_pct.is_none();
+
+    (run_options, options)
+}
+
+fn bench(matches: &ArgMatches) {
+    let (mut run_options, mut options) = options_from_matches(matches);
+    let duration_str = matches.value_of("duration").unwrap();
+    let duration = parse::parse_duration(duration_str).unwrap();
+    // no pacing and all cores unless the user asked for a specific parallelism
+    run_options.interval = Duration::from_millis(0);
+    options.fast_wrap = None;
+    if matches.occurrences_of("parallelism") == 0 {
+        run_options.parallelism = num_cpus::get();
+    }
+
+    let shared = Shared::new();
+    let timer_stop = shared.stop.clone();
+    thread::spawn(move || {
+        sleep(duration);
+        timer_stop.store(true, Ordering::Relaxed);
+    });
+
+    let started = Instant::now();
+    if run_and_wait(matches, run_options, options, &shared) {
+        bench::report(&shared.stats.status(), started.elapsed());
+    }
+}
+
+fn shell(matches: &ArgMatches) {
+    let (mut run_options, mut options) = options_from_matches(matches);
+    // parallelism COUNT changes the worker count
+    run_options.rebalance = true;
+    // rate and rate GLOB pace line by line, see options_from_matches
+    options.zero_copy = false;
+    let shared = Shared::new();
+    shell::start(Shell {
+        stats: shared.stats.clone(),
+        pacer: shared.pacer.clone(),
+        rebalance: shared.rebalance.clone(),
+        stop: shared.stop.clone(),
+        rotate_requests: shared.rotate_requests.clone(),
+        file_rotations: shared.file_rotations.clone(),
+    });
+    run_and_wait(matches, run_options, options, &shared);
+}
+
+fn verify_output(matches: &ArgMatches) {
+    let out_dir = matches.value_of("out-base-dir").unwrap();
+    match manifest::verify(Path::new(out_dir)) {
+        Ok(0) => {}
+        Ok(_) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("Error verifying {}: {:?}", out_dir, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn take_snapshot(matches: &ArgMatches) {
+    let out_dir = matches.value_of("out-base-dir").unwrap();
+    let snapshot = match snapshot::take(Path::new(out_dir)) {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            eprintln!("Error taking snapshot of {}: {}", out_dir, err);
+            std::process::exit(1);
+        }
+    };
+
+    let content = serde_json::to_string_pretty(&snapshot).unwrap();
+    match matches.value_of("output") {
+        Some(path) => {
+            if let Err(err) = fs::write(path, content + "\n") {
+                eprintln!("Error writing {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+        None => println!("{}", content),
+    }
+}
+
+fn build_dataset(matches: &ArgMatches) {
+    let total = parse::parse_count(matches.value_of("total-lines").unwrap()).unwrap();
+    let layout_path = matches.value_of("layout").unwrap();
+    let out_dir = matches.value_of("out-base-dir").unwrap();
+    let threads = match matches.value_of("parallelism") {
+        Some(count) => count.parse().unwrap(),
+        None => num_cpus::get(),
+    };
+
+    let start = Instant::now();
+    let result = dataset::load_layout(Path::new(layout_path))
+        .and_then(|layout| dataset::build(&layout, total, Path::new(out_dir), threads));
+    let manifest = match result {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            eprintln!("Error building dataset in {}: {}", out_dir, err);
+            std::process::exit(1);
+        }
+    };
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let bytes: u64 = manifest.files.values().map(|segment| segment.bytes).sum();
+    println!(
+        "wrote {} lines ({} bytes) in {} files to {} in {:.1}s ({:.0} lines/s), manifest in {}",
+        manifest.total_lines,
+        bytes,
+        manifest.files.len(),
+        out_dir,
+        elapsed,
+        manifest.total_lines as f64 / elapsed.max(0.001),
+        Path::new(out_dir).join(dataset::MANIFEST).display()
+    );
+}
+
+fn diff_snapshots(matches: &ArgMatches) {
+    let load = |name| {
+        let path = matches.value_of(name).unwrap();
+        snapshot::load(Path::new(path)).unwrap_or_else(|err| {
+            eprintln!("Error reading snapshot {}: {}", path, err);
+            // 1 means there are changes, like diff
+            std::process::exit(2);
+        })
+    };
+    let changes = snapshot::diff(&load("before"), &load("after"));
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&changes).unwrap());
+    } else {
+        for change in &changes {
+            println!("{}", change.describe());
+        }
+    }
+
+    if !changes.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn emit_logrotate(matches: &ArgMatches) {
+    let out_dir = matches.value_of("out-base-dir").unwrap();
+    let options = logrotate::LogrotateOptions {
+        size: parse::parse_size(matches.value_of("size").unwrap()).unwrap(),
+        rotate: matches.value_of("rotate").unwrap().parse::<u64>().unwrap(),
+        compress: matches.is_present("compress"),
+    };
+
+    match logrotate::output_files(Path::new(out_dir)) {
+        Ok(files) if files.is_empty() => {
+            eprintln!("Error: no output files in {}, run loggen first", out_dir);
+            std::process::exit(1);
+        }
+        Ok(files) => print!("{}", logrotate::render(&files, &options)),
+        Err(err) => {
+            eprintln!("Error listing {}: {:?}", out_dir, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// generate with `strategy` into a temporary directory for `duration` while
+/// following the outputs, true if every line arrived
+fn self_test_strategy(strategy: &str, duration: Duration, interval: &str, keep: bool) -> bool {
+    let dir = std::env::temp_dir().join(format!(
+        "loggen-selftest-{}-{}",
+        std::process::id(),
+        strategy
+    ));
+    let (in_dir, out_dir) = (dir.join("in"), dir.join("out"));
+    let samples = match selftest::write_samples(&in_dir) {
+        Ok(samples) => samples,
+        Err(err) => {
+            eprintln!("Error writing samples to {}: {}", in_dir.display(), err);
+            return false;
+        }
+    };
+    for sample in &samples {
+        if let Some(parent) = out_dir.join(&sample.rel_path).parent() {
+            // followers watch the directories before the files are created
+            fs::create_dir_all(parent).ok();
+        }
+    }
+
+    let done = Arc::new(AtomicBool::new(false));
+    let followers: Vec<_> = samples
+        .iter()
+        .map(|sample| {
+            let path = out_dir.join(&sample.rel_path);
+            let sample = sample.clone();
+            let done = done.clone();
+            thread::spawn(move || selftest::follow(path, sample, done))
+        })
+        .collect();
+
+    let args = [
+        "loggen",
+        "-i",
+        &in_dir.display().to_string(),
+        "-o",
+        &out_dir.display().to_string(),
+        "-w",
+        strategy,
+        "-t",
+        interval,
+        "-p",
+        "1",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect::<Vec<_>>();
+    let matches = app().get_matches_from(args);
+    let (run_options, options) = options_from_matches(&matches);
+    let shared = Shared::new();
+    let timer_stop = shared.stop.clone();
+    thread::spawn(move || {
+        sleep(duration);
+        timer_stop.store(true, Ordering::Relaxed);
+    });
+    let ran = run_and_wait(&matches, run_options, options, &shared);
+    done.store(true, Ordering::Relaxed);
+
+    let written: HashMap<String, u64> = shared
+        .stats
+        .status()
+        .files
+        .into_iter()
+        .map(|file| (file.path_out, file.lines))
+        .collect();
+    let mut passed = ran;
+    for follower in followers {
+        let report = follower.join().unwrap_or_default();
+        let rel_path = report.rel_path.display().to_string();
+        let lines = written.get(&rel_path).copied().unwrap_or(0);
+        let ok = report.mismatches == 0 && report.lines == lines && lines > 0;
+        passed &= ok;
+        println!(
+            "{} {}: {} {} lines written, {} read, {} truncations, {} rotations, {} mismatches",
+            if ok { "ok" } else { "FAIL" },
+            strategy,
+            rel_path,
+            lines,
+            report.lines,
+            report.truncations,
+            report.rotations,
+            report.mismatches
+        );
+        for description in &report.described {
+            println!("    {}", description);
+        }
+    }
+
+    if keep {
+        println!("kept {}", dir.display());
+    } else {
+        fs::remove_dir_all(&dir).ok();
+    }
+    passed
+}
+
+fn self_test(matches: &ArgMatches) {
+    let strategies: Vec<&str> = match matches.values_of("wrap-strategy") {
+        Some(values) => values.collect(),
+        None => WrapStrategy::NAMES.to_vec(),
+    };
+    let duration = parse::parse_duration(matches.value_of("duration").unwrap()).unwrap();
+    let interval = matches.value_of("interval").unwrap();
+    let keep = matches.is_present("keep");
+
+    let mut passed = true;
+    for strategy in strategies {
+        passed &= self_test_strategy(strategy, duration, interval, keep);
+    }
+    if !passed {
+        std::process::exit(1);
+    }
+}
+
+fn await_marker(matches: &ArgMatches) {
+    let from = match matches.value_of("file") {
+        Some(path) => marker::AwaitFrom::File(PathBuf::from(path)),
+        None => marker::AwaitFrom::Url {
+            url: matches.value_of("url").unwrap().to_string(),
+            poll: parse::parse_duration(matches.value_of("poll").unwrap()).unwrap(),
+        },
+    };
+    let options = marker::AwaitOptions {
+        from,
+        count: matches
+            .value_of("count")
+            .map(|v| v.parse::<usize>().unwrap()),
+        timeout: matches
+            .value_of("timeout")
+            .map(|v| parse::parse_duration(v).unwrap()),
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    stop_on_signal(stop.clone());
+    if let Err(err) = marker::await_markers(&options, &stop) {
+        eprintln!("Error: {:?}", err);
+        std::process::exit(1);
+    }
+}
+
+fn validate_samples(matches: &ArgMatches) {
+    let in_dir = matches.value_of("in-base-dir").unwrap();
+    let max_line_length = parse::parse_size(matches.value_of("max-line-length").unwrap()).unwrap();
+    match validate::validate(Path::new(in_dir), max_line_length as usize) {
+        Ok(0) => {}
+        Ok(_) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("Error validating {}: {:?}", in_dir, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn normalize_samples(matches: &ArgMatches) {
+    let in_dir = matches.value_of("in-base-dir").unwrap();
+    let options = normalize::NormalizeOptions {
+        collapse_duplicates: !matches.is_present("keep-duplicates"),
+        max_lines: matches
+            .value_of("max-lines")
+            .map(|v| v.parse::<u64>().unwrap()),
+    };
+    match normalize::normalize(
+        Path::new(in_dir),
+        matches.value_of("out-base-dir").map(Path::new),
+        options,
+    ) {
+        Ok(0) => {}
+        Ok(_) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("Error normalizing {}: {}", in_dir, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn inspect_samples(matches: &ArgMatches) {
+    let in_dir = matches.value_of("in-base-dir").unwrap();
+    let lines = matches.value_of("lines").unwrap().parse::<usize>().unwrap();
+    let report = match inspect::inspect(Path::new(in_dir), lines) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Error inspecting {}: {}", in_dir, err);
+            std::process::exit(1);
+        }
+    };
+
+    let content = serde_json::to_string_pretty(&report).unwrap();
+    match matches.value_of("output") {
+        Some(path) => {
+            if let Err(err) = fs::write(path, content + "\n") {
+                eprintln!("Error writing {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+        None => println!("{}", content),
+    }
+}
+
+fn merge_samples(matches: &ArgMatches) {
+    let paths: Vec<&Path> = matches
+        .values_of("samples")
+        .unwrap()
+        .map(Path::new)
+        .collect();
+    let mode = MergeMode::parse(matches.value_of("mode").unwrap());
+    let output = matches.value_of("output");
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(io::BufWriter::new(file)),
+            Err(err) => {
+                eprintln!("Error creating {}: {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        None => Box::new(io::BufWriter::new(io::stdout().lock())),
+    };
+
+    match merge::merge(&paths, mode, &mut out) {
+        // the merged lines can be on stdout
+        Ok(report) => eprintln!("{}: {}", output.unwrap_or("stdout"), report.summary()),
+        Err(err) => {
+            eprintln!("Error merging samples: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("loggen")
+        .version("0.2.0")
+        .author("Mariano Guerra <mariano@marianoguerra.org>")
+        .about("Generate logs from a directory tree of sample logs")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        // options from --config come first, the command line overrides them
+        .global_setting(AppSettings::AllArgsOverrideSelf)
+        .args(&gen_args())
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Generate as fast as possible for a while and report throughput per output")
+                .args(&gen_args())
+                .args(&bench_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("shell")
+                .about("Generate while reading commands from stdin that change rates, pause and rotate files, type help for the list")
+                .args(&gen_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("config-schema")
+                .about("Print the JSON schema of --config files"),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-output")
+                .about("Check the output files against the manifests written with --manifest")
+                .arg(
+                    Arg::with_name("out-base-dir")
+                        .short("o")
+                        .long("out-base-dir")
+                        .value_name("DIR")
+                        .help("Output directory to check")
+                        .required(true)
+            .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Record the files of an output directory with their line count, byte count and checksum")
+                .arg(
+                    Arg::with_name("out-base-dir")
+                        .short("o")
+                        .long("out-base-dir")
+                        .value_name("DIR")
+                        .help("Output directory to record")
+                        .required(true)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Write the snapshot to FILE instead of stdout")
+            .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare two snapshots and list the files added, removed and changed, exits with 1 if there are changes")
+                .arg(
+                    Arg::with_name("before")
+                        .value_name("BEFORE")
+                        .help("Snapshot taken before the run")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("after")
+                        .value_name("AFTER")
+                        .help("Snapshot taken after the run")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the changes as a JSON array"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("build-dataset")
+                .about("Write a bounded corpus with an exact number of lines per file, the same on every run, with a manifest of the counts")
+                .arg(
+                    Arg::with_name("total-lines")
+                        .long("total-lines")
+                        .value_name("COUNT")
+                        .help("Lines in the whole dataset, accepts K, M and G suffixes like 100M")
+                        .validator(parse::is_count)
+                        .required(true)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("layout")
+                        .long("layout")
+                        .value_name("FILE")
+                        .help("YAML file listing the files of the dataset with their sample or format and share or exact lines")
+                        .required(true)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("out-base-dir")
+                        .short("o")
+                        .long("out-base-dir")
+                        .value_name("DIR")
+                        .help("New or empty directory to write the dataset to")
+                        .required(true)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("parallelism")
+                        .short("p")
+                        .long("parallelism")
+                        .value_name("COUNT")
+                        .help("Number of files written at the same time, defaults to the number of cpus")
+                        .validator(is_positive_number)
+            .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("emit-logrotate")
+                .about("Print a logrotate config that rotates the files in an output directory")
+                .arg(
+                    Arg::with_name("out-base-dir")
+                        .short("o")
+                        .long("out-base-dir")
+                        .value_name("DIR")
+                        .help("Output directory with the files to rotate")
+                        .required(true)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .value_name("SIZE")
+                        .help("Rotate files bigger than this, for example 512KB or 10MB")
+                        .validator(parse::is_size)
+                        .default_value("10MB")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("rotate")
+                        .long("rotate")
+                        .value_name("COUNT")
+                        .help("Rotated files to keep")
+                        .validator(is_positive_number)
+                        .default_value("5")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("compress")
+                        .long("compress")
+                        .help("Gzip rotated files"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("selftest")
+                .about("Generate into a temporary directory while following the outputs like tail -F, checking every line arrives whole and in order across wraps")
+                .arg(
+                    Arg::with_name("wrap-strategy")
+                        .short("w")
+                        .long("wrap-strategy")
+                        .value_name("STRATEGY")
+                        .help("Wrap strategy to test, can be repeated [default: all]")
+                        .possible_values(WrapStrategy::NAMES)
+                        .multiple(true)
+                        .number_of_values(1)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("duration")
+                        .long("duration")
+                        .value_name("DURATION")
+                        .help("How long to generate with each wrap strategy")
+                        .validator(parse::is_duration)
+                        .default_value("5s")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("interval")
+                        .short("t")
+                        .long("interval")
+                        .value_name("MS")
+                        .help("Time in milliseconds between reads of each file")
+                        .validator(is_positive_number)
+                        .default_value("2")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("keep")
+                        .long("keep")
+                        .help("Keep the temporary directories to look at the samples and outputs"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("await-marker")
+                .about("Wait for marker lines to come out of a pipeline and print their latency")
+                .arg(
+                    Arg::with_name("file")
+                        .long("file")
+                        .value_name("FILE")
+                        .help("File to tail for markers")
+                        .required_unless("url")
+                        .conflicts_with("url")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("url")
+                        .long("url")
+                        .value_name("URL")
+                        .help("URL to query for markers, for example a search API of the downstream system")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("poll")
+                        .long("poll")
+                        .value_name("DURATION")
+                        .help("Time between --url queries")
+                        .validator(parse::is_duration)
+                        .default_value("1s")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .short("n")
+                        .long("count")
+                        .value_name("COUNT")
+                        .help("Stop after this many markers")
+                        .validator(is_non_zero_number)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("timeout")
+                        .long("timeout")
+                        .value_name("DURATION")
+                        .help("Stop after this long")
+                        .validator(parse::is_duration)
+            .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("validate-samples")
+                .about("Check sample files for problems and detect their timestamp format, without generating")
+                .arg(
+                    Arg::with_name("in-base-dir")
+                        .short("i")
+                        .long("in-base-dir")
+                        .value_name("DIR")
+                        .help("Input base directory to check")
+                        .required(true)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-line-length")
+                        .long("max-line-length")
+                        .value_name("SIZE")
+                        .help("Report lines longer than this")
+                        .validator(parse::is_size)
+                        .default_value("64KB")
+            .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("normalize-samples")
+                .about("Rewrite sample files for clean replay: strip ANSI codes, convert them to UTF-8 with LF endings, collapse duplicate consecutive lines and optionally keep only the first lines")
+                .arg(
+                    Arg::with_name("in-base-dir")
+                        .short("i")
+                        .long("in-base-dir")
+                        .value_name("DIR")
+                        .help("Input base directory to normalize")
+                        .required(true)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("out-base-dir")
+                        .short("o")
+                        .long("out-base-dir")
+                        .value_name("DIR")
+                        .help("Write the normalized files here instead of replacing the samples")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-lines")
+                        .short("n")
+                        .long("max-lines")
+                        .value_name("COUNT")
+                        .help("Lines to keep from the start of each file")
+                        .validator(is_non_zero_number)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("keep-duplicates")
+                        .long("keep-duplicates")
+                        .help("Don't collapse duplicate consecutive lines"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Sample the first lines of each input file and write a JSON report with their timestamp format, line lengths, separator and kind (json or plain)")
+                .arg(
+                    Arg::with_name("in-base-dir")
+                        .short("i")
+                        .long("in-base-dir")
+                        .value_name("DIR")
+                        .help("Input base directory to inspect")
+                        .required(true)
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("lines")
+                        .short("n")
+                        .long("lines")
+                        .value_name("COUNT")
+                        .help("Lines to sample from the start of each file")
+                        .validator(is_non_zero_number)
+                        .default_value("100")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Write the report to FILE instead of stdout")
+            .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge-samples")
+                .about("Merge sample files into a single one interleaved by timestamp, like one stream of captures of several components")
+                .arg(
+                    Arg::with_name("samples")
+                        .value_name("SAMPLE")
+                        .help("Sample files or directories with them, lines with the same timestamp keep this order")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Write the merged sample to FILE instead of stdout")
+            .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("mode")
+                        .long("mode")
+                        .value_name("MODE")
+                        .help("interleave sorts lines by timestamp, concat writes one file after the other")
+                        .possible_values(MergeMode::NAMES)
+                        .default_value("interleave")
+            .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("service")
+                .about("Run loggen as a launchd (macOS), systemd (Linux) or Windows service")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("install")
+                        .about("Install and start a service that runs loggen with ARGS")
+                        .args(&service_args())
+                        .arg(
+                            Arg::with_name("print")
+                                .long("print")
+                                .help("Print the service file instead of installing it"),
+                        )
+                        .arg(
+                            Arg::with_name("args")
+                                .value_name("ARGS")
+                                .help("loggen options for the service, after --")
+                                .required(true)
+                                .multiple(true)
+                                .last(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("uninstall")
+                        .about("Stop and remove a service")
+                        .args(&service_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("run")
+                        .about("Run loggen with ARGS, this is what the installed service runs")
+                        .arg(name_arg())
+                        .arg(
+                            Arg::with_name("work-dir")
+                                .long("work-dir")
+                                .value_name("DIR")
+                                .help("Directory to run in, relative paths in ARGS are relative to it")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("args")
+                                .value_name("ARGS")
+                                .help("loggen options, after --")
+                                .required(true)
+                                .multiple(true)
+                                .last(true),
+                        ),
+                ),
+        )
+}
+
+/// --name of service subcommands
+fn name_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("name")
+        .long("name")
+        .value_name("NAME")
+        .help("Service name, to run more than one")
+        .default_value("default")
+        .validator(|v| {
+            if !v.is_empty()
+                && v.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                Ok(())
+            } else {
+                Err(format!("{} should only have letters, digits, - and _", v))
+            }
+        })
+        .takes_value(true)
+}
+
+fn service_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        name_arg(),
+        Arg::with_name("system")
+            .long("system")
+            .help("Install for the whole system (needs root) instead of the current user"),
+    ]
+}
+
+fn service(matches: &ArgMatches) {
+    let options_for = |matches: &ArgMatches| service::ServiceOptions {
+        name: matches.value_of("name").unwrap().to_string(),
+        system: matches.is_present("system"),
+    };
+    let args_of = |matches: &ArgMatches| -> Vec<String> {
+        matches
+            .values_of("args")
+            .map(|values| values.map(String::from).collect())
+            .unwrap_or_default()
+    };
+
+    let result = match matches.subcommand() {
+        ("install", Some(sub_matches)) if sub_matches.is_present("print") => {
+            service::service_file(&options_for(sub_matches), &args_of(sub_matches)).map(
+                |(path, content)| {
+                    println!("# {}", path.display());
+                    print!("{}", content);
+                },
+            )
+        }
+        ("install", Some(sub_matches)) => {
+            service::install(&options_for(sub_matches), &args_of(sub_matches))
+                .map(|path| println!("installed {}", path.display()))
+        }
+        ("uninstall", Some(sub_matches)) => service::uninstall(&options_for(sub_matches))
+            .map(|path| println!("removed {}", path.display())),
+        ("run", Some(sub_matches)) => {
+            let mut args = vec![std::ffi::OsString::from("loggen")];
+            args.extend(
+                args_of(sub_matches)
+                    .into_iter()
+                    .map(std::ffi::OsString::from),
+            );
+            let options = options_for(sub_matches);
+            match sub_matches.value_of("work-dir") {
+                Some(dir) => std::env::set_current_dir(dir),
+                None => Ok(()),
+            }
+            .map(|_| service::run(&options, Box::new(move || run_command(args))))
+        }
+        _ => Ok(()),
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run_command(args: Vec<std::ffi::OsString>) {
+    let args = match config::args_with_config(args, config_options) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let matches = app().get_matches_from(args);
+
+    match matches.subcommand() {
+        ("bench", Some(sub_matches)) => bench(sub_matches),
+        ("shell", Some(sub_matches)) => shell(sub_matches),
+        ("config-schema", Some(_)) => {
+            let schema = config::schema(&config_options(None));
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+        }
+        ("verify-output", Some(sub_matches)) => verify_output(sub_matches),
+        ("snapshot", Some(sub_matches)) => take_snapshot(sub_matches),
+        ("diff", Some(sub_matches)) => diff_snapshots(sub_matches),
+        ("build-dataset", Some(sub_matches)) => build_dataset(sub_matches),
+        ("emit-logrotate", Some(sub_matches)) => emit_logrotate(sub_matches),
+        ("await-marker", Some(sub_matches)) => await_marker(sub_matches),
+        ("selftest", Some(sub_matches)) => self_test(sub_matches),
+        ("validate-samples", Some(sub_matches)) => validate_samples(sub_matches),
+        ("normalize-samples", Some(sub_matches)) => normalize_samples(sub_matches),
+        ("inspect", Some(sub_matches)) => inspect_samples(sub_matches),
+        ("merge-samples", Some(sub_matches)) => merge_samples(sub_matches),
+        ("service", Some(sub_matches)) => service(sub_matches),
+        _ => {
+            let (run_options, options) = options_from_matches(&matches);
+            run_and_wait(&matches, run_options, options, &Shared::new());
+        }
+    }
+}
+
+fn main() {
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+
+    run_command(std::env::args_os().collect());
 }
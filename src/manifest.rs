@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+const EXTENSION: &str = "manifest";
+/// how often the manifest of a file that is being written is saved
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a 64 bit checksum that can be updated as bytes are written
+#[derive(Debug, Clone, Copy)]
+pub struct Checksum(u64);
+
+impl Checksum {
+    pub fn new() -> Checksum {
+        Checksum(FNV_OFFSET)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub lines: u64,
+    pub bytes: u64,
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rotation {
+    /// file name the output was renamed to
+    pub rotated_to: String,
+    #[serde(flatten)]
+    pub segment: Segment,
+}
+
+/// what was written to an output file, saved next to it as <name>.manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// current content of the file
+    #[serde(flatten)]
+    pub current: Segment,
+    /// content of the file each time it was rotated, in order
+    pub rotations: Vec<Rotation>,
+    /// content of the file each time it was truncated, in order
+    pub truncations: Vec<Segment>,
+}
+
+pub fn manifest_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(EXTENSION);
+    path.with_file_name(name)
+}
+
+/// count lines, bytes and checksum of a file
+fn scan(path: &Path) -> io::Result<(u64, u64, Checksum)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut checksum = Checksum::new();
+    let mut lines = 0;
+    let mut bytes = 0;
+    let mut buf = vec![];
+
+    loop {
+        buf.clear();
+        let len = reader.read_until(b'\n', &mut buf)?;
+        if len == 0 {
+            break;
+        }
+        checksum.update(&buf);
+        bytes += len as u64;
+        if buf.ends_with(b"\n") {
+            lines += 1;
+        }
+    }
+
+    Ok((lines, bytes, checksum))
+}
+
+/// lines, bytes and checksum of a file as it is now
+pub fn segment_of(path: &Path) -> io::Result<Segment> {
+    let (lines, bytes, checksum) = scan(path)?;
+    Ok(Segment {
+        lines,
+        bytes,
+        checksum: checksum.hex(),
+    })
+}
+
+/// keeps the manifest of an output file up to date as it's written
+#[derive(Debug)]
+pub struct ManifestTracker {
+    path: PathBuf,
+    lines: u64,
+    bytes: u64,
+    checksum: Checksum,
+    rotations: Vec<Rotation>,
+    truncations: Vec<Segment>,
+    last_saved: Instant,
+}
+
+impl ManifestTracker {
+    /// start tracking `path`, content already in it (from previous runs) is counted
+    pub fn open(path: &Path) -> io::Result<ManifestTracker> {
+        let (lines, bytes, checksum) = if path.exists() {
+            scan(path)?
+        } else {
+            (0, 0, Checksum::new())
+        };
+
+        Ok(ManifestTracker {
+            path: path.to_path_buf(),
+            lines,
+            bytes,
+            checksum,
+            rotations: vec![],
+            truncations: vec![],
+            last_saved: Instant::now(),
+        })
+    }
+
+    fn segment(&self) -> Segment {
+        Segment {
+            lines: self.lines,
+            bytes: self.bytes,
+            checksum: self.checksum.hex(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lines = 0;
+        self.bytes = 0;
+        self.checksum = Checksum::new();
+    }
+
+    /// record bytes written to the file, saves the manifest now and then
+    pub fn written(&mut self, data: &str) -> io::Result<()> {
+        self.checksum.update(data.as_bytes());
+        self.bytes += data.len() as u64;
+        self.lines += data.matches('\n').count() as u64;
+
+        if self.last_saved.elapsed() >= SAVE_INTERVAL {
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn truncated(&mut self) -> io::Result<()> {
+        self.truncations.push(self.segment());
+        self.reset();
+        self.save()
+    }
+
+    pub fn rotated(&mut self, rotated_to: &Path) -> io::Result<()> {
+        self.rotations.push(Rotation {
+            rotated_to: rotated_to
+                .file_name()
+                .map(|v| v.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            segment: self.segment(),
+        });
+        self.reset();
+        self.save()
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let manifest = Manifest {
+            current: self.segment(),
+            rotations: self.rotations.clone(),
+            truncations: self.truncations.clone(),
+        };
+        let json = serde_json::to_string_pretty(&manifest).map_err(io::Error::other)?;
+
+        // write and rename so readers never see a partial manifest
+        let path = manifest_path(&self.path);
+        let tmp_path = path.with_extension(format!("{}.tmp", EXTENSION));
+        fs::write(&tmp_path, json)?;
+        fs::rename(&tmp_path, &path)?;
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+}
+
+fn check_segment(path: &Path, expected: &Segment) -> Result<(), String> {
+    let (lines, bytes, checksum) = scan(path).map_err(|err| format!("{}", err))?;
+    if lines != expected.lines || bytes != expected.bytes || checksum.hex() != expected.checksum {
+        return Err(format!(
+            "expected {} lines, {} bytes, checksum {} found {} lines, {} bytes, checksum {}",
+            expected.lines,
+            expected.bytes,
+            expected.checksum,
+            lines,
+            bytes,
+            checksum.hex()
+        ));
+    }
+
+    Ok(())
+}
+
+/// check every manifest under `out_dir` against the files it describes, prints
+/// a line per checked file and returns the number of failures
+pub fn verify(out_dir: &Path) -> io::Result<usize> {
+    let mut checked = 0;
+    let mut failures = 0;
+
+    for entry in WalkDir::new(out_dir).into_iter().filter_map(|e| e.ok()) {
+        let manifest_file = entry.path();
+        if !manifest_file.is_file()
+            || manifest_file.extension().and_then(|v| v.to_str()) != Some(EXTENSION)
+        {
+            continue;
+        }
+
+        let path = manifest_file.with_extension("");
+        let manifest: Manifest = match serde_json::from_str(&fs::read_to_string(manifest_file)?) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                println!(
+                    "FAIL {}: invalid manifest: {}",
+                    manifest_file.display(),
+                    err
+                );
+                failures += 1;
+                continue;
+            }
+        };
+
+        let mut results = vec![(path.clone(), check_segment(&path, &manifest.current))];
+
+        // a rotated name can be reused, only its last rotation is still on disk
+        for (index, rotation) in manifest.rotations.iter().enumerate() {
+            let reused = manifest.rotations[index + 1..]
+                .iter()
+                .any(|later| later.rotated_to == rotation.rotated_to);
+            let rotated_path = path.with_file_name(&rotation.rotated_to);
+            if !reused && rotated_path.exists() {
+                results.push((
+                    rotated_path.clone(),
+                    check_segment(&rotated_path, &rotation.segment),
+                ));
+            }
+        }
+
+        for (checked_path, result) in results {
+            checked += 1;
+            match result {
+                Ok(()) => println!("OK   {}", checked_path.display()),
+                Err(err) => {
+                    failures += 1;
+                    println!("FAIL {}: {}", checked_path.display(), err);
+                }
+            }
+        }
+    }
+
+    println!("{} files checked, {} failed", checked, failures);
+    Ok(failures)
+}
@@ -0,0 +1,233 @@
+use rand::Rng;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const PREFIX: &str = "loggen-marker ";
+const POLL_WAIT: Duration = Duration::from_millis(100);
+
+fn unix_micros() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros())
+        .unwrap_or(0)
+}
+
+/// injects a marker line into one output every `interval`
+#[derive(Debug)]
+pub struct Marker {
+    interval: Duration,
+    file: String,
+    next_due: Instant,
+}
+
+impl Marker {
+    pub fn new(interval: Duration, rel_path: &Path) -> Marker {
+        Marker {
+            interval,
+            file: rel_path.display().to_string().replace(' ', "_"),
+            next_due: Instant::now() + interval,
+        }
+    }
+
+    /// marker line if the interval elapsed since the last one
+    pub fn next_due(&mut self) -> Option<String> {
+        let now = Instant::now();
+        if now < self.next_due {
+            return None;
+        }
+
+        self.next_due = now + self.interval;
+        Some(format!(
+            "{}id={:016x} file={} sent_us={}\n",
+            PREFIX,
+            rand::thread_rng().gen::<u64>(),
+            self.file,
+            unix_micros()
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Seen {
+    pub id: String,
+    pub file: String,
+    pub sent_us: u128,
+}
+
+/// markers anywhere in `text`, values end at whitespace, quotes or backslashes
+/// so markers embedded in JSON query results are found too
+pub fn find_markers(text: &str) -> Vec<Seen> {
+    let mut found = vec![];
+    for (start, _) in text.match_indices(PREFIX) {
+        let rest = &text[start + PREFIX.len()..];
+        let (mut id, mut file, mut sent_us) = (None, None, None);
+        for field in rest
+            .split(|c: char| c.is_whitespace() || c == '"' || c == '\\')
+            .take(3)
+        {
+            match field.split_once('=') {
+                Some(("id", v)) => id = Some(v.to_string()),
+                Some(("file", v)) => file = Some(v.to_string()),
+                Some(("sent_us", v)) => sent_us = v.parse::<u128>().ok(),
+                _ => {}
+            }
+        }
+
+        if let (Some(id), Some(file), Some(sent_us)) = (id, file, sent_us) {
+            found.push(Seen { id, file, sent_us });
+        }
+    }
+
+    found
+}
+
+/// where to look for markers that went through the pipeline
+#[derive(Debug, Clone)]
+pub enum AwaitFrom {
+    /// tail a file from its current end
+    File(PathBuf),
+    /// GET the url now and then and look for markers in the response body
+    Url { url: String, poll: Duration },
+}
+
+#[derive(Debug, Clone)]
+pub struct AwaitOptions {
+    pub from: AwaitFrom,
+    /// stop after this many markers
+    pub count: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct Latencies {
+    seen: HashSet<(String, String)>,
+    micros: Vec<u128>,
+}
+
+impl Latencies {
+    /// print and record markers seen for the first time
+    fn record(&mut self, markers: Vec<Seen>) {
+        let now = unix_micros();
+        for marker in markers {
+            if self.seen.insert((marker.id.clone(), marker.file.clone())) {
+                let latency = now.saturating_sub(marker.sent_us);
+                println!(
+                    "{} {} {:.3}ms",
+                    marker.id,
+                    marker.file,
+                    latency as f64 / 1000.0
+                );
+                self.micros.push(latency);
+            }
+        }
+    }
+
+    fn report(&mut self) {
+        if self.micros.is_empty() {
+            println!("no markers seen");
+            return;
+        }
+
+        self.micros.sort_unstable();
+        let ms = |v: u128| v as f64 / 1000.0;
+        let len = self.micros.len();
+        let sum: u128 = self.micros.iter().sum();
+        println!(
+            "{} markers, latency min {:.3}ms avg {:.3}ms p50 {:.3}ms p99 {:.3}ms max {:.3}ms",
+            len,
+            ms(self.micros[0]),
+            ms(sum / len as u128),
+            ms(self.micros[len / 2]),
+            ms(self.micros[(len * 99 / 100).min(len - 1)]),
+            ms(self.micros[len - 1])
+        );
+    }
+}
+
+fn tail_file(path: &Path, reader: &mut Option<BufReader<File>>) -> io::Result<Vec<Seen>> {
+    if reader.is_none() {
+        // wait for the file to be created
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        file.seek(SeekFrom::End(0))?;
+        *reader = Some(BufReader::new(file));
+    }
+
+    let current = reader.as_mut().unwrap();
+    // start again if the file was truncated or rotated under us
+    let position = current.stream_position()?;
+    let len = path.metadata().map(|m| m.len()).unwrap_or(0);
+    if len < position {
+        *current = BufReader::new(File::open(path)?);
+    }
+
+    let mut markers = vec![];
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if current.read_line(&mut line)? == 0 {
+            break;
+        }
+        markers.extend(find_markers(&line));
+    }
+
+    Ok(markers)
+}
+
+fn query_url(agent: &ureq::Agent, url: &str) -> io::Result<Vec<Seen>> {
+    let body = agent
+        .get(url)
+        .call()
+        .map_err(|err| io::Error::other(format!("GET {} failed: {}", url, err)))?
+        .into_string()?;
+    Ok(find_markers(&body))
+}
+
+/// wait for markers and print the latency of each one, reports a summary when
+/// `count` markers were seen, on timeout or when `stop` is set
+pub fn await_markers(options: &AwaitOptions, stop: &AtomicBool) -> io::Result<()> {
+    let started = Instant::now();
+    let mut latencies = Latencies::default();
+    let mut reader = None;
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .build();
+
+    while !stop.load(Ordering::Relaxed) {
+        let (markers, wait) = match &options.from {
+            AwaitFrom::File(path) => (tail_file(path, &mut reader)?, POLL_WAIT),
+            AwaitFrom::Url { url, poll } => match query_url(&agent, url) {
+                Ok(markers) => (markers, *poll),
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    (vec![], *poll)
+                }
+            },
+        };
+        latencies.record(markers);
+
+        if let Some(count) = options.count {
+            if latencies.micros.len() >= count {
+                break;
+            }
+        }
+        if let Some(timeout) = options.timeout {
+            if started.elapsed() >= timeout {
+                break;
+            }
+        }
+
+        sleep(wait);
+    }
+
+    latencies.report();
+    Ok(())
+}
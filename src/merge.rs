@@ -0,0 +1,129 @@
+use crate::validate::parse_timestamp;
+use chrono::{DateTime, Utc};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// how the lines of the samples are put together, see merge-samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeMode {
+    /// by timestamp, lines with the same one keep the order of their files
+    Interleave,
+    /// one file after the other
+    Concat,
+}
+
+impl MergeMode {
+    pub const NAMES: &'static [&'static str] = &["interleave", "concat"];
+
+    pub fn parse(v: &str) -> MergeMode {
+        match v {
+            "concat" => MergeMode::Concat,
+            _ => MergeMode::Interleave,
+        }
+    }
+}
+
+/// a line of a sample with the timestamp it's sorted by
+#[derive(Debug)]
+struct Line {
+    time: Option<DateTime<Utc>>,
+    text: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    pub files: usize,
+    pub lines: u64,
+    /// lines without a timestamp that went with the line before
+    pub untimed: u64,
+}
+
+impl MergeReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!("merged {} lines of {} files", self.lines, self.files);
+        if self.untimed > 0 {
+            summary.push_str(&format!(
+                ", {} without a timestamp went with the line before",
+                self.untimed
+            ));
+        }
+        summary
+    }
+}
+
+/// the files in `paths` in the order given, directories are walked sorted by name
+fn sample_files(paths: &[&Path]) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            for entry in WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    files.push(entry.into_path());
+                }
+            }
+        } else if path.is_file() {
+            files.push(path.to_path_buf());
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} isn't a file or directory", path.display()),
+            ));
+        }
+    }
+    Ok(files)
+}
+
+/// lines of `path`, the ones without a timestamp, like stack traces, take the
+/// one of the line before so they stay with it
+fn read_lines(path: &Path, mode: MergeMode, report: &mut MergeReport) -> io::Result<Vec<Line>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut lines = vec![];
+    let mut last_time = None;
+    loop {
+        let mut text = vec![];
+        if reader.read_until(b'\n', &mut text)? == 0 {
+            return Ok(lines);
+        }
+        if text.last() != Some(&b'\n') {
+            text.push(b'\n');
+        }
+        if mode == MergeMode::Interleave {
+            match parse_timestamp(&String::from_utf8_lossy(&text)) {
+                Some(time) => last_time = Some(time),
+                None => report.untimed += 1,
+            }
+        }
+        report.lines += 1;
+        lines.push(Line {
+            time: last_time,
+            text,
+        });
+    }
+}
+
+/// merge the samples in `paths` into `out`, lines before the first timestamp
+/// of a file sort before every timestamp
+pub fn merge(paths: &[&Path], mode: MergeMode, out: &mut dyn Write) -> io::Result<MergeReport> {
+    let mut report = MergeReport::default();
+    let mut lines = vec![];
+    for path in sample_files(paths)? {
+        let file_lines = read_lines(&path, mode, &mut report)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        report.files += 1;
+        lines.extend(file_lines);
+    }
+
+    if mode == MergeMode::Interleave {
+        // stable, so lines with the same time keep the order of the files and
+        // of their lines in them
+        lines.sort_by_key(|line| line.time);
+    }
+    for line in &lines {
+        out.write_all(&line.text)?;
+    }
+    out.flush()?;
+    Ok(report)
+}
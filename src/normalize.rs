@@ -0,0 +1,232 @@
+use crate::ansi::strip_ansi;
+use crate::validate::{is_binary, BINARY_SAMPLE};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use walkdir::WalkDir;
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const UTF16LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16BE_BOM: &[u8] = &[0xfe, 0xff];
+
+/// windows-1252 characters for 0x80 to 0x9f, the rest is the same as latin-1,
+/// unassigned bytes are kept as their latin-1 control character
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// what to do to each file, see normalize-samples
+#[derive(Debug, Clone, Copy)]
+pub struct NormalizeOptions {
+    pub collapse_duplicates: bool,
+    /// lines to keep from the start of each file
+    pub max_lines: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct FileReport {
+    lines_in: u64,
+    lines_out: u64,
+    /// utf-8 or utf-16 with a BOM, empty for utf-8 without one
+    encoding: &'static str,
+    /// lines that weren't utf-8, read as windows-1252
+    reencoded: u64,
+    ansi: u64,
+    crlf: u64,
+    duplicates: u64,
+    trimmed: u64,
+}
+
+impl FileReport {
+    fn summary(&self) -> String {
+        let mut changes = vec![];
+        if !self.encoding.is_empty() {
+            changes.push(format!("converted from {}", self.encoding));
+        }
+        for (count, what) in [
+            (self.reencoded, "lines read as windows-1252"),
+            (self.ansi, "lines without ANSI codes"),
+            (self.crlf, "CRLF endings replaced"),
+            (self.duplicates, "duplicate lines collapsed"),
+            (self.trimmed, "lines trimmed"),
+        ] {
+            if count > 0 {
+                changes.push(format!("{} {}", count, what));
+            }
+        }
+        if changes.is_empty() {
+            changes.push("unchanged".to_string());
+        }
+        format!(
+            "{} -> {} lines, {}",
+            self.lines_in,
+            self.lines_out,
+            changes.join(", ")
+        )
+    }
+}
+
+fn decode_cp1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| match b {
+            0x80..=0x9f => CP1252_HIGH[(b - 0x80) as usize],
+            _ => *b as char,
+        })
+        .collect()
+}
+
+/// the content as utf-8 bytes and the encoding it was converted from
+fn decode(content: Vec<u8>) -> (Vec<u8>, &'static str) {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|c| from([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&units).into_bytes()
+    };
+    if let Some(rest) = content.strip_prefix(UTF8_BOM) {
+        (rest.to_vec(), "utf-8 with BOM")
+    } else if let Some(rest) = content.strip_prefix(UTF16LE_BOM) {
+        (utf16(rest, u16::from_le_bytes), "utf-16le")
+    } else if let Some(rest) = content.strip_prefix(UTF16BE_BOM) {
+        (utf16(rest, u16::from_be_bytes), "utf-16be")
+    } else {
+        (content, "")
+    }
+}
+
+/// write the normalized lines of `path_in` to `path_out`, None if it's binary
+fn normalize_file(
+    path_in: &Path,
+    path_out: &Path,
+    options: NormalizeOptions,
+) -> io::Result<Option<FileReport>> {
+    let (content, encoding) = decode(fs::read(path_in)?);
+    if encoding.is_empty() && is_binary(&content[..content.len().min(BINARY_SAMPLE)]) {
+        return Ok(None);
+    }
+
+    let mut report = FileReport {
+        encoding,
+        ..FileReport::default()
+    };
+    let mut out = BufWriter::new(File::create(path_out)?);
+    let mut previous: Option<String> = None;
+    for raw in content.split_inclusive(|b| *b == b'\n') {
+        report.lines_in += 1;
+        let mut raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+        if let Some(rest) = raw.strip_suffix(b"\r") {
+            report.crlf += 1;
+            raw = rest;
+        }
+        let mut line = match std::str::from_utf8(raw) {
+            Ok(line) => line.to_string(),
+            Err(_) => {
+                report.reencoded += 1;
+                decode_cp1252(raw)
+            }
+        };
+        if let Some(stripped) = strip_ansi(&line) {
+            report.ansi += 1;
+            line = stripped;
+        }
+
+        if options.collapse_duplicates && previous.as_deref() == Some(line.as_str()) {
+            report.duplicates += 1;
+            continue;
+        }
+        if options.max_lines.is_some_and(|max| report.lines_out >= max) {
+            report.trimmed += 1;
+            continue;
+        }
+        out.write_all(line.as_bytes())?;
+        out.write_all(b"\n")?;
+        report.lines_out += 1;
+        previous = Some(line);
+    }
+    out.flush()?;
+
+    Ok(Some(report))
+}
+
+/// normalize every file under `in_dir` into the same path under `out_dir`, or
+/// in place without one, prints a line per file and returns how many failed
+pub fn normalize(
+    in_dir: &Path,
+    out_dir: Option<&Path>,
+    options: NormalizeOptions,
+) -> io::Result<usize> {
+    // the input directory itself is normalized in place
+    let out_dir = out_dir.filter(|out_dir| *out_dir != in_dir);
+    if out_dir.is_some_and(|out_dir| out_dir.starts_with(in_dir)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the output directory can't be inside the input directory",
+        ));
+    }
+
+    let mut normalized = 0;
+    let mut skipped = 0;
+    let mut failed = 0;
+
+    for entry in WalkDir::new(in_dir)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let rel_path = path.strip_prefix(in_dir).unwrap_or(path);
+        // in place the file is written next to the sample and renamed over it
+        let (path_out, tmp_path) = match out_dir {
+            Some(out_dir) => (out_dir.join(rel_path), None),
+            None => {
+                let mut name = path.file_name().unwrap_or_default().to_os_string();
+                name.push(".normalizing");
+                (path.to_path_buf(), Some(path.with_file_name(name)))
+            }
+        };
+        let write_path = tmp_path.as_deref().unwrap_or(&path_out);
+        let result = write_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| normalize_file(path, write_path, options))
+            .and_then(|report| {
+                match (&tmp_path, &report) {
+                    (Some(tmp_path), Some(_)) => fs::rename(tmp_path, &path_out)?,
+                    // binary files are copied as they are
+                    (None, None) => {
+                        fs::copy(path, &path_out)?;
+                    }
+                    _ => {}
+                }
+                Ok(report)
+            });
+
+        match result {
+            Ok(Some(report)) => {
+                normalized += 1;
+                println!("OK   {}: {}", rel_path.display(), report.summary());
+            }
+            Ok(None) => {
+                skipped += 1;
+                println!("SKIP {}: binary file, left as it is", rel_path.display());
+            }
+            Err(err) => {
+                failed += 1;
+                if let Some(tmp_path) = &tmp_path {
+                    fs::remove_file(tmp_path).ok();
+                }
+                println!("FAIL {}: {}", rel_path.display(), err);
+            }
+        }
+    }
+
+    println!(
+        "{} files normalized, {} binary files skipped, {} failed",
+        normalized, skipped, failed
+    );
+    Ok(failed)
+}
@@ -0,0 +1,124 @@
+use crate::parse::parse_duration;
+use crate::profile::ProfiledFile;
+use std::io::{self, BufWriter, LineWriter, Write};
+use std::time::{Duration, Instant};
+
+/// when a buffered output is flushed, see --buffered-writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// when the buffer is full, the output is switched or loggen stops
+    Full,
+    /// also this long after the first write that wasn't flushed
+    Every(Duration),
+}
+
+impl FlushPolicy {
+    pub fn parse(v: &str) -> Result<FlushPolicy, String> {
+        match v {
+            "full" => Ok(FlushPolicy::Full),
+            _ => match parse_duration(v) {
+                Ok(every) if !every.is_zero() => Ok(FlushPolicy::Every(every)),
+                _ => Err(format!(
+                    "{} should be full or a duration like 100ms or 1s",
+                    v
+                )),
+            },
+        }
+    }
+}
+
+pub fn is_flush_policy(v: String) -> Result<(), String> {
+    FlushPolicy::parse(&v).map(|_| ())
+}
+
+/// how output files are buffered, see --write-buffer and --buffered-writes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteBuffering {
+    /// buffer size, the std default if None
+    pub capacity: Option<usize>,
+    /// write whole buffers instead of flushing at each line ending
+    pub flush: Option<FlushPolicy>,
+}
+
+/// buffered writer of an output file, flushed at line endings by default
+#[derive(Debug)]
+pub enum OutWriter {
+    Line(LineWriter<ProfiledFile>),
+    Buffered {
+        writer: BufWriter<ProfiledFile>,
+        every: Option<Duration>,
+        /// when the first write that wasn't flushed happened
+        dirty_since: Option<Instant>,
+    },
+}
+
+impl OutWriter {
+    pub fn new(file: ProfiledFile, buffering: &WriteBuffering) -> OutWriter {
+        match (buffering.flush, buffering.capacity) {
+            (None, None) => OutWriter::Line(LineWriter::new(file)),
+            (None, Some(capacity)) => OutWriter::Line(LineWriter::with_capacity(capacity, file)),
+            (Some(policy), capacity) => OutWriter::Buffered {
+                writer: match capacity {
+                    Some(capacity) => BufWriter::with_capacity(capacity, file),
+                    None => BufWriter::new(file),
+                },
+                every: match policy {
+                    FlushPolicy::Full => None,
+                    FlushPolicy::Every(every) => Some(every),
+                },
+                dirty_since: None,
+            },
+        }
+    }
+
+    pub fn get_ref(&self) -> &ProfiledFile {
+        match self {
+            OutWriter::Line(writer) => writer.get_ref(),
+            OutWriter::Buffered { writer, .. } => writer.get_ref(),
+        }
+    }
+
+    /// flush if the flush interval passed since the first unflushed write
+    pub fn flush_due(&mut self) -> io::Result<()> {
+        match self {
+            OutWriter::Buffered {
+                every: Some(every),
+                dirty_since: Some(since),
+                ..
+            } if since.elapsed() >= *every => self.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Write for OutWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match self {
+            OutWriter::Line(writer) => return writer.write(buf),
+            OutWriter::Buffered {
+                writer,
+                dirty_since,
+                ..
+            } => {
+                dirty_since.get_or_insert_with(Instant::now);
+                writer.write(buf)?
+            }
+        };
+        self.flush_due()?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutWriter::Line(writer) => writer.flush(),
+            OutWriter::Buffered {
+                writer,
+                dirty_since,
+                ..
+            } => {
+                *dirty_since = None;
+                writer.flush()
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// longest sleep while waiting, so rate changes and stop requests are noticed
+const MAX_WAIT_STEP: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+struct PacerState {
+    /// lines per second for all workers together, None means no limit
+    rate: Option<f64>,
+    /// upper bound set by --max-memory and --max-cpu-pct while over a limit
+    cap: Option<f64>,
+    /// nothing is written while paused, whatever the rate
+    paused: bool,
+    next_slot: Instant,
+}
+
+/// time between lines at `rate`, None if it's too long to represent
+fn slot_len(rate: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(1.0 / rate).ok()
+}
+
+impl PacerState {
+    /// the rate or the cap, whichever is lower
+    fn limit(&self) -> Option<f64> {
+        match (self.rate, self.cap) {
+            (Some(rate), Some(cap)) => Some(rate.min(cap)),
+            (rate, None) => rate,
+            (None, cap) => cap,
+        }
+    }
+
+    fn effective_rate(&self) -> Option<f64> {
+        if self.paused {
+            return Some(0.0);
+        }
+        self.limit()
+    }
+}
+
+/// paces the lines written by all workers to a combined rate
+#[derive(Debug)]
+pub struct Pacer {
+    state: Mutex<PacerState>,
+}
+
+impl Pacer {
+    pub fn new(rate: Option<f64>) -> Pacer {
+        Pacer {
+            state: Mutex::new(PacerState {
+                rate,
+                cap: None,
+                paused: false,
+                next_slot: Instant::now(),
+            }),
+        }
+    }
+
+    /// change the rate, Some(0.0) stops all emission until it changes again
+    pub fn set_rate(&self, rate: Option<f64>) {
+        let mut state = self.state.lock().unwrap();
+        state.rate = rate;
+        state.next_slot = Instant::now();
+    }
+
+    pub fn rate(&self) -> Option<f64> {
+        self.state.lock().unwrap().rate
+    }
+
+    /// limit the rate to `cap` without changing the configured one, None removes it
+    pub fn set_cap(&self, cap: Option<f64>) {
+        self.state.lock().unwrap().cap = cap;
+    }
+
+    pub fn cap(&self) -> Option<f64> {
+        self.state.lock().unwrap().cap
+    }
+
+    /// the rate or the cap, whichever is lower, None without a limit
+    pub fn limit(&self) -> Option<f64> {
+        self.state.lock().unwrap().limit()
+    }
+
+    /// stop all emission until unpaused, keeping the rate and cap
+    pub fn set_paused(&self, paused: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.paused = paused;
+        state.next_slot = Instant::now();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// give back the slot taken by the last wait, for reads that didn't produce a line
+    pub fn refund(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(rate) = state.effective_rate() {
+            if let Some(slot) = slot_len(rate).filter(|_| rate > 0.0) {
+                state.next_slot = state.next_slot.checked_sub(slot).unwrap_or(state.next_slot);
+            }
+        }
+    }
+
+    /// take a slot if one is free now, otherwise how long until the next one
+    pub fn try_take(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        match state.effective_rate() {
+            None => Ok(()),
+            Some(rate) if rate > 0.0 => {
+                let now = Instant::now();
+                let slot = state.next_slot.max(now);
+                if slot > now {
+                    return Err(slot - now);
+                }
+                // a rate so low the next slot can't be represented is a pause
+                match slot_len(rate).and_then(|len| slot.checked_add(len)) {
+                    Some(next_slot) => {
+                        state.next_slot = next_slot;
+                        Ok(())
+                    }
+                    None => Err(MAX_WAIT_STEP),
+                }
+            }
+            Some(_) => Err(MAX_WAIT_STEP),
+        }
+    }
+
+    /// block until the caller may write its next line, returns early when stopped
+    pub fn wait(&self, stop: &AtomicBool) {
+        self.wait_for(stop, None);
+    }
+
+    /// like wait but gives up after `timeout`, true if the caller may write
+    pub fn wait_for(&self, stop: &AtomicBool, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        while !stop.load(Ordering::Relaxed) {
+            let wait = match self.try_take() {
+                Ok(()) => return true,
+                Err(wait) => wait,
+            };
+
+            let mut wait = wait.min(MAX_WAIT_STEP);
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return false;
+                }
+                wait = wait.min(deadline - now);
+            }
+            sleep(wait);
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pacer;
+
+    #[test]
+    fn tiny_rates_pause_instead_of_panicking() {
+        let pacer = Pacer::new(Some(1e-300));
+        assert!(pacer.try_take().is_err());
+        pacer.refund();
+        assert!(pacer.try_take().is_err());
+        pacer.set_rate(Some(1000.0));
+        assert!(pacer.try_take().is_ok());
+    }
+}
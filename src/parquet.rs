@@ -0,0 +1,90 @@
+use crate::source::Source;
+use parquet::file::reader::FileReader;
+use parquet::file::serialized_reader::SerializedFileReader;
+use parquet::record::reader::RowIter;
+use parquet::record::Field;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+/// parse "parquet:events.parquet" into the file path
+pub fn parse_url(url: &str) -> Result<PathBuf, String> {
+    match url.strip_prefix("parquet:") {
+        Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Err(format!("{} should be parquet:path", url)),
+    }
+}
+
+fn to_io_error(err: parquet::errors::ParquetError) -> io::Error {
+    io::Error::other(format!("parquet: {}", err))
+}
+
+/// iterates the rows of a parquet file emitting one column as the line
+pub struct ParquetSource {
+    path: PathBuf,
+    column: String,
+    rows: RowIter<'static>,
+}
+
+impl fmt::Debug for ParquetSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParquetSource")
+            .field("path", &self.path)
+            .field("column", &self.column)
+            .finish()
+    }
+}
+
+fn open_rows(path: &PathBuf, column: &str) -> io::Result<RowIter<'static>> {
+    let reader = SerializedFileReader::new(File::open(path)?).map_err(to_io_error)?;
+    let schema = reader.metadata().file_metadata().schema_descr();
+    if !schema.columns().iter().any(|c| c.name() == column) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("parquet: {:?} has no column {}", path, column),
+        ));
+    }
+
+    Ok(RowIter::from_file_into(Box::new(reader)))
+}
+
+impl ParquetSource {
+    pub fn new(path: PathBuf, column: &str) -> io::Result<ParquetSource> {
+        let rows = open_rows(&path, column)?;
+        Ok(ParquetSource {
+            path,
+            column: column.to_string(),
+            rows,
+        })
+    }
+}
+
+impl Source for ParquetSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let row = match self.rows.next() {
+            Some(row) => row.map_err(to_io_error)?,
+            None => return Ok(None),
+        };
+
+        let mut line = row
+            .get_column_iter()
+            .find(|(name, _)| **name == self.column)
+            .map(|(_, field)| match field {
+                Field::Str(value) => value.clone(),
+                Field::Null => String::new(),
+                other => other.to_string(),
+            })
+            .unwrap_or_default();
+        if !line.ends_with('\n') {
+            line.push('\n');
+        }
+
+        Ok(Some(line))
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.rows = open_rows(&self.path, &self.column)?;
+        Ok(())
+    }
+}
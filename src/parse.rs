@@ -0,0 +1,158 @@
+use chrono::format::{Item, StrftimeItems};
+use std::time::Duration;
+
+/// parse "0.5%" or "0.5" as a fraction between 0.0 and 1.0
+pub fn parse_percentage(v: &str) -> Result<f64, String> {
+    let num = v.trim().trim_end_matches('%');
+    match num.parse::<f64>() {
+        Ok(pct) if (0.0..=100.0).contains(&pct) => Ok(pct / 100.0),
+        _ => Err(format!("{} isn't a percentage between 0 and 100", v)),
+    }
+}
+
+pub fn is_percentage(v: String) -> Result<(), String> {
+    parse_percentage(&v).map(|_| ())
+}
+
+/// lowest rate other than 0, a line every 1000 seconds
+pub const MIN_RATE: f64 = 0.001;
+
+/// true for 0, which stops emission, and for rates from MIN_RATE up
+pub fn is_valid_rate(rate: f64) -> bool {
+    rate == 0.0 || (rate >= MIN_RATE && rate.is_finite())
+}
+
+/// parse a rate in lines per second like "100lps"
+pub fn parse_rate(v: &str) -> Result<f64, String> {
+    match v.strip_suffix("lps").map(|num| num.parse::<f64>()) {
+        Some(Ok(rate)) if is_valid_rate(rate) => Ok(rate),
+        Some(Ok(_)) => Err(format!(
+            "{} isn't a valid rate, it should be 0 or at least {}lps",
+            v, MIN_RATE
+        )),
+        _ => Err(format!("{} isn't a valid rate, use for example 100lps", v)),
+    }
+}
+
+/// error for `v` when it isn't one of the `valid` names of a `what`
+pub fn unknown_value(what: &str, v: &str, valid: &[&str]) -> String {
+    format!(
+        "{} isn't a valid {}, expected one of: {}",
+        v,
+        what,
+        valid.join(", ")
+    )
+}
+
+/// parse "key=value,key=value" into pairs, keeping the order they were given
+pub fn parse_kv_list(v: &str) -> Result<Vec<(String, String)>, String> {
+    v.split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| match part.find('=') {
+            Some(pos) => Ok((
+                part[..pos].trim().to_string(),
+                part[pos + 1..].trim().to_string(),
+            )),
+            None => Err(format!("{} isn't a key=value pair", part)),
+        })
+        .collect()
+}
+
+/// parse durations like "250ms", "10s", "5m", "1h", a number without unit is in seconds
+pub fn parse_duration(v: &str) -> Result<Duration, String> {
+    let v = v.trim();
+    let split = v
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(v.len());
+    let (num, unit) = v.split_at(split);
+    let num = match num.parse::<f64>() {
+        Ok(num) if num >= 0.0 => num,
+        _ => return Err(format!("{} isn't a valid duration", v)),
+    };
+
+    let secs = match unit.trim() {
+        "ms" => num / 1000.0,
+        "" | "s" => num,
+        "m" => num * 60.0,
+        "h" => num * 3600.0,
+        "d" => num * 86400.0,
+        _ => {
+            return Err(format!(
+                "{} isn't a valid duration unit, use ms, s, m, h or d",
+                unit
+            ))
+        }
+    };
+
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("{} is too long", v))
+}
+
+pub fn is_duration(v: String) -> Result<(), String> {
+    parse_duration(&v).map(|_| ())
+}
+
+/// true if `format` is a strftime format chrono can render
+pub fn is_valid_date_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| item == Item::Error)
+}
+
+/// parse counts like "5000", "10K", "100M" or "1G" (1K = 1000)
+pub fn parse_count(v: &str) -> Result<u64, String> {
+    let v = v.trim();
+    let split = v.find(|c: char| !c.is_ascii_digit()).unwrap_or(v.len());
+    let (num, unit) = v.split_at(split);
+    let num = num
+        .parse::<u64>()
+        .map_err(|_| format!("{} isn't a valid count", v))?;
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" => 1_000,
+        "M" => 1_000_000,
+        "G" => 1_000_000_000,
+        _ => return Err(format!("{} isn't a valid count unit, use K, M or G", unit)),
+    };
+
+    num.checked_mul(multiplier)
+        .ok_or_else(|| format!("{} is too big", v))
+}
+
+pub fn is_count(v: String) -> Result<(), String> {
+    parse_count(&v).map(|_| ())
+}
+
+/// parse sizes like "512", "16KB", "4MB" or "1GB" in bytes (1KB = 1024 bytes)
+pub fn parse_size(v: &str) -> Result<u64, String> {
+    let v = v.trim();
+    let split = v.find(|c: char| !c.is_ascii_digit()).unwrap_or(v.len());
+    let (num, unit) = v.split_at(split);
+    let num = num
+        .parse::<u64>()
+        .map_err(|_| format!("{} isn't a valid size", v))?;
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        _ => {
+            return Err(format!(
+                "{} isn't a valid size unit, use B, KB, MB or GB",
+                unit
+            ))
+        }
+    };
+
+    num.checked_mul(multiplier)
+        .ok_or_else(|| format!("{} isn't a valid size", v))
+}
+
+pub fn is_size(v: String) -> Result<(), String> {
+    parse_size(&v).map(|_| ())
+}
+
+/// a size for a buffer, which can't be empty
+pub fn is_buffer_size(v: String) -> Result<(), String> {
+    match parse_size(&v)? {
+        0 => Err("a buffer size should be greater than 0".to_string()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,82 @@
+use crate::parse::unknown_value;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub enum OutPartition {
+    None,
+    Hourly,
+    Daily,
+}
+
+impl FromStr for OutPartition {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<OutPartition, String> {
+        match v {
+            "none" => Ok(OutPartition::None),
+            "hourly" => Ok(OutPartition::Hourly),
+            "daily" => Ok(OutPartition::Daily),
+            _ => Err(unknown_value("out partition", v, OutPartition::NAMES)),
+        }
+    }
+}
+
+impl OutPartition {
+    pub const NAMES: &'static [&'static str] = &["none", "hourly", "daily"];
+
+    /// relative directory for the partition that contains `now`, None if not partitioning
+    pub fn dir_for(&self, now: DateTime<Utc>) -> Option<PathBuf> {
+        match self {
+            OutPartition::None => None,
+            OutPartition::Hourly => Some(
+                Path::new(&now.format("%Y-%m-%d").to_string()).join(now.format("%H").to_string()),
+            ),
+            OutPartition::Daily => Some(PathBuf::from(now.format("%Y-%m-%d").to_string())),
+        }
+    }
+
+    /// full output path for `rel_path` under `out_base` at time `now`
+    pub fn path_for(&self, out_base: &Path, rel_path: &Path, now: DateTime<Utc>) -> PathBuf {
+        match self.dir_for(now) {
+            Some(dir) => out_base.join(dir).join(rel_path),
+            None => out_base.join(rel_path),
+        }
+    }
+}
+
+/// what happens to the output file when its sample wraps
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutFilePolicy {
+    /// keep writing to the same file, as --wrap-strategy says
+    Reuse,
+    /// close it and start a new file with the next number, files are never written again
+    NewPerWrap,
+}
+
+impl OutFilePolicy {
+    pub const NAMES: &'static [&'static str] = &["reuse", "new-per-wrap"];
+}
+
+impl FromStr for OutFilePolicy {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<OutFilePolicy, String> {
+        match v {
+            "reuse" => Ok(OutFilePolicy::Reuse),
+            "new-per-wrap" => Ok(OutFilePolicy::NewPerWrap),
+            _ => Err(unknown_value("out file policy", v, OutFilePolicy::NAMES)),
+        }
+    }
+}
+
+/// `path` with `n` before its extension, access.log is access.3.log for n = 3
+pub fn numbered_path(path: &Path, n: u64) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, n),
+    };
+    path.with_file_name(file_name)
+}
@@ -0,0 +1,121 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{chown, PermissionsExt};
+use std::path::Path;
+
+/// mode and owner set on output files when loggen creates them
+#[derive(Debug, Clone, Default)]
+pub struct OutPerms {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// octal permission bits like "0640" or "644"
+pub fn parse_mode(v: &str) -> Result<u32, String> {
+    match u32::from_str_radix(v, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("{} isn't an octal file mode like 0640", v)),
+    }
+}
+
+pub fn is_mode(v: String) -> Result<(), String> {
+    parse_mode(&v).map(|_| ())
+}
+
+fn lookup_uid(name: &str) -> Result<u32, String> {
+    if let Ok(uid) = name.parse::<u32>() {
+        return Ok(uid);
+    }
+
+    let c_name = CString::new(name).map_err(|_| format!("invalid user {}", name))?;
+    let passwd = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if passwd.is_null() {
+        return Err(format!("unknown user {}", name));
+    }
+    Ok(unsafe { (*passwd).pw_uid })
+}
+
+fn lookup_gid(name: &str) -> Result<u32, String> {
+    if let Ok(gid) = name.parse::<u32>() {
+        return Ok(gid);
+    }
+
+    let c_name = CString::new(name).map_err(|_| format!("invalid group {}", name))?;
+    let group = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if group.is_null() {
+        return Err(format!("unknown group {}", name));
+    }
+    Ok(unsafe { (*group).gr_gid })
+}
+
+/// "user:group", "user" or ":group", names or numeric ids
+pub fn parse_owner(v: &str) -> Result<(Option<u32>, Option<u32>), String> {
+    let (user, group) = match v.split_once(':') {
+        Some((user, group)) => (user, group),
+        None => (v, ""),
+    };
+    if user.is_empty() && group.is_empty() {
+        return Err(format!("{} should be user:group, user or :group", v));
+    }
+
+    let uid = if user.is_empty() {
+        None
+    } else {
+        Some(lookup_uid(user)?)
+    };
+    let gid = if group.is_empty() {
+        None
+    } else {
+        Some(lookup_gid(group)?)
+    };
+    Ok((uid, gid))
+}
+
+pub fn is_owner(v: String) -> Result<(), String> {
+    parse_owner(&v).map(|_| ())
+}
+
+impl OutPerms {
+    /// set the mode and owner of a file that was just created
+    pub fn apply(&self, path: &Path) -> io::Result<()> {
+        if let Some(mode) = self.mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        if self.uid.is_some() || self.gid.is_some() {
+            chown(path, self.uid, self.gid)?;
+        }
+
+        Ok(())
+    }
+
+    /// fail at startup if files in `out_dir` can't get the requested owner,
+    /// changing it needs root or CAP_CHOWN (a group the user is in is enough for the group)
+    pub fn check(&self, out_dir: &Path) -> io::Result<()> {
+        if self.uid.is_none() && self.gid.is_none() && self.mode.is_none() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(out_dir)?;
+        let probe = out_dir.join(format!(".loggen-perms-check-{}", std::process::id()));
+        fs::write(&probe, "")?;
+        let result = self.apply(&probe);
+        fs::remove_file(&probe)?;
+
+        result.map_err(|err| {
+            if err.kind() == io::ErrorKind::PermissionDenied {
+                io::Error::new(
+                    err.kind(),
+                    format!(
+                        "can't set the owner of files in {}, it needs root or CAP_CHOWN: {}",
+                        out_dir.display(),
+                        err
+                    ),
+                )
+            } else {
+                err
+            }
+        })
+    }
+}
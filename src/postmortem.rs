@@ -0,0 +1,231 @@
+use crate::pacer::Pacer;
+use crate::sink::Sink;
+use crate::stats::{FileStatus, Stats};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, Instant};
+
+const PREFIX: &str = "state-";
+
+/// where, how often and how many state files to keep, see --state-dir
+#[derive(Debug, Clone)]
+pub struct StateDumps {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    pub keep: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct LastError {
+    time: String,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct FileState {
+    #[serde(flatten)]
+    status: FileStatus,
+    /// lines per second since the previous dump
+    lines_per_sec: f64,
+    worker: Option<usize>,
+    /// how late the last read of the file was
+    behind_ms: u64,
+    last_error: Option<LastError>,
+}
+
+#[derive(Debug, Serialize)]
+struct WorkerState {
+    /// files the worker read last
+    files: usize,
+    /// how late the latest of them is
+    behind_ms: u64,
+}
+
+/// what the generators were doing at `time`
+#[derive(Debug, Serialize)]
+struct State {
+    /// dumps are numbered from 1, the highest in the ring is the latest
+    seq: u64,
+    time: String,
+    uptime_secs: u64,
+    ready: bool,
+    /// --rate or the rate of the current scenario phase, lowered by --max-memory
+    /// and --max-cpu
+    rate_limit: Option<f64>,
+    lines_per_sec: f64,
+    sink_errors: u64,
+    workers: BTreeMap<usize, WorkerState>,
+    files: Vec<FileState>,
+}
+
+/// writes the state of the generators to a ring of files
+#[derive(Debug)]
+struct Dumper {
+    config: StateDumps,
+    stats: Arc<Stats>,
+    sink: Option<Arc<dyn Sink>>,
+    pacer: Arc<Pacer>,
+    seq: u64,
+    /// lines of each file at the previous dump, files are only ever added
+    last_lines: Vec<u64>,
+    last_dump: Instant,
+}
+
+impl Dumper {
+    fn state(&mut self) -> State {
+        let secs = self.last_dump.elapsed().as_secs_f64().max(0.001);
+        self.last_dump = Instant::now();
+        self.seq += 1;
+        let mut workers: BTreeMap<usize, WorkerState> = BTreeMap::new();
+        let mut files = vec![];
+        for (index, file_stats) in self.stats.files().iter().enumerate() {
+            let status = file_stats.status();
+            if index == self.last_lines.len() {
+                self.last_lines.push(0);
+            }
+            let lines = status.lines - self.last_lines[index];
+            self.last_lines[index] = status.lines;
+            let worker = file_stats.worker();
+            let behind_ms = file_stats.behind_ms();
+            if let Some(worker) = worker {
+                let state = workers.entry(worker).or_insert(WorkerState {
+                    files: 0,
+                    behind_ms: 0,
+                });
+                state.files += 1;
+                state.behind_ms = state.behind_ms.max(behind_ms);
+            }
+            files.push(FileState {
+                status,
+                lines_per_sec: lines as f64 / secs,
+                worker,
+                behind_ms,
+                last_error: file_stats.last_error().map(|(time, message)| LastError {
+                    time: time.to_rfc3339_opts(SecondsFormat::Millis, true),
+                    message,
+                }),
+            });
+        }
+
+        State {
+            seq: self.seq,
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            uptime_secs: self.stats.uptime().as_secs(),
+            ready: self.stats.is_ready(),
+            rate_limit: self.pacer.limit(),
+            lines_per_sec: files.iter().map(|file| file.lines_per_sec).sum(),
+            sink_errors: self.sink.as_ref().map_or(0, |sink| sink.errors()),
+            workers,
+            files,
+        }
+    }
+
+    /// write the next file of the ring, replacing the oldest
+    fn dump(&mut self) -> io::Result<()> {
+        let state = self.state();
+        let json = serde_json::to_string_pretty(&state).map_err(io::Error::other)?;
+        let slot = (state.seq - 1) % self.config.keep as u64;
+        let path = self.config.dir.join(format!("{}{:04}.json", PREFIX, slot));
+        // write and rename so a crash never leaves a partial dump
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, json)?;
+        fs::rename(&tmp_path, &path)
+    }
+}
+
+/// dumps state every interval until finished
+#[derive(Debug)]
+pub struct StateWriter {
+    done: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl StateWriter {
+    /// workers finished, the last dump has the state they stopped in
+    pub fn finish(self) {
+        self.done.store(true, Ordering::Relaxed);
+        if self.handle.join().is_err() {
+            eprintln!("Error in state dump thread");
+        }
+    }
+}
+
+/// dump the state of the generators to `config.dir` every `config.interval`
+/// from a background thread
+pub fn start(
+    config: StateDumps,
+    stats: Arc<Stats>,
+    sink: Option<Arc<dyn Sink>>,
+    pacer: Arc<Pacer>,
+) -> io::Result<StateWriter> {
+    fs::create_dir_all(&config.dir)?;
+    check_dir(&config.dir)?;
+    let done = Arc::new(AtomicBool::new(false));
+    let thread_done = done.clone();
+    let interval = config.interval;
+    let mut dumper = Dumper {
+        seq: last_seq(&config.dir),
+        config,
+        stats,
+        sink,
+        pacer,
+        last_lines: vec![],
+        last_dump: Instant::now(),
+    };
+
+    let handle = thread::spawn(move || {
+        let mut next = Instant::now() + interval;
+        loop {
+            let finishing = thread_done.load(Ordering::Relaxed);
+            if finishing || Instant::now() >= next {
+                if let Err(err) = dumper.dump() {
+                    eprintln!("Error writing state to {:?}: {}", dumper.config.dir, err);
+                }
+                next += interval;
+            }
+            if finishing {
+                return;
+            }
+            sleep(
+                next.saturating_duration_since(Instant::now())
+                    .min(Duration::from_millis(100)),
+            );
+        }
+    });
+
+    Ok(StateWriter { done, handle })
+}
+
+/// highest seq of the dumps already in `dir`, a new run goes on around the ring
+/// from there so the highest is still the latest
+fn last_seq(dir: &Path) -> u64 {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.starts_with(PREFIX) && name.ends_with(".json")
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .filter_map(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .filter_map(|state| state["seq"].as_u64())
+        .max()
+        .unwrap_or(0)
+}
+
+/// fail early if state can't be written to `dir`
+fn check_dir(dir: &Path) -> io::Result<()> {
+    let path = dir.join(".loggen-check");
+    fs::write(&path, b"")?;
+    fs::remove_file(&path)
+}
@@ -0,0 +1,152 @@
+use crate::backpressure::FullQueue;
+use crate::stats::FileStats;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// io counters of an output, only updated with --profile-io
+#[derive(Debug, Default)]
+pub struct IoCounters {
+    /// write syscalls on the output file or sends to the sink
+    calls: AtomicU64,
+    bytes: AtomicU64,
+    flushes: AtomicU64,
+    opens: AtomicU64,
+    blocked_nanos: AtomicU64,
+}
+
+impl IoCounters {
+    pub fn called(&self, bytes: usize, blocked: Duration) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.blocked_nanos
+            .fetch_add(blocked.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn flushed(&self, blocked: Duration) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        self.blocked_nanos
+            .fetch_add(blocked.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn opened(&self) {
+        self.opens.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// output file that counts the write syscalls made on it when profiling
+#[derive(Debug)]
+pub struct ProfiledFile {
+    file: File,
+    stats: Option<Arc<FileStats>>,
+    /// for pipes with --block-on-full
+    full: Option<FullQueue>,
+}
+
+impl ProfiledFile {
+    pub fn new(file: File, stats: Option<Arc<FileStats>>) -> ProfiledFile {
+        if let Some(stats) = &stats {
+            stats.io.opened();
+        }
+
+        ProfiledFile {
+            file,
+            stats,
+            full: None,
+        }
+    }
+
+    /// write through `full`, the file must be non blocking
+    pub fn with_full_queue(mut self, full: FullQueue) -> ProfiledFile {
+        self.full = Some(full);
+        self
+    }
+
+    fn write_file(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.full {
+            Some(full) => full.write(&mut self.file, buf).map(|_| buf.len()),
+            None => self.file.write(buf),
+        }
+    }
+
+    fn flush_file(&mut self) -> io::Result<()> {
+        match &mut self.full {
+            Some(full) => full.drain(&mut self.file),
+            None => self.file.flush(),
+        }
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+impl Write for ProfiledFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let started = self.stats.as_ref().map(|_| Instant::now());
+        let written = self.write_file(buf)?;
+        if let (Some(stats), Some(started)) = (&self.stats, started) {
+            stats.io.called(written, started.elapsed());
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let started = self.stats.as_ref().map(|_| Instant::now());
+        self.flush_file()?;
+        if let (Some(stats), Some(started)) = (&self.stats, started) {
+            stats.io.flushed(started.elapsed());
+        }
+        Ok(())
+    }
+}
+
+/// print the io counters of every output, `sink` is true when lines went to a
+/// sink instead of files
+pub fn report(files: &[Arc<FileStats>], elapsed: Duration, sink: bool) {
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    let calls_name = if sink { "sends" } else { "writes" };
+    println!(
+        "{:>12} {:>10} {:>12} {:>10} {:>8} {:>12} {:>9}  path",
+        calls_name, "per line", "bytes/call", "flushes", "opens", "blocked ms", "blocked%"
+    );
+
+    let mut total_lines = 0;
+    let total = IoCounters::default();
+    for file in files {
+        let lines = file.status().lines;
+        print_row(lines, &file.io, secs, &file.rel_path.display().to_string());
+
+        total_lines += lines;
+        for (sum, counter) in [
+            (&total.calls, &file.io.calls),
+            (&total.bytes, &file.io.bytes),
+            (&total.flushes, &file.io.flushes),
+            (&total.opens, &file.io.opens),
+            (&total.blocked_nanos, &file.io.blocked_nanos),
+        ] {
+            sum.fetch_add(counter.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    // blocked time is summed over all outputs, so its share can pass 100% with many workers
+    print_row(total_lines, &total, secs, &format!("total in {:.2}s", secs));
+}
+
+fn print_row(lines: u64, io: &IoCounters, secs: f64, name: &str) {
+    let calls = io.calls.load(Ordering::Relaxed);
+    let blocked = io.blocked_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+    println!(
+        "{:>12} {:>10.2} {:>12.0} {:>10} {:>8} {:>12.1} {:>8.1}%  {}",
+        calls,
+        calls as f64 / lines.max(1) as f64,
+        io.bytes.load(Ordering::Relaxed) as f64 / calls.max(1) as f64,
+        io.flushes.load(Ordering::Relaxed),
+        io.opens.load(Ordering::Relaxed),
+        blocked * 1000.0,
+        blocked * 100.0 / secs,
+        name
+    );
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// changes of the worker count asked for while running, see --control-socket
+#[derive(Debug, Default)]
+pub struct Rebalance {
+    /// workers running now
+    current: AtomicUsize,
+    /// workers asked for, 0 when nothing is pending
+    requested: AtomicUsize,
+    /// set while workers hand their files back to be distributed again
+    yielding: AtomicBool,
+}
+
+impl Rebalance {
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    pub fn set_current(&self, workers: usize) {
+        self.current.store(workers, Ordering::Relaxed);
+    }
+
+    /// ask for `workers` workers, a later request before it's applied replaces it
+    pub fn request(&self, workers: usize) {
+        self.requested.store(workers, Ordering::Relaxed);
+    }
+
+    /// the pending request, if any, clearing it
+    pub fn take_request(&self) -> Option<usize> {
+        match self.requested.swap(0, Ordering::Relaxed) {
+            0 => None,
+            workers => Some(workers),
+        }
+    }
+
+    pub fn is_yielding(&self) -> bool {
+        self.yielding.load(Ordering::Relaxed)
+    }
+
+    pub fn set_yielding(&self, yielding: bool) {
+        self.yielding.store(yielding, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::parse::unknown_value;
+use crate::source::Source;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// records bigger than this are taken as a sign of the wrong framing
+const MAX_RECORD: u64 = 64 * 1024 * 1024;
+/// a varint for a u64 takes at most 10 bytes
+const MAX_VARINT: usize = 10;
+
+/// how binary records are delimited, see --records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// 4 byte big endian length before each record
+    U32Be,
+    /// 4 byte little endian length before each record
+    U32Le,
+    /// protobuf varint length before each record, like writeDelimitedTo
+    Varint,
+}
+
+impl Framing {
+    pub const NAMES: &'static [&'static str] = &["u32be", "u32le", "varint"];
+
+    /// `record` with its length prefix
+    pub fn frame(&self, record: &[u8]) -> Vec<u8> {
+        let len = record.len() as u64;
+        let mut framed = Vec::with_capacity(record.len() + MAX_VARINT);
+        match self {
+            Framing::U32Be => framed.extend_from_slice(&(len as u32).to_be_bytes()),
+            Framing::U32Le => framed.extend_from_slice(&(len as u32).to_le_bytes()),
+            Framing::Varint => {
+                let mut rest = len;
+                while rest >= 0x80 {
+                    framed.push((rest as u8 & 0x7f) | 0x80);
+                    rest >>= 7;
+                }
+                framed.push(rest as u8);
+            }
+        }
+        framed.extend_from_slice(record);
+        framed
+    }
+
+    /// length prefix of the next record and its size, None at the end
+    fn read_len<R: BufRead>(&self, reader: &mut R) -> io::Result<Option<(u64, usize)>> {
+        if reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
+        let mut prefix = [0; 4];
+        match self {
+            Framing::U32Be => {
+                reader.read_exact(&mut prefix).map_err(truncated)?;
+                Ok(Some((u32::from_be_bytes(prefix) as u64, 4)))
+            }
+            Framing::U32Le => {
+                reader.read_exact(&mut prefix).map_err(truncated)?;
+                Ok(Some((u32::from_le_bytes(prefix) as u64, 4)))
+            }
+            Framing::Varint => {
+                let mut len = 0;
+                for size in 1..=MAX_VARINT {
+                    let mut byte = [0];
+                    reader.read_exact(&mut byte).map_err(truncated)?;
+                    len |= ((byte[0] & 0x7f) as u64) << (7 * (size - 1));
+                    if byte[0] & 0x80 == 0 {
+                        return Ok(Some((len, size)));
+                    }
+                }
+                Err(invalid(
+                    "varint length prefix is longer than 10 bytes".to_string(),
+                ))
+            }
+        }
+    }
+
+    /// next record without its prefix and the bytes read for it, None at the end
+    pub fn read<R: BufRead>(&self, reader: &mut R) -> io::Result<Option<(Vec<u8>, u64)>> {
+        let (len, prefix_size) = match self.read_len(reader)? {
+            Some(prefix) => prefix,
+            None => return Ok(None),
+        };
+        if len > MAX_RECORD {
+            return Err(invalid(format!(
+                "record of {} bytes is over the {} bytes limit, check the --records framing",
+                len, MAX_RECORD
+            )));
+        }
+
+        let mut record = vec![0; len as usize];
+        reader.read_exact(&mut record).map_err(truncated)?;
+        Ok(Some((record, prefix_size as u64 + len)))
+    }
+}
+
+impl FromStr for Framing {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<Framing, String> {
+        match v {
+            "u32be" => Ok(Framing::U32Be),
+            "u32le" => Ok(Framing::U32Le),
+            "varint" => Ok(Framing::Varint),
+            _ => Err(unknown_value("record framing", v, Framing::NAMES)),
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn truncated(err: io::Error) -> io::Error {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        invalid("sample ends in the middle of a record".to_string())
+    } else {
+        err
+    }
+}
+
+/// sample file of framed binary records, opened lazily and reopened at the
+/// same offset after close
+#[derive(Debug)]
+pub struct RecordSource {
+    path: PathBuf,
+    framing: Framing,
+    reader: Option<BufReader<File>>,
+    offset: u64,
+    /// reader buffer size, the std default if None
+    capacity: Option<usize>,
+}
+
+impl RecordSource {
+    pub fn new(path: PathBuf, framing: Framing, capacity: Option<usize>) -> RecordSource {
+        RecordSource {
+            path,
+            framing,
+            reader: None,
+            offset: 0,
+            capacity,
+        }
+    }
+
+    fn reader(&mut self) -> io::Result<&mut BufReader<File>> {
+        if self.reader.is_none() {
+            let file = File::open(&self.path)?;
+            let mut reader = match self.capacity {
+                Some(capacity) => BufReader::with_capacity(capacity, file),
+                None => BufReader::new(file),
+            };
+            reader.seek(SeekFrom::Start(self.offset))?;
+            self.reader = Some(reader);
+        }
+
+        Ok(self.reader.as_mut().unwrap())
+    }
+}
+
+impl Source for RecordSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "binary samples are read as records",
+        ))
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let framing = self.framing;
+        match framing.read(self.reader()?) {
+            Ok(Some((record, size))) => {
+                self.offset += size;
+                Ok(Some(record))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => {
+                // the reader stopped inside a record, reopen it where it started
+                self.reader = None;
+                Err(err)
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        match &mut self.reader {
+            Some(reader) => reader.seek(SeekFrom::Start(0)).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn close(&mut self) {
+        self.reader = None;
+    }
+}
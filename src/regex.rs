@@ -0,0 +1,747 @@
+/// a small regex for matching lines, supports literals, `.`,
+/// classes like `[a-z]` and `[^0-9]`, `\d \w \s` (and `\D \W \S`), anchors
+/// `^ $`, groups with alternatives `(a|b)` and quantifiers `* + ? {n} {n,}
+/// {n,m}`, lazy with a `?` after them, groups capture unless they are
+/// `(?:...)` and can be named with `(?<name>...)` or `(?P<name>...)`, other
+/// syntax is rejected rather than matched differently than expected
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    /// name of each capture group, in order
+    names: Vec<Option<String>>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Group {
+        /// each a sequence
+        alternatives: Vec<Vec<Node>>,
+        capture: Option<usize>,
+    },
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        /// as few as possible instead of as many
+        lazy: bool,
+    },
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    names: Vec<Option<String>>,
+}
+
+impl<'a> Parser<'a> {
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.chars.peek() == Some(&'|') {
+            self.chars.next();
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = vec![];
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.chars.next().unwrap() {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '(' => {
+                let capture = if self.chars.peek() == Some(&'?') {
+                    self.chars.next();
+                    match self.chars.next() {
+                        Some(':') => None,
+                        Some('P') if self.chars.next() == Some('<') => Some(self.name()?),
+                        Some('<') => Some(self.name()?),
+                        _ => {
+                            return Err(
+                                "only (?:...) and (?<name>...) groups are supported".to_string()
+                            )
+                        }
+                    }
+                } else {
+                    Some(None)
+                };
+                let capture = capture.map(|name| {
+                    self.names.push(name);
+                    self.names.len() - 1
+                });
+                let alternatives = self.alternatives()?;
+                match self.chars.next() {
+                    Some(')') => Ok(Node::Group {
+                        alternatives,
+                        capture,
+                    }),
+                    _ => Err("missing )".to_string()),
+                }
+            }
+            '[' => self.class(),
+            '\\' => self.escape(),
+            c @ ('*' | '+' | '?' | '{') => Err(format!("nothing to repeat before {}", c)),
+            ')' => Err("unmatched )".to_string()),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    /// name of a capture group up to the closing >
+    fn name(&mut self) -> Result<Option<String>, String> {
+        let mut name = String::new();
+        loop {
+            match self.chars.next() {
+                Some('>') if !name.is_empty() => return Ok(Some(name)),
+                Some(c) if c.is_alphanumeric() || c == '_' => name.push(c),
+                _ => return Err("invalid group name".to_string()),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let class = |ranges: &[(char, char)], negated| Node::Class {
+            ranges: ranges.to_vec(),
+            negated,
+        };
+        match self.chars.next() {
+            Some('d') => Ok(class(DIGIT, false)),
+            Some('D') => Ok(class(DIGIT, true)),
+            Some('w') => Ok(class(WORD, false)),
+            Some('W') => Ok(class(WORD, true)),
+            Some('s') => Ok(class(SPACE, false)),
+            Some('S') => Ok(class(SPACE, true)),
+            Some(c) => Ok(Node::Char(escaped(c)?)),
+            None => Err("pattern ends with \\".to_string()),
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.chars.peek() == Some(&'^');
+        if negated {
+            self.chars.next();
+        }
+
+        let mut ranges = vec![];
+        let mut first = true;
+        loop {
+            let c = match self.chars.next() {
+                Some(']') if !first => break,
+                Some('\\') => match self.chars.next() {
+                    Some('d') => {
+                        ranges.extend_from_slice(DIGIT);
+                        continue;
+                    }
+                    Some('w') => {
+                        ranges.extend_from_slice(WORD);
+                        continue;
+                    }
+                    Some('s') => {
+                        ranges.extend_from_slice(SPACE);
+                        continue;
+                    }
+                    Some(c @ ('D' | 'W' | 'S')) => {
+                        return Err(format!("\\{} isn't supported in classes", c))
+                    }
+                    Some(c) => escaped(c)?,
+                    None => return Err("missing ]".to_string()),
+                },
+                Some(c) => c,
+                None => return Err("missing ]".to_string()),
+            };
+            first = false;
+
+            let mut lookahead = self.chars.clone();
+            if lookahead.next() == Some('-') && !matches!(lookahead.peek(), Some(']') | None) {
+                self.chars.next();
+                let end = match self.chars.next() {
+                    Some('\\') => match self.chars.next() {
+                        Some(c) => escaped(c)?,
+                        None => return Err("missing ]".to_string()),
+                    },
+                    Some(end) => end,
+                    None => return Err("missing ]".to_string()),
+                };
+                if end < c {
+                    return Err(format!("invalid range {}-{}", c, end));
+                }
+                ranges.push((c, end));
+            } else {
+                ranges.push((c, c));
+            }
+        }
+
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let mut digits = String::new();
+        while let Some(c) = self.chars.peek().filter(|c| c.is_ascii_digit()) {
+            digits.push(*c);
+            self.chars.next();
+        }
+        digits.parse().ok()
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.chars.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.chars.next();
+                let min = self.number().ok_or("{ needs a count")?;
+                let max = match self.chars.next() {
+                    Some('}') => Some(min),
+                    Some(',') => {
+                        let max = self.number();
+                        if self.chars.next() != Some('}') {
+                            return Err("missing }".to_string());
+                        }
+                        max
+                    }
+                    _ => return Err("missing }".to_string()),
+                };
+                if max.is_some_and(|max| max < min) {
+                    return Err(format!("invalid repetition {{{},{:?}}}", min, max));
+                }
+                return self.repeat(atom, min, max);
+            }
+            _ => return Ok(atom),
+        };
+        self.chars.next();
+        self.repeat(atom, min, max)
+    }
+
+    fn repeat(&mut self, atom: Node, min: usize, max: Option<usize>) -> Result<Node, String> {
+        if matches!(atom, Node::Start | Node::End) {
+            return Err("anchors can't be repeated".to_string());
+        }
+        let lazy = self.chars.peek() == Some(&'?');
+        if lazy {
+            self.chars.next();
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            lazy,
+        })
+    }
+}
+
+/// the char `\c` stands for, letters and digits without a meaning here are
+/// rejected so `\b` or `\1` don't silently match a b or a 1
+fn escaped(c: char) -> Result<char, String> {
+    match c {
+        't' => Ok('\t'),
+        'n' => Ok('\n'),
+        'r' => Ok('\r'),
+        c if c.is_alphanumeric() => Err(format!("\\{} isn't supported", c)),
+        c => Ok(c),
+    }
+}
+
+impl Node {
+    /// whether `c` matches, None if this node isn't a single char
+    fn matches_char(&self, c: char) -> Option<bool> {
+        match self {
+            Node::Char(expected) => Some(*expected == c),
+            Node::Any => Some(c != '\n'),
+            Node::Class { ranges, negated } => {
+                let found = ranges.iter().any(|(from, to)| (*from..=*to).contains(&c));
+                Some(found != *negated)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// most instructions a pattern compiles to, counted repetitions are copied
+const MAX_PROGRAM: usize = 100_000;
+
+/// what patterns compile to, run by `Regex::find_at`
+#[derive(Debug, Clone)]
+enum Inst {
+    /// a Char, Any or Class node matching the next char
+    One(Node),
+    Start,
+    End,
+    /// continue at both, the first is preferred
+    Split(usize, usize),
+    Jump(usize),
+    /// remember the position in a capture slot
+    Save(usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, String> {
+        if self.program.len() >= MAX_PROGRAM {
+            return Err("too many repetitions".to_string());
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    /// point the split at `at` to the next instruction and `out`, in the
+    /// order the repetition prefers them
+    fn patch_split(&mut self, at: usize, out: usize, lazy: bool) {
+        self.program[at] = if lazy {
+            Inst::Split(out, at + 1)
+        } else {
+            Inst::Split(at + 1, out)
+        };
+    }
+
+    fn sequence(&mut self, nodes: &[Node]) -> Result<(), String> {
+        nodes.iter().try_for_each(|node| self.node(node))
+    }
+
+    fn alternatives(&mut self, alternatives: &[Vec<Node>]) -> Result<(), String> {
+        let (last, rest) = alternatives.split_last().unwrap();
+        let mut jumps = vec![];
+        for alternative in rest {
+            let split = self.emit(Inst::Split(0, 0))?;
+            self.sequence(alternative)?;
+            jumps.push(self.emit(Inst::Jump(0))?);
+            self.program[split] = Inst::Split(split + 1, self.program.len());
+        }
+        self.sequence(last)?;
+        for jump in jumps {
+            self.program[jump] = Inst::Jump(self.program.len());
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Start => self.emit(Inst::Start).map(|_| ()),
+            Node::End => self.emit(Inst::End).map(|_| ()),
+            Node::Group {
+                alternatives,
+                capture,
+            } => {
+                if let Some(index) = capture {
+                    self.emit(Inst::Save(2 + index * 2))?;
+                }
+                self.alternatives(alternatives)?;
+                if let Some(index) = capture {
+                    self.emit(Inst::Save(3 + index * 2))?;
+                }
+                Ok(())
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                lazy,
+            } => {
+                for _ in 0..*min {
+                    self.node(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.node(node)?;
+                        self.emit(Inst::Jump(split))?;
+                        self.patch_split(split, self.program.len(), *lazy);
+                    }
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0))?);
+                            self.node(node)?;
+                        }
+                        for split in splits {
+                            self.patch_split(split, self.program.len(), *lazy);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            single => self.emit(Inst::One(single.clone())).map(|_| ()),
+        }
+    }
+}
+
+/// start and end of matches or of capture groups, None for groups that
+/// didn't take part in a match
+pub type Spans = Vec<Option<(usize, usize)>>;
+
+/// positions saved by a thread, the match in 0 and 1 and then each group's
+type Slots = Vec<Option<usize>>;
+
+/// threads at one position in the order they are preferred, each
+/// instruction is only run by the first thread that gets to it
+struct Threads {
+    /// the step each instruction was last reached at
+    seen: Vec<u64>,
+    step: u64,
+    list: Vec<(usize, Slots)>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            seen: vec![0; len],
+            step: 1,
+            list: vec![],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.step += 1;
+        self.list.clear();
+    }
+
+    /// follow the instructions that don't read a char from `pc` and add the
+    /// threads that wait for one, without recursion so long patterns can't
+    /// overflow the stack
+    fn add(&mut self, program: &[Inst], pc: usize, slots: Slots, pos: usize, len: usize) {
+        let mut pending = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = pending.pop() {
+            if self.seen[pc] == self.step {
+                continue;
+            }
+            self.seen[pc] = self.step;
+            match &program[pc] {
+                Inst::Jump(to) => pending.push((*to, slots)),
+                Inst::Split(first, second) => {
+                    pending.push((*second, slots.clone()));
+                    pending.push((*first, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(pos);
+                    pending.push((pc + 1, slots));
+                }
+                Inst::Start if pos == 0 => pending.push((pc + 1, slots)),
+                Inst::End if pos == len => pending.push((pc + 1, slots)),
+                Inst::Start | Inst::End => {}
+                Inst::One(_) | Inst::Match => self.list.push((pc, slots)),
+            }
+        }
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let invalid = |err: String| format!("invalid regex {}: {}", pattern, err);
+        let mut parser = Parser {
+            chars: pattern.chars().peekable(),
+            names: vec![],
+        };
+        let alternatives = parser.alternatives().map_err(invalid)?;
+        if parser.chars.next().is_some() {
+            return Err(invalid("unmatched )".to_string()));
+        }
+
+        let mut compiler = Compiler { program: vec![] };
+        compiler.emit(Inst::Save(0)).map_err(invalid)?;
+        compiler.alternatives(&alternatives).map_err(invalid)?;
+        compiler.emit(Inst::Save(1)).map_err(invalid)?;
+        compiler.emit(Inst::Match).map_err(invalid)?;
+        Ok(Regex {
+            program: compiler.program,
+            names: parser.names,
+        })
+    }
+
+    /// name of each capture group, None for unnamed ones
+    pub fn names(&self) -> &[Option<String>] {
+        &self.names
+    }
+
+    /// where the leftmost match has each capture group
+    fn find(&self, chars: &[char]) -> Option<Spans> {
+        self.find_at(chars, 0).map(|(_, captures)| captures)
+    }
+
+    /// the leftmost match starting at `from` or after, with where it is and
+    /// where it has each capture group, all threads advance a char at a time
+    /// so time grows with the length of the line and never exponentially
+    fn find_at(&self, chars: &[char], from: usize) -> Option<((usize, usize), Spans)> {
+        let program = &self.program;
+        let len = chars.len();
+        let mut current = Threads::new(program.len());
+        let mut next = Threads::new(program.len());
+        let mut matched: Option<Slots> = None;
+
+        for pos in from..=len {
+            // a match starting here is preferred less than the ones started before
+            if matched.is_none() {
+                let slots = vec![None; 2 + self.names.len() * 2];
+                current.add(program, 0, slots, pos, len);
+            }
+            if current.list.is_empty() && matched.is_some() {
+                break;
+            }
+            for (pc, slots) in current.list.drain(..) {
+                match &program[pc] {
+                    // the threads after this one are preferred less
+                    Inst::Match => {
+                        matched = Some(slots);
+                        break;
+                    }
+                    Inst::One(node) => {
+                        if chars.get(pos).and_then(|c| node.matches_char(*c)) == Some(true) {
+                            next.add(program, pc + 1, slots, pos + 1, len);
+                        }
+                    }
+                    _ => unreachable!("only threads waiting for a char are listed"),
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        let slots = matched?;
+        let span = |index: usize| Some((slots[index]?, slots[index + 1]?));
+        Some((
+            span(0)?,
+            (0..self.names.len())
+                .map(|group| span(2 + group * 2))
+                .collect(),
+        ))
+    }
+
+    /// every match in `text` that doesn't overlap the one before, as byte
+    /// ranges: the whole match first and then each capture group
+    pub fn find_all(&self, text: &str) -> Vec<Spans> {
+        let (offsets, chars): (Vec<usize>, Vec<char>) = text.char_indices().unzip();
+        let offset = |pos: usize| offsets.get(pos).copied().unwrap_or(text.len());
+        let mut found = vec![];
+        let mut from = 0;
+        while from <= chars.len() {
+            let ((start, end), captures) = match self.find_at(&chars, from) {
+                Some(found) => found,
+                None => break,
+            };
+            let mut ranges = vec![Some((offset(start), offset(end)))];
+            ranges.extend(
+                captures
+                    .into_iter()
+                    .map(|capture| capture.map(|(start, end)| (offset(start), offset(end)))),
+            );
+            found.push(ranges);
+            // an empty match would be found again at the same place
+            from = if end > start { end } else { end + 1 };
+        }
+        found
+    }
+
+    /// true if the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        self.find(&chars).is_some()
+    }
+
+    /// text of each capture group in the leftmost match, None for groups
+    /// that didn't take part in it
+    #[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+    pub fn captures<'t>(&self, text: &'t str) -> Option<Vec<Option<&'t str>>> {
+        let (offsets, chars): (Vec<usize>, Vec<char>) = text.char_indices().unzip();
+        let offset = |pos: usize| offsets.get(pos).copied().unwrap_or(text.len());
+        let captures = self.find(&chars)?;
+        Some(
+            captures
+                .into_iter()
+                .map(|capture| capture.map(|(start, end)| &text[offset(start)..offset(end)]))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regex;
+
+    fn regex(pattern: &str) -> Regex {
+        Regex::new(pattern).unwrap()
+    }
+
+    /// the text of each match in `text`
+    fn found<'t>(pattern: &str, text: &'t str) -> Vec<&'t str> {
+        regex(pattern)
+            .find_all(text)
+            .into_iter()
+            .map(|spans| {
+                let (start, end) = spans[0].unwrap();
+                &text[start..end]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn classes() {
+        assert!(regex("[a-c]x").is_match("bx"));
+        assert!(!regex("[a-c]x").is_match("dx"));
+        assert!(regex("[^0-9]").is_match("5a"));
+        assert!(!regex("[^0-9]").is_match("123"));
+        assert!(regex(r"^\d+\s\w+$").is_match("42 user_1"));
+        assert!(!regex(r"^\d+$").is_match("42a"));
+        assert!(regex(r"^\D\W\S$").is_match("a-b"));
+        assert!(regex(r"[\d.]+").is_match("1.5"));
+        assert!(regex(r"[\]-]").is_match("]"));
+        assert!(regex("[-a]").is_match("-"));
+        assert!(regex(r"\t\.").is_match("\t."));
+        assert!(!regex(r"a\.b").is_match("axb"));
+        assert!(!regex("a.b").is_match("a\nb"));
+        assert!(regex("é.").is_match("café!"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(regex("^abc").is_match("abcd"));
+        assert!(!regex("^abc").is_match("xabc"));
+        assert!(regex("abc$").is_match("xabc"));
+        assert!(!regex("abc$").is_match("abcx"));
+        assert!(regex("^$").is_match(""));
+        assert!(!regex("^$").is_match("a"));
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(regex("timeout|refused").is_match("connection refused"));
+        assert!(!regex("timeout|refused").is_match("connection reset"));
+        assert!(regex("^(GET|POST) /").is_match("POST /api"));
+        assert!(!regex("^(GET|POST) /").is_match("PUT /api"));
+        assert!(regex("^(a|ab)c$").is_match("abc"));
+        assert_eq!(found("a|b", "cab"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn repetition_bounds() {
+        assert!(regex("^a{3}$").is_match("aaa"));
+        assert!(!regex("^a{3}$").is_match("aa"));
+        assert!(!regex("^a{3}$").is_match("aaaa"));
+        assert!(regex("^a{2,}$").is_match("aaaaa"));
+        assert!(!regex("^a{2,}$").is_match("a"));
+        assert!(regex("^a{1,2}$").is_match("aa"));
+        assert!(!regex("^a{1,2}$").is_match("aaa"));
+        assert!(regex("^(ab){2}$").is_match("abab"));
+        assert!(!regex("^(ab){2}$").is_match("ababab"));
+        assert!(regex("^colou?r$").is_match("color"));
+        assert!(regex("^a+b*$").is_match("aaa"));
+        assert!(!regex("^a+b*$").is_match("b"));
+        assert!(regex("^(a*)*$").is_match("aaa"));
+        assert!(regex("^x*$").is_match(&"x".repeat(100_000)));
+    }
+
+    #[test]
+    fn long_lines_repeating_groups() {
+        let line = "ab".repeat(5000);
+        assert!(regex("^(ab)+$").is_match(&line));
+        assert!(regex("^(ab)+?$").is_match(&line));
+        assert!(regex("^(?:a|b)*$").is_match(&line));
+        assert!(!regex("^(ab)+$").is_match(&format!("{}a", line)));
+        assert_eq!(
+            regex("^(ab)*?(b*)$").captures(&line),
+            Some(vec![Some("ab"), Some("")])
+        );
+        assert!(regex("^(a|b)+$").is_match(&"ab".repeat(100_000)));
+    }
+
+    #[test]
+    fn greedy_and_lazy() {
+        assert_eq!(found(r#""(.*)""#, r#"a "b" c "d" e"#), vec![r#""b" c "d""#]);
+        assert_eq!(
+            found(r#""(.*?)""#, r#"a "b" c "d" e"#),
+            vec![r#""b""#, r#""d""#]
+        );
+        assert_eq!(found("a+?", "aaa"), vec!["a", "a", "a"]);
+        assert_eq!(found("a??b", "ab b"), vec!["ab", "b"]);
+        assert_eq!(found("a{2,3}?", "aaaaa"), vec!["aa", "aa"]);
+        assert_eq!(found("(ab)+?", "abab"), vec!["ab", "ab"]);
+        assert_eq!(found("(ab)*?c", "ababc"), vec!["ababc"]);
+        assert_eq!(found("^(.*?),", "a,b,c"), vec!["a,"]);
+    }
+
+    #[test]
+    fn captures() {
+        let re = regex(r"^(?<method>\w+) (\S+)(?: (HTTP/[\d.]+))?$");
+        assert_eq!(re.names(), &[Some("method".to_string()), None, None]);
+        assert_eq!(
+            re.captures("GET /index.html HTTP/1.1"),
+            Some(vec![Some("GET"), Some("/index.html"), Some("HTTP/1.1")])
+        );
+        assert_eq!(
+            re.captures("GET /"),
+            Some(vec![Some("GET"), Some("/"), None])
+        );
+        assert_eq!(re.captures("GET"), None);
+        assert_eq!(
+            regex("(?P<n>a)|(b)").captures("b"),
+            Some(vec![None, Some("b")])
+        );
+        // the last repetition is the one captured
+        assert_eq!(regex("(a|b)+").captures("ab"), Some(vec![Some("b")]));
+    }
+
+    #[test]
+    fn find_all_byte_offsets() {
+        let spans = regex("(é+)x").find_all("aéx éex");
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0], vec![Some((1, 4)), Some((1, 3))]);
+        assert_eq!(found("x*", "ab").len(), 3);
+    }
+
+    #[test]
+    fn rejected_syntax() {
+        for pattern in [
+            r"\bERROR\b",
+            r"\Bx",
+            r"\Astart",
+            r"end\z",
+            r"(a)\1",
+            r"\x41",
+            r"[\D]",
+            r"[a-\b]",
+            "(?i)error",
+            "(?=a)",
+            "(?<=a)b",
+            "a**",
+            "*a",
+            "a{",
+            "a{2",
+            "a{3,1}",
+            "(a",
+            "a)",
+            "[a",
+            "[z-a]",
+            "^*",
+            "a\\",
+        ] {
+            assert!(
+                Regex::new(pattern).is_err(),
+                "{} should be rejected",
+                pattern
+            );
+        }
+    }
+}
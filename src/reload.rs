@@ -0,0 +1,167 @@
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+/// how often watched files are checked for changes
+const POLL: Duration = Duration::from_millis(250);
+
+static RELOAD_SIGNALS: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn on_reload_signal(_signal: libc::c_int) {
+    RELOAD_SIGNALS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// size and modification time, a file is loaded again when they change
+fn fingerprint(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// what a file was loaded into, replaced whole by a new load so a line is
+/// transformed by the old or the new version and never by a half loaded one
+#[derive(Debug)]
+pub struct Reloadable<T> {
+    path: PathBuf,
+    /// what the file has, for messages
+    what: &'static str,
+    load: fn(&Path) -> io::Result<T>,
+    current: Mutex<Arc<T>>,
+    /// incremented on each reload so users only lock when it changed
+    generation: AtomicU64,
+    /// of the last version loaded or that failed to load
+    seen: Mutex<Option<(u64, SystemTime)>>,
+    /// a change seen on the last check, loaded if it's the same on the next
+    /// one so files being written aren't loaded half way
+    changed: Mutex<Option<(u64, SystemTime)>>,
+}
+
+impl<T: Debug + Send + Sync> Reloadable<T> {
+    /// load `path` with `load`, fails if it can't be loaded now
+    pub fn load(
+        path: &Path,
+        what: &'static str,
+        load: fn(&Path) -> io::Result<T>,
+    ) -> io::Result<Arc<Reloadable<T>>> {
+        let seen = fingerprint(path);
+        let value = load(path)?;
+        Ok(Arc::new(Reloadable {
+            path: path.to_path_buf(),
+            what,
+            load,
+            current: Mutex::new(Arc::new(value)),
+            generation: AtomicU64::new(0),
+            seen: Mutex::new(seen),
+            changed: Mutex::new(None),
+        }))
+    }
+
+    /// the version loaded last, kept until a reload is done with it
+    pub fn current(&self) -> Arc<T> {
+        self.current.lock().unwrap().clone()
+    }
+
+    fn reload(&self, seen: Option<(u64, SystemTime)>) {
+        *self.seen.lock().unwrap() = seen;
+        match (self.load)(&self.path) {
+            Ok(value) => {
+                *self.current.lock().unwrap() = Arc::new(value);
+                self.generation.fetch_add(1, Ordering::Relaxed);
+                eprintln!("Reloaded {} {}", self.what, self.path.display());
+            }
+            Err(err) => eprintln!(
+                "Error reloading {} {}: {}, keeping the previous version",
+                self.what,
+                self.path.display(),
+                err
+            ),
+        }
+    }
+}
+
+/// a file the watcher loads again
+pub trait Watched: Debug + Send + Sync {
+    /// load it if it changed and stayed the same since the last check, or now
+    /// if `forced`
+    fn check(&self, forced: bool);
+}
+
+impl<T: Debug + Send + Sync> Watched for Reloadable<T> {
+    fn check(&self, forced: bool) {
+        let now = fingerprint(&self.path);
+        if forced {
+            *self.changed.lock().unwrap() = None;
+            self.reload(now);
+            return;
+        }
+        if now.is_none() || now == *self.seen.lock().unwrap() {
+            *self.changed.lock().unwrap() = None;
+            return;
+        }
+
+        let mut changed = self.changed.lock().unwrap();
+        if *changed == now {
+            *changed = None;
+            drop(changed);
+            self.reload(now);
+        } else {
+            *changed = now;
+        }
+    }
+}
+
+/// a Reloadable as one output uses it, it only locks to get the new version
+/// after a reload
+#[derive(Debug)]
+pub struct Latest<T> {
+    source: Arc<Reloadable<T>>,
+    value: Arc<T>,
+    generation: u64,
+}
+
+impl<T: Debug + Send + Sync> Latest<T> {
+    pub fn new(source: Arc<Reloadable<T>>) -> Latest<T> {
+        Latest {
+            value: source.current(),
+            generation: source.generation.load(Ordering::Relaxed),
+            source,
+        }
+    }
+
+    pub fn get(&mut self) -> &Arc<T> {
+        let generation = self.source.generation.load(Ordering::Relaxed);
+        if generation != self.generation {
+            self.value = self.source.current();
+            self.generation = generation;
+        }
+        &self.value
+    }
+}
+
+/// load `files` again when they change or loggen gets SIGUSR2, until `stop`
+/// is set
+pub fn watch(files: Vec<Arc<dyn Watched>>, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+    unsafe {
+        libc::signal(
+            libc::SIGUSR2,
+            on_reload_signal as *const () as libc::sighandler_t,
+        );
+    }
+
+    thread::spawn(move || {
+        let mut signals = RELOAD_SIGNALS.load(Ordering::Relaxed);
+        while !stop.load(Ordering::Relaxed) {
+            sleep(POLL);
+            let now = RELOAD_SIGNALS.load(Ordering::Relaxed);
+            let forced = now != signals;
+            signals = now;
+            for file in &files {
+                file.check(forced);
+            }
+        }
+    })
+}
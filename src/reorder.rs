@@ -0,0 +1,73 @@
+use crate::parse::{parse_kv_list, parse_percentage};
+use rand::Rng;
+use std::collections::VecDeque;
+use std::io;
+
+#[derive(Debug, Clone)]
+pub struct ReorderConfig {
+    pub window: usize,
+    pub rate: f64,
+}
+
+impl ReorderConfig {
+    /// parse "window=50,rate=2%"
+    pub fn parse(v: &str) -> Result<ReorderConfig, String> {
+        let mut config = ReorderConfig {
+            window: 50,
+            rate: 0.01,
+        };
+
+        for (key, value) in parse_kv_list(v)? {
+            match key.as_str() {
+                "window" => match value.parse::<usize>() {
+                    Ok(window) if window > 0 => config.window = window,
+                    _ => return Err(format!("{} isn't a valid window size", value)),
+                },
+                "rate" => config.rate = parse_percentage(&value)?,
+                _ => return Err(format!("unknown reorder option {}", key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+pub fn is_reorder_config(v: String) -> Result<(), String> {
+    ReorderConfig::parse(&v).map(|_| ())
+}
+
+/// buffers a sliding window of lines and sometimes emits one out of order
+#[derive(Debug)]
+pub struct Reorderer {
+    config: ReorderConfig,
+    window: VecDeque<String>,
+}
+
+impl Reorderer {
+    pub fn new(config: ReorderConfig) -> Reorderer {
+        let window = VecDeque::with_capacity(config.window);
+        Reorderer { config, window }
+    }
+
+    /// fill the window using `read` and emit the next line, None when both
+    /// the window and the source are exhausted
+    pub fn next<F>(&mut self, mut read: F) -> io::Result<Option<String>>
+    where
+        F: FnMut() -> io::Result<Option<String>>,
+    {
+        while self.window.len() < self.config.window {
+            match read()? {
+                Some(line) => self.window.push_back(line),
+                None => break,
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        if self.window.len() > 1 && rng.gen_bool(self.config.rate) {
+            let index = rng.gen_range(1..self.window.len());
+            Ok(self.window.remove(index))
+        } else {
+            Ok(self.window.pop_front())
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// kept in the output directory, see --resume-output
+const FILE_NAME: &str = ".loggen-state";
+
+/// where an output was left by the last run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputState {
+    /// output file the run wrote to last
+    pub path: String,
+    /// size of the output file when the run stopped
+    pub bytes: u64,
+    pub rotations: u64,
+    /// number of the last file with --out-file-policy new-per-wrap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_number: Option<u64>,
+    /// lines numbered by --script
+    #[serde(default)]
+    pub script_seq: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    /// values of `{{counter}}` by name
+    #[serde(default)]
+    counters: HashMap<String, u64>,
+    /// by output path relative to the output directory
+    #[serde(default)]
+    outputs: BTreeMap<String, OutputState>,
+}
+
+/// state of the outputs of previous runs, updated as this one stops
+#[derive(Debug)]
+pub struct ResumeState {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl ResumeState {
+    /// state left in `out_dir`, empty for the first run
+    pub fn load(out_dir: &Path) -> io::Result<ResumeState> {
+        let path = out_dir.join(FILE_NAME);
+        let state = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} isn't a loggen state file: {}", path.display(), err),
+                )
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => State::default(),
+            Err(err) => return Err(err),
+        };
+
+        Ok(ResumeState {
+            path,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn counters(&self) -> HashMap<String, u64> {
+        self.state.lock().unwrap().counters.clone()
+    }
+
+    pub fn output(&self, rel_path: &Path) -> Option<OutputState> {
+        let state = self.state.lock().unwrap();
+        state.outputs.get(&key(rel_path)).cloned()
+    }
+
+    pub fn update(&self, rel_path: &Path, output: OutputState) {
+        let mut state = self.state.lock().unwrap();
+        state.outputs.insert(key(rel_path), output);
+    }
+
+    /// write the state with the current `counters`, outputs that weren't
+    /// generated this time are kept as they were
+    pub fn save(&self, counters: HashMap<String, u64>) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.counters.extend(counters);
+        let json = serde_json::to_string_pretty(&*state).map_err(io::Error::other)?;
+
+        // write and rename so a run stopped while saving leaves the old state
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, json)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+fn key(rel_path: &Path) -> String {
+    rel_path.display().to_string()
+}
+
+/// first line of `path` without its ending, None if it's missing or empty
+pub fn first_line(path: &Path) -> Option<String> {
+    let mut line = String::new();
+    BufReader::new(File::open(path).ok()?)
+        .read_line(&mut line)
+        .ok()?;
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.is_empty() {
+        None
+    } else {
+        Some(line.to_string())
+    }
+}
@@ -0,0 +1,242 @@
+use crate::regex::Regex;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// part of a replacement, the text or a group of the match
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    /// 0 is the whole match
+    Group(usize),
+}
+
+/// a `/REGEX/ -> REPLACEMENT` rule of a --rewrite-rules file
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    replacement: Vec<Part>,
+}
+
+/// parse a replacement where $0 is the match, $1 or ${1} a group by number,
+/// ${name} one by name and $$ a $
+fn parse_replacement(v: &str, regex: &Regex) -> Result<Vec<Part>, String> {
+    let groups = regex.names().len();
+    let group = |name: &str| -> Result<usize, String> {
+        let index = match name.parse::<usize>() {
+            Ok(index) => Some(index),
+            Err(_) => regex
+                .names()
+                .iter()
+                .position(|group| group.as_deref() == Some(name))
+                .map(|pos| pos + 1),
+        };
+        index
+            .filter(|index| *index <= groups)
+            .ok_or_else(|| format!("the regex has no group {}", name))
+    };
+
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = v.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            text.push(c);
+            continue;
+        }
+        let name: String = match chars.peek() {
+            Some('$') => {
+                chars.next();
+                text.push('$');
+                continue;
+            }
+            Some('{') => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                if name.is_empty() {
+                    return Err(format!("{} has an empty ${{}}", v));
+                }
+                name
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(*c);
+                    chars.next();
+                }
+                digits
+            }
+            _ => return Err(format!("{} has a $ without a group, use $$ for a $", v)),
+        };
+        if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(&mut text)));
+        }
+        parts.push(Part::Group(group(&name)?));
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+impl Rule {
+    /// parse "/REGEX/ -> REPLACEMENT", the replacement can be empty to remove
+    /// what matches
+    fn parse(v: &str) -> Result<Rule, String> {
+        let arrow = v
+            .strip_prefix('/')
+            .and_then(|rest| rest.find("/ ->"))
+            .ok_or_else(|| format!("{} should be /REGEX/ -> REPLACEMENT", v))?;
+        let regex = Regex::new(&v[1..arrow + 1])?;
+        let replacement = &v[arrow + 5..];
+        let replacement = replacement.strip_prefix(' ').unwrap_or(replacement);
+        Ok(Rule {
+            replacement: parse_replacement(replacement, &regex)?,
+            regex,
+        })
+    }
+
+    fn apply(&self, line: &str) -> Option<String> {
+        let found = self.regex.find_all(line);
+        if found.is_empty() {
+            return None;
+        }
+
+        let mut out = String::with_capacity(line.len());
+        let mut at = 0;
+        for spans in found {
+            let (start, end) = spans[0].unwrap();
+            out.push_str(&line[at..start]);
+            for part in &self.replacement {
+                match part {
+                    Part::Text(text) => out.push_str(text),
+                    Part::Group(index) => {
+                        if let Some((start, end)) = spans[*index] {
+                            out.push_str(&line[start..end]);
+                        }
+                    }
+                }
+            }
+            at = end;
+        }
+        out.push_str(&line[at..]);
+        Some(out)
+    }
+}
+
+/// content rewriting rules, each applied in order to what the ones before left
+#[derive(Debug, Clone)]
+pub struct Rewriter {
+    rules: Vec<Rule>,
+}
+
+impl Rewriter {
+    /// one rule per line, blank lines and lines starting with # are skipped
+    pub fn parse(text: &str) -> Result<Rewriter, String> {
+        let mut rules = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            rules.push(Rule::parse(line).map_err(|err| format!("line {}: {}", number + 1, err))?);
+        }
+        Ok(Rewriter { rules })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Rewriter> {
+        Rewriter::parse(&fs::read_to_string(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// `line` with every rule applied, the line ending is kept
+    pub fn rewrite(&self, line: &str) -> String {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let mut content = content.to_string();
+        for rule in &self.rules {
+            if let Some(rewritten) = rule.apply(&content) {
+                content = rewritten;
+            }
+        }
+        content.push_str(ending);
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rewriter;
+
+    fn rewrite(rules: &str, line: &str) -> String {
+        Rewriter::parse(rules).unwrap().rewrite(line)
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rules = "# comment\n\n  /a/ -> b\n/c/ ->\n/x/ -> y z\n";
+        assert_eq!(Rewriter::parse(rules).unwrap().rules.len(), 3);
+        assert_eq!(rewrite("/c/ ->", "abc"), "ab");
+        assert_eq!(rewrite("/x/ -> y z", "x"), "y z");
+        assert_eq!(rewrite("/a/b/ -> c", "a/b"), "c");
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for rules in [
+            "a -> b",
+            "/a/ b",
+            "/a/",
+            "/(a/ -> b",
+            r"/\bERROR\b/ -> E",
+            "/a/ -> $2",
+            "/(a)/ -> ${name}",
+            "/a/ -> ${}",
+            "/a/ -> cost $",
+        ] {
+            assert!(
+                Rewriter::parse(rules).is_err(),
+                "{} should be rejected",
+                rules
+            );
+        }
+        let err = Rewriter::parse("/a/ -> b\n\n/(/ -> c").unwrap_err();
+        assert!(err.starts_with("line 3: "), "{}", err);
+    }
+
+    #[test]
+    fn substitutes_captures() {
+        assert_eq!(
+            rewrite(r"/user=(\w+)/ -> user=<$1>", "login user=alice ok"),
+            "login user=<alice> ok"
+        );
+        assert_eq!(
+            rewrite(r"/(?<key>\w+)=(?<value>\d+)/ -> ${value}=${key}", "a=1"),
+            "1=a"
+        );
+        assert_eq!(rewrite(r"/(\d)(\d)/ -> ${2}0$1", "12"), "201");
+        assert_eq!(rewrite(r"/\d+/ -> [$0] $$", "id 42"), "id [42] $");
+        // a group that didn't take part is empty
+        assert_eq!(rewrite("/(a)|(b)/ -> <$1$2>", "b"), "<b>");
+    }
+
+    #[test]
+    fn rewrites_every_match() {
+        assert_eq!(
+            rewrite(r#"/"(.*?)"/ -> Q"#, r#"a "b" c "d" e"#),
+            "a Q c Q e"
+        );
+        assert_eq!(rewrite(r#"/"(.*)"/ -> Q"#, r#"a "b" c "d" e"#), "a Q e");
+        assert_eq!(rewrite(r"/\d+/ -> N", "1 22 333"), "N N N");
+        assert_eq!(rewrite("/x*/ -> -", "ab"), "-a-b-");
+        assert_eq!(rewrite("/é/ -> e", "café crème"), "cafe crème");
+    }
+
+    #[test]
+    fn applies_rules_in_order_and_keeps_line_endings() {
+        let rules = "/secret=\\S+/ -> secret=***\n/\\*+/ -> X\n";
+        assert_eq!(rewrite(rules, "a secret=hunter2\r\n"), "a secret=X\r\n");
+        assert_eq!(rewrite("/$/ -> !", "end\n"), "end!\n");
+        assert_eq!(rewrite("/nothing/ -> x", "keep\n"), "keep\n");
+    }
+}
@@ -0,0 +1,199 @@
+use crate::parse::{is_valid_date_format, unknown_value};
+use crate::perms::OutPerms;
+use chrono::{DateTime, Utc};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Stem,
+    Ext,
+    Name,
+    Counter,
+    Date(String),
+}
+
+/// template for rotated file names, for example '{stem}.{date:%Y%m%d-%H%M%S}.{n}{ext}'
+///
+/// placeholders:
+/// * {stem}: file name without extension
+/// * {ext}: extension including the dot, empty if the file has none
+/// * {name}: full file name
+/// * {n}: number of times this file has been rotated, starting at 1
+/// * {date} or {date:FORMAT}: current UTC time formatted with strftime syntax
+#[derive(Debug, Clone)]
+pub struct RotateName {
+    parts: Vec<Part>,
+}
+
+impl RotateName {
+    pub fn parse(template: &str) -> Result<RotateName, String> {
+        let mut parts = vec![];
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(format!("unclosed placeholder in {}", template)),
+            };
+
+            let placeholder = &rest[start + 1..end];
+            parts.push(match placeholder {
+                "stem" => Part::Stem,
+                "ext" => Part::Ext,
+                "name" => Part::Name,
+                "n" => Part::Counter,
+                "date" => Part::Date(DEFAULT_DATE_FORMAT.to_string()),
+                _ if placeholder.starts_with("date:") => {
+                    let format = &placeholder[5..];
+                    if !is_valid_date_format(format) {
+                        return Err(format!("invalid date format {}", format));
+                    }
+                    Part::Date(format.to_string())
+                }
+                _ => return Err(format!("unknown placeholder {{{}}}", placeholder)),
+            });
+
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        if parts.iter().any(|part| match part {
+            Part::Text(text) => text.contains('/'),
+            _ => false,
+        }) {
+            return Err(format!("{} must not contain a path separator", template));
+        }
+
+        Ok(RotateName { parts })
+    }
+
+    /// path to rename `path` to when rotating it for the `n`th time
+    pub fn render(&self, path: &Path, n: u64, now: DateTime<Utc>) -> PathBuf {
+        let stem = path
+            .file_stem()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .map(|v| format!(".{}", v.to_string_lossy()))
+            .unwrap_or_default();
+        let name = path
+            .file_name()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut file_name = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(text) => file_name.push_str(text),
+                Part::Stem => file_name.push_str(&stem),
+                Part::Ext => file_name.push_str(&ext),
+                Part::Name => file_name.push_str(&name),
+                Part::Counter => file_name.push_str(&n.to_string()),
+                Part::Date(format) => file_name.push_str(&now.format(format).to_string()),
+            }
+        }
+
+        path.with_file_name(file_name)
+    }
+}
+
+impl Default for RotateName {
+    /// the name loggen always used: the extension replaced by .rotated
+    fn default() -> RotateName {
+        RotateName {
+            parts: vec![Part::Stem, Part::Text(".rotated".to_string())],
+        }
+    }
+}
+
+pub fn is_rotate_name(v: String) -> Result<(), String> {
+    RotateName::parse(&v).map(|_| ())
+}
+
+/// how an output file is rotated, see --rotate-method
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotateMethod {
+    /// rename it and create a new one
+    Rename,
+    /// hardlink it to the rotated name and rename a new file over it, the
+    /// path never goes missing
+    Link,
+    /// copy it to the rotated name and truncate it, the file keeps its inode
+    CopyTruncate,
+}
+
+impl RotateMethod {
+    pub const NAMES: &'static [&'static str] = &["rename", "link", "copytruncate"];
+}
+
+impl FromStr for RotateMethod {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<RotateMethod, String> {
+        match v {
+            "rename" => Ok(RotateMethod::Rename),
+            "link" => Ok(RotateMethod::Link),
+            "copytruncate" => Ok(RotateMethod::CopyTruncate),
+            _ => Err(unknown_value("rotate method", v, RotateMethod::NAMES)),
+        }
+    }
+}
+
+/// hardlink `path` to `rotated` and replace it with a new empty file, a
+/// rotated file left by an earlier run is replaced as rename would
+pub fn link_and_replace(path: &Path, rotated: &Path, out_perms: &OutPerms) -> io::Result<()> {
+    match fs::remove_file(rotated) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    fs::hard_link(path, rotated)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let new = path.with_file_name(format!(".{}.new", name));
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&new)?;
+    out_perms.apply(&new)?;
+    fs::rename(&new, path)
+}
+
+/// copy `path` to `rotated` and empty it in place, writers keep their file
+pub fn copy_and_truncate(path: &Path, rotated: &Path) -> io::Result<()> {
+    fs::copy(path, rotated)?;
+    OpenOptions::new().write(true).open(path)?.set_len(0)
+}
+
+/// lines already in `path`, 0 if it doesn't exist, for --rotate-lines
+pub fn lines_in(path: &Path) -> io::Result<u64> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let mut reader = BufReader::new(file);
+    let mut lines = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(lines);
+        }
+        lines += buf.iter().filter(|byte| **byte == b'\n').count() as u64;
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
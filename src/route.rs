@@ -0,0 +1,232 @@
+use crate::perms::OutPerms;
+use crate::regex::Regex;
+use crate::sink::{expand_path_template, Sink, SinkOutput};
+use rand::Rng;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// which lines a route or a throttle rule takes
+#[derive(Debug, Clone)]
+pub enum Match {
+    /// `*`, every line
+    Any,
+    /// `FIELD=VALUE`, a JSON field or a key=value pair in plain lines
+    Field { name: String, value: String },
+    /// `/REGEX/`, anywhere in the line
+    Regex(Regex),
+}
+
+impl Match {
+    /// parse "FIELD=VALUE", "/REGEX/" or "*"
+    pub fn parse(rule: &str) -> Result<Match, String> {
+        if rule == "*" {
+            Ok(Match::Any)
+        } else if rule.len() >= 2 && rule.starts_with('/') && rule.ends_with('/') {
+            Ok(Match::Regex(Regex::new(&rule[1..rule.len() - 1])?))
+        } else {
+            match rule.find('=') {
+                Some(pos) if pos > 0 => Ok(Match::Field {
+                    name: rule[..pos].to_string(),
+                    value: rule[pos + 1..].to_string(),
+                }),
+                _ => Err(format!(
+                    "{} isn't a line match, use FIELD=VALUE, /REGEX/ or *",
+                    rule
+                )),
+            }
+        }
+    }
+
+    /// `json` is the line parsed as a JSON object, parsed once for all rules
+    pub fn matches(&self, line: &str, json: &mut Option<Option<Value>>) -> bool {
+        match self {
+            Match::Any => true,
+            Match::Regex(regex) => regex.is_match(line.trim_end_matches(['\r', '\n'])),
+            Match::Field { name, value } => {
+                let json = json.get_or_insert_with(|| {
+                    if line.trim_start().starts_with('{') {
+                        serde_json::from_str(line).ok()
+                    } else {
+                        None
+                    }
+                });
+                match json.as_ref().and_then(|json| json.get(name)) {
+                    Some(Value::String(found)) => found == value,
+                    Some(found) => &found.to_string() == value,
+                    None => line.split_whitespace().any(|pair| {
+                        pair.strip_prefix(name.as_str())
+                            .and_then(|rest| rest.strip_prefix('='))
+                            .is_some_and(|found| found.trim_matches('"') == value)
+                    }),
+                }
+            }
+        }
+    }
+}
+
+/// a --route rule: `MATCH [PCT%] -> PATH` moves matching lines to PATH,
+/// `+>` copies them there and to the regular output too
+#[derive(Debug, Clone)]
+pub struct Route {
+    matcher: Match,
+    /// share of matching lines that are routed
+    probability: f64,
+    copy: bool,
+    /// relative to the output directory, may have {dir}, {file} and {stem}
+    target: String,
+}
+
+impl Route {
+    /// parse "level=ERROR -> errors/{stem}.log" or "/timeout|refused/ 10% +> sampled.log"
+    pub fn parse(v: &str) -> Result<Route, String> {
+        let (rule, target, copy) = match (v.find("->"), v.find("+>")) {
+            (Some(pos), _) => (&v[..pos], &v[pos + 2..], false),
+            (None, Some(pos)) => (&v[..pos], &v[pos + 2..], true),
+            (None, None) => {
+                return Err(format!(
+                    "{} has no target, use MATCH -> PATH or MATCH +> PATH",
+                    v
+                ))
+            }
+        };
+        let target = target.trim();
+        if target.is_empty() {
+            return Err(format!("{} has no target path", v));
+        }
+        if Path::new(target)
+            .components()
+            .any(|part| part.as_os_str() == "..")
+        {
+            return Err(format!(
+                "route target {} can't leave the output directory",
+                target
+            ));
+        }
+
+        let mut rule = rule.trim();
+        let mut probability = 1.0;
+        if let Some(pos) = rule.rfind(char::is_whitespace) {
+            if let Some(pct) = rule[pos + 1..].strip_suffix('%') {
+                probability = match pct.parse::<f64>() {
+                    Ok(pct) if pct > 0.0 && pct <= 100.0 => pct / 100.0,
+                    _ => return Err(format!("{}% isn't a percentage between 0 and 100", pct)),
+                };
+                rule = rule[..pos].trim_end();
+            }
+        }
+
+        let matcher = Match::parse(rule)?;
+
+        Ok(Route {
+            matcher,
+            probability,
+            copy,
+            target: target.to_string(),
+        })
+    }
+
+    /// `json` is the line parsed as a JSON object, parsed once for all routes
+    fn matches(&self, line: &str, json: &mut Option<Option<Value>>) -> bool {
+        let matched = self.matcher.matches(line, json);
+        matched && (self.probability >= 1.0 || rand::thread_rng().gen_bool(self.probability))
+    }
+}
+
+pub fn is_route(v: String) -> Result<(), String> {
+    Route::parse(&v).map(|_| ())
+}
+
+/// where routed lines of one generated file go
+#[derive(Debug)]
+enum RouteOutput {
+    /// opened on first use and closed with the generated file
+    File {
+        path: PathBuf,
+        writer: Option<LineWriter<File>>,
+    },
+    Sink(Box<dyn SinkOutput>),
+}
+
+/// sends the lines of one generated file to the routes they match
+#[derive(Debug)]
+pub struct Router {
+    routes: Arc<Vec<Route>>,
+    outputs: Vec<RouteOutput>,
+    out_perms: OutPerms,
+}
+
+impl Router {
+    pub fn new(
+        routes: Arc<Vec<Route>>,
+        out_base: &Path,
+        rel_path: &Path,
+        sink: Option<&Arc<dyn Sink>>,
+        out_perms: &OutPerms,
+    ) -> Router {
+        let outputs = routes
+            .iter()
+            .map(|route| {
+                // empty parts, like {dir} for files at the top, are dropped
+                let target: PathBuf = expand_path_template(&route.target, rel_path, "/")
+                    .split('/')
+                    .filter(|part| !part.is_empty() && *part != ".")
+                    .collect();
+                match sink {
+                    Some(sink) => RouteOutput::Sink(sink.output(&target)),
+                    None => RouteOutput::File {
+                        path: out_base.join(target),
+                        writer: None,
+                    },
+                }
+            })
+            .collect();
+
+        Router {
+            routes,
+            outputs,
+            out_perms: out_perms.clone(),
+        }
+    }
+
+    /// write `line` to the routes it matches, true if one of them took it
+    /// instead of the regular output
+    pub fn route(&mut self, line: &str) -> io::Result<bool> {
+        let mut json = None;
+        for (route, output) in self.routes.iter().zip(self.outputs.iter_mut()) {
+            if !route.matches(line, &mut json) {
+                continue;
+            }
+
+            match output {
+                RouteOutput::Sink(sink) => sink.write(line)?,
+                RouteOutput::File { path, writer } => {
+                    if writer.is_none() {
+                        *writer = Some(LineWriter::new(crate::open_append(path, &self.out_perms)?));
+                    }
+                    writer.as_mut().unwrap().write_all(line.as_bytes())?;
+                }
+            }
+            if !route.copy {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// close route files, they are reopened on next use
+    pub fn close(&mut self) -> io::Result<()> {
+        for output in self.outputs.iter_mut() {
+            if let RouteOutput::File { writer, .. } = output {
+                if let Some(mut writer) = writer.take() {
+                    writer.flush()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
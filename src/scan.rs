@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// a sample found by `walk`
+#[derive(Debug)]
+pub struct Found {
+    pub path: PathBuf,
+    pub is_pipe: bool,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    dirs: VecDeque<PathBuf>,
+    /// directories being read, their subdirectories aren't queued yet
+    reading: usize,
+}
+
+/// walk `root` with `threads` threads sending regular files and named pipes to
+/// `found` as they're seen, symlinks to directories aren't followed, returns
+/// when the whole tree was walked, `stop` is set or nobody takes what's found
+pub fn walk(root: &Path, threads: usize, found: Sender<Found>, stop: &AtomicBool) {
+    if !root.is_dir() {
+        if let Some(sample) = sample(root) {
+            found.send(sample).ok();
+        }
+        return;
+    }
+
+    let queue = Mutex::new(Queue {
+        dirs: VecDeque::from(vec![root.to_path_buf()]),
+        reading: 0,
+    });
+    let changed = Condvar::new();
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let found = found.clone();
+            let (queue, changed) = (&queue, &changed);
+            scope.spawn(move || {
+                while let Some(dir) = next_dir(queue, changed, stop) {
+                    let subdirs = read_dir(&dir, &found);
+                    let mut queue = queue.lock().unwrap();
+                    queue.reading -= 1;
+                    match subdirs {
+                        Some(subdirs) => queue.dirs.extend(subdirs),
+                        // nobody is listening, let the others finish
+                        None => queue.dirs.clear(),
+                    }
+                    changed.notify_all();
+                }
+            });
+        }
+    });
+}
+
+/// the next directory to read, None once there are none left
+fn next_dir(queue: &Mutex<Queue>, changed: &Condvar, stop: &AtomicBool) -> Option<PathBuf> {
+    let mut queue = queue.lock().unwrap();
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        if let Some(dir) = queue.dirs.pop_front() {
+            queue.reading += 1;
+            return Some(dir);
+        }
+        if queue.reading == 0 {
+            return None;
+        }
+        queue = changed
+            .wait_timeout(queue, Duration::from_millis(100))
+            .unwrap()
+            .0;
+    }
+}
+
+/// send the samples in `dir` and return its subdirectories, None if `found`
+/// was dropped, unreadable entries are skipped
+fn read_dir(dir: &Path, found: &Sender<Found>) -> Option<Vec<PathBuf>> {
+    let mut subdirs = vec![];
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Some(subdirs),
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            subdirs.push(entry.path());
+        } else if let Some(sample) = sample(&entry.path()) {
+            found.send(sample).ok()?;
+        }
+    }
+
+    Some(subdirs)
+}
+
+/// `path` if it's a file or a named pipe, following symlinks
+fn sample(path: &Path) -> Option<Found> {
+    let file_type = fs::metadata(path).ok()?.file_type();
+    if file_type.is_file() || file_type.is_fifo() {
+        Some(Found {
+            path: path.to_path_buf(),
+            is_pipe: file_type.is_fifo(),
+        })
+    } else {
+        None
+    }
+}
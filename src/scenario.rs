@@ -0,0 +1,81 @@
+use crate::pacer::Pacer;
+use crate::parse::{parse_duration, parse_rate};
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct Phase {
+    pub name: String,
+    pub duration: Duration,
+    /// lines per second for all files together, 0 for silence
+    pub rate: f64,
+}
+
+/// parse a phase line: "<name> <duration> at <rate>lps" or "<name> <duration>" for silence
+fn parse_phase(line: &str) -> Result<Phase, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [name, duration] => Ok(Phase {
+            name: name.to_string(),
+            duration: parse_duration(duration)?,
+            rate: 0.0,
+        }),
+        [name, duration, "at", rate] => Ok(Phase {
+            name: name.to_string(),
+            duration: parse_duration(duration)?,
+            rate: parse_rate(rate)?,
+        }),
+        _ => Err("expected '<name> <duration> at <rate>lps' or '<name> <duration>'".to_string()),
+    }
+}
+
+/// phases to run in order, one per line, empty lines and lines starting with # are ignored
+pub fn parse(source: &str) -> Result<Vec<Phase>, String> {
+    let mut phases = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        phases.push(parse_phase(line).map_err(|err| format!("line {}: {}", index + 1, err))?);
+    }
+
+    if phases.is_empty() {
+        return Err("scenario has no phases".to_string());
+    }
+
+    Ok(phases)
+}
+
+pub fn load(path: &str) -> Result<Vec<Phase>, String> {
+    let source = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    parse(&source).map_err(|err| format!("{}: {}", path, err))
+}
+
+pub fn is_scenario(v: String) -> Result<(), String> {
+    load(&v).map(|_| ())
+}
+
+/// set the pacer rate for each phase in turn and stop generation after the last one
+pub fn start(phases: Vec<Phase>, pacer: Arc<Pacer>, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for phase in phases {
+            println!(
+                "phase {} for {:?} at {} lines/s",
+                phase.name, phase.duration, phase.rate
+            );
+            pacer.set_rate(Some(phase.rate));
+
+            let end = Instant::now() + phase.duration;
+            while !stop.load(Ordering::Relaxed) && Instant::now() < end {
+                sleep((end - Instant::now()).min(Duration::from_millis(100)));
+            }
+        }
+
+        stop.store(true, Ordering::Relaxed);
+    })
+}
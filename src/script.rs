@@ -0,0 +1,135 @@
+use crate::reload::{Latest, Reloadable};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// name of the function the script must define
+#[cfg(feature = "script")]
+const TRANSFORM_FN: &str = "transform";
+
+/// user script with a `transform(line, meta)` function called for every line
+/// read, see --script
+#[derive(Debug)]
+pub struct Script {
+    #[cfg(feature = "script")]
+    engine: rhai::Engine,
+    #[cfg(feature = "script")]
+    ast: rhai::AST,
+}
+
+#[cfg(feature = "script")]
+fn script_error(err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+#[cfg(feature = "script")]
+impl Script {
+    pub fn load(path: &Path) -> io::Result<Script> {
+        let engine = rhai::Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(script_error)?;
+        let has_transform = ast
+            .iter_functions()
+            .any(|f| f.name == TRANSFORM_FN && f.params.len() == 2);
+        if !has_transform {
+            return Err(script_error(format!(
+                "{} must define fn {}(line, meta)",
+                path.display(),
+                TRANSFORM_FN
+            )));
+        }
+
+        Ok(Script { engine, ast })
+    }
+
+    /// lines to write instead of `line`, which has no line ending
+    fn call(&self, line: &str, path: &str, seq: i64) -> io::Result<Vec<String>> {
+        let mut meta = rhai::Map::new();
+        meta.insert("path".into(), path.into());
+        meta.insert("seq".into(), seq.into());
+        meta.insert("time".into(), chrono::Utc::now().to_rfc3339().into());
+
+        let result: rhai::Dynamic = self
+            .engine
+            .call_fn(
+                &mut rhai::Scope::new(),
+                &self.ast,
+                TRANSFORM_FN,
+                (line.to_string(), meta),
+            )
+            .map_err(script_error)?;
+
+        let invalid = || script_error("transform must return a string, an array of strings or ()");
+        if result.is_unit() {
+            Ok(vec![])
+        } else if result.is_string() {
+            Ok(vec![result.into_string().map_err(|_| invalid())?])
+        } else if result.is_array() {
+            result
+                .into_array()
+                .map_err(|_| invalid())?
+                .into_iter()
+                .map(|v| v.into_string().map_err(|_| invalid()))
+                .collect()
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+#[cfg(not(feature = "script"))]
+impl Script {
+    pub fn load(_path: &Path) -> io::Result<Script> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "loggen was built without script support, build it with --features script",
+        ))
+    }
+
+    fn call(&self, line: &str, _path: &str, _seq: i64) -> io::Result<Vec<String>> {
+        Ok(vec![line.to_string()])
+    }
+}
+
+/// runs the script for the lines of one file, numbering them, with the
+/// version loaded last
+#[derive(Debug)]
+pub struct ScriptState {
+    script: Latest<Script>,
+    path: String,
+    seq: i64,
+}
+
+impl ScriptState {
+    pub fn new(script: Arc<Reloadable<Script>>, rel_path: &Path) -> ScriptState {
+        ScriptState {
+            script: Latest::new(script),
+            path: rel_path.display().to_string(),
+            seq: 0,
+        }
+    }
+
+    /// number of the last line passed to the script
+    pub fn seq(&self) -> i64 {
+        self.seq
+    }
+
+    /// number lines from `seq` on, see --resume-output
+    pub fn continue_from(&mut self, seq: i64) {
+        self.seq = seq;
+    }
+
+    /// lines to write for `line`, none if the script dropped it, each with the
+    /// line's ending
+    pub fn transform(&mut self, line: &str) -> io::Result<Vec<String>> {
+        self.seq += 1;
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let lines = self.script.get().call(content, &self.path, self.seq)?;
+        Ok(lines
+            .into_iter()
+            .map(|line| format!("{}{}", line, ending))
+            .collect())
+    }
+}
@@ -0,0 +1,294 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// how long a follower waits for changes before looking again
+const WAIT: Duration = Duration::from_millis(100);
+/// mismatches described in a report, the rest are only counted
+const MAX_DESCRIBED: usize = 5;
+
+/// a sample written for the self test, its lines are what the output must have
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub rel_path: PathBuf,
+    pub lines: Arc<Vec<String>>,
+}
+
+/// write the samples of the self test in `in_dir`: short plain lines and
+/// JSON lines with non ascii text, some long enough to need several writes
+pub fn write_samples(in_dir: &Path) -> io::Result<Vec<Sample>> {
+    let payload = "abcdefghijklmnopqrstuvwxyz".repeat(8);
+    let plain: Vec<String> = (0..300)
+        .map(|n| format!("plain {:06} {}", n, &payload[..20 + n * 13 % 150]))
+        .collect();
+    let json: Vec<String> = (0..120)
+        .map(|n| {
+            let text = if n % 25 == 0 {
+                "ünïcødé ✓ ".repeat(800)
+            } else {
+                "ünïcødé ✓ ".repeat(1 + n % 5)
+            };
+            format!(r#"{{"seq":{},"level":"info","msg":"{}"}}"#, n, text)
+        })
+        .collect();
+
+    let mut samples = vec![];
+    for (rel_path, lines) in [("plain.log", plain), ("nested/json.log", json)] {
+        let path = in_dir.join(rel_path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, lines.join("\n") + "\n")?;
+        samples.push(Sample {
+            rel_path: PathBuf::from(rel_path),
+            lines: Arc::new(lines),
+        });
+    }
+
+    Ok(samples)
+}
+
+/// what a follower read from one output
+#[derive(Debug, Default)]
+pub struct Report {
+    pub rel_path: PathBuf,
+    pub lines: u64,
+    pub truncations: u64,
+    pub rotations: u64,
+    /// lines that weren't the next one of the sample, lost or torn
+    pub mismatches: u64,
+    pub described: Vec<String>,
+}
+
+impl Report {
+    fn mismatch(&mut self, description: String) {
+        self.mismatches += 1;
+        if self.described.len() < MAX_DESCRIBED {
+            self.described.push(description);
+        }
+    }
+}
+
+/// wakes a follower when something changes in a directory
+#[cfg(target_os = "linux")]
+struct Watcher {
+    fd: Option<i32>,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    fn new(dir: &Path) -> Watcher {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Watcher { fd: None };
+        }
+        let dir = CString::new(dir.as_os_str().as_bytes()).unwrap_or_default();
+        let mask = libc::IN_MODIFY
+            | libc::IN_CREATE
+            | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO
+            | libc::IN_DELETE
+            | libc::IN_CLOSE_WRITE;
+        if unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), mask) } < 0 {
+            unsafe { libc::close(fd) };
+            return Watcher { fd: None };
+        }
+        Watcher { fd: Some(fd) }
+    }
+
+    /// wait for a change or `timeout`
+    fn wait(&self, timeout: Duration) {
+        let fd = match self.fd {
+            Some(fd) => fd,
+            None => return std::thread::sleep(timeout),
+        };
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as i32) };
+        // the events only wake us, what changed is checked on the file
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if let Some(fd) = self.fd {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    fn new(_dir: &Path) -> Watcher {
+        Watcher
+    }
+
+    fn wait(&self, _timeout: Duration) {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// the output being read, like tail -F: reopened when the path is another
+/// file and read from the start when it shrinks
+struct Followed {
+    file: File,
+    ino: u64,
+    offset: u64,
+}
+
+/// read `path` as it's written until `done` is set and everything was read,
+/// checking every line is the next line of `sample`
+pub fn follow(path: PathBuf, sample: Sample, done: Arc<AtomicBool>) -> Report {
+    let lines = &sample.lines;
+    let mut report = Report {
+        rel_path: sample.rel_path.clone(),
+        ..Report::default()
+    };
+    let watcher = Watcher::new(path.parent().unwrap_or(Path::new(".")));
+    let mut followed: Option<Followed> = None;
+    let mut pending: Vec<u8> = vec![];
+    let mut next = 0;
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        // set once the generator stopped, the pass after it reads the rest
+        let finishing = done.load(Ordering::Relaxed);
+
+        if followed.is_none() {
+            followed = File::open(&path).ok().and_then(|file| {
+                let ino = file.metadata().ok()?.ino();
+                Some(Followed {
+                    file,
+                    ino,
+                    offset: 0,
+                })
+            });
+        }
+        let current = match &mut followed {
+            Some(current) => current,
+            None if finishing => {
+                report.mismatch(format!("{} was never written", path.display()));
+                return report;
+            }
+            None => {
+                watcher.wait(WAIT);
+                continue;
+            }
+        };
+
+        // checked before reading so what was written before a rotation is read
+        let rotated = fs::metadata(&path).map_or(true, |meta| meta.ino() != current.ino);
+        let truncated = current
+            .file
+            .metadata()
+            .is_ok_and(|meta| meta.len() < current.offset);
+        if truncated {
+            report.truncations += 1;
+            if !pending.is_empty() {
+                report.mismatch(format!(
+                    "truncated after a partial line: {}",
+                    String::from_utf8_lossy(&pending)
+                ));
+                pending.clear();
+            }
+            if next != 0 {
+                report.mismatch(format!(
+                    "truncated before line {} of {} was read, the rest of the pass was lost",
+                    next + 1,
+                    lines.len()
+                ));
+                next = 0;
+            }
+            current.offset = 0;
+            if current.file.seek(SeekFrom::Start(0)).is_err() {
+                followed = None;
+                continue;
+            }
+        }
+
+        let mut read_any = false;
+        loop {
+            let len = match current.file.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(len) => len,
+            };
+            read_any = true;
+            current.offset += len as u64;
+            pending.extend_from_slice(&buf[..len]);
+        }
+
+        while let Some(pos) = pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line[..pos]);
+            report.lines += 1;
+            if line != lines[next] {
+                report.mismatch(format!(
+                    "line {} read: expected line {} of the sample {:?}, got {:?}",
+                    report.lines,
+                    next + 1,
+                    preview(&lines[next]),
+                    preview(&line)
+                ));
+                // continue after the line read if it's a sample line
+                if let Some(pos) = lines.iter().position(|expected| *expected == line) {
+                    next = pos;
+                }
+            }
+            next = (next + 1) % lines.len();
+        }
+
+        if rotated {
+            report.rotations += 1;
+            if !pending.is_empty() {
+                report.mismatch(format!(
+                    "rotated after a partial line: {}",
+                    String::from_utf8_lossy(&pending)
+                ));
+                pending.clear();
+            }
+            if next != 0 {
+                report.mismatch(format!(
+                    "rotated before line {} of {} was written, the rest of the pass was lost",
+                    next + 1,
+                    lines.len()
+                ));
+                next = 0;
+            }
+            followed = None;
+            continue;
+        }
+
+        if finishing && !read_any {
+            if !pending.is_empty() {
+                report.mismatch(format!(
+                    "ended with a partial line: {}",
+                    String::from_utf8_lossy(&pending)
+                ));
+            }
+            return report;
+        }
+        if !read_any {
+            watcher.wait(WAIT);
+        }
+    }
+}
+
+fn preview(line: &str) -> String {
+    line.chars().take(60).collect()
+}
@@ -0,0 +1,265 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[cfg(windows)]
+mod windows;
+
+/// where and how a service is installed
+#[derive(Debug, Clone)]
+pub struct ServiceOptions {
+    pub name: String,
+    /// for all users (needs root) instead of the current user
+    pub system: bool,
+}
+
+fn label(name: &str) -> String {
+    format!("org.loggen.{}", name)
+}
+
+fn home() -> io::Result<PathBuf> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::other("HOME isn't set"))
+}
+
+/// command line the service manager runs, generation args go after `service run --`
+fn program_args(options: &ServiceOptions, args: &[String]) -> io::Result<Vec<String>> {
+    let exe = env::current_exe()?.display().to_string();
+    let mut program = vec![
+        exe,
+        "service".to_string(),
+        "run".to_string(),
+        "--name".to_string(),
+        options.name.clone(),
+    ];
+    if cfg!(windows) {
+        // windows services have no working directory setting
+        program.push("--work-dir".to_string());
+        program.push(env::current_dir()?.display().to_string());
+    }
+    program.push("--".to_string());
+    program.extend(args.iter().cloned());
+    Ok(program)
+}
+
+fn xml_escape(v: &str) -> String {
+    v.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// launchd job that keeps loggen running, stdout and stderr go to a log file
+fn launchd_plist(
+    options: &ServiceOptions,
+    program: &[String],
+    work_dir: &str,
+    log_path: &str,
+) -> String {
+    let args: Vec<String> = program
+        .iter()
+        .map(|arg| format!("        <string>{}</string>", xml_escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{}</string>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ExitTimeOut</key>
+    <integer>30</integer>
+    <key>StandardOutPath</key>
+    <string>{}</string>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+        xml_escape(&label(&options.name)),
+        args.join("\n"),
+        xml_escape(work_dir),
+        xml_escape(log_path),
+        xml_escape(log_path)
+    )
+}
+
+/// quote an ExecStart argument, % and $ are expanded by systemd otherwise
+fn systemd_quote(v: &str) -> String {
+    format!(
+        "\"{}\"",
+        v.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+            .replace('$', "$$")
+    )
+}
+
+/// systemd unit, output goes to the journal
+fn systemd_unit(options: &ServiceOptions, program: &[String], work_dir: &str) -> String {
+    let exec: Vec<String> = program.iter().map(|arg| systemd_quote(arg)).collect();
+    format!(
+        "[Unit]\nDescription=loggen {}\nAfter=network-online.target\n\n\
+         [Service]\nExecStart={}\nWorkingDirectory={}\nRestart=on-failure\nKillSignal=SIGTERM\nTimeoutStopSec=30\n\n\
+         [Install]\nWantedBy={}\n",
+        options.name,
+        exec.join(" "),
+        work_dir.replace('%', "%%"),
+        if options.system {
+            "multi-user.target"
+        } else {
+            "default.target"
+        }
+    )
+}
+
+/// the service file for this platform, its path and content, on windows the
+/// service name and the sc.exe command that creates it
+pub fn service_file(options: &ServiceOptions, args: &[String]) -> io::Result<(PathBuf, String)> {
+    let program = program_args(options, args)?;
+    #[cfg(windows)]
+    {
+        let create: Vec<String> = windows::create_args(options, &program)
+            .iter()
+            .map(|arg| windows::quote(arg))
+            .collect();
+        return Ok((
+            PathBuf::from(windows::service_name(options)),
+            format!("sc.exe {}\n", create.join(" ")),
+        ));
+    }
+    // relative paths in the args keep working
+    let work_dir = env::current_dir()?.display().to_string();
+    if cfg!(target_os = "macos") {
+        let (dir, log_dir) = if options.system {
+            (
+                PathBuf::from("/Library/LaunchDaemons"),
+                PathBuf::from("/Library/Logs"),
+            )
+        } else {
+            (
+                home()?.join("Library/LaunchAgents"),
+                home()?.join("Library/Logs"),
+            )
+        };
+        let log_path = log_dir.join(format!("loggen-{}.log", options.name));
+        let path = dir.join(format!("{}.plist", label(&options.name)));
+        Ok((
+            path,
+            launchd_plist(
+                options,
+                &program,
+                &work_dir,
+                &log_path.display().to_string(),
+            ),
+        ))
+    } else if cfg!(target_os = "linux") {
+        let dir = if options.system {
+            PathBuf::from("/etc/systemd/system")
+        } else {
+            home()?.join(".config/systemd/user")
+        };
+        let path = dir.join(format!("loggen-{}.service", options.name));
+        Ok((path, systemd_unit(options, &program, &work_dir)))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "services can't be installed on {}, only with launchd on macOS, systemd on Linux and the service control manager on Windows",
+                env::consts::OS
+            ),
+        ))
+    }
+}
+
+fn run_manager(program: &str, args: &[&str]) -> io::Result<()> {
+    let status = Command::new(program).args(args).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} {} failed with {}",
+            program,
+            args.join(" "),
+            status
+        )))
+    }
+}
+
+fn systemctl(options: &ServiceOptions, args: &[&str]) -> io::Result<()> {
+    let mut all = vec![];
+    if !options.system {
+        all.push("--user");
+    }
+    all.extend_from_slice(args);
+    run_manager("systemctl", &all)
+}
+
+/// write the service file and start the service
+pub fn install(options: &ServiceOptions, args: &[String]) -> io::Result<PathBuf> {
+    #[cfg(windows)]
+    return windows::install(options, &program_args(options, args)?);
+    let (path, content) = service_file(options, args)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, content)?;
+
+    let path_str = path.display().to_string();
+    if cfg!(target_os = "macos") {
+        run_manager("launchctl", &["load", "-w", &path_str])?;
+    } else {
+        let unit = format!("loggen-{}.service", options.name);
+        systemctl(options, &["daemon-reload"])?;
+        systemctl(options, &["enable", "--now", &unit])?;
+    }
+
+    Ok(path)
+}
+
+/// stop the service and remove its file
+pub fn uninstall(options: &ServiceOptions) -> io::Result<PathBuf> {
+    #[cfg(windows)]
+    return windows::uninstall(options);
+    let (path, _) = service_file(options, &[])?;
+    let path_str = path.display().to_string();
+    if cfg!(target_os = "macos") {
+        run_manager("launchctl", &["unload", "-w", &path_str])?;
+    } else {
+        let unit = format!("loggen-{}.service", options.name);
+        systemctl(options, &["disable", "--now", &unit])?;
+    }
+
+    fs::remove_file(&path)?;
+    if cfg!(target_os = "linux") {
+        systemctl(options, &["daemon-reload"])?;
+    }
+
+    Ok(path)
+}
+
+/// run `generate` as the installed service, on windows it reports to the
+/// service control manager and stops on its stop request
+#[cfg_attr(not(windows), allow(unused_variables))]
+pub fn run(options: &ServiceOptions, generate: Box<dyn FnOnce() + Send>) {
+    #[cfg(windows)]
+    return windows::run(options, generate);
+    #[cfg(not(windows))]
+    generate();
+}
@@ -0,0 +1,243 @@
+use super::{run_manager, ServiceOptions};
+use std::env;
+use std::ffi::{c_void, OsStr};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::IntoRawHandle;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Mutex;
+
+const SERVICE_WIN32_OWN_PROCESS: u32 = 0x10;
+const SERVICE_STOPPED: u32 = 1;
+const SERVICE_STOP_PENDING: u32 = 3;
+const SERVICE_RUNNING: u32 = 4;
+const SERVICE_ACCEPT_STOP: u32 = 0x1;
+const SERVICE_ACCEPT_SHUTDOWN: u32 = 0x4;
+const SERVICE_CONTROL_STOP: u32 = 1;
+const SERVICE_CONTROL_INTERROGATE: u32 = 4;
+const SERVICE_CONTROL_SHUTDOWN: u32 = 5;
+const NO_ERROR: u32 = 0;
+const ERROR_CALL_NOT_IMPLEMENTED: u32 = 120;
+const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+
+#[repr(C)]
+struct ServiceStatus {
+    service_type: u32,
+    current_state: u32,
+    controls_accepted: u32,
+    win32_exit_code: u32,
+    service_specific_exit_code: u32,
+    check_point: u32,
+    wait_hint: u32,
+}
+
+#[repr(C)]
+struct ServiceTableEntry {
+    service_name: *mut u16,
+    service_proc: Option<unsafe extern "system" fn(u32, *mut *mut u16)>,
+}
+
+type ControlHandler = unsafe extern "system" fn(u32, u32, *mut c_void, *mut c_void) -> u32;
+
+#[link(name = "advapi32")]
+extern "system" {
+    fn StartServiceCtrlDispatcherW(table: *const ServiceTableEntry) -> i32;
+    fn RegisterServiceCtrlHandlerExW(
+        name: *const u16,
+        handler: ControlHandler,
+        context: *mut c_void,
+    ) -> *mut c_void;
+    fn SetServiceStatus(handle: *mut c_void, status: *const ServiceStatus) -> i32;
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn SetStdHandle(std_handle: u32, handle: *mut c_void) -> i32;
+}
+
+/// handle to report the service state to the service control manager
+static STATUS_HANDLE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+type Generate = Box<dyn FnOnce() + Send>;
+
+/// service name, log file and generation for service_main, which gets no
+/// context from the dispatcher
+static SERVICE: Mutex<Option<(Vec<u16>, PathBuf, Generate)>> = Mutex::new(None);
+
+pub fn service_name(options: &ServiceOptions) -> String {
+    format!("loggen-{}", options.name)
+}
+
+/// %ProgramData%\loggen\loggen-NAME.log, services have no console
+fn log_path(name: &str) -> PathBuf {
+    env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join("loggen")
+        .join(format!("loggen-{}.log", name))
+}
+
+/// quote `arg` so CommandLineToArgvW gives it back as is
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from("\"");
+    // backslashes are only special before a quote
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let escapes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.push_str(&"\\".repeat(escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// sc.exe arguments that create the service, it starts with the system
+pub fn create_args(options: &ServiceOptions, program: &[String]) -> Vec<String> {
+    let command_line: Vec<String> = program.iter().map(|arg| quote(arg)).collect();
+    vec![
+        "create".to_string(),
+        service_name(options),
+        "binPath=".to_string(),
+        command_line.join(" "),
+        "start=".to_string(),
+        "auto".to_string(),
+        "DisplayName=".to_string(),
+        format!("loggen {}", options.name),
+    ]
+}
+
+fn sc(args: &[String]) -> io::Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    run_manager("sc.exe", &args)
+}
+
+/// create the service and start it, needs an administrator
+pub fn install(options: &ServiceOptions, program: &[String]) -> io::Result<PathBuf> {
+    sc(&create_args(options, program))?;
+    sc(&["start".to_string(), service_name(options)])?;
+    Ok(PathBuf::from(service_name(options)))
+}
+
+/// stop the service if it runs and delete it
+pub fn uninstall(options: &ServiceOptions) -> io::Result<PathBuf> {
+    // fails when it's already stopped
+    sc(&["stop".to_string(), service_name(options)]).ok();
+    sc(&["delete".to_string(), service_name(options)])?;
+    Ok(PathBuf::from(service_name(options)))
+}
+
+fn set_status(state: u32, accepted: u32) {
+    let status = ServiceStatus {
+        service_type: SERVICE_WIN32_OWN_PROCESS,
+        current_state: state,
+        controls_accepted: accepted,
+        win32_exit_code: NO_ERROR,
+        service_specific_exit_code: 0,
+        check_point: 0,
+        wait_hint: if state == SERVICE_STOP_PENDING {
+            30_000
+        } else {
+            0
+        },
+    };
+    unsafe {
+        SetServiceStatus(STATUS_HANDLE.load(Ordering::SeqCst), &status);
+    }
+}
+
+/// send stdout and stderr to `path`, std looks the handles up on each write
+fn log_to(path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let out = OpenOptions::new().create(true).append(true).open(path)?;
+    let err = out.try_clone()?;
+    unsafe {
+        SetStdHandle(STD_OUTPUT_HANDLE, out.into_raw_handle());
+        SetStdHandle(STD_ERROR_HANDLE, err.into_raw_handle());
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn control_handler(
+    control: u32,
+    _event_type: u32,
+    _event_data: *mut c_void,
+    _context: *mut c_void,
+) -> u32 {
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            set_status(SERVICE_STOP_PENDING, 0);
+            // the same path as SIGTERM, outputs are flushed and closed
+            libc::raise(libc::SIGTERM);
+            NO_ERROR
+        }
+        SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+        _ => ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+unsafe extern "system" fn service_main(_argc: u32, _argv: *mut *mut u16) {
+    let service = SERVICE.lock().unwrap().take();
+    let (name, log, generate) = match service {
+        Some(service) => service,
+        None => return,
+    };
+    let handle = RegisterServiceCtrlHandlerExW(name.as_ptr(), control_handler, ptr::null_mut());
+    if handle.is_null() {
+        return;
+    }
+    STATUS_HANDLE.store(handle, Ordering::SeqCst);
+    set_status(
+        SERVICE_RUNNING,
+        SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN,
+    );
+    // nowhere to report it, generation runs without a log
+    log_to(&log).ok();
+    generate();
+    set_status(SERVICE_STOPPED, 0);
+}
+
+/// run `generate` under the service control manager, or right away when
+/// started from a console
+pub fn run(options: &ServiceOptions, generate: Generate) {
+    let mut name: Vec<u16> = OsStr::new(&service_name(options))
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    *SERVICE.lock().unwrap() = Some((name.clone(), log_path(&options.name), generate));
+    let table = [
+        ServiceTableEntry {
+            service_name: name.as_mut_ptr(),
+            service_proc: Some(service_main),
+        },
+        ServiceTableEntry {
+            service_name: ptr::null_mut(),
+            service_proc: None,
+        },
+    ];
+    // returns once the service stopped, fails if it wasn't started as one
+    if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+        let service = SERVICE.lock().unwrap().take();
+        if let Some((_, _, generate)) = service {
+            generate();
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::group::glob_match;
+use crate::pacer::Pacer;
+use crate::parse::{parse_duration, parse_rate};
+use crate::rebalance::Rebalance;
+use crate::stats::{FileStats, FileStatus, Stats};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep, JoinHandle};
+
+const HELP: &str = "commands:
+  stats                      totals of every file
+  files [GLOB]               lines, errors, rate and state of each file
+  rate [GLOB] RATE|off       lines per second of all files, or of each file matching GLOB
+  pause [GLOB]               stop writing to all files, or to the ones matching GLOB
+  resume [GLOB]              undo pause
+  rotate [GLOB]              rotate all outputs, or the ones matching GLOB
+  parallelism [COUNT]        show or change the number of workers
+  sleep DURATION             wait before the next command, for scripts
+  quit                       stop generating and exit
+globs without a / match file names in any directory, ** matches across directories";
+
+/// what the shell can see and change, see `loggen shell`
+#[derive(Debug)]
+pub struct Shell {
+    pub stats: Arc<Stats>,
+    pub pacer: Arc<Pacer>,
+    pub rebalance: Arc<Rebalance>,
+    pub stop: Arc<AtomicBool>,
+    /// bumped to rotate every output
+    pub rotate_requests: Arc<AtomicU64>,
+    /// bumped after asking single files to rotate
+    pub file_rotations: Arc<AtomicU64>,
+}
+
+/// read commands from stdin on a background thread, generation stops at quit
+/// or at the end of the input
+pub fn start(shell: Shell) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let interactive = io::stdin().is_terminal();
+        if interactive {
+            println!("loggen shell, type help for commands");
+        }
+        let mut lines = io::stdin().lock().lines();
+        while !shell.stop.load(Ordering::Relaxed) {
+            if interactive {
+                print!("> ");
+                io::stdout().flush().ok();
+            }
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(err)) => {
+                    eprintln!("Error reading commands: {}", err);
+                    break;
+                }
+                None => break,
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["quit"] | ["exit"] => break,
+                words => match shell.execute(words) {
+                    Ok(reply) => println!("{}", reply),
+                    Err(message) => println!("error: {}", message),
+                },
+            }
+        }
+        shell.stop.store(true, Ordering::Relaxed);
+    })
+}
+
+impl Shell {
+    fn execute(&self, words: &[&str]) -> Result<String, String> {
+        match words {
+            ["help"] => Ok(HELP.to_string()),
+            ["stats"] => Ok(self.summary()),
+            ["files"] => Ok(self.list("**")),
+            ["files", glob] => Ok(self.list(glob)),
+            ["rate", rate] => {
+                self.pacer.set_rate(parse_limit(rate)?);
+                Ok(format!("rate {}", show_rate(self.pacer.rate())))
+            }
+            ["rate", glob, rate] => {
+                let rate = parse_limit(rate)?;
+                self.each(glob, |file| file.control.set_rate(rate))
+                    .map(|count| format!("rate {} for {} files", show_rate(rate), count))
+            }
+            ["pause"] => {
+                self.pacer.set_paused(true);
+                Ok("paused".to_string())
+            }
+            ["resume"] => {
+                self.pacer.set_paused(false);
+                Ok("resumed".to_string())
+            }
+            ["pause", glob] => self
+                .each(glob, |file| file.control.set_paused(true))
+                .map(|count| format!("paused {} files", count)),
+            ["resume", glob] => self
+                .each(glob, |file| file.control.set_paused(false))
+                .map(|count| format!("resumed {} files", count)),
+            ["rotate"] => {
+                self.rotate_requests.fetch_add(1, Ordering::Relaxed);
+                Ok("rotating every output".to_string())
+            }
+            ["rotate", glob] => {
+                let count = self.each(glob, |file| file.control.request_rotate())?;
+                self.file_rotations.fetch_add(1, Ordering::Relaxed);
+                Ok(format!("rotating {} outputs", count))
+            }
+            ["parallelism"] => Ok(format!("parallelism {}", self.rebalance.current())),
+            ["parallelism", workers] => match workers.parse::<usize>() {
+                Ok(workers) if workers > 0 => {
+                    self.rebalance.request(workers);
+                    Ok(format!("rebalancing to {} workers", workers))
+                }
+                _ => Err(format!("{} isn't a number greater than 0", workers)),
+            },
+            ["sleep", duration] => {
+                sleep(parse_duration(duration)?);
+                Ok(format!("slept {}", duration))
+            }
+            [command, ..] => Err(format!(
+                "unknown command or arguments for {}, type help for commands",
+                command
+            )),
+            [] => Ok(String::new()),
+        }
+    }
+
+    /// call `f` on each file matching `glob`, with how many there were
+    fn each(&self, glob: &str, f: impl Fn(&FileStats)) -> Result<usize, String> {
+        let files: Vec<_> = self
+            .stats
+            .files()
+            .into_iter()
+            .filter(|file| matches(glob, file))
+            .collect();
+        if files.is_empty() {
+            return Err(format!("no file matches {}", glob));
+        }
+        for file in &files {
+            f(file);
+        }
+        Ok(files.len())
+    }
+
+    fn summary(&self) -> String {
+        let status = self.stats.status();
+        let total = |count: fn(&FileStatus) -> u64| -> u64 { status.files.iter().map(count).sum() };
+        format!(
+            "{} files, {} lines, {} bytes, {} wraps, {} errors, rate {}{}, {} workers, up {}s",
+            status.files.len(),
+            total(|file| file.lines),
+            total(|file| file.bytes),
+            total(|file| file.wraps),
+            total(|file| file.errors),
+            show_rate(self.pacer.limit()),
+            if self.pacer.is_paused() {
+                ", paused"
+            } else {
+                ""
+            },
+            self.rebalance.current(),
+            status.uptime_secs
+        )
+    }
+
+    fn list(&self, glob: &str) -> String {
+        let lines: Vec<String> = self
+            .stats
+            .files()
+            .iter()
+            .filter(|file| matches(glob, file))
+            .map(|file| {
+                let status = file.status();
+                let mut line = format!(
+                    "{} {} lines, {} errors",
+                    status.path_out, status.lines, status.errors
+                );
+                if let Some(rate) = file.control.rate() {
+                    line.push_str(&format!(", rate {}", show_rate(Some(rate))));
+                }
+                if file.control.is_paused() {
+                    line.push_str(", paused");
+                }
+                line
+            })
+            .collect();
+        if lines.is_empty() {
+            format!("no file matches {}", glob)
+        } else {
+            lines.join("\n")
+        }
+    }
+}
+
+/// globs without a / match the file name in any directory
+fn matches(glob: &str, file: &FileStats) -> bool {
+    if glob.contains('/') {
+        glob_match(glob, &file.rel_path.to_string_lossy())
+    } else {
+        file.rel_path
+            .file_name()
+            .is_some_and(|name| glob_match(glob, &name.to_string_lossy()))
+    }
+}
+
+/// lines per second like 500 or 500lps, off for no limit
+fn parse_limit(v: &str) -> Result<Option<f64>, String> {
+    if v == "off" {
+        return Ok(None);
+    }
+    match v.parse::<f64>() {
+        Ok(rate) => parse_rate(&format!("{}lps", rate)).map(Some),
+        Err(_) => parse_rate(v).map(Some),
+    }
+}
+
+fn show_rate(rate: Option<f64>) -> String {
+    match rate {
+        Some(rate) => format!("{}lps", rate),
+        None => "off".to_string(),
+    }
+}
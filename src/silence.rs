@@ -0,0 +1,67 @@
+use crate::parse::{parse_duration, parse_kv_list};
+use std::time::{Duration, Instant};
+
+/// stop all output for `length` once every `every`, see --silence
+#[derive(Debug, Clone)]
+pub struct Silence {
+    pub every: Duration,
+    pub length: Duration,
+    /// windows are counted from here, the first one starts after `every`
+    pub started: Instant,
+}
+
+impl Silence {
+    /// parse "every=10m,for=30s", spaces can separate the pairs too
+    pub fn parse(v: &str) -> Result<Silence, String> {
+        let mut every = None;
+        let mut length = None;
+
+        for (key, value) in parse_kv_list(&v.replace(char::is_whitespace, ","))? {
+            match key.as_str() {
+                "every" => every = Some(parse_duration(&value)?),
+                "for" => length = Some(parse_duration(&value)?),
+                _ => return Err(format!("unknown silence option {}", key)),
+            }
+        }
+
+        let every = every.ok_or_else(|| "silence needs every=DURATION".to_string())?;
+        let length = length.ok_or_else(|| "silence needs for=DURATION".to_string())?;
+        if length.is_zero() || length >= every {
+            return Err(format!(
+                "silence for={:?} must be more than 0 and less than every={:?}",
+                length, every
+            ));
+        }
+
+        Ok(Silence {
+            every,
+            length,
+            started: Instant::now(),
+        })
+    }
+
+    /// time left of the current silence, None while lines can be written
+    pub fn remaining(&self) -> Option<Duration> {
+        let elapsed = self.started.elapsed();
+        if elapsed < self.every {
+            return None;
+        }
+
+        let into_window = Duration::from_nanos((elapsed.as_nanos() % self.every.as_nanos()) as u64);
+        self.length
+            .checked_sub(into_window)
+            .filter(|left| !left.is_zero())
+    }
+}
+
+/// checks the keys and durations of one part, both are checked together later
+pub fn is_silence_part(v: String) -> Result<(), String> {
+    for (key, value) in parse_kv_list(&v.replace(char::is_whitespace, ","))? {
+        match key.as_str() {
+            "every" | "for" => parse_duration(&value).map(|_| ())?,
+            _ => return Err(format!("unknown silence option {}", key)),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,646 @@
+use super::{
+    dotted_path_template, BatchQueue, BatchSender, Batches, Batching, Sink, SinkOptions,
+    SinkOutput, SinkUrl,
+};
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 5672;
+const DEFAULT_ROUTING_KEY: &str = "{file}";
+/// messages published before confirms are awaited or errors are checked
+const BATCH_SIZE: usize = 500;
+const TIMEOUT: Duration = Duration::from_secs(30);
+const PARAMS: &[&str] = &["exchange", "routing_key", "confirm", "persistent"];
+
+const PROTOCOL_HEADER: &[u8] = b"AMQP\x00\x00\x09\x01";
+const FRAME_METHOD: u8 = 1;
+const FRAME_HEADER: u8 = 2;
+const FRAME_BODY: u8 = 3;
+const FRAME_HEARTBEAT: u8 = 8;
+const FRAME_END: u8 = 0xce;
+/// frame size asked for when the server has no limit
+const FRAME_MAX: u32 = 128 * 1024;
+/// lines are published on this channel, 0 is for the connection
+const CHANNEL: u16 = 1;
+
+/// (class, method) ids
+type MethodId = (u16, u16);
+const CONNECTION_START: MethodId = (10, 10);
+const CONNECTION_START_OK: MethodId = (10, 11);
+const CONNECTION_TUNE: MethodId = (10, 30);
+const CONNECTION_TUNE_OK: MethodId = (10, 31);
+const CONNECTION_OPEN: MethodId = (10, 40);
+const CONNECTION_OPEN_OK: MethodId = (10, 41);
+const CONNECTION_CLOSE: MethodId = (10, 50);
+const CONNECTION_CLOSE_OK: MethodId = (10, 51);
+const CHANNEL_OPEN: MethodId = (20, 10);
+const CHANNEL_OPEN_OK: MethodId = (20, 11);
+const CHANNEL_CLOSE: MethodId = (20, 40);
+const BASIC_PUBLISH: MethodId = (60, 40);
+const BASIC_ACK: MethodId = (60, 80);
+const BASIC_NACK: MethodId = (60, 120);
+const CONFIRM_SELECT: MethodId = (85, 10);
+const CONFIRM_SELECT_OK: MethodId = (85, 11);
+
+#[derive(Debug, Clone)]
+struct Target {
+    addr: String,
+    user: String,
+    password: String,
+    vhost: String,
+    exchange: String,
+    /// may have {dir}, {file} and {stem}
+    routing_key: String,
+    /// wait for the broker to confirm every message
+    confirm: bool,
+    /// delivery mode 2, stored on disk by durable queues
+    persistent: bool,
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn bool_param(url: &SinkUrl, key: &str) -> io::Result<bool> {
+    match url.param(key) {
+        None | Some("false") => Ok(false),
+        Some("true") => Ok(true),
+        Some(v) => Err(invalid_input(format!(
+            "{}={} should be true or false",
+            key, v
+        ))),
+    }
+}
+
+/// amqp://[user:password@]host[:port][/vhost][?exchange=NAME][&routing_key=KEY][&confirm=true][&persistent=true]
+fn parse_url(url: &str) -> io::Result<Target> {
+    let parsed = SinkUrl::parse(url, "amqp://")?;
+    parsed.check_params(PARAMS)?;
+    // the default vhost is /, which is %2f in urls
+    let vhost = match parsed.path.as_str() {
+        "" => "/".to_string(),
+        path => path.replace("%2f", "/").replace("%2F", "/"),
+    };
+
+    Ok(Target {
+        addr: parsed.addr(DEFAULT_PORT),
+        user: parsed.user.clone().unwrap_or_else(|| "guest".to_string()),
+        password: parsed
+            .password
+            .clone()
+            .unwrap_or_else(|| "guest".to_string()),
+        vhost,
+        exchange: parsed.param("exchange").unwrap_or_default().to_string(),
+        routing_key: parsed
+            .param("routing_key")
+            .unwrap_or(DEFAULT_ROUTING_KEY)
+            .to_string(),
+        confirm: bool_param(&parsed, "confirm")?,
+        persistent: bool_param(&parsed, "persistent")?,
+    })
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// arguments of a method or properties of a content header
+#[derive(Debug, Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn method((class, method): MethodId) -> Encoder {
+        Encoder::default().u16(class).u16(method)
+    }
+
+    fn u8(mut self, v: u8) -> Encoder {
+        self.0.push(v);
+        self
+    }
+
+    fn u16(mut self, v: u16) -> Encoder {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn u32(mut self, v: u32) -> Encoder {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn u64(mut self, v: u64) -> Encoder {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    /// up to 255 bytes, longer values are cut
+    fn short_str(mut self, v: &str) -> Encoder {
+        let v = &v.as_bytes()[..v.len().min(255)];
+        self.0.push(v.len() as u8);
+        self.0.extend_from_slice(v);
+        self
+    }
+
+    fn long_str(self, v: &[u8]) -> Encoder {
+        let mut encoder = self.u32(v.len() as u32);
+        encoder.0.extend_from_slice(v);
+        encoder
+    }
+
+    /// field table with string values
+    fn table(self, entries: &[(&str, &str)]) -> Encoder {
+        let mut table = Encoder::default();
+        for (name, value) in entries {
+            table = table.short_str(name).u8(b'S').long_str(value.as_bytes());
+        }
+        self.long_str(&table.0)
+    }
+}
+
+/// reads the arguments of a method
+#[derive(Debug)]
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(protocol_error("truncated amqp method".to_string()));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn short_str(&mut self) -> io::Result<String> {
+        let len = self.u8()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    kind: u8,
+    channel: u16,
+    payload: Vec<u8>,
+}
+
+fn write_frame<W: Write>(writer: &mut W, kind: u8, channel: u16, payload: &[u8]) {
+    // errors surface when the buffer is written to the socket
+    let mut frame = vec![kind];
+    frame.extend_from_slice(&channel.to_be_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame.push(FRAME_END);
+    writer.write_all(&frame).ok();
+}
+
+fn read_frame<R: BufRead>(reader: &mut R) -> io::Result<Frame> {
+    let mut header = [0; 7];
+    reader.read_exact(&mut header)?;
+    let size = u32::from_be_bytes(header[3..7].try_into().unwrap()) as usize;
+    let mut payload = vec![0; size + 1];
+    reader.read_exact(&mut payload)?;
+    if payload.pop() != Some(FRAME_END) {
+        return Err(protocol_error("amqp frame without frame end".to_string()));
+    }
+
+    Ok(Frame {
+        kind: header[0],
+        channel: u16::from_be_bytes([header[1], header[2]]),
+        payload,
+    })
+}
+
+#[derive(Debug)]
+struct Connection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+    frame_max: u32,
+    /// delivery tag of the next message in confirm mode
+    next_tag: u64,
+}
+
+impl Connection {
+    fn open(target: &Target) -> io::Result<Connection> {
+        let stream = TcpStream::connect(&target.addr)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            frame_max: FRAME_MAX,
+            next_tag: 1,
+        };
+
+        connection.writer.write_all(PROTOCOL_HEADER)?;
+        connection.expect(CONNECTION_START)?;
+        let response = format!("\0{}\0{}", target.user, target.password);
+        let start_ok = Encoder::method(CONNECTION_START_OK)
+            .table(&[
+                ("product", "loggen"),
+                ("version", env!("CARGO_PKG_VERSION")),
+            ])
+            .short_str("PLAIN")
+            .long_str(response.as_bytes())
+            .short_str("en_US");
+        connection.send_method(0, start_ok)?;
+
+        // a wrong password closes the connection here
+        let tune = connection.expect(CONNECTION_TUNE).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("amqp login as {} failed: {}", target.user, err),
+            )
+        })?;
+        let mut tune = Decoder { data: &tune };
+        let channel_max = tune.u16()?;
+        let frame_max = tune.u32()?;
+        if frame_max != 0 {
+            connection.frame_max = frame_max.min(FRAME_MAX);
+        }
+        // heartbeats are disabled, the connection is busy or closed at the end
+        let tune_ok = Encoder::method(CONNECTION_TUNE_OK)
+            .u16(channel_max)
+            .u32(connection.frame_max)
+            .u16(0);
+        connection.send_method(0, tune_ok)?;
+
+        let open = Encoder::method(CONNECTION_OPEN)
+            .short_str(&target.vhost)
+            .short_str("")
+            .u8(0);
+        connection.send_method(0, open)?;
+        connection.expect(CONNECTION_OPEN_OK)?;
+
+        connection.send_method(CHANNEL, Encoder::method(CHANNEL_OPEN).short_str(""))?;
+        connection.expect(CHANNEL_OPEN_OK)?;
+
+        if target.confirm {
+            connection.send_method(CHANNEL, Encoder::method(CONFIRM_SELECT).u8(0))?;
+            connection.expect(CONFIRM_SELECT_OK)?;
+        }
+
+        Ok(connection)
+    }
+
+    fn send_method(&mut self, channel: u16, method: Encoder) -> io::Result<()> {
+        let mut frame = vec![];
+        write_frame(&mut frame, FRAME_METHOD, channel, &method.0);
+        self.writer.write_all(&frame)
+    }
+
+    /// next method from the server, closes are turned into errors
+    fn read_method(&mut self) -> io::Result<(MethodId, Vec<u8>)> {
+        loop {
+            let frame = read_frame(&mut self.reader)?;
+            if frame.kind == FRAME_HEARTBEAT {
+                continue;
+            } else if frame.kind != FRAME_METHOD {
+                return Err(protocol_error(format!(
+                    "unexpected amqp frame type {} on channel {}",
+                    frame.kind, frame.channel
+                )));
+            }
+
+            let mut decoder = Decoder {
+                data: &frame.payload,
+            };
+            let id = (decoder.u16()?, decoder.u16()?);
+            if id == CONNECTION_CLOSE || id == CHANNEL_CLOSE {
+                let code = decoder.u16()?;
+                let text = decoder.short_str()?;
+                if id == CONNECTION_CLOSE {
+                    self.send_method(0, Encoder::method(CONNECTION_CLOSE_OK))
+                        .ok();
+                }
+                return Err(io::Error::other(format!(
+                    "amqp {} closed with {}: {}",
+                    if id == CONNECTION_CLOSE {
+                        "connection"
+                    } else {
+                        "channel"
+                    },
+                    code,
+                    text
+                )));
+            }
+
+            return Ok((id, decoder.data.to_vec()));
+        }
+    }
+
+    /// arguments of the next method, which must be `expected`
+    fn expect(&mut self, expected: MethodId) -> io::Result<Vec<u8>> {
+        let (id, args) = self.read_method()?;
+        if id != expected {
+            return Err(protocol_error(format!(
+                "expected amqp method {:?} but got {:?}",
+                expected, id
+            )));
+        }
+        Ok(args)
+    }
+
+    /// true if the server sent something, like a channel close, without
+    /// being asked
+    fn has_pending(&mut self) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(true);
+        }
+
+        self.writer.set_nonblocking(true)?;
+        let peeked = self.writer.peek(&mut [0]);
+        self.writer.set_nonblocking(false)?;
+        match peeked {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// publish a batch of (routing key, line) messages, with confirms waits for
+    /// all of them and returns how many were nacked
+    fn publish(
+        &mut self,
+        batch: &[Message],
+        target: &Target,
+        labels: &[(String, String)],
+    ) -> io::Result<usize> {
+        let labels: Vec<(&str, &str)> = labels
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let mut data = vec![];
+        for (routing_key, line) in batch {
+            write_message(
+                &mut data,
+                self.frame_max,
+                target,
+                routing_key,
+                line,
+                &labels,
+            );
+        }
+        self.writer.write_all(&data)?;
+
+        if !target.confirm {
+            // errors like a missing exchange close the channel without an answer
+            if self.has_pending()? {
+                self.read_method()?;
+            }
+            return Ok(0);
+        }
+
+        let mut pending: BTreeSet<u64> =
+            (self.next_tag..self.next_tag + batch.len() as u64).collect();
+        self.next_tag += batch.len() as u64;
+        let mut nacked = 0;
+        while !pending.is_empty() {
+            let (id, args) = self.read_method()?;
+            if id != BASIC_ACK && id != BASIC_NACK {
+                continue;
+            }
+            let mut decoder = Decoder { data: &args };
+            let tag = decoder.u64()?;
+            let multiple = decoder.u8()? & 1 == 1;
+            let confirmed = if multiple {
+                let rest = pending.split_off(&(tag + 1));
+                std::mem::replace(&mut pending, rest).len()
+            } else {
+                pending.remove(&tag) as usize
+            };
+            if id == BASIC_NACK {
+                nacked += confirmed;
+            }
+        }
+
+        Ok(nacked)
+    }
+
+    fn close(mut self) {
+        let close = Encoder::method(CONNECTION_CLOSE)
+            .u16(200)
+            .short_str("bye")
+            .u16(0)
+            .u16(0);
+        if self.send_method(0, close).is_ok() {
+            // Close-Ok, or the close the server sent first
+            self.read_method().ok();
+        }
+    }
+}
+
+/// frames publishing `line`: the method, the content header and the body split
+/// in frames of at most `frame_max` bytes
+fn write_message(
+    data: &mut Vec<u8>,
+    frame_max: u32,
+    target: &Target,
+    routing_key: &str,
+    line: &str,
+    labels: &[(&str, &str)],
+) {
+    let publish = Encoder::method(BASIC_PUBLISH)
+        .u16(0)
+        .short_str(&target.exchange)
+        .short_str(routing_key)
+        .u8(0);
+    write_frame(data, FRAME_METHOD, CHANNEL, &publish.0);
+
+    // content-type, headers if there are labels and delivery-mode
+    let mut flags = 0x8000 | 0x1000;
+    if !labels.is_empty() {
+        flags |= 0x2000;
+    }
+    let mut header = Encoder::default()
+        .u16(BASIC_PUBLISH.0)
+        .u16(0)
+        .u64(line.len() as u64)
+        .u16(flags)
+        .short_str("text/plain");
+    if !labels.is_empty() {
+        header = header.table(labels);
+    }
+    header = header.u8(if target.persistent { 2 } else { 1 });
+    write_frame(data, FRAME_HEADER, CHANNEL, &header.0);
+
+    // 7 bytes of frame header and the frame end
+    for chunk in line.as_bytes().chunks(frame_max as usize - 8) {
+        write_frame(data, FRAME_BODY, CHANNEL, chunk);
+    }
+}
+
+/// routing key and line to publish
+type Message = (Arc<str>, String);
+
+/// publishes batches over its own connection, reconnecting after errors
+fn send_batches(target: &Target, labels: &[(String, String)], batches: &Batches<Message>) {
+    let mut connection: Option<Connection> = None;
+    batches.send_each(|batch| {
+        if connection.is_none() {
+            connection = Some(Connection::open(target)?);
+        }
+        match connection.as_mut().unwrap().publish(batch, target, labels) {
+            Ok(0) => Ok(()),
+            Ok(nacked) => {
+                eprintln!(
+                    "Error: the broker nacked {} of {} lines",
+                    nacked,
+                    batch.len()
+                );
+                Ok(())
+            }
+            Err(err) => {
+                connection = None;
+                Err(err)
+            }
+        }
+    });
+
+    if let Some(connection) = connection {
+        connection.close();
+    }
+}
+
+/// publishes lines to an AMQP 0-9-1 exchange (RabbitMQ) with routing keys
+/// from the relative input path, over `concurrency` connections
+#[derive(Debug)]
+pub struct AmqpSink {
+    target: Target,
+    sender: BatchSender<Message>,
+}
+
+impl AmqpSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<AmqpSink> {
+        let target = parse_url(url)?;
+        // fail at startup if the broker can't be reached or refuses the login
+        Connection::open(&target)?.close();
+
+        let batching = Batching {
+            size: BATCH_SIZE,
+            wait: None,
+            max_bytes: usize::MAX,
+        };
+        let sender = {
+            let target = target.clone();
+            let labels = options.labels.clone();
+            let destination = format!("amqp {}", target.addr);
+            BatchSender::start("amqp", destination, batching, options, move |batches| {
+                send_batches(&target, &labels, batches)
+            })
+        };
+
+        Ok(AmqpSink { target, sender })
+    }
+}
+
+impl Sink for AmqpSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(AmqpOutput {
+            routing_key: dotted_path_template(&self.target.routing_key, rel_path).into(),
+            messages: self.sender.queue(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.sender.close()
+    }
+
+    fn failures(&self) -> u64 {
+        self.sender.failures()
+    }
+
+    fn errors(&self) -> u64 {
+        self.sender.errors()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        Connection::open(&self.target)?.close();
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct AmqpOutput {
+    routing_key: Arc<str>,
+    messages: BatchQueue<Message>,
+}
+
+impl SinkOutput for AmqpOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let message = line.trim_end_matches(['\r', '\n']).to_string();
+        let size = message.len();
+        self.messages
+            .send((self.routing_key.clone(), message), size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_url, read_frame, write_message, Decoder, FRAME_BODY, FRAME_HEADER, FRAME_METHOD,
+    };
+
+    #[test]
+    fn bodies_are_split_to_fit_frame_max() {
+        let target = parse_url("amqp://localhost/?exchange=logs").unwrap();
+        let line = "0123456789abcdefghij";
+        let mut data = vec![];
+        write_message(&mut data, 16, &target, "app.log", line, &[]);
+
+        let mut reader = &data[..];
+        let mut frames = vec![];
+        while !reader.is_empty() {
+            frames.push(read_frame(&mut reader).unwrap());
+        }
+        let kinds: Vec<u8> = frames.iter().map(|frame| frame.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                FRAME_METHOD,
+                FRAME_HEADER,
+                FRAME_BODY,
+                FRAME_BODY,
+                FRAME_BODY
+            ]
+        );
+
+        // class, weight and then the body size
+        let mut header = Decoder {
+            data: &frames[1].payload,
+        };
+        header.u16().unwrap();
+        header.u16().unwrap();
+        assert_eq!(header.u64().unwrap(), line.len() as u64);
+
+        let bodies: Vec<&[u8]> = frames[2..]
+            .iter()
+            .map(|frame| frame.payload.as_slice())
+            .collect();
+        assert_eq!(bodies, [&b"01234567"[..], b"89abcdef", b"ghij"]);
+    }
+}
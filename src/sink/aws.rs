@@ -0,0 +1,769 @@
+use super::{
+    with_labels, BatchQueue, BatchSender, Batching, Sink, SinkOptions, SinkOutput, SinkUrl,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::Utc;
+use rand::Rng;
+use ring::{digest, hmac};
+use serde_json::{json, Value};
+use std::env;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// a batch is sent when it's full, too big or this long after its first line
+const DEFAULT_BATCH_WAIT: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// PutLogEvents takes up to 10000 events and 1MB, counting 26 bytes per event
+const CLOUDWATCH_MAX_EVENTS: usize = 10_000;
+const CLOUDWATCH_MAX_BYTES: usize = 1_048_576;
+const CLOUDWATCH_EVENT_OVERHEAD: usize = 26;
+/// PutRecords takes up to 500 records and 5MB, data and partition keys
+const KINESIS_MAX_RECORDS: usize = 500;
+const KINESIS_MAX_BYTES: usize = 5 * 1024 * 1024;
+/// a sequence token rejected by PutLogEvents is retried this many times
+const SEQUENCE_RETRIES: usize = 3;
+const CLOUDWATCH_PARAMS: &[&str] = &["region", "endpoint", "batch_size", "batch_wait"];
+const KINESIS_PARAMS: &[&str] = &[
+    "region",
+    "endpoint",
+    "batch_size",
+    "batch_wait",
+    "partition",
+];
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+/// a request signed with signature version 4 and the steps to get there
+#[derive(Debug)]
+struct Signed {
+    scope: String,
+    signed_headers: String,
+    #[cfg_attr(not(test), allow(dead_code))]
+    canonical_request: String,
+    #[cfg_attr(not(test), allow(dead_code))]
+    string_to_sign: String,
+    signature: String,
+}
+
+/// sign a request to / without a query, `headers` are lowercase and sorted
+/// and `amz_date` is the x-amz-date header
+fn sign_v4(
+    method: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+    amz_date: &str,
+    region: &str,
+    service: &str,
+    secret_key: &str,
+) -> Signed {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let canonical_request = format!(
+        "{}\n/\n\n{}\n{}\n{}",
+        method,
+        canonical_headers,
+        signed_headers,
+        sha256_hex(body)
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let mut key = format!("AWS4{}", secret_key).into_bytes();
+    for part in [date, region, service, "aws4_request"] {
+        key = hmac_sha256(&key, part);
+    }
+    let signature = hex(&hmac_sha256(&key, &string_to_sign));
+    Signed {
+        scope,
+        signed_headers,
+        canonical_request,
+        string_to_sign,
+        signature,
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+/// access keys from AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and
+/// AWS_SESSION_TOKEN for temporary ones
+#[derive(Clone)]
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key", &self.access_key)
+            .finish()
+    }
+}
+
+impl Credentials {
+    fn from_env() -> Option<Credentials> {
+        let var = |name| env::var(name).ok().filter(|value| !value.is_empty());
+        Some(Credentials {
+            access_key: var("AWS_ACCESS_KEY_ID")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+}
+
+/// how kinesis records are spread over shards
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Partition {
+    /// the relative path of the generated file, each file stays on one shard
+    File,
+    /// a random key per record
+    Random,
+    /// files are given the shards in turn, with explicit hash keys
+    Shard,
+}
+
+#[derive(Debug)]
+enum Api {
+    /// PutLogEvents to a log stream, which is created if it doesn't exist
+    CloudWatch {
+        group: String,
+        stream: String,
+        /// the next sequence token, held while a batch is sent
+        sequence_token: Mutex<Option<String>>,
+    },
+    /// PutRecords to a data stream
+    Kinesis {
+        stream: String,
+        partition: Partition,
+        /// starting hash key of each open shard, for Partition::Shard
+        shards: Vec<String>,
+    },
+}
+
+#[derive(Debug)]
+struct Target {
+    api: Api,
+    region: String,
+    /// where requests are sent, https://logs.REGION.amazonaws.com for cloudwatch
+    endpoint: String,
+    /// None when sending to an endpoint without credentials, requests aren't signed
+    credentials: Option<Credentials>,
+    batch_size: usize,
+    batch_wait: Duration,
+}
+
+/// cloudwatch://group/stream or kinesis://stream, both with [?region=REGION]
+/// [&endpoint=URL][&batch_size=N][&batch_wait=DURATION], kinesis also takes
+/// [&partition=file|random|shard]
+fn parse_url(url: &str) -> io::Result<Target> {
+    let cloudwatch = url.starts_with("cloudwatch://");
+    let parsed = if cloudwatch {
+        SinkUrl::parse(url, "cloudwatch://")?
+    } else {
+        SinkUrl::parse(url, "kinesis://")?
+    };
+    parsed.check_params(if cloudwatch {
+        CLOUDWATCH_PARAMS
+    } else {
+        KINESIS_PARAMS
+    })?;
+
+    let path = parsed.path.trim_end_matches('/');
+    let (api, service, max_batch) = if cloudwatch {
+        if path.is_empty() {
+            return Err(invalid(format!("{} should end with /stream", url)));
+        }
+        let api = Api::CloudWatch {
+            group: parsed.host.clone(),
+            stream: path.to_string(),
+            sequence_token: Mutex::new(None),
+        };
+        (api, "logs", CLOUDWATCH_MAX_EVENTS)
+    } else {
+        if !path.is_empty() {
+            return Err(invalid(format!("{} should be kinesis://stream", url)));
+        }
+        let partition = match parsed.param("partition") {
+            None | Some("file") => Partition::File,
+            Some("random") => Partition::Random,
+            Some("shard") => Partition::Shard,
+            Some(v) => {
+                return Err(invalid(format!(
+                    "partition={} should be file, random or shard",
+                    v
+                )))
+            }
+        };
+        let api = Api::Kinesis {
+            stream: parsed.host.clone(),
+            partition,
+            shards: vec![],
+        };
+        (api, "kinesis", KINESIS_MAX_RECORDS)
+    };
+
+    let batch_size = match parsed.param("batch_size") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(size) if size > 0 && size <= max_batch => size,
+            _ => {
+                return Err(invalid(format!(
+                    "batch_size={} should be a number from 1 to {}",
+                    v, max_batch
+                )))
+            }
+        },
+        None => max_batch,
+    };
+    let batch_wait = match parsed.param("batch_wait") {
+        Some(v) => crate::parse::parse_duration(v).map_err(invalid)?,
+        None => DEFAULT_BATCH_WAIT,
+    };
+
+    let region = parsed
+        .param("region")
+        .map(|region| region.to_string())
+        .or_else(|| env::var("AWS_REGION").ok())
+        .or_else(|| env::var("AWS_DEFAULT_REGION").ok())
+        .filter(|region| !region.is_empty())
+        .ok_or_else(|| {
+            invalid(format!(
+                "{} needs a region, set AWS_REGION or region=REGION",
+                url
+            ))
+        })?;
+    let endpoint = parsed
+        .param("endpoint")
+        .map(|endpoint| endpoint.to_string());
+    let credentials = Credentials::from_env();
+    if credentials.is_none() && endpoint.is_none() {
+        return Err(invalid(format!(
+            "{} needs credentials, set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+            url
+        )));
+    }
+    let endpoint =
+        endpoint.unwrap_or_else(|| format!("https://{}.{}.amazonaws.com", service, region));
+
+    Ok(Target {
+        api,
+        region,
+        endpoint: endpoint.trim_end_matches('/').to_string(),
+        credentials,
+        batch_size,
+        batch_wait,
+    })
+}
+
+impl Target {
+    fn name(&self) -> String {
+        match &self.api {
+            Api::CloudWatch { group, stream, .. } => format!("{}/{}", group, stream),
+            Api::Kinesis { stream, .. } => stream.clone(),
+        }
+    }
+
+    fn service(&self) -> (&'static str, &'static str) {
+        match &self.api {
+            Api::CloudWatch { .. } => ("logs", "Logs_20140328"),
+            Api::Kinesis { .. } => ("kinesis", "Kinesis_20131202"),
+        }
+    }
+
+    fn max_bytes(&self) -> usize {
+        match &self.api {
+            Api::CloudWatch { .. } => CLOUDWATCH_MAX_BYTES,
+            Api::Kinesis { .. } => KINESIS_MAX_BYTES,
+        }
+    }
+
+    /// headers for `body` signed with signature version 4
+    fn sign(&self, target: &str, body: &[u8]) -> Vec<(String, String)> {
+        let host = self
+            .endpoint
+            .split_once("://")
+            .map_or(self.endpoint.as_str(), |(_, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = vec![
+            (
+                "content-type".to_string(),
+                "application/x-amz-json-1.1".to_string(),
+            ),
+            ("host".to_string(), host),
+            ("x-amz-date".to_string(), amz_date.clone()),
+            ("x-amz-target".to_string(), target.to_string()),
+        ];
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return headers,
+        };
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        headers.sort();
+
+        let (service, _) = self.service();
+        let signed = sign_v4(
+            "POST",
+            &headers,
+            body,
+            &amz_date,
+            &self.region,
+            service,
+            &credentials.secret_key,
+        );
+        headers.push((
+            "authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                credentials.access_key, signed.scope, signed.signed_headers, signed.signature
+            ),
+        ));
+        headers
+    }
+
+    /// call `action` with `body`, errors carry the exception name AWS answered
+    fn call(&self, agent: &ureq::Agent, action: &str, body: &Value) -> io::Result<Value> {
+        let (_, prefix) = self.service();
+        let body = body.to_string();
+        let mut request = agent.post(&self.endpoint);
+        for (name, value) in self.sign(&format!("{}.{}", prefix, action), body.as_bytes()) {
+            if name != "host" {
+                request = request.set(&name, &value);
+            }
+        }
+
+        match request.send_string(&body) {
+            Ok(response) => {
+                let text = response.into_string()?;
+                if text.trim().is_empty() {
+                    Ok(Value::Null)
+                } else {
+                    serde_json::from_str(&text).map_err(io::Error::other)
+                }
+            }
+            Err(ureq::Error::Status(code, response)) => {
+                let text = response.into_string().unwrap_or_default();
+                Err(AwsError::from_response(code, &text).into())
+            }
+            Err(err) => Err(io::Error::other(format!("{}: {}", self.endpoint, err))),
+        }
+    }
+
+    /// create the cloudwatch log stream or list the kinesis shards for partition=shard
+    fn prepare(&mut self, agent: &ureq::Agent) -> io::Result<()> {
+        match &self.api {
+            Api::CloudWatch { group, stream, .. } => {
+                let body = json!({"logGroupName": group, "logStreamName": stream});
+                match self.call(agent, "CreateLogStream", &body) {
+                    Err(err) if AwsError::kind(&err) == Some("ResourceAlreadyExistsException") => {
+                        Ok(())
+                    }
+                    result => result.map(|_| ()),
+                }
+            }
+            Api::Kinesis {
+                stream, partition, ..
+            } => {
+                if *partition != Partition::Shard {
+                    return Ok(());
+                }
+                let mut shards = vec![];
+                let mut next_token: Option<String> = None;
+                loop {
+                    // StreamName can't be sent with NextToken
+                    let body = match &next_token {
+                        Some(token) => json!({ "NextToken": token }),
+                        None => json!({ "StreamName": stream }),
+                    };
+                    let response = self.call(agent, "ListShards", &body)?;
+                    for shard in response["Shards"].as_array().into_iter().flatten() {
+                        // closed shards have an ending sequence number
+                        if shard["SequenceNumberRange"]["EndingSequenceNumber"].is_null() {
+                            if let Some(start) = shard["HashKeyRange"]["StartingHashKey"].as_str() {
+                                shards.push(start.to_string());
+                            }
+                        }
+                    }
+                    next_token = response["NextToken"].as_str().map(|t| t.to_string());
+                    if next_token.is_none() {
+                        break;
+                    }
+                }
+                if shards.is_empty() {
+                    return Err(io::Error::other(format!(
+                        "kinesis stream {} has no open shards",
+                        stream
+                    )));
+                }
+                let partition = *partition;
+                let stream = stream.clone();
+                self.api = Api::Kinesis {
+                    stream,
+                    partition,
+                    shards,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    fn put_log_events(&self, agent: &ureq::Agent, batch: &[Value]) -> io::Result<()> {
+        let (group, stream, sequence_token) = match &self.api {
+            Api::CloudWatch {
+                group,
+                stream,
+                sequence_token,
+            } => (group, stream, sequence_token),
+            Api::Kinesis { .. } => unreachable!("put_log_events on kinesis"),
+        };
+        // events have to be in order, lines from different files can cross
+        let mut batch: Vec<&Value> = batch.iter().collect();
+        batch.sort_by_key(|event| event["timestamp"].as_i64());
+
+        // the token is held while sending, so batches to the stream go one at a time
+        let mut token = sequence_token.lock().unwrap();
+        let mut retries = 0;
+        loop {
+            let mut body = json!({
+                "logGroupName": group,
+                "logStreamName": stream,
+                "logEvents": batch,
+            });
+            if let Some(token) = &*token {
+                body["sequenceToken"] = token.as_str().into();
+            }
+
+            let err = match self.call(agent, "PutLogEvents", &body) {
+                Ok(response) => {
+                    *token = response["nextSequenceToken"]
+                        .as_str()
+                        .map(|t| t.to_string());
+                    if let Some(rejected) = response.get("rejectedLogEventsInfo") {
+                        return Err(io::Error::other(format!(
+                            "some events were rejected: {}",
+                            rejected
+                        )));
+                    }
+                    return Ok(());
+                }
+                Err(err) => err,
+            };
+            // a token from another writer, or one accepted before a timeout
+            let expected = AwsError::expected_token(&err);
+            match AwsError::kind(&err) {
+                Some("DataAlreadyAcceptedException") => {
+                    *token = expected;
+                    return Ok(());
+                }
+                Some("InvalidSequenceTokenException") if retries < SEQUENCE_RETRIES => {
+                    *token = expected;
+                    retries += 1;
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
+    fn put_records(&self, agent: &ureq::Agent, batch: &[Value]) -> io::Result<()> {
+        let stream = match &self.api {
+            Api::Kinesis { stream, .. } => stream,
+            Api::CloudWatch { .. } => unreachable!("put_records on cloudwatch"),
+        };
+        let count = batch.len();
+        let body = json!({"StreamName": stream, "Records": batch});
+        let response = self.call(agent, "PutRecords", &body)?;
+
+        let failed = response["FailedRecordCount"].as_u64().unwrap_or(0);
+        if failed == 0 {
+            return Ok(());
+        }
+        // throttled records are the limit being probed, report why they failed
+        let mut codes: Vec<&str> = response["Records"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|record| record["ErrorCode"].as_str())
+            .collect();
+        codes.sort_unstable();
+        codes.dedup();
+        Err(io::Error::other(format!(
+            "{} of {} records failed: {}",
+            failed,
+            count,
+            codes.join(", ")
+        )))
+    }
+
+    fn send(&self, agent: &ureq::Agent, batch: &[Value]) -> io::Result<()> {
+        match &self.api {
+            Api::CloudWatch { .. } => self.put_log_events(agent, batch),
+            Api::Kinesis { .. } => self.put_records(agent, batch),
+        }
+    }
+}
+
+/// the exception AWS answered with, as the message of an io::Error
+#[derive(Debug)]
+struct AwsError {
+    code: u16,
+    kind: String,
+    message: String,
+    /// set by InvalidSequenceTokenException and DataAlreadyAcceptedException
+    expected_token: Option<String>,
+}
+
+impl AwsError {
+    fn from_response(code: u16, text: &str) -> AwsError {
+        let body: Value = serde_json::from_str(text).unwrap_or(Value::Null);
+        // __type can be prefixed with a namespace and #
+        let kind = body["__type"]
+            .as_str()
+            .map(|kind| kind.rsplit('#').next().unwrap_or(kind).to_string())
+            .unwrap_or_default();
+        let message = body["message"]
+            .as_str()
+            .or_else(|| body["Message"].as_str())
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| text.trim().to_string());
+        AwsError {
+            code,
+            kind,
+            message,
+            expected_token: body["expectedSequenceToken"]
+                .as_str()
+                .map(|t| t.to_string()),
+        }
+    }
+
+    fn kind(err: &io::Error) -> Option<&str> {
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<AwsError>())
+            .map(|err| err.kind.as_str())
+    }
+
+    fn expected_token(err: &io::Error) -> Option<String> {
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<AwsError>())
+            .and_then(|err| err.expected_token.clone())
+    }
+}
+
+impl std::fmt::Display for AwsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "answered {} {}: {}", self.code, self.kind, self.message)
+    }
+}
+
+impl std::error::Error for AwsError {}
+
+impl From<AwsError> for io::Error {
+    fn from(err: AwsError) -> io::Error {
+        io::Error::other(err)
+    }
+}
+
+/// sends lines to a CloudWatch Logs stream or a Kinesis data stream in
+/// batches, over `concurrency` connections
+#[derive(Debug)]
+pub struct AwsSink {
+    target: Arc<Target>,
+    labels: Arc<[(String, String)]>,
+    /// outputs created, to give kinesis shards to files in turn
+    outputs: AtomicUsize,
+    sender: BatchSender<Value>,
+}
+
+impl AwsSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<AwsSink> {
+        let mut target = parse_url(url)?;
+
+        // the agent is shared so senders reuse connections from its pool
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .max_idle_connections_per_host(options.concurrency.max(1))
+            .build();
+        target.prepare(&agent)?;
+        let target = Arc::new(target);
+
+        let batching = Batching {
+            size: target.batch_size,
+            wait: Some(target.batch_wait),
+            max_bytes: target.max_bytes(),
+        };
+        let sender = {
+            let target = target.clone();
+            BatchSender::start("aws", target.name(), batching, options, move |batches| {
+                batches.send_each(|batch| target.send(&agent, batch))
+            })
+        };
+
+        Ok(AwsSink {
+            target,
+            labels: options.labels.clone().into(),
+            outputs: AtomicUsize::new(0),
+            sender,
+        })
+    }
+}
+
+impl Sink for AwsSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        let index = self.outputs.fetch_add(1, Ordering::Relaxed);
+        let (partition, hash_key) = match &self.target.api {
+            Api::Kinesis {
+                partition, shards, ..
+            } => (
+                Some(*partition),
+                match partition {
+                    Partition::Shard => Some(shards[index % shards.len()].clone()),
+                    _ => None,
+                },
+            ),
+            Api::CloudWatch { .. } => (None, None),
+        };
+        Box::new(AwsOutput {
+            partition,
+            partition_key: rel_path.to_string_lossy().to_string(),
+            hash_key,
+            labels: self.labels.clone(),
+            items: self.sender.queue(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.sender.close()
+    }
+
+    fn failures(&self) -> u64 {
+        self.sender.failures()
+    }
+
+    fn errors(&self) -> u64 {
+        self.sender.errors()
+    }
+}
+
+#[derive(Debug)]
+struct AwsOutput {
+    /// None for cloudwatch
+    partition: Option<Partition>,
+    partition_key: String,
+    /// explicit hash key of the shard this file goes to, for Partition::Shard
+    hash_key: Option<String>,
+    labels: Arc<[(String, String)]>,
+    items: BatchQueue<Value>,
+}
+
+impl SinkOutput for AwsOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let line = if self.labels.is_empty() {
+            line.to_string()
+        } else {
+            with_labels(line, &self.labels)
+        };
+        let (item, size) = match self.partition {
+            None => {
+                let size = line.len() + CLOUDWATCH_EVENT_OVERHEAD;
+                let event = json!({
+                    "timestamp": Utc::now().timestamp_millis(),
+                    "message": line,
+                });
+                (event, size)
+            }
+            Some(partition) => {
+                let key = match partition {
+                    Partition::Random => format!("{:016x}", rand::thread_rng().gen::<u64>()),
+                    Partition::File | Partition::Shard => self.partition_key.clone(),
+                };
+                let size = line.len() + key.len();
+                let mut record = json!({
+                    "Data": STANDARD.encode(&line),
+                    "PartitionKey": key,
+                });
+                if let Some(hash_key) = &self.hash_key {
+                    record["ExplicitHashKey"] = hash_key.as_str().into();
+                }
+                (record, size)
+            }
+        };
+        self.items.send(item, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sign_v4;
+
+    /// get-vanilla from AWS's signature version 4 test suite
+    #[test]
+    fn signs_the_get_vanilla_request() {
+        let headers = [
+            ("host".to_string(), "example.amazonaws.com".to_string()),
+            ("x-amz-date".to_string(), "20150830T123600Z".to_string()),
+        ];
+        let signed = sign_v4(
+            "GET",
+            &headers,
+            b"",
+            "20150830T123600Z",
+            "us-east-1",
+            "service",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        );
+
+        assert_eq!(
+            signed.canonical_request,
+            "GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:20150830T123600Z\n\n\
+             host;x-amz-date\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            signed.string_to_sign,
+            "AWS4-HMAC-SHA256\n20150830T123600Z\n20150830/us-east-1/service/aws4_request\n\
+             bb579772317eb040ac9ed261061d46c1f17a8133879d6129b6e1c25292927e63"
+        );
+        assert_eq!(
+            signed.signature,
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        assert_eq!(signed.signed_headers, "host;x-amz-date");
+        assert_eq!(signed.scope, "20150830/us-east-1/service/aws4_request");
+    }
+}
@@ -0,0 +1,255 @@
+use super::{
+    with_labels, BatchQueue, BatchSender, Batching, Sink, SinkOptions, SinkOutput, SinkUrl,
+};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 8123;
+const DEFAULT_FORMAT: &str = "JSONEachRow";
+const DEFAULT_BATCH_SIZE: usize = 10_000;
+/// a batch is sent when it's full or this long after its first line
+const DEFAULT_BATCH_WAIT: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(30);
+const PARAMS: &[&str] = &["format", "batch_size", "batch_wait", "async_insert", "wait"];
+
+#[derive(Debug, Clone)]
+struct Target {
+    /// http(s)://host:port/
+    base_url: String,
+    user: Option<String>,
+    password: Option<String>,
+    /// table or database.table
+    table: String,
+    format: String,
+    batch_size: usize,
+    batch_wait: Duration,
+    /// let the server buffer inserts, see async_insert in the clickhouse docs
+    async_insert: bool,
+    /// with async_insert, wait until the server flushed the insert
+    wait: bool,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn parse_bool(key: &str, value: &str) -> io::Result<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(invalid(format!(
+            "{}={} should be true or false",
+            key, value
+        ))),
+    }
+}
+
+/// clickhouse://[user[:password]@]host[:port]/[database.]table[?format=FORMAT]
+/// [&batch_size=N][&batch_wait=DURATION][&async_insert=true][&wait=false],
+/// clickhouses:// uses https
+fn parse_url(url: &str) -> io::Result<Target> {
+    let (scheme, http) = if url.starts_with("clickhouses://") {
+        ("clickhouses://", "https")
+    } else {
+        ("clickhouse://", "http")
+    };
+    let parsed = SinkUrl::parse(url, scheme)?;
+    parsed.check_params(PARAMS)?;
+
+    let table = parsed.path.trim_end_matches('/').to_string();
+    if table.is_empty() || table.contains('/') {
+        return Err(invalid(format!("{} should end with /database.table", url)));
+    }
+    let batch_size = match parsed.param("batch_size") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(size) if size > 0 => size,
+            _ => {
+                return Err(invalid(format!(
+                    "batch_size={} should be a number greater than 0",
+                    v
+                )))
+            }
+        },
+        None => DEFAULT_BATCH_SIZE,
+    };
+    let batch_wait = match parsed.param("batch_wait") {
+        Some(v) => crate::parse::parse_duration(v).map_err(invalid)?,
+        None => DEFAULT_BATCH_WAIT,
+    };
+    let async_insert = match parsed.param("async_insert") {
+        Some(v) => parse_bool("async_insert", v)?,
+        None => false,
+    };
+    let wait = match parsed.param("wait") {
+        Some(v) => parse_bool("wait", v)?,
+        None => true,
+    };
+
+    Ok(Target {
+        base_url: format!("{}://{}/", http, parsed.addr(DEFAULT_PORT)),
+        user: parsed.user.clone(),
+        password: parsed.password.clone(),
+        table,
+        format: parsed.param("format").unwrap_or(DEFAULT_FORMAT).to_string(),
+        batch_size,
+        batch_wait,
+        async_insert,
+        wait,
+    })
+}
+
+impl Target {
+    fn request(&self, agent: &ureq::Agent, query: &str) -> ureq::Request {
+        let mut request = agent.post(&self.base_url).query("query", query);
+        if let Some(user) = &self.user {
+            request = request.set("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &self.password {
+            request = request.set("X-ClickHouse-Key", password);
+        }
+        request
+    }
+
+    /// run `query` with `body`, the response body if it succeeded
+    fn send(&self, request: ureq::Request, body: &[u8]) -> io::Result<String> {
+        match request.send_bytes(body) {
+            Ok(response) => response.into_string(),
+            Err(ureq::Error::Status(code, response)) => Err(io::Error::other(format!(
+                "clickhouse answered {}: {}",
+                code,
+                response.into_string().unwrap_or_default().trim()
+            ))),
+            Err(err) => Err(io::Error::other(format!("clickhouse: {}", err))),
+        }
+    }
+
+    /// fail at startup if clickhouse can't be reached or the table doesn't exist
+    fn check(&self, agent: &ureq::Agent) -> io::Result<()> {
+        let query = format!("EXISTS TABLE {}", self.table);
+        let exists = self.send(self.request(agent, &query), &[])?;
+        if exists.trim() != "1" {
+            return Err(invalid(format!(
+                "clickhouse table {} doesn't exist",
+                self.table
+            )));
+        }
+        Ok(())
+    }
+
+    fn insert(&self, agent: &ureq::Agent, batch: &[String]) -> io::Result<()> {
+        let query = format!("INSERT INTO {} FORMAT {}", self.table, self.format);
+        let mut request = self.request(agent, &query);
+        if self.async_insert {
+            request = request
+                .query("async_insert", "1")
+                .query("wait_for_async_insert", if self.wait { "1" } else { "0" });
+        }
+
+        let mut body = Vec::with_capacity(batch.iter().map(|line| line.len() + 1).sum());
+        for line in batch {
+            body.extend_from_slice(line.as_bytes());
+            body.push(b'\n');
+        }
+        self.send(request, &body).map(|_| ())
+    }
+}
+
+/// inserts lines into a clickhouse table over http in batches, over
+/// `concurrency` connections
+#[derive(Debug)]
+pub struct ClickHouseSink {
+    target: Target,
+    agent: ureq::Agent,
+    labels: Arc<[(String, String)]>,
+    sender: BatchSender<String>,
+}
+
+impl ClickHouseSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<ClickHouseSink> {
+        let target = parse_url(url)?;
+        if !options.labels.is_empty() && target.format != DEFAULT_FORMAT {
+            return Err(invalid(format!(
+                "--sink-label only works with format={}",
+                DEFAULT_FORMAT
+            )));
+        }
+
+        // the agent is shared so senders reuse connections from its pool
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .max_idle_connections_per_host(options.concurrency.max(1))
+            .build();
+        target.check(&agent)?;
+
+        let batching = Batching {
+            size: target.batch_size,
+            wait: Some(target.batch_wait),
+            max_bytes: usize::MAX,
+        };
+        let sender = {
+            let target = target.clone();
+            let agent = agent.clone();
+            let destination = format!("clickhouse table {}", target.table);
+            BatchSender::start(
+                "clickhouse",
+                destination,
+                batching,
+                options,
+                move |batches| batches.send_each(|batch| target.insert(&agent, batch)),
+            )
+        };
+
+        Ok(ClickHouseSink {
+            target,
+            agent,
+            labels: options.labels.clone().into(),
+            sender,
+        })
+    }
+}
+
+impl Sink for ClickHouseSink {
+    fn output(&self, _rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(ClickHouseOutput {
+            labels: self.labels.clone(),
+            lines: self.sender.queue(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.sender.close()
+    }
+
+    fn failures(&self) -> u64 {
+        self.sender.failures()
+    }
+
+    fn errors(&self) -> u64 {
+        self.sender.errors()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        self.target.check(&self.agent)
+    }
+}
+
+#[derive(Debug)]
+struct ClickHouseOutput {
+    labels: Arc<[(String, String)]>,
+    lines: BatchQueue<String>,
+}
+
+impl SinkOutput for ClickHouseOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let line = if self.labels.is_empty() {
+            line.to_string()
+        } else {
+            with_labels(line, &self.labels)
+        };
+        let size = line.len();
+        self.lines.send(line, size)
+    }
+}
@@ -0,0 +1,300 @@
+use super::{
+    expand_path_template, BatchQueue, BatchSender, Batching, Sink, SinkOptions, SinkOutput, SinkUrl,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::hmac;
+use serde_json::{json, Map, Value};
+use std::env;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+/// a batch is sent when it's full, too big or this long after its first line
+const DEFAULT_BATCH_WAIT: Duration = Duration::from_secs(1);
+/// batches are limited to 1MB, this leaves room for the json around the lines
+const MAX_BATCH_BYTES: usize = 900 * 1024;
+const DEFAULT_PARTITION_KEY: &str = "{file}";
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// how long a SAS token is valid, a new one is made for each request
+const TOKEN_LIFETIME: u64 = 3600;
+const CONNECTION_STRING_VAR: &str = "EVENTHUBS_CONNECTION_STRING";
+const PARAMS: &[&str] = &[
+    "partition_key",
+    "batch_size",
+    "batch_wait",
+    "endpoint",
+    "transport",
+];
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// `value` with everything but unreserved characters percent encoded
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// a shared access policy name and key
+#[derive(Clone)]
+struct SasKey {
+    name: String,
+    key: String,
+}
+
+impl std::fmt::Debug for SasKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SasKey").field("name", &self.name).finish()
+    }
+}
+
+impl SasKey {
+    /// SharedAccessKeyName and SharedAccessKey from a connection string like
+    /// Endpoint=sb://ns.servicebus.windows.net/;SharedAccessKeyName=..;SharedAccessKey=..
+    fn from_connection_string(value: &str) -> Option<SasKey> {
+        let field = |name: &str| {
+            value
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key.trim() == name)
+                .map(|(_, value)| value.trim().to_string())
+        };
+        Some(SasKey {
+            name: field("SharedAccessKeyName")?,
+            key: field("SharedAccessKey")?,
+        })
+    }
+
+    /// a SAS token for `resource` valid for TOKEN_LIFETIME
+    fn token(&self, resource: &str) -> String {
+        let expiry = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default()
+            + TOKEN_LIFETIME;
+        let resource = url_encode(&resource.to_lowercase());
+        let to_sign = format!("{}\n{}", resource, expiry);
+        let key = hmac::Key::new(hmac::HMAC_SHA256, self.key.as_bytes());
+        let signature = STANDARD.encode(hmac::sign(&key, to_sign.as_bytes()));
+        format!(
+            "SharedAccessSignature sr={}&sig={}&se={}&skn={}",
+            resource,
+            url_encode(&signature),
+            expiry,
+            url_encode(&self.name)
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Target {
+    /// https://namespace.servicebus.windows.net/hub
+    hub_url: String,
+    /// template for the partition key of each file, empty to let event hubs
+    /// spread events over partitions
+    partition_key: String,
+    /// None when sending to an endpoint without auth
+    sas_key: Option<SasKey>,
+    batch_size: usize,
+    batch_wait: Duration,
+}
+
+/// eventhubs://[policy:key@]namespace/hub[?partition_key=TEMPLATE]
+/// [&batch_size=N][&batch_wait=DURATION][&endpoint=URL][&transport=https],
+/// a namespace without dots is in servicebus.windows.net
+fn parse_url(url: &str) -> io::Result<Target> {
+    let parsed = SinkUrl::parse(url, "eventhubs://")?;
+    parsed.check_params(PARAMS)?;
+
+    let hub = parsed.path.trim_end_matches('/');
+    if hub.is_empty() || hub.contains('/') {
+        return Err(invalid(format!("{} should end with /hub", url)));
+    }
+    match parsed.param("transport") {
+        None | Some("https") => {}
+        Some("amqp") => {
+            return Err(invalid(
+                "the eventhubs sink only sends over https, AMQP 1.0 isn't supported".to_string(),
+            ))
+        }
+        Some(v) => return Err(invalid(format!("transport={} should be https", v))),
+    }
+
+    let batch_size = match parsed.param("batch_size") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(size) if size > 0 => size,
+            _ => {
+                return Err(invalid(format!(
+                    "batch_size={} should be a number greater than 0",
+                    v
+                )))
+            }
+        },
+        None => DEFAULT_BATCH_SIZE,
+    };
+    let batch_wait = match parsed.param("batch_wait") {
+        Some(v) => crate::parse::parse_duration(v).map_err(invalid)?,
+        None => DEFAULT_BATCH_WAIT,
+    };
+
+    let sas_key = match (&parsed.user, &parsed.password) {
+        (Some(name), Some(key)) => Some(SasKey {
+            name: name.clone(),
+            key: key.clone(),
+        }),
+        _ => env::var(CONNECTION_STRING_VAR)
+            .ok()
+            .and_then(|value| SasKey::from_connection_string(&value)),
+    };
+    let endpoint = match parsed.param("endpoint") {
+        Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+        None if sas_key.is_none() => {
+            return Err(invalid(format!(
+                "{} needs a shared access key, set {} or put policy:key@ before the namespace",
+                url, CONNECTION_STRING_VAR
+            )))
+        }
+        None if parsed.host.contains('.') => format!("https://{}", parsed.host),
+        None => format!("https://{}.servicebus.windows.net", parsed.host),
+    };
+
+    Ok(Target {
+        hub_url: format!("{}/{}", endpoint, hub),
+        partition_key: parsed
+            .param("partition_key")
+            .unwrap_or(DEFAULT_PARTITION_KEY)
+            .to_string(),
+        sas_key,
+        batch_size,
+        batch_wait,
+    })
+}
+
+impl Target {
+    fn send(&self, agent: &ureq::Agent, batch: &[Value]) -> io::Result<()> {
+        let mut request = agent
+            .post(&format!("{}/messages", self.hub_url))
+            .set("Content-Type", "application/vnd.microsoft.servicebus.json");
+        if let Some(sas_key) = &self.sas_key {
+            request = request.set("Authorization", &sas_key.token(&self.hub_url));
+        }
+
+        match request.send_string(&json!(batch).to_string()) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, response)) => Err(io::Error::other(format!(
+                "event hubs answered {}: {}",
+                code,
+                response.into_string().unwrap_or_default().trim()
+            ))),
+            Err(err) => Err(io::Error::other(format!("event hubs: {}", err))),
+        }
+    }
+}
+
+/// sends lines to an event hub over https in batches, over `concurrency`
+/// connections
+#[derive(Debug)]
+pub struct EventHubsSink {
+    target: Arc<Target>,
+    labels: Arc<Map<String, Value>>,
+    sender: BatchSender<Value>,
+}
+
+impl EventHubsSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<EventHubsSink> {
+        let target = Arc::new(parse_url(url)?);
+
+        // the agent is shared so senders reuse connections from its pool
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .max_idle_connections_per_host(options.concurrency.max(1))
+            .build();
+
+        let batching = Batching {
+            size: target.batch_size,
+            wait: Some(target.batch_wait),
+            max_bytes: MAX_BATCH_BYTES,
+        };
+        let sender = {
+            let target = target.clone();
+            let destination = target.hub_url.clone();
+            BatchSender::start(
+                "event hubs",
+                destination,
+                batching,
+                options,
+                move |batches| batches.send_each(|batch| target.send(&agent, batch)),
+            )
+        };
+
+        Ok(EventHubsSink {
+            target,
+            labels: Arc::new(
+                options
+                    .labels
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.as_str().into()))
+                    .collect(),
+            ),
+            sender,
+        })
+    }
+}
+
+impl Sink for EventHubsSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        let partition_key = expand_path_template(&self.target.partition_key, rel_path, "/");
+        Box::new(EventHubsOutput {
+            partition_key: Some(partition_key).filter(|key| !key.is_empty()),
+            labels: self.labels.clone(),
+            events: self.sender.queue(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.sender.close()
+    }
+
+    fn failures(&self) -> u64 {
+        self.sender.failures()
+    }
+
+    fn errors(&self) -> u64 {
+        self.sender.errors()
+    }
+}
+
+#[derive(Debug)]
+struct EventHubsOutput {
+    partition_key: Option<String>,
+    /// sent as user properties of every event
+    labels: Arc<Map<String, Value>>,
+    events: BatchQueue<Value>,
+}
+
+impl SinkOutput for EventHubsOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let mut event = json!({ "Body": line });
+        if !self.labels.is_empty() {
+            event["UserProperties"] = Value::Object((*self.labels).clone());
+        }
+        if let Some(key) = &self.partition_key {
+            event["BrokerProperties"] = json!({ "PartitionKey": key });
+        }
+        // about the size once encoded
+        let size = line.len() + 64;
+        self.events.send(event, size)
+    }
+}
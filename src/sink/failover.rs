@@ -0,0 +1,156 @@
+use super::{Failures, Sink, SinkOutput};
+use crate::backpressure::FullCounters;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+/// how often the primary's failures are checked
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// when to fail over and back, see --sink-failover
+#[derive(Debug, Clone)]
+pub struct FailoverOptions {
+    /// failed sends in a row of the primary before failing over
+    pub errors: u64,
+    /// how often the primary is probed while failed over
+    pub retry: Duration,
+}
+
+#[derive(Debug)]
+struct State {
+    on_secondary: AtomicBool,
+    /// writes the primary's outputs refused in a row
+    write_failures: Failures,
+    stop: AtomicBool,
+}
+
+/// sends to the primary sink and to the secondary while the primary fails
+#[derive(Debug)]
+pub struct FailoverSink {
+    primary: Arc<dyn Sink>,
+    secondary: Arc<dyn Sink>,
+    state: Arc<State>,
+    monitor: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+/// fails over after `errors` failures in a row of the primary, then probes it
+/// every `retry` and fails back once it answers
+fn monitor(
+    primary: Arc<dyn Sink>,
+    names: (String, String),
+    options: FailoverOptions,
+    state: Arc<State>,
+) {
+    // failures counted before failing back, the primary resets them on its next success
+    let mut baseline = 0;
+    let mut last_probe = Instant::now();
+    while !state.stop.load(Ordering::Relaxed) {
+        sleep(CHECK_INTERVAL);
+        let failures = primary.failures().max(state.write_failures.get());
+        if failures < baseline {
+            baseline = 0;
+        }
+
+        if !state.on_secondary.load(Ordering::Relaxed) {
+            if failures - baseline >= options.errors {
+                eprintln!(
+                    "Warning: sink {} failed {} times in a row, failing over to {}",
+                    names.0, failures, names.1
+                );
+                state.on_secondary.store(true, Ordering::Relaxed);
+                last_probe = Instant::now();
+            }
+        } else if last_probe.elapsed() >= options.retry {
+            last_probe = Instant::now();
+            if primary.probe().is_ok() {
+                eprintln!("sink {} is back, failing back from {}", names.0, names.1);
+                baseline = failures;
+                state.write_failures.succeeded();
+                state.on_secondary.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl FailoverSink {
+    pub fn new(
+        primary: Arc<dyn Sink>,
+        secondary: Arc<dyn Sink>,
+        names: (String, String),
+        options: FailoverOptions,
+    ) -> FailoverSink {
+        let state = Arc::new(State {
+            on_secondary: AtomicBool::new(false),
+            write_failures: Failures::default(),
+            stop: AtomicBool::new(false),
+        });
+        let monitor_primary = primary.clone();
+        let monitor_state = state.clone();
+        let monitor =
+            thread::spawn(move || monitor(monitor_primary, names, options, monitor_state));
+
+        FailoverSink {
+            primary,
+            secondary,
+            state,
+            monitor: Mutex::new(Some(monitor)),
+        }
+    }
+}
+
+impl Sink for FailoverSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(FailoverOutput {
+            primary: self.primary.output(rel_path),
+            secondary: self.secondary.output(rel_path),
+            state: self.state.clone(),
+        })
+    }
+
+    fn errors(&self) -> u64 {
+        self.primary.errors() + self.secondary.errors() + self.state.write_failures.total()
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.state.stop.store(true, Ordering::Relaxed);
+        if let Some(monitor) = self.monitor.lock().unwrap().take() {
+            monitor.join().ok();
+        }
+        // both are closed even if the primary fails
+        let primary = self.primary.close();
+        self.secondary.close()?;
+        primary
+    }
+}
+
+#[derive(Debug)]
+struct FailoverOutput {
+    primary: Box<dyn SinkOutput>,
+    secondary: Box<dyn SinkOutput>,
+    state: Arc<State>,
+}
+
+impl SinkOutput for FailoverOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if !self.state.on_secondary.load(Ordering::Relaxed) {
+            match self.primary.write(line) {
+                Ok(()) => {
+                    self.state.write_failures.succeeded();
+                    return Ok(());
+                }
+                // the line goes to the secondary instead
+                Err(_) => self.state.write_failures.failed(),
+            }
+        }
+
+        self.secondary.write(line)
+    }
+
+    fn count_full(&mut self, counters: Arc<FullCounters>) {
+        self.primary.count_full(counters.clone());
+        self.secondary.count_full(counters);
+    }
+}
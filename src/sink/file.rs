@@ -0,0 +1,64 @@
+use super::{Sink, SinkOptions, SinkOutput};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// appends lines to files under a directory with the relative path of the
+/// generated file, mostly as the secondary of --sink-failover
+#[derive(Debug)]
+pub struct FileSink {
+    dir: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(url: &str, _options: &SinkOptions) -> io::Result<FileSink> {
+        let dir = match url.strip_prefix("file:") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} should be file:DIR", url),
+                ))
+            }
+        };
+        fs::create_dir_all(&dir)?;
+        Ok(FileSink { dir })
+    }
+}
+
+impl Sink for FileSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(FileOutput {
+            path: self.dir.join(rel_path),
+            writer: None,
+        })
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        fs::metadata(&self.dir).map(|_| ())
+    }
+}
+
+#[derive(Debug)]
+struct FileOutput {
+    path: PathBuf,
+    /// opened on the first line
+    writer: Option<LineWriter<File>>,
+}
+
+impl SinkOutput for FileOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.writer.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.writer = Some(LineWriter::new(file));
+        }
+
+        self.writer.as_mut().unwrap().write_all(line.as_bytes())
+    }
+}
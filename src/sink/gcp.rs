@@ -0,0 +1,481 @@
+use super::{
+    expand_path_template, BatchQueue, BatchSender, Batching, Sink, SinkOptions, SinkOutput, SinkUrl,
+};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const PUBSUB_URL: &str = "https://pubsub.googleapis.com";
+const LOGGING_URL: &str = "https://logging.googleapis.com";
+const PUBSUB_SCOPE: &str = "https://www.googleapis.com/auth/pubsub";
+const LOGGING_SCOPE: &str = "https://www.googleapis.com/auth/logging.write";
+/// publish and entries.write take up to 1000 messages or entries
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const MAX_BATCH_SIZE: usize = 1000;
+/// a batch is sent when it's full, this big or this long after its first line
+const DEFAULT_BATCH_WAIT: Duration = Duration::from_secs(1);
+/// requests are limited to 10MB, this leaves room for the json around the lines
+const MAX_BATCH_BYTES: usize = 8 * 1024 * 1024;
+const DEFAULT_LOG: &str = "{stem}";
+const DEFAULT_RESOURCE: &str = "global";
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// access tokens are refreshed this long before they expire
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+const TOKEN_LIFETIME: u64 = 3600;
+const PARAMS: &[&str] = &["credentials", "endpoint", "batch_size", "batch_wait"];
+const LOGGING_PARAMS: &[&str] = &[
+    "credentials",
+    "endpoint",
+    "batch_size",
+    "batch_wait",
+    "log",
+    "resource",
+];
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+/// the fields of a service account key file used to get access tokens
+#[derive(Debug, Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    #[serde(default)]
+    private_key_id: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// oauth2 access tokens for a service account, from a JWT signed with its key
+struct ServiceAccount {
+    key: ServiceAccountKey,
+    signer: RsaKeyPair,
+    scope: &'static str,
+    /// the token and when it has to be refreshed
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl std::fmt::Debug for ServiceAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ServiceAccount")
+            .field("client_email", &self.key.client_email)
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+impl ServiceAccount {
+    fn load(path: &str, scope: &'static str) -> io::Result<ServiceAccount> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| invalid(format!("reading credentials {}: {}", path, err)))?;
+        let key: ServiceAccountKey = serde_json::from_str(&content).map_err(|err| {
+            invalid(format!(
+                "{} isn't a service account key file: {}",
+                path, err
+            ))
+        })?;
+
+        let pem: String = key
+            .private_key
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let der = STANDARD
+            .decode(pem.trim())
+            .map_err(|err| invalid(format!("private key in {}: {}", path, err)))?;
+        let signer = RsaKeyPair::from_pkcs8(&der)
+            .map_err(|err| invalid(format!("private key in {}: {}", path, err)))?;
+
+        Ok(ServiceAccount {
+            key,
+            signer,
+            scope,
+            token: Mutex::new(None),
+        })
+    }
+
+    /// the signed JWT asking for a token with the scope
+    fn assertion(&self) -> io::Result<String> {
+        let now = unix_now();
+        let header = json!({"alg": "RS256", "typ": "JWT", "kid": self.key.private_key_id});
+        let claims = json!({
+            "iss": self.key.client_email,
+            "scope": self.scope,
+            "aud": self.key.token_uri,
+            "iat": now,
+            "exp": now + TOKEN_LIFETIME,
+        });
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+
+        let mut signature = vec![0; self.signer.public().modulus_len()];
+        self.signer
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .map_err(|_| io::Error::other("signing the token request failed"))?;
+        Ok(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)))
+    }
+
+    /// a cached access token, a new one if it's about to expire
+    fn access_token(&self, agent: &ureq::Agent) -> io::Result<String> {
+        let mut token = self.token.lock().unwrap();
+        if let Some((token, refresh_at)) = &*token {
+            if Instant::now() < *refresh_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let response = agent.post(&self.key.token_uri).send_form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", &self.assertion()?),
+        ]);
+        let response: TokenResponse = match response {
+            Ok(response) => serde_json::from_reader(response.into_reader())?,
+            Err(ureq::Error::Status(code, response)) => {
+                return Err(io::Error::other(format!(
+                    "getting an access token for {} answered {}: {}",
+                    self.key.client_email,
+                    code,
+                    response.into_string().unwrap_or_default().trim()
+                )))
+            }
+            Err(err) => {
+                return Err(io::Error::other(format!(
+                    "getting an access token: {}",
+                    err
+                )))
+            }
+        };
+
+        let lifetime = Duration::from_secs(response.expires_in).saturating_sub(TOKEN_MARGIN);
+        *token = Some((response.access_token.clone(), Instant::now() + lifetime));
+        Ok(response.access_token)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Api {
+    /// messages published to projects/{project}/topics/{topic}
+    PubSub { topic: String },
+    /// entries written to projects/{project}/logs/{log}
+    Logging {
+        /// log name template, can have {dir}, {file} and {stem}
+        log: String,
+        resource: String,
+    },
+}
+
+#[derive(Debug)]
+struct Target {
+    api: Api,
+    project: String,
+    /// where requests are sent, https://pubsub.googleapis.com for pubsub
+    endpoint: String,
+    /// None for emulators and endpoints without auth
+    account: Option<ServiceAccount>,
+    batch_size: usize,
+    batch_wait: Duration,
+}
+
+/// pubsub://project/topic or gcp-logging://project, both with
+/// [?credentials=PATH][&endpoint=URL][&batch_size=N][&batch_wait=DURATION],
+/// gcp-logging also takes [&log=TEMPLATE][&resource=TYPE]
+fn parse_url(url: &str) -> io::Result<Target> {
+    let pubsub = url.starts_with("pubsub://");
+    let parsed = if pubsub {
+        SinkUrl::parse(url, "pubsub://")?
+    } else {
+        SinkUrl::parse(url, "gcp-logging://")?
+    };
+    parsed.check_params(if pubsub { PARAMS } else { LOGGING_PARAMS })?;
+
+    let project = parsed.host.clone();
+    let api = if pubsub {
+        let topic = parsed.path.trim_end_matches('/').to_string();
+        if topic.is_empty() || topic.contains('/') {
+            return Err(invalid(format!("{} should end with /topic", url)));
+        }
+        Api::PubSub { topic }
+    } else {
+        if !parsed.path.trim_end_matches('/').is_empty() {
+            return Err(invalid(format!(
+                "{} should be gcp-logging://project, the log name goes in log=",
+                url
+            )));
+        }
+        Api::Logging {
+            log: parsed.param("log").unwrap_or(DEFAULT_LOG).to_string(),
+            resource: parsed
+                .param("resource")
+                .unwrap_or(DEFAULT_RESOURCE)
+                .to_string(),
+        }
+    };
+
+    let batch_size = match parsed.param("batch_size") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(size) if size > 0 && size <= MAX_BATCH_SIZE => size,
+            _ => {
+                return Err(invalid(format!(
+                    "batch_size={} should be a number from 1 to {}",
+                    v, MAX_BATCH_SIZE
+                )))
+            }
+        },
+        None => DEFAULT_BATCH_SIZE,
+    };
+    let batch_wait = match parsed.param("batch_wait") {
+        Some(v) => crate::parse::parse_duration(v).map_err(invalid)?,
+        None => DEFAULT_BATCH_WAIT,
+    };
+
+    // the pubsub emulator doesn't check auth, like the client libraries
+    // requests go to it when PUBSUB_EMULATOR_HOST is set
+    let emulator = env::var("PUBSUB_EMULATOR_HOST")
+        .ok()
+        .filter(|host| pubsub && !host.is_empty())
+        .map(|host| format!("http://{}", host));
+    let endpoint = parsed
+        .param("endpoint")
+        .map(|endpoint| endpoint.to_string());
+    let credentials = parsed
+        .param("credentials")
+        .map(|path| path.to_string())
+        .or_else(|| env::var("GOOGLE_APPLICATION_CREDENTIALS").ok());
+    let scope = if pubsub { PUBSUB_SCOPE } else { LOGGING_SCOPE };
+    let account = match credentials {
+        Some(path) if emulator.is_none() => Some(ServiceAccount::load(&path, scope)?),
+        None if emulator.is_none() && endpoint.is_none() => {
+            return Err(invalid(format!(
+                "{} needs a service account key, set GOOGLE_APPLICATION_CREDENTIALS or credentials=PATH",
+                url
+            )))
+        }
+        _ => None,
+    };
+    let endpoint = endpoint
+        .or(emulator)
+        .unwrap_or_else(|| (if pubsub { PUBSUB_URL } else { LOGGING_URL }).to_string());
+
+    Ok(Target {
+        api,
+        project,
+        endpoint: endpoint.trim_end_matches('/').to_string(),
+        account,
+        batch_size,
+        batch_wait,
+    })
+}
+
+/// `name` with the characters log ids can't have replaced by _ and / escaped
+fn log_id(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '.' => c.to_string(),
+            '/' => "%2F".to_string(),
+            _ => "_".to_string(),
+        })
+        .collect()
+}
+
+impl Target {
+    fn name(&self) -> String {
+        match &self.api {
+            Api::PubSub { topic } => format!("projects/{}/topics/{}", self.project, topic),
+            Api::Logging { .. } => format!("projects/{}", self.project),
+        }
+    }
+
+    fn request(&self, agent: &ureq::Agent, url: &str) -> io::Result<ureq::Request> {
+        let request = agent.post(url);
+        Ok(match &self.account {
+            Some(account) => request.set(
+                "Authorization",
+                &format!("Bearer {}", account.access_token(agent)?),
+            ),
+            None => request,
+        })
+    }
+
+    /// the message or log entry for a line of the file at `rel_path`
+    fn item(&self, line: &str, rel_path: &Path, labels: &[(String, String)]) -> Value {
+        let mut attributes: Map<String, Value> = labels
+            .iter()
+            .map(|(name, value)| (name.clone(), value.as_str().into()))
+            .collect();
+        attributes
+            .entry("file")
+            .or_insert_with(|| rel_path.to_string_lossy().into());
+
+        match &self.api {
+            Api::PubSub { .. } => json!({
+                "data": STANDARD.encode(line),
+                "attributes": attributes,
+            }),
+            Api::Logging { log, resource } => {
+                let log = log_id(&expand_path_template(log, rel_path, "/"));
+                let mut entry = json!({
+                    "logName": format!("projects/{}/logs/{}", self.project, log),
+                    "resource": {"type": resource},
+                    "labels": attributes,
+                });
+                // JSON lines become structured entries
+                match serde_json::from_str::<Value>(line) {
+                    Ok(payload @ Value::Object(_)) => entry["jsonPayload"] = payload,
+                    _ => entry["textPayload"] = line.into(),
+                }
+                entry
+            }
+        }
+    }
+
+    fn send(&self, agent: &ureq::Agent, batch: &[Value]) -> io::Result<()> {
+        let (url, body) = match &self.api {
+            Api::PubSub { .. } => (
+                format!("{}/v1/{}:publish", self.endpoint, self.name()),
+                json!({ "messages": batch }),
+            ),
+            Api::Logging { .. } => (
+                format!("{}/v2/entries:write", self.endpoint),
+                json!({ "entries": batch }),
+            ),
+        };
+        match self
+            .request(agent, &url)?
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())
+        {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, response)) => Err(io::Error::other(format!(
+                "{} answered {}: {}",
+                self.endpoint,
+                code,
+                response.into_string().unwrap_or_default().trim()
+            ))),
+            Err(err) => Err(io::Error::other(format!("{}: {}", self.endpoint, err))),
+        }
+    }
+}
+
+/// publishes lines to a Pub/Sub topic or writes them to Cloud Logging in
+/// batches, over `concurrency` connections
+#[derive(Debug)]
+pub struct GcpSink {
+    target: Arc<Target>,
+    agent: ureq::Agent,
+    labels: Arc<[(String, String)]>,
+    sender: BatchSender<Value>,
+}
+
+impl GcpSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<GcpSink> {
+        let target = Arc::new(parse_url(url)?);
+
+        // the agent is shared so senders reuse connections from its pool
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .max_idle_connections_per_host(options.concurrency.max(1))
+            .build();
+        // fail at startup if the key is rejected
+        if let Some(account) = &target.account {
+            account.access_token(&agent)?;
+        }
+
+        let batching = Batching {
+            size: target.batch_size,
+            wait: Some(target.batch_wait),
+            max_bytes: MAX_BATCH_BYTES,
+        };
+        let sender = {
+            let target = target.clone();
+            let agent = agent.clone();
+            BatchSender::start("gcp", target.name(), batching, options, move |batches| {
+                batches.send_each(|batch| target.send(&agent, batch))
+            })
+        };
+
+        Ok(GcpSink {
+            target,
+            agent,
+            labels: options.labels.clone().into(),
+            sender,
+        })
+    }
+}
+
+impl Sink for GcpSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(GcpOutput {
+            target: self.target.clone(),
+            rel_path: rel_path.to_path_buf(),
+            labels: self.labels.clone(),
+            items: self.sender.queue(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.sender.close()
+    }
+
+    fn failures(&self) -> u64 {
+        self.sender.failures()
+    }
+
+    fn errors(&self) -> u64 {
+        self.sender.errors()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        match &self.target.account {
+            Some(account) => account.access_token(&self.agent).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct GcpOutput {
+    target: Arc<Target>,
+    rel_path: PathBuf,
+    labels: Arc<[(String, String)]>,
+    items: BatchQueue<Value>,
+}
+
+impl SinkOutput for GcpOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let item = self.target.item(line, &self.rel_path, &self.labels);
+        // about the size once encoded, pubsub data is base64
+        let size = line.len() * 4 / 3 + 128;
+        self.items.send(item, size)
+    }
+}
@@ -0,0 +1,194 @@
+use super::{Sink, SinkOptions, SinkOutput};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tonic::codegen::http::uri::PathAndQuery;
+
+const PUSH_PATH: &str = "/loggen.LogStream/Push";
+/// lines buffered per stream before writers wait
+const MAX_PENDING: usize = 10000;
+
+/// messages in proto/logstream.proto
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LogLine {
+    #[prost(int64, tag = "1")]
+    pub timestamp_unix_nanos: i64,
+    #[prost(string, tag = "2")]
+    pub source: String,
+    #[prost(string, tag = "3")]
+    pub body: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PushSummary {
+    #[prost(uint64, tag = "1")]
+    pub accepted: u64,
+}
+
+/// "grpc://host:port" to "http://host:port", grpcs:// uses https
+fn endpoint_url(url: &str) -> io::Result<String> {
+    let (scheme, rest) = if let Some(rest) = url.strip_prefix("grpc://") {
+        ("http", rest)
+    } else if let Some(rest) = url.strip_prefix("grpcs://") {
+        ("https", rest)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} isn't a grpc:// url", url),
+        ));
+    };
+
+    let host = rest.trim_end_matches('/');
+    if host.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has no host", url),
+        ));
+    }
+
+    Ok(format!("{}://{}", scheme, host))
+}
+
+async fn push(
+    endpoint: String,
+    lines: mpsc::Receiver<LogLine>,
+) -> Result<PushSummary, Box<dyn std::error::Error + Send + Sync>> {
+    let mut endpoint = tonic::transport::Endpoint::from_shared(endpoint)?;
+    if endpoint.uri().scheme_str() == Some("https") {
+        endpoint =
+            endpoint.tls_config(tonic::transport::ClientTlsConfig::new().with_native_roots())?;
+    }
+    let channel = endpoint.connect().await?;
+    let mut client = tonic::client::Grpc::new(channel);
+    client.ready().await?;
+
+    let codec = tonic::codec::ProstCodec::<LogLine, PushSummary>::default();
+    let request = tonic::Request::new(tokio_stream::wrappers::ReceiverStream::new(lines));
+    let response = client
+        .client_streaming(request, PathAndQuery::from_static(PUSH_PATH), codec)
+        .await?;
+    Ok(response.into_inner())
+}
+
+/// client stream to the LogStream service, runs on its own thread until its
+/// sender is dropped
+#[derive(Debug)]
+struct Stream {
+    lines: mpsc::Sender<LogLine>,
+    pusher: thread::JoinHandle<io::Result<PushSummary>>,
+}
+
+impl Stream {
+    fn open(endpoint: String) -> io::Result<Stream> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let (lines, receiver) = mpsc::channel(MAX_PENDING);
+        let pusher = thread::spawn(move || {
+            let result = runtime.block_on(push(endpoint.clone(), receiver));
+            result.map_err(|err| {
+                let err = io::Error::other(format!("grpc push to {} failed: {}", endpoint, err));
+                eprintln!("Error: {}", err);
+                err
+            })
+        });
+
+        Ok(Stream { lines, pusher })
+    }
+}
+
+/// streams lines to a gRPC LogStream service (see proto/logstream.proto), with
+/// --sink-concurrency client streams that outputs are spread over
+#[derive(Debug)]
+pub struct GrpcSink {
+    senders: Mutex<Vec<mpsc::Sender<LogLine>>>,
+    pushers: Mutex<Vec<thread::JoinHandle<io::Result<PushSummary>>>>,
+    next: AtomicUsize,
+}
+
+impl GrpcSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<GrpcSink> {
+        let endpoint = endpoint_url(url)?;
+        let mut senders = vec![];
+        let mut pushers = vec![];
+        for _ in 0..options.concurrency.max(1) {
+            let stream = Stream::open(endpoint.clone())?;
+            senders.push(stream.lines);
+            pushers.push(stream.pusher);
+        }
+
+        Ok(GrpcSink {
+            senders: Mutex::new(senders),
+            pushers: Mutex::new(pushers),
+            next: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Sink for GrpcSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        let senders = self.senders.lock().unwrap();
+        let lines = if senders.is_empty() {
+            None
+        } else {
+            let index = self.next.fetch_add(1, Ordering::Relaxed) % senders.len();
+            Some(senders[index].clone())
+        };
+        Box::new(GrpcOutput {
+            source: rel_path.display().to_string(),
+            lines,
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        // outputs were dropped with their generators, dropping the last senders ends the streams
+        self.senders.lock().unwrap().clear();
+        let pushers: Vec<_> = self.pushers.lock().unwrap().drain(..).collect();
+        let mut accepted = 0;
+        for pusher in pushers {
+            let summary = pusher
+                .join()
+                .map_err(|_| io::Error::other("grpc pusher panicked"))??;
+            accepted += summary.accepted;
+        }
+
+        println!("grpc sink: {} lines accepted", accepted);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct GrpcOutput {
+    source: String,
+    lines: Option<mpsc::Sender<LogLine>>,
+}
+
+impl SinkOutput for GrpcOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as i64)
+            .unwrap_or(0);
+        let lines = self
+            .lines
+            .as_ref()
+            .ok_or_else(|| io::Error::other("grpc stream closed"))?;
+
+        let sent = lines.blocking_send(LogLine {
+            timestamp_unix_nanos: nanos,
+            source: self.source.clone(),
+            body: line.trim_end_matches(['\r', '\n']).to_string(),
+        });
+        if sent.is_err() {
+            // the stream failed, the error is reported when the sink is closed
+            self.lines = None;
+            return Err(io::Error::other("grpc stream closed"));
+        }
+
+        Ok(())
+    }
+}
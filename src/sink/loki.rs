@@ -0,0 +1,363 @@
+use super::{Failures, Sink, SinkOptions, SinkOutput};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const PUSH_PATH: &str = "/loki/api/v1/push";
+const READY_PATH: &str = "/ready";
+/// entries to buffer before pushing, a push also happens every BATCH_WAIT
+const BATCH_SIZE: usize = 1000;
+const BATCH_WAIT: Duration = Duration::from_secs(1);
+/// writers wait when this many entries are waiting to be pushed
+const MAX_PENDING: usize = BATCH_SIZE * 10;
+
+#[derive(Debug, Default)]
+struct Pending {
+    /// entries (unix nanos, line) by their stream labels
+    streams: HashMap<String, Vec<(u128, String)>>,
+    count: usize,
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    pending: Mutex<Pending>,
+    ready: Condvar,
+    /// batches taken from pending and not pushed yet
+    inflight: Mutex<usize>,
+    pushed: Condvar,
+    max_inflight: usize,
+    failures: Failures,
+}
+
+type Batch = HashMap<String, Vec<(u128, String)>>;
+
+/// pushes lines to Grafana Loki as snappy compressed protobuf, one stream per
+/// generated file with labels derived from its path
+#[derive(Debug)]
+pub struct LokiSink {
+    push_url: String,
+    static_labels: Vec<(String, String)>,
+    shared: Arc<Shared>,
+    sender: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+/// "loki://host:3100" to "http://host:3100/loki/api/v1/push", lokis:// uses https
+fn push_url(url: &str) -> io::Result<String> {
+    let (scheme, rest) = if let Some(rest) = url.strip_prefix("loki://") {
+        ("http", rest)
+    } else if let Some(rest) = url.strip_prefix("lokis://") {
+        ("https", rest)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} isn't a loki:// url", url),
+        ));
+    };
+
+    let host = rest.trim_end_matches('/');
+    if host.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has no host", url),
+        ));
+    }
+
+    Ok(format!("{}://{}{}", scheme, host, PUSH_PATH))
+}
+
+fn escape_label_value(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// label names must match [a-zA-Z_][a-zA-Z0-9_]*
+fn sanitize_label_name(v: &str) -> String {
+    let name: String = v
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_digit() => format!("_{}", name),
+        Some(_) => name,
+        None => "_".to_string(),
+    }
+}
+
+/// labels for a file in Loki's selector syntax: {dir="logs", filename="logs/app.log"}
+fn stream_labels(rel_path: &Path, static_labels: &[(String, String)]) -> String {
+    let mut labels: Vec<(String, String)> = vec![
+        (
+            "dir".to_string(),
+            rel_path
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+        ),
+        ("filename".to_string(), rel_path.display().to_string()),
+    ];
+    for (name, value) in static_labels {
+        labels.push((sanitize_label_name(name), value.clone()));
+    }
+
+    let inner: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect();
+    format!("{{{}}}", inner.join(", "))
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    put_varint(buf, ((field << 3) | 2) as u64);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn put_uint(buf: &mut Vec<u8>, field: u32, v: u64) {
+    put_varint(buf, (field << 3) as u64);
+    put_varint(buf, v);
+}
+
+/// logproto.PushRequest { repeated StreamAdapter streams = 1 }
+/// StreamAdapter { string labels = 1; repeated EntryAdapter entries = 2 }
+/// EntryAdapter { google.protobuf.Timestamp timestamp = 1; string line = 2 }
+fn encode_push_request(streams: &HashMap<String, Vec<(u128, String)>>) -> Vec<u8> {
+    let mut request = vec![];
+    for (labels, entries) in streams {
+        let mut stream = vec![];
+        put_bytes(&mut stream, 1, labels.as_bytes());
+        for (nanos, line) in entries {
+            let mut timestamp = vec![];
+            put_uint(&mut timestamp, 1, (nanos / 1_000_000_000) as u64);
+            put_uint(&mut timestamp, 2, (nanos % 1_000_000_000) as u64);
+
+            let mut entry = vec![];
+            put_bytes(&mut entry, 1, &timestamp);
+            put_bytes(
+                &mut entry,
+                2,
+                line.trim_end_matches(['\r', '\n']).as_bytes(),
+            );
+            put_bytes(&mut stream, 2, &entry);
+        }
+        put_bytes(&mut request, 1, &stream);
+    }
+
+    request
+}
+
+fn push(
+    agent: &ureq::Agent,
+    url: &str,
+    streams: &HashMap<String, Vec<(u128, String)>>,
+) -> io::Result<()> {
+    let body = snap::raw::Encoder::new()
+        .compress_vec(&encode_push_request(streams))
+        .map_err(io::Error::other)?;
+
+    match agent
+        .post(url)
+        .set("Content-Type", "application/x-protobuf")
+        .send_bytes(&body)
+    {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, response)) => Err(io::Error::other(format!(
+            "loki push failed with {}: {}",
+            code,
+            response.into_string().unwrap_or_default().trim()
+        ))),
+        Err(err) => Err(io::Error::other(format!("loki push failed: {}", err))),
+    }
+}
+
+fn push_loop(
+    url: String,
+    agent: ureq::Agent,
+    batches: Arc<Mutex<Receiver<Batch>>>,
+    shared: Arc<Shared>,
+) {
+    loop {
+        // the lock is only held while waiting, other pushers push meanwhile
+        let streams = match batches.lock().unwrap().recv() {
+            Ok(streams) => streams,
+            Err(_) => break,
+        };
+
+        match push(&agent, &url, &streams) {
+            Ok(()) => shared.failures.succeeded(),
+            Err(err) => {
+                shared.failures.failed();
+                eprintln!("Error: {}", err);
+            }
+        }
+
+        *shared.inflight.lock().unwrap() -= 1;
+        shared.pushed.notify_all();
+    }
+}
+
+/// takes batches from pending and hands them to `concurrency` pushers, waits
+/// while max_inflight batches are being pushed so writers feel the backpressure
+fn send_loop(url: String, shared: Arc<Shared>, concurrency: usize) {
+    // the agent is shared so pushers reuse connections from its pool
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .max_idle_connections_per_host(concurrency)
+        .build();
+    let (batches_tx, batches_rx) = mpsc::channel();
+    let batches_rx = Arc::new(Mutex::new(batches_rx));
+    let pushers: Vec<_> = (0..concurrency)
+        .map(|_| {
+            let url = url.clone();
+            let agent = agent.clone();
+            let batches_rx = batches_rx.clone();
+            let shared = shared.clone();
+            thread::spawn(move || push_loop(url, agent, batches_rx, shared))
+        })
+        .collect();
+
+    loop {
+        {
+            let inflight = shared.inflight.lock().unwrap();
+            let _inflight = shared
+                .pushed
+                .wait_while(inflight, |inflight| *inflight >= shared.max_inflight)
+                .unwrap();
+        }
+
+        let (streams, closed) = {
+            let pending = shared.pending.lock().unwrap();
+            let (mut pending, _) = shared
+                .ready
+                .wait_timeout_while(pending, BATCH_WAIT, |p| p.count < BATCH_SIZE && !p.closed)
+                .unwrap();
+            pending.count = 0;
+            (std::mem::take(&mut pending.streams), pending.closed)
+        };
+        shared.ready.notify_all();
+
+        if !streams.is_empty() {
+            *shared.inflight.lock().unwrap() += 1;
+            batches_tx.send(streams).unwrap();
+        }
+
+        if closed {
+            break;
+        }
+    }
+
+    drop(batches_tx);
+    for pusher in pushers {
+        pusher.join().ok();
+    }
+}
+
+impl LokiSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<LokiSink> {
+        let push_url = push_url(url)?;
+        let shared = Arc::new(Shared {
+            pending: Mutex::new(Pending::default()),
+            ready: Condvar::new(),
+            inflight: Mutex::new(0),
+            pushed: Condvar::new(),
+            max_inflight: options.inflight.max(1),
+            failures: Failures::default(),
+        });
+
+        let sender_url = push_url.clone();
+        let sender_shared = shared.clone();
+        let concurrency = options.concurrency.max(1);
+        let sender = thread::spawn(move || send_loop(sender_url, sender_shared, concurrency));
+
+        Ok(LokiSink {
+            push_url,
+            static_labels: options.labels.clone(),
+            shared,
+            sender: Mutex::new(Some(sender)),
+        })
+    }
+}
+
+impl Sink for LokiSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(LokiOutput {
+            labels: stream_labels(rel_path, &self.static_labels),
+            shared: self.shared.clone(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.shared.pending.lock().unwrap().closed = true;
+        self.shared.ready.notify_all();
+        if let Some(sender) = self.sender.lock().unwrap().take() {
+            sender.join().map_err(|_| {
+                io::Error::other(format!("loki sender for {} panicked", self.push_url))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn failures(&self) -> u64 {
+        self.shared.failures.get()
+    }
+
+    fn errors(&self) -> u64 {
+        self.shared.failures.total()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        let ready_url = self.push_url.replace(PUSH_PATH, READY_PATH);
+        ureq::get(&ready_url)
+            .timeout(Duration::from_secs(5))
+            .call()
+            .map(|_| ())
+            .map_err(|err| io::Error::other(format!("loki isn't ready: {}", err)))
+    }
+}
+
+#[derive(Debug)]
+struct LokiOutput {
+    labels: String,
+    shared: Arc<Shared>,
+}
+
+impl SinkOutput for LokiOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+
+        let pending = self.shared.pending.lock().unwrap();
+        let mut pending = self
+            .shared
+            .ready
+            .wait_while(pending, |p| p.count >= MAX_PENDING && !p.closed)
+            .unwrap();
+        pending
+            .streams
+            .entry(self.labels.clone())
+            .or_default()
+            .push((nanos, line.to_string()));
+        pending.count += 1;
+        if pending.count >= BATCH_SIZE {
+            self.shared.ready.notify_one();
+        }
+
+        Ok(())
+    }
+}
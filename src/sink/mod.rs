@@ -0,0 +1,686 @@
+use crate::backpressure::{FullCounters, WhenFull};
+use serde_json::Value;
+use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod amqp;
+mod aws;
+mod clickhouse;
+mod eventhubs;
+mod failover;
+mod file;
+mod gcp;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod loki;
+mod nats;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod pool;
+mod redis;
+mod syslog;
+mod tcp;
+mod tls;
+
+pub use failover::FailoverOptions;
+pub use pool::{Balance, PoolOptions};
+pub use tcp::{is_reconnect, Reconnect};
+pub use tls::{is_rotate, parse_rotate};
+
+/// destination for generated lines other than files in the output directory
+pub trait Sink: Debug + Send + Sync {
+    /// handle used by one generated file to send its lines
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput>;
+
+    /// send anything still buffered, called once all generators stopped
+    fn close(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// sends that failed in a row, for --sink-failover
+    fn failures(&self) -> u64 {
+        0
+    }
+
+    /// sends that failed since the sink was opened, for --error-budget
+    fn errors(&self) -> u64 {
+        0
+    }
+
+    /// check the destination can be reached, --sink-failover fails back when it can
+    fn probe(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub trait SinkOutput: Debug + Send {
+    /// send a line, including its line ending
+    fn write(&mut self, line: &str) -> io::Result<()>;
+
+    /// where to count lines dropped and time blocked on a full consumer, for
+    /// sinks that support --block-on-full
+    fn count_full(&mut self, _counters: Arc<FullCounters>) {}
+}
+
+/// count of sends that failed in a row and since the start, shared by a
+/// sink's senders
+#[derive(Debug, Default)]
+pub struct Failures {
+    in_a_row: AtomicU64,
+    total: AtomicU64,
+}
+
+impl Failures {
+    pub fn failed(&self) {
+        self.in_a_row.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn succeeded(&self) {
+        self.in_a_row.store(0, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.in_a_row.load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+}
+
+/// how a sink's senders group queued items into batches
+#[derive(Debug, Clone, Copy)]
+pub struct Batching {
+    /// most items in a batch
+    pub size: usize,
+    /// how long a batch waits to fill after its first item, None to only
+    /// take what is already queued
+    pub wait: Option<Duration>,
+    /// most bytes in a batch, the item that doesn't fit starts the next one
+    pub max_bytes: usize,
+}
+
+/// items queued by a sink's outputs, taken in batches by its senders
+#[derive(Debug)]
+pub struct Batches<T> {
+    items: Mutex<Receiver<(T, usize)>>,
+    batching: Batching,
+    /// where batches go, for errors
+    destination: String,
+    failures: Arc<Failures>,
+}
+
+impl<T> Batches<T> {
+    /// send batches with `send` until every output and the sink are closed
+    pub fn send_each(&self, mut send: impl FnMut(&[T]) -> io::Result<()>) {
+        // an item that didn't fit in the previous batch
+        let mut carry = None;
+        loop {
+            // the lock is only held while taking a batch, other senders send meanwhile
+            let batch = match self.next_batch(&self.items.lock().unwrap(), &mut carry) {
+                Some(batch) => batch,
+                None => break,
+            };
+
+            match send(&batch) {
+                Ok(()) => self.failures.succeeded(),
+                Err(err) => {
+                    self.failures.failed();
+                    eprintln!(
+                        "Error sending {} lines to {}: {}",
+                        batch.len(),
+                        self.destination,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    /// takes items until the batch is full or its wait passed since the
+    /// first, starting with the item that didn't fit in the previous batch
+    fn next_batch(
+        &self,
+        items: &Receiver<(T, usize)>,
+        carry: &mut Option<(T, usize)>,
+    ) -> Option<Vec<T>> {
+        let (first, mut bytes) = match carry.take() {
+            Some(item) => item,
+            None => items.recv().ok()?,
+        };
+        let deadline = self.batching.wait.map(|wait| Instant::now() + wait);
+        let mut batch = vec![first];
+        while batch.len() < self.batching.size {
+            let next = match deadline {
+                Some(deadline) => items
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    .ok(),
+                None => items.try_recv().ok(),
+            };
+            match next {
+                Some((item, size)) if bytes + size > self.batching.max_bytes => {
+                    *carry = Some((item, size));
+                    break;
+                }
+                Some((item, size)) => {
+                    batch.push(item);
+                    bytes += size;
+                }
+                None => break,
+            }
+        }
+        Some(batch)
+    }
+}
+
+/// the queue and sender threads of a sink that sends lines in batches, over
+/// `concurrency` senders
+#[derive(Debug)]
+pub struct BatchSender<T> {
+    /// name of the sink, for errors
+    name: &'static str,
+    items: Mutex<Option<SyncSender<(T, usize)>>>,
+    senders: Mutex<Vec<thread::JoinHandle<()>>>,
+    failures: Arc<Failures>,
+}
+
+impl<T: Send + 'static> BatchSender<T> {
+    /// start the senders, each running `sender` until the sink is closed,
+    /// which sets up what it needs and calls Batches::send_each
+    pub fn start<F>(
+        name: &'static str,
+        destination: String,
+        batching: Batching,
+        options: &SinkOptions,
+        sender: F,
+    ) -> BatchSender<T>
+    where
+        F: Fn(&Batches<T>) + Send + Sync + 'static,
+    {
+        let (items_tx, items_rx) = mpsc::sync_channel(options.inflight.max(1) * batching.size);
+        let failures = Arc::new(Failures::default());
+        let batches = Arc::new(Batches {
+            items: Mutex::new(items_rx),
+            batching,
+            destination,
+            failures: failures.clone(),
+        });
+        let sender = Arc::new(sender);
+        let senders = (0..options.concurrency.max(1))
+            .map(|_| {
+                let batches = batches.clone();
+                let sender = sender.clone();
+                thread::spawn(move || sender(&batches))
+            })
+            .collect();
+
+        BatchSender {
+            name,
+            items: Mutex::new(Some(items_tx)),
+            senders: Mutex::new(senders),
+            failures,
+        }
+    }
+
+    /// where an output queues its items
+    pub fn queue(&self) -> BatchQueue<T> {
+        BatchQueue {
+            name: self.name,
+            items: self.items.lock().unwrap().clone(),
+        }
+    }
+
+    /// wait until the senders sent everything queued
+    pub fn close(&self) -> io::Result<()> {
+        // senders stop once every output and this sender are dropped
+        self.items.lock().unwrap().take();
+        for sender in self.senders.lock().unwrap().drain(..) {
+            sender
+                .join()
+                .map_err(|_| io::Error::other(format!("{} sender panicked", self.name)))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures.get()
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.failures.total()
+    }
+}
+
+/// handle an output uses to queue items for a BatchSender
+#[derive(Debug)]
+pub struct BatchQueue<T> {
+    name: &'static str,
+    items: Option<SyncSender<(T, usize)>>,
+}
+
+impl<T> BatchQueue<T> {
+    /// queue `item`, about `size` bytes once sent
+    pub fn send(&self, item: T, size: usize) -> io::Result<()> {
+        let closed = || io::Error::other(format!("{} sink is closed", self.name));
+        // blocks while the senders are behind
+        match &self.items {
+            Some(items) => items.send((item, size)).map_err(|_| closed()),
+            None => Err(closed()),
+        }
+    }
+}
+
+/// `line` with the labels as fields, lines that aren't JSON objects are left as they are
+pub fn with_labels(line: &str, labels: &[(String, String)]) -> String {
+    match serde_json::from_str::<Value>(line) {
+        Ok(Value::Object(mut object)) => {
+            for (name, value) in labels {
+                object
+                    .entry(name.clone())
+                    .or_insert_with(|| value.as_str().into());
+            }
+            Value::Object(object).to_string()
+        }
+        _ => line.to_string(),
+    }
+}
+
+/// parts of scheme://[user[:password]@]host[:port][/path][?key=value&key=value],
+/// host can be an IPv6 address in brackets like [::1]
+#[derive(Debug, Clone, Default)]
+pub struct SinkUrl {
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    /// without the leading /
+    pub path: String,
+    pub query: Vec<(String, String)>,
+}
+
+impl SinkUrl {
+    pub fn parse(url: &str, scheme: &str) -> io::Result<SinkUrl> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let rest = url
+            .strip_prefix(scheme)
+            .ok_or_else(|| invalid(format!("{} isn't a {} url", url, scheme)))?;
+
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (credentials, host_port) = match authority.rsplit_once('@') {
+            Some((credentials, host_port)) => (Some(credentials), host_port),
+            None => (None, authority),
+        };
+
+        let mut parsed = SinkUrl {
+            path: path.to_string(),
+            ..SinkUrl::default()
+        };
+        if let Some(credentials) = credentials {
+            let (user, password) = match credentials.split_once(':') {
+                Some((user, password)) => (user, Some(password.to_string())),
+                None => (credentials, None),
+            };
+            parsed.user = Some(user.to_string()).filter(|user| !user.is_empty());
+            parsed.password = password;
+        }
+        // IPv6 addresses are in brackets, which are kept so addr() can be connected to
+        let port_at = match host_port.rfind(']') {
+            Some(end) => host_port[end..].find(':').map(|pos| end + pos),
+            None => host_port.rfind(':'),
+        };
+        match port_at.map(|pos| (&host_port[..pos], &host_port[pos + 1..])) {
+            Some((host, port)) => {
+                parsed.host = host.to_string();
+                parsed.port = Some(
+                    port.parse::<u16>()
+                        .map_err(|_| invalid(format!("{} has an invalid port {}", url, port)))?,
+                );
+            }
+            None => parsed.host = host_port.to_string(),
+        }
+        if parsed.host.is_empty() {
+            return Err(invalid(format!("{} has no host", url)));
+        }
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("{} in {} isn't key=value", pair, url)))?;
+            parsed.query.push((key.to_string(), value.to_string()));
+        }
+
+        Ok(parsed)
+    }
+
+    /// value of `key` in the query
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// error if the query has keys other than `known`
+    pub fn check_params(&self, known: &[&str]) -> io::Result<()> {
+        match self
+            .query
+            .iter()
+            .find(|(key, _)| !known.contains(&key.as_str()))
+        {
+            Some((key, _)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unknown sink option {}, supported: {}",
+                    key,
+                    known.join(", ")
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// host:port with `default_port` if it has none
+    pub fn addr(&self, default_port: u16) -> String {
+        format!("{}:{}", self.host, self.port.unwrap_or(default_port))
+    }
+}
+
+/// `template` with {file} replaced by the relative path of the generated file,
+/// {dir} by its directory and {stem} by its name without extension, path
+/// separators are replaced by `separator`
+pub fn expand_path_template(template: &str, rel_path: &Path, separator: &str) -> String {
+    let join = |path: &Path| {
+        path.iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(separator)
+    };
+    let dir = rel_path.parent().map(join).unwrap_or_default();
+    let stem = rel_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    template
+        .replace("{file}", &join(rel_path))
+        .replace("{dir}", &dir)
+        .replace("{stem}", &stem)
+}
+
+/// like expand_path_template with . as separator for dotted names like NATS
+/// subjects or AMQP routing keys, which can't have empty parts or whitespace
+pub fn dotted_path_template(template: &str, rel_path: &Path) -> String {
+    expand_path_template(template, rel_path, ".")
+        .split('.')
+        .filter(|token| !token.is_empty())
+        .map(|token| token.replace(char::is_whitespace, "_"))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SinkOptions {
+    /// labels or tags added to every line, for sinks that support them
+    pub labels: Vec<(String, String)>,
+    /// requests sent at the same time, for network sinks
+    pub concurrency: usize,
+    /// batches buffered or being sent before writers wait, for network sinks
+    pub inflight: usize,
+    /// for --sink with several urls
+    pub pool: PoolOptions,
+    /// what stream sinks do when the consumer is full
+    pub block_on_full: Option<WhenFull>,
+    /// connections of each output, for tcp sinks
+    pub connections: usize,
+    /// when tcp sinks replace a connection, never if Default
+    pub reconnect: Reconnect,
+    /// how often syslog+tls:// sinks switch client certificates, see --tls-rotate-certs
+    pub tls_rotate: Option<Duration>,
+}
+
+const SCHEMES: &[&str] = &[
+    "loki://",
+    "lokis://",
+    "grpc://",
+    "grpcs://",
+    "redis://",
+    "nats://",
+    "amqp://",
+    "clickhouse://",
+    "clickhouses://",
+    "parquet:",
+    "file:",
+    "syslog://",
+    "syslog+tls://",
+    "pubsub://",
+    "gcp-logging://",
+    "cloudwatch://",
+    "kinesis://",
+    "eventhubs://",
+    "tcp://",
+];
+
+pub fn open(url: &str, options: &SinkOptions) -> io::Result<Arc<dyn Sink>> {
+    let urls = split_pool(url);
+    if urls.len() > 1 {
+        return open_pool(&urls, options);
+    }
+
+    if url.starts_with("loki://") || url.starts_with("lokis://") {
+        return Ok(Arc::new(loki::LokiSink::new(url, options)?));
+    } else if url.starts_with("grpc://") || url.starts_with("grpcs://") {
+        return open_grpc(url, options);
+    } else if url.starts_with("redis://") {
+        return Ok(Arc::new(redis::RedisSink::new(url, options)?));
+    } else if url.starts_with("nats://") {
+        return Ok(Arc::new(nats::NatsSink::new(url, options)?));
+    } else if url.starts_with("amqp://") {
+        return Ok(Arc::new(amqp::AmqpSink::new(url, options)?));
+    } else if url.starts_with("clickhouse://") || url.starts_with("clickhouses://") {
+        return Ok(Arc::new(clickhouse::ClickHouseSink::new(url, options)?));
+    } else if url.starts_with("parquet:") {
+        return open_parquet(url, options);
+    } else if url.starts_with("file:") {
+        return Ok(Arc::new(file::FileSink::new(url, options)?));
+    } else if url.starts_with("syslog://") || url.starts_with("syslog+tls://") {
+        return Ok(Arc::new(syslog::SyslogSink::new(url, options)?));
+    } else if url.starts_with("pubsub://") || url.starts_with("gcp-logging://") {
+        return Ok(Arc::new(gcp::GcpSink::new(url, options)?));
+    } else if url.starts_with("cloudwatch://") || url.starts_with("kinesis://") {
+        return Ok(Arc::new(aws::AwsSink::new(url, options)?));
+    } else if url.starts_with("eventhubs://") {
+        return Ok(Arc::new(eventhubs::EventHubsSink::new(url, options)?));
+    } else if url.starts_with("tcp://") {
+        return Ok(Arc::new(tcp::TcpSink::new(url, options)?));
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "unsupported sink {}, supported: {}",
+            url,
+            SCHEMES.join(", ")
+        ),
+    ))
+}
+
+#[cfg(feature = "grpc")]
+fn open_grpc(url: &str, options: &SinkOptions) -> io::Result<Arc<dyn Sink>> {
+    Ok(Arc::new(grpc::GrpcSink::new(url, options)?))
+}
+
+#[cfg(not(feature = "grpc"))]
+fn open_grpc(_url: &str, _options: &SinkOptions) -> io::Result<Arc<dyn Sink>> {
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "loggen was built without grpc support, build it with --features grpc",
+    ))
+}
+
+#[cfg(feature = "parquet")]
+fn open_parquet(url: &str, options: &SinkOptions) -> io::Result<Arc<dyn Sink>> {
+    Ok(Arc::new(parquet::ParquetSink::new(url, options)?))
+}
+
+#[cfg(not(feature = "parquet"))]
+fn open_parquet(_url: &str, _options: &SinkOptions) -> io::Result<Arc<dyn Sink>> {
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "loggen was built without parquet support, build it with --features parquet",
+    ))
+}
+
+/// split "URL,URL..." at the commas before each sink url, urls can have commas too
+fn split_pool(v: &str) -> Vec<&str> {
+    let mut urls = vec![];
+    let mut start = 0;
+    for (pos, _) in v.match_indices(',') {
+        if pos > start
+            && SCHEMES
+                .iter()
+                .any(|scheme| v[pos + 1..].starts_with(scheme))
+        {
+            urls.push(&v[start..pos]);
+            start = pos + 1;
+        }
+    }
+    urls.push(&v[start..]);
+    urls
+}
+
+/// sink that spreads lines over the sinks at `urls`
+fn open_pool(urls: &[&str], options: &SinkOptions) -> io::Result<Arc<dyn Sink>> {
+    let mut endpoints = vec![];
+    for url in urls {
+        let sink = open(url, options)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", url, err)))?;
+        endpoints.push((url.to_string(), sink));
+    }
+    Ok(Arc::new(pool::PoolSink::new(
+        endpoints,
+        options.pool.clone(),
+    )))
+}
+
+/// split "PRIMARY,SECONDARY" at the comma before the second sink url, urls
+/// can have commas too
+pub fn split_failover(v: &str) -> Result<(&str, &str), String> {
+    let split = v.match_indices(',').map(|(pos, _)| pos).find(|pos| {
+        SCHEMES
+            .iter()
+            .any(|scheme| v[pos + 1..].starts_with(scheme))
+    });
+    match split {
+        Some(pos) if pos > 0 => {
+            let (primary, secondary) = (&v[..pos], &v[pos + 1..]);
+            is_sink_url(primary.to_string())?;
+            Ok((primary, secondary))
+        }
+        _ => Err(format!(
+            "{} should be PRIMARY,SECONDARY with two sink urls, supported: {}",
+            v,
+            SCHEMES.join(", ")
+        )),
+    }
+}
+
+pub fn is_failover(v: String) -> Result<(), String> {
+    split_failover(&v).map(|_| ())
+}
+
+/// sink that sends to `primary` and fails over to `secondary` while it fails
+pub fn open_failover(
+    primary: &str,
+    secondary: &str,
+    options: &SinkOptions,
+    failover: FailoverOptions,
+) -> io::Result<Arc<dyn Sink>> {
+    let with_url =
+        |url: &str, err: io::Error| io::Error::new(err.kind(), format!("{}: {}", url, err));
+    let primary_sink = open(primary, options).map_err(|err| with_url(primary, err))?;
+    let secondary_sink = open(secondary, options).map_err(|err| with_url(secondary, err))?;
+    Ok(Arc::new(failover::FailoverSink::new(
+        primary_sink,
+        secondary_sink,
+        (primary.to_string(), secondary.to_string()),
+        failover,
+    )))
+}
+
+pub fn is_sink_url(v: String) -> Result<(), String> {
+    if split_pool(&v)
+        .iter()
+        .all(|url| SCHEMES.iter().any(|scheme| url.starts_with(scheme)))
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "unsupported sink {}, supported: {}",
+            v,
+            SCHEMES.join(", ")
+        ))
+    }
+}
+
+pub fn is_label(v: String) -> Result<(), String> {
+    match v.find('=') {
+        Some(pos) if pos > 0 => Ok(()),
+        _ => Err(format!("{} should be name=value", v)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchSender, Batching, SinkOptions};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn batches_split_at_size_and_bytes() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let batching = Batching {
+            size: 3,
+            wait: None,
+            max_bytes: 10,
+        };
+        let sender = {
+            let sent = sent.clone();
+            BatchSender::start(
+                "test",
+                "test".to_string(),
+                batching,
+                &SinkOptions::default(),
+                {
+                    move |batches| {
+                        // wait until everything is queued so batches are as full as they can be
+                        std::thread::sleep(std::time::Duration::from_millis(100));
+                        batches.send_each(|batch: &[usize]| {
+                            sent.lock().unwrap().push(batch.to_vec());
+                            Ok(())
+                        })
+                    }
+                },
+            )
+        };
+        let queue = sender.queue();
+        for (item, size) in [(1, 1), (2, 1), (3, 1), (4, 1), (5, 9), (6, 1), (7, 1)] {
+            queue.send(item, size).unwrap();
+        }
+        drop(queue);
+        sender.close().unwrap();
+
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![vec![1, 2, 3], vec![4, 5], vec![6, 7]]
+        );
+        assert_eq!(sender.errors(), 0);
+    }
+}
@@ -0,0 +1,467 @@
+use super::{
+    dotted_path_template, BatchQueue, BatchSender, Batches, Batching, Sink, SinkOptions,
+    SinkOutput, SinkUrl,
+};
+use rand::Rng;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_PORT: u16 = 4222;
+const DEFAULT_SUBJECT: &str = "logs";
+/// messages written in one write, with jetstream their acks are awaited together
+const BATCH_SIZE: usize = 500;
+const TIMEOUT: Duration = Duration::from_secs(30);
+const PARAMS: &[&str] = &["subject", "jetstream"];
+
+#[derive(Debug, Clone)]
+struct Target {
+    addr: String,
+    user: Option<String>,
+    password: Option<String>,
+    /// may have {dir}, {file} and {stem}
+    subject: String,
+    /// publish with a reply subject and wait for the stream to ack every message
+    jetstream: bool,
+}
+
+/// nats://[user:password@ or token@]host[:port][?subject=SUBJECT][&jetstream=true]
+fn parse_url(url: &str) -> io::Result<Target> {
+    let parsed = SinkUrl::parse(url, "nats://")?;
+    parsed.check_params(PARAMS)?;
+    let jetstream = match parsed.param("jetstream") {
+        None | Some("false") => false,
+        Some("true") => true,
+        Some(v) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("jetstream={} should be true or false", v),
+            ))
+        }
+    };
+
+    Ok(Target {
+        addr: parsed.addr(DEFAULT_PORT),
+        user: parsed.user.clone(),
+        password: parsed.password.clone(),
+        subject: parsed
+            .param("subject")
+            .unwrap_or(DEFAULT_SUBJECT)
+            .to_string(),
+        jetstream,
+    })
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// subject and line to publish
+type Message = (Arc<str>, String);
+
+/// what the reader hands to the sender
+#[derive(Debug)]
+enum Reply {
+    /// jetstream ack, Err with the description if the message wasn't stored
+    Ack(Result<(), String>),
+    Pong,
+}
+
+/// reads what the server sends: answers pings, reports errors and hands
+/// jetstream acks and pongs to the sender
+fn read_loop<R: BufRead, W: Write>(
+    mut reader: R,
+    writer: Arc<Mutex<W>>,
+    replies: mpsc::Sender<Reply>,
+) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        let op = line.trim_end();
+        if op == "PING" {
+            writer.lock().unwrap().write_all(b"PONG\r\n")?;
+        } else if op == "PONG" {
+            replies.send(Reply::Pong).ok();
+        } else if let Some(err) = op.strip_prefix("-ERR ") {
+            eprintln!("Error from nats: {}", err);
+        } else if op.starts_with("MSG ") {
+            // MSG <subject> <sid> [reply-to] <#bytes>
+            let len = op
+                .rsplit(' ')
+                .next()
+                .and_then(|len| len.parse::<usize>().ok())
+                .ok_or_else(|| protocol_error(format!("invalid nats message {}", op)))?;
+            let mut payload = vec![0; len + 2];
+            reader.read_exact(&mut payload)?;
+            let ack: serde_json::Value =
+                serde_json::from_slice(&payload[..len]).unwrap_or_default();
+            let result = match ack.get("error") {
+                Some(err) => Err(err
+                    .get("description")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown error")
+                    .to_string()),
+                None => Ok(()),
+            };
+            replies.send(Reply::Ack(result)).ok();
+        }
+    }
+}
+
+/// PUB, or HPUB with the `headers` block, of `message` to `subject`, `reply`
+/// is empty or a space and the reply subject
+fn write_publish(
+    data: &mut Vec<u8>,
+    subject: &str,
+    reply: &str,
+    headers: &Option<String>,
+    message: &str,
+) {
+    let op = match headers {
+        Some(headers) => format!(
+            "HPUB {}{} {} {}\r\n{}{}\r\n",
+            subject,
+            reply,
+            headers.len(),
+            headers.len() + message.len(),
+            headers,
+            message
+        ),
+        None => format!(
+            "PUB {}{} {}\r\n{}\r\n",
+            subject,
+            reply,
+            message.len(),
+            message
+        ),
+    };
+    data.extend_from_slice(op.as_bytes());
+}
+
+#[derive(Debug)]
+struct Connection {
+    writer: Arc<Mutex<TcpStream>>,
+    replies: Receiver<Reply>,
+    /// prefix of the reply subjects for jetstream acks
+    inbox: String,
+    sent: u64,
+}
+
+impl Connection {
+    fn open(target: &Target) -> io::Result<Connection> {
+        let stream = TcpStream::connect(&target.addr)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut info = String::new();
+        reader.read_line(&mut info)?;
+        if !info.starts_with("INFO ") {
+            return Err(protocol_error(format!(
+                "{} isn't a nats server, it said {}",
+                target.addr,
+                info.trim_end()
+            )));
+        }
+        let mut connect = serde_json::json!({
+            "verbose": false,
+            "pedantic": false,
+            "headers": true,
+            "name": "loggen",
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        match (&target.user, &target.password) {
+            (Some(user), Some(password)) => {
+                connect["user"] = user.as_str().into();
+                connect["pass"] = password.as_str().into();
+            }
+            (Some(token), None) => connect["auth_token"] = token.as_str().into(),
+            _ => {}
+        }
+
+        let inbox = format!("_INBOX.loggen.{:016x}", rand::thread_rng().gen::<u64>());
+        let mut handshake = format!("CONNECT {}\r\n", connect);
+        if target.jetstream {
+            handshake.push_str(&format!("SUB {}.* 1\r\n", inbox));
+        }
+        // the server answers the ping after processing CONNECT, or with an error
+        handshake.push_str("PING\r\n");
+        let mut writer = stream;
+        writer.write_all(handshake.as_bytes())?;
+
+        let mut answer = String::new();
+        reader.read_line(&mut answer)?;
+        if answer.trim_end() != "PONG" {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("nats refused the connection: {}", answer.trim_end()),
+            ));
+        }
+        // the reader waits for pings, which come every couple of minutes
+        writer.set_read_timeout(None)?;
+
+        let writer = Arc::new(Mutex::new(writer));
+        let (replies_tx, replies) = mpsc::channel();
+        let reader_writer = writer.clone();
+        thread::spawn(move || {
+            if let Err(err) = read_loop(reader, reader_writer, replies_tx) {
+                eprintln!("Error reading from nats: {}", err);
+            }
+        });
+
+        Ok(Connection {
+            writer,
+            replies,
+            inbox,
+            sent: 0,
+        })
+    }
+
+    /// publish a batch of (subject, message) with the `headers` block, if any,
+    /// returns the errors jetstream acked with
+    fn send(
+        &mut self,
+        batch: &[Message],
+        headers: &Option<String>,
+        jetstream: bool,
+    ) -> io::Result<Vec<String>> {
+        let mut data = vec![];
+        for (subject, message) in batch {
+            let reply = if jetstream {
+                self.sent += 1;
+                format!(" {}.{}", self.inbox, self.sent)
+            } else {
+                String::new()
+            };
+            write_publish(&mut data, subject, &reply, headers, message);
+        }
+        self.writer.lock().unwrap().write_all(&data)?;
+
+        let mut errors = vec![];
+        if jetstream {
+            let deadline = Instant::now() + TIMEOUT;
+            let mut acked = 0;
+            while acked < batch.len() {
+                match self.reply(deadline)? {
+                    Reply::Ack(Ok(())) => acked += 1,
+                    Reply::Ack(Err(err)) => {
+                        acked += 1;
+                        errors.push(err);
+                    }
+                    Reply::Pong => {}
+                }
+            }
+        }
+
+        Ok(errors)
+    }
+
+    fn reply(&self, deadline: Instant) -> io::Result<Reply> {
+        let left = deadline.saturating_duration_since(Instant::now());
+        self.replies.recv_timeout(left).map_err(|err| match err {
+            RecvTimeoutError::Timeout => io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out waiting for nats, with jetstream is there a stream for the subject?",
+            ),
+            RecvTimeoutError::Disconnected => io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "nats closed the connection",
+            ),
+        })
+    }
+
+    /// wait until the server processed everything sent, it answers a ping
+    /// after what came before it
+    fn flush(&self) -> io::Result<()> {
+        self.writer.lock().unwrap().write_all(b"PING\r\n")?;
+        let deadline = Instant::now() + TIMEOUT;
+        while !matches!(self.reply(deadline)?, Reply::Pong) {}
+        Ok(())
+    }
+
+    fn close(&self) {
+        self.writer
+            .lock()
+            .unwrap()
+            .shutdown(std::net::Shutdown::Both)
+            .ok();
+    }
+}
+
+/// "NATS/1.0\r\nname: value\r\n\r\n" for HPUB, None without labels
+fn headers_block(labels: &[(String, String)]) -> Option<String> {
+    if labels.is_empty() {
+        return None;
+    }
+
+    let mut headers = "NATS/1.0\r\n".to_string();
+    for (name, value) in labels {
+        headers.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    headers.push_str("\r\n");
+    Some(headers)
+}
+
+/// sends batches over its own connection, reconnecting after errors
+fn send_batches(target: &Target, headers: &Option<String>, batches: &Batches<Message>) {
+    let mut connection: Option<Connection> = None;
+    batches.send_each(|batch| {
+        if connection.is_none() {
+            connection = Some(Connection::open(target)?);
+        }
+        let opened = connection.as_mut().unwrap();
+        match opened.send(batch, headers, target.jetstream) {
+            Ok(errors) => {
+                if let Some(err) = errors.first() {
+                    eprintln!(
+                        "Error: jetstream rejected {} of {} lines: {}",
+                        errors.len(),
+                        batch.len(),
+                        err
+                    );
+                }
+                Ok(())
+            }
+            Err(err) => {
+                opened.close();
+                connection = None;
+                Err(err)
+            }
+        }
+    });
+
+    if let Some(connection) = connection {
+        if let Err(err) = connection.flush() {
+            eprintln!("Error flushing nats {}: {}", target.addr, err);
+        }
+        connection.close();
+    }
+}
+
+/// publishes lines as NATS messages, to JetStream waiting for acks when asked,
+/// over `concurrency` connections
+#[derive(Debug)]
+pub struct NatsSink {
+    target: Target,
+    sender: BatchSender<Message>,
+}
+
+impl NatsSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<NatsSink> {
+        let target = parse_url(url)?;
+        // fail at startup if nats can't be reached
+        Connection::open(&target)?.close();
+
+        let headers = headers_block(&options.labels);
+        let batching = Batching {
+            size: BATCH_SIZE,
+            wait: None,
+            max_bytes: usize::MAX,
+        };
+        let sender = {
+            let target = target.clone();
+            let destination = format!("nats {}", target.addr);
+            BatchSender::start("nats", destination, batching, options, move |batches| {
+                send_batches(&target, &headers, batches)
+            })
+        };
+
+        Ok(NatsSink { target, sender })
+    }
+}
+
+impl Sink for NatsSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(NatsOutput {
+            subject: dotted_path_template(&self.target.subject, rel_path).into(),
+            messages: self.sender.queue(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.sender.close()
+    }
+
+    fn failures(&self) -> u64 {
+        self.sender.failures()
+    }
+
+    fn errors(&self) -> u64 {
+        self.sender.errors()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        Connection::open(&self.target)?.close();
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct NatsOutput {
+    subject: Arc<str>,
+    messages: BatchQueue<Message>,
+}
+
+impl SinkOutput for NatsOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let message = line.trim_end_matches(['\r', '\n']).to_string();
+        let size = message.len();
+        self.messages.send((self.subject.clone(), message), size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{headers_block, read_loop, write_publish, Reply};
+    use std::sync::{mpsc, Arc, Mutex};
+
+    #[test]
+    fn publishes_with_and_without_headers() {
+        let mut data = vec![];
+        write_publish(&mut data, "logs.app", "", &None, "a line");
+        let headers = headers_block(&[("env".to_string(), "test".to_string())]);
+        write_publish(&mut data, "logs.app", " _INBOX.x.1", &headers, "a line");
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "PUB logs.app 6\r\na line\r\n\
+             HPUB logs.app _INBOX.x.1 23 29\r\nNATS/1.0\r\nenv: test\r\n\r\na line\r\n"
+        );
+    }
+
+    #[test]
+    fn reader_answers_pings_and_hands_over_acks() {
+        let ack = r#"{"stream":"logs","seq":1}"#;
+        let nack = r#"{"error":{"code":503,"description":"no responders"}}"#;
+        let input = format!(
+            "PING\r\nMSG _INBOX.x.1 1 {}\r\n{}\r\nMSG _INBOX.x.2 1 {}\r\n{}\r\nPONG\r\n",
+            ack.len(),
+            ack,
+            nack.len(),
+            nack
+        );
+        let writer = Arc::new(Mutex::new(vec![]));
+        let (replies_tx, replies) = mpsc::channel();
+        read_loop(input.as_bytes(), writer.clone(), replies_tx).unwrap();
+
+        assert_eq!(*writer.lock().unwrap(), b"PONG\r\n");
+        let replies: Vec<Reply> = replies.iter().collect();
+        assert!(matches!(
+            replies.as_slice(),
+            [
+                Reply::Ack(Ok(())),
+                Reply::Ack(Err(err)),
+                Reply::Pong
+            ] if err == "no responders"
+        ));
+    }
+}
@@ -0,0 +1,492 @@
+use super::{Sink, SinkOptions, SinkOutput};
+use crate::parse::parse_duration;
+use crate::regex::Regex;
+use chrono::Utc;
+use parquet::basic::{ConvertedType, Repetition, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_ROW_GROUP: usize = 10_000;
+const DEFAULT_FILE_ROWS: usize = 1_000_000;
+/// lines buffered before writers wait
+const QUEUE_SIZE: usize = 10_000;
+/// how often the writer checks if the file has to roll while no lines come
+const TICK: Duration = Duration::from_secs(1);
+const PARAMS: &[&str] = &["columns", "row_group", "file_rows", "roll", "regex"];
+
+/// how lines are split into columns
+#[derive(Debug, Clone)]
+enum Parser {
+    /// top level fields of JSON objects
+    Json,
+    /// named groups of a regex
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+struct Target {
+    dir: PathBuf,
+    parser: Parser,
+    /// parsed from the lines, followed by file and the labels
+    columns: Vec<String>,
+    row_group: usize,
+    file_rows: usize,
+    /// start a new file after this long even if it has less than file_rows
+    roll: Option<Duration>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn parse_count(key: &str, value: &str) -> io::Result<usize> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(invalid(format!(
+            "{}={} should be a number greater than 0",
+            key, value
+        ))),
+    }
+}
+
+/// parquet:DIR[?columns=a,b][&row_group=N][&file_rows=N][&roll=DURATION][&regex=REGEX],
+/// regex goes last and takes the rest of the url, & and = included
+fn parse_url(url: &str) -> io::Result<Target> {
+    let rest = url
+        .strip_prefix("parquet:")
+        .ok_or_else(|| invalid(format!("{} isn't a parquet: url", url)))?;
+    let (dir, query) = rest.split_once('?').unwrap_or((rest, ""));
+    if dir.is_empty() {
+        return Err(invalid(format!("{} should be parquet:DIR", url)));
+    }
+
+    let (query, regex) = match query.find("regex=") {
+        Some(pos) if pos == 0 || query[..pos].ends_with('&') => (
+            query[..pos].trim_end_matches('&'),
+            Some(&query[pos + "regex=".len()..]),
+        ),
+        _ => (query, None),
+    };
+
+    let mut target = Target {
+        dir: PathBuf::from(dir),
+        parser: Parser::Json,
+        columns: vec![],
+        row_group: DEFAULT_ROW_GROUP,
+        file_rows: DEFAULT_FILE_ROWS,
+        roll: None,
+    };
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match key {
+            "columns" => {
+                target.columns = value
+                    .split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "row_group" => target.row_group = parse_count(key, value)?,
+            "file_rows" => target.file_rows = parse_count(key, value)?,
+            "roll" => target.roll = Some(parse_duration(value).map_err(invalid)?),
+            _ => {
+                return Err(invalid(format!(
+                    "unknown parquet sink option {}, supported: {}",
+                    key,
+                    PARAMS.join(", ")
+                )))
+            }
+        }
+    }
+
+    if let Some(regex) = regex {
+        let regex = Regex::new(regex).map_err(invalid)?;
+        let named: Vec<String> = regex.names().iter().flatten().cloned().collect();
+        if named.is_empty() {
+            return Err(invalid(
+                "the parquet sink regex needs named groups, like (?<level>\\w+)".to_string(),
+            ));
+        }
+        if let Some(missing) = target.columns.iter().find(|name| !named.contains(name)) {
+            return Err(invalid(format!(
+                "the parquet sink regex has no group {}",
+                missing
+            )));
+        }
+        if target.columns.is_empty() {
+            target.columns = named;
+        }
+        target.parser = Parser::Regex(regex);
+    } else if target.columns.is_empty() {
+        return Err(invalid(
+            "the parquet sink needs columns=a,b,c to parse JSON lines, or a regex".to_string(),
+        ));
+    }
+
+    Ok(target)
+}
+
+fn to_io_error(err: parquet::errors::ParquetError) -> io::Error {
+    io::Error::other(format!("parquet: {}", err))
+}
+
+impl Target {
+    /// values of `columns` in `line`, None if it can't be parsed
+    fn parse(&self, line: &str) -> Option<Vec<Option<String>>> {
+        match &self.parser {
+            Parser::Json => {
+                let json: Value = serde_json::from_str(line).ok()?;
+                let object = json.as_object()?;
+                Some(
+                    self.columns
+                        .iter()
+                        .map(|name| match object.get(name) {
+                            None | Some(Value::Null) => None,
+                            Some(Value::String(value)) => Some(value.clone()),
+                            Some(value) => Some(value.to_string()),
+                        })
+                        .collect(),
+                )
+            }
+            Parser::Regex(regex) => {
+                let captures = regex.captures(line)?;
+                Some(
+                    self.columns
+                        .iter()
+                        .map(|name| {
+                            regex
+                                .names()
+                                .iter()
+                                .position(|group| group.as_deref() == Some(name.as_str()))
+                                .and_then(|group| captures[group])
+                                .map(str::to_string)
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+/// all columns are optional UTF8 strings
+fn schema(columns: &[String]) -> io::Result<Arc<Type>> {
+    let fields = columns
+        .iter()
+        .map(|name| {
+            Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+                .with_repetition(Repetition::OPTIONAL)
+                .with_converted_type(ConvertedType::UTF8)
+                .build()
+                .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(to_io_error)?;
+    Type::group_type_builder("loggen")
+        .with_fields(fields)
+        .build()
+        .map(Arc::new)
+        .map_err(to_io_error)
+}
+
+/// the file being written, under a .tmp name until it's closed
+struct OpenFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    writer: SerializedFileWriter<BufWriter<File>>,
+    rows: usize,
+}
+
+/// rolls parquet files in a directory, buffering rows until a row group is full
+struct Writer {
+    dir: PathBuf,
+    schema: Arc<Type>,
+    properties: Arc<WriterProperties>,
+    row_group: usize,
+    file_rows: usize,
+    roll: Option<Duration>,
+    /// values of the row group being filled, by column
+    buffered: Vec<Vec<Option<String>>>,
+    file: Option<OpenFile>,
+    files: usize,
+    /// when the first row of the current file came, for `roll`
+    first_row: Option<Instant>,
+}
+
+impl Writer {
+    fn new(target: &Target, columns: &[String]) -> io::Result<Writer> {
+        Ok(Writer {
+            dir: target.dir.clone(),
+            schema: schema(columns)?,
+            properties: Arc::new(
+                WriterProperties::builder()
+                    .set_max_row_group_size(target.row_group)
+                    .build(),
+            ),
+            row_group: target.row_group,
+            file_rows: target.file_rows,
+            roll: target.roll,
+            buffered: vec![vec![]; columns.len()],
+            file: None,
+            files: 0,
+            first_row: None,
+        })
+    }
+
+    fn buffered_rows(&self) -> usize {
+        self.buffered.first().map_or(0, Vec::len)
+    }
+
+    fn push(&mut self, row: Vec<Option<String>>) -> io::Result<()> {
+        self.first_row.get_or_insert_with(Instant::now);
+        for (column, value) in self.buffered.iter_mut().zip(row) {
+            column.push(value);
+        }
+
+        let rows = self.buffered_rows();
+        let file_rows = self.file.as_ref().map_or(0, |file| file.rows);
+        if rows >= self.row_group || file_rows + rows >= self.file_rows {
+            self.write_row_group()?;
+        }
+        if self
+            .file
+            .as_ref()
+            .is_some_and(|file| file.rows >= self.file_rows)
+        {
+            self.close_file()?;
+        }
+        Ok(())
+    }
+
+    /// roll the file if its first row came longer than `roll` ago
+    fn tick(&mut self) -> io::Result<()> {
+        let due = match (self.roll, self.first_row) {
+            (Some(roll), Some(first_row)) => first_row.elapsed() >= roll,
+            _ => false,
+        };
+        if due {
+            self.write_row_group()?;
+            self.close_file()?;
+        }
+        Ok(())
+    }
+
+    fn open_file(&mut self) -> io::Result<OpenFile> {
+        self.files += 1;
+        let name = format!(
+            "loggen-{}-{:05}.parquet",
+            Utc::now().format("%Y%m%dT%H%M%S"),
+            self.files
+        );
+        let path = self.dir.join(name);
+        let tmp_path = path.with_extension("parquet.tmp");
+        let writer = SerializedFileWriter::new(
+            BufWriter::new(File::create(&tmp_path)?),
+            self.schema.clone(),
+            self.properties.clone(),
+        )
+        .map_err(to_io_error)?;
+
+        Ok(OpenFile {
+            path,
+            tmp_path,
+            writer,
+            rows: 0,
+        })
+    }
+
+    fn write_row_group(&mut self) -> io::Result<()> {
+        let rows = self.buffered_rows();
+        if rows == 0 {
+            return Ok(());
+        }
+        if self.file.is_none() {
+            self.file = Some(self.open_file()?);
+        }
+
+        let file = self.file.as_mut().unwrap();
+        let mut row_group = file.writer.next_row_group().map_err(to_io_error)?;
+        for column in self.buffered.iter_mut() {
+            let mut writer = row_group
+                .next_column()
+                .map_err(to_io_error)?
+                .ok_or_else(|| io::Error::other("parquet: fewer columns than expected"))?;
+            let levels: Vec<i16> = column.iter().map(|v| v.is_some() as i16).collect();
+            let values: Vec<ByteArray> = column.drain(..).flatten().map(ByteArray::from).collect();
+            writer
+                .typed::<ByteArrayType>()
+                .write_batch(&values, Some(&levels), None)
+                .map_err(to_io_error)?;
+            writer.close().map_err(to_io_error)?;
+        }
+        row_group.close().map_err(to_io_error)?;
+        file.rows += rows;
+        Ok(())
+    }
+
+    fn close_file(&mut self) -> io::Result<()> {
+        self.first_row = None;
+        if let Some(file) = self.file.take() {
+            file.writer.close().map_err(to_io_error)?;
+            fs::rename(&file.tmp_path, &file.path)?;
+        }
+        Ok(())
+    }
+
+    fn close(mut self) -> io::Result<()> {
+        self.write_row_group()?;
+        self.close_file()
+    }
+}
+
+/// file the line came from and the line without its line ending
+type Line = (Arc<str>, String);
+
+/// parses lines into rows and writes them, counting the lines that don't parse
+fn write_loop(target: Target, labels: Vec<(String, String)>, lines: Receiver<Line>) {
+    let mut columns = target.columns.clone();
+    let with_file = !columns.iter().any(|name| name == "file");
+    if with_file {
+        columns.push("file".to_string());
+    }
+    for (name, _) in &labels {
+        if !columns.contains(name) {
+            columns.push(name.clone());
+        }
+    }
+    let extra = &columns[target.columns.len()..];
+
+    let mut writer = match Writer::new(&target, &columns) {
+        Ok(writer) => writer,
+        Err(err) => {
+            eprintln!(
+                "Error opening parquet sink {}: {}",
+                target.dir.display(),
+                err
+            );
+            return;
+        }
+    };
+    let mut skipped: u64 = 0;
+    let mut failed = false;
+
+    loop {
+        let result = match lines.recv_timeout(TICK) {
+            Ok((file, line)) => match target.parse(&line) {
+                Some(mut row) => {
+                    for name in extra {
+                        row.push(if name == "file" && with_file {
+                            Some(file.to_string())
+                        } else {
+                            labels
+                                .iter()
+                                .find(|(label, _)| label == name)
+                                .map(|(_, value)| value.clone())
+                        });
+                    }
+                    writer.push(row)
+                }
+                None => {
+                    skipped += 1;
+                    Ok(())
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => Ok(()),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        .and_then(|()| writer.tick());
+        // errors are reported once, the rows of a failed file are lost
+        if let Err(err) = result {
+            if !failed {
+                eprintln!("Error writing parquet to {}: {}", target.dir.display(), err);
+            }
+            failed = true;
+        }
+    }
+
+    if let Err(err) = writer.close() {
+        eprintln!(
+            "Error closing parquet file in {}: {}",
+            target.dir.display(),
+            err
+        );
+    }
+    if skipped > 0 {
+        eprintln!(
+            "Warning: {} lines didn't parse and weren't written to parquet",
+            skipped
+        );
+    }
+}
+
+/// writes lines parsed into columns as rolling parquet files in a directory
+#[derive(Debug)]
+pub struct ParquetSink {
+    lines: Mutex<Option<SyncSender<Line>>>,
+    writer: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl ParquetSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<ParquetSink> {
+        let target = parse_url(url)?;
+        fs::create_dir_all(&target.dir)?;
+
+        let labels = options.labels.clone();
+        let (lines_tx, lines_rx) = mpsc::sync_channel(QUEUE_SIZE);
+        let writer = thread::spawn(move || write_loop(target, labels, lines_rx));
+
+        Ok(ParquetSink {
+            lines: Mutex::new(Some(lines_tx)),
+            writer: Mutex::new(Some(writer)),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ParquetOutput {
+    file: Arc<str>,
+    lines: Option<SyncSender<Line>>,
+}
+
+impl SinkOutput for ParquetOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let lines = self
+            .lines
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "parquet sink closed"))?;
+        lines
+            .send((
+                self.file.clone(),
+                line.trim_end_matches(['\r', '\n']).to_string(),
+            ))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "parquet writer stopped"))
+    }
+}
+
+impl Sink for ParquetSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(ParquetOutput {
+            file: rel_path.display().to_string().into(),
+            lines: self.lines.lock().unwrap().clone(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.lines.lock().unwrap().take();
+        if let Some(writer) = self.writer.lock().unwrap().take() {
+            writer
+                .join()
+                .map_err(|_| io::Error::other("parquet writer panicked"))?;
+        }
+        Ok(())
+    }
+}
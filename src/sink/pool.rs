@@ -0,0 +1,271 @@
+use super::{Failures, Sink, SinkOutput};
+use crate::backpressure::FullCounters;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+/// how often the endpoints' failures are checked
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// which endpoint of a pool gets a line, see --balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balance {
+    /// each line to the next endpoint
+    RoundRobin,
+    /// all lines of a generated file to the same endpoint while it's up
+    HashByFile,
+}
+
+impl Balance {
+    pub const NAMES: &'static [&'static str] = &["round-robin", "hash-by-file"];
+
+    pub fn parse(v: &str) -> Balance {
+        match v {
+            "hash-by-file" => Balance::HashByFile,
+            _ => Balance::RoundRobin,
+        }
+    }
+}
+
+/// how lines are spread over the endpoints of a pool and when they're taken
+/// out and put back
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub balance: Balance,
+    /// failed sends in a row before an endpoint is taken out
+    pub errors: u64,
+    /// how often endpoints that were taken out are probed
+    pub retry: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> PoolOptions {
+        PoolOptions {
+            balance: Balance::RoundRobin,
+            errors: 5,
+            retry: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    sink: Arc<dyn Sink>,
+    up: AtomicBool,
+    /// writes the endpoint's outputs refused in a row
+    write_failures: Failures,
+}
+
+#[derive(Debug)]
+struct State {
+    endpoints: Vec<Endpoint>,
+    /// next endpoint for round-robin
+    next: AtomicUsize,
+    /// writes that no endpoint took
+    failed_everywhere: Failures,
+    stop: AtomicBool,
+}
+
+/// spreads lines over several sinks, endpoints that fail are taken out until
+/// they answer again
+#[derive(Debug)]
+pub struct PoolSink {
+    balance: Balance,
+    state: Arc<State>,
+    monitor: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+/// takes endpoints out after `errors` failures in a row, then probes them
+/// every `retry` and puts them back once they answer
+fn monitor(state: Arc<State>, options: PoolOptions) {
+    let count = state.endpoints.len();
+    // failures counted before an endpoint was put back, reset on its next success
+    let mut baselines = vec![0; count];
+    let mut last_probes = vec![Instant::now(); count];
+    while !state.stop.load(Ordering::Relaxed) {
+        sleep(CHECK_INTERVAL);
+        for (index, endpoint) in state.endpoints.iter().enumerate() {
+            let failures = endpoint.sink.failures().max(endpoint.write_failures.get());
+            if failures < baselines[index] {
+                baselines[index] = 0;
+            }
+
+            if endpoint.up.load(Ordering::Relaxed) {
+                if failures - baselines[index] >= options.errors {
+                    eprintln!(
+                        "Warning: sink {} failed {} times in a row, taking it out of the pool",
+                        endpoint.url, failures
+                    );
+                    endpoint.up.store(false, Ordering::Relaxed);
+                    last_probes[index] = Instant::now();
+                }
+            } else if last_probes[index].elapsed() >= options.retry {
+                last_probes[index] = Instant::now();
+                if endpoint.sink.probe().is_ok() {
+                    eprintln!("sink {} is back in the pool", endpoint.url);
+                    baselines[index] = failures;
+                    endpoint.write_failures.succeeded();
+                    endpoint.up.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+impl PoolSink {
+    pub fn new(endpoints: Vec<(String, Arc<dyn Sink>)>, options: PoolOptions) -> PoolSink {
+        let state = Arc::new(State {
+            endpoints: endpoints
+                .into_iter()
+                .map(|(url, sink)| Endpoint {
+                    url,
+                    sink,
+                    up: AtomicBool::new(true),
+                    write_failures: Failures::default(),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            failed_everywhere: Failures::default(),
+            stop: AtomicBool::new(false),
+        });
+        let balance = options.balance;
+        let monitor_state = state.clone();
+        let monitor = thread::spawn(move || monitor(monitor_state, options));
+
+        PoolSink {
+            balance,
+            state,
+            monitor: Mutex::new(Some(monitor)),
+        }
+    }
+}
+
+impl Sink for PoolSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        let mut hasher = DefaultHasher::new();
+        rel_path.hash(&mut hasher);
+        Box::new(PoolOutput {
+            outputs: self
+                .state
+                .endpoints
+                .iter()
+                .map(|endpoint| endpoint.sink.output(rel_path))
+                .collect(),
+            balance: self.balance,
+            file_hash: hasher.finish() as usize,
+            state: self.state.clone(),
+        })
+    }
+
+    fn failures(&self) -> u64 {
+        self.state.failed_everywhere.get()
+    }
+
+    fn errors(&self) -> u64 {
+        self.state
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.sink.errors() + endpoint.write_failures.total())
+            .sum()
+    }
+
+    /// the pool answers if any endpoint does
+    fn probe(&self) -> io::Result<()> {
+        let mut result = Ok(());
+        for endpoint in &self.state.endpoints {
+            result = endpoint.sink.probe();
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.state.stop.store(true, Ordering::Relaxed);
+        if let Some(monitor) = self.monitor.lock().unwrap().take() {
+            monitor.join().ok();
+        }
+        // every endpoint is closed even if one fails
+        let mut result = Ok(());
+        for endpoint in &self.state.endpoints {
+            if let Err(err) = endpoint.sink.close() {
+                result = Err(io::Error::new(
+                    err.kind(),
+                    format!("{}: {}", endpoint.url, err),
+                ));
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug)]
+struct PoolOutput {
+    /// one per endpoint, in the same order
+    outputs: Vec<Box<dyn SinkOutput>>,
+    balance: Balance,
+    file_hash: usize,
+    state: Arc<State>,
+}
+
+impl PoolOutput {
+    /// try endpoints from `first` on, only those that are up unless `down` is true
+    fn try_write(&mut self, line: &str, first: usize, down: bool) -> Option<io::Result<()>> {
+        let count = self.outputs.len();
+        let mut result = None;
+        for index in (first..first + count).map(|index| index % count) {
+            let endpoint = &self.state.endpoints[index];
+            if endpoint.up.load(Ordering::Relaxed) == down {
+                continue;
+            }
+            match self.outputs[index].write(line) {
+                Ok(()) => {
+                    endpoint.write_failures.succeeded();
+                    return Some(Ok(()));
+                }
+                // the line goes to the next endpoint instead
+                Err(err) => {
+                    endpoint.write_failures.failed();
+                    result = Some(Err(err));
+                }
+            }
+        }
+        result
+    }
+}
+
+impl SinkOutput for PoolOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let first = match self.balance {
+            Balance::RoundRobin => self.state.next.fetch_add(1, Ordering::Relaxed),
+            // only files of an endpoint that was taken out move to others
+            Balance::HashByFile => self.file_hash,
+        } % self.outputs.len();
+        // with every endpoint out the line is still tried on them
+        let result = match self.try_write(line, first, false) {
+            Some(Ok(())) => Ok(()),
+            tried => self
+                .try_write(line, first, true)
+                .or(tried)
+                .unwrap_or_else(|| Err(io::Error::other("no sink in the pool"))),
+        };
+        match result {
+            Ok(()) => self.state.failed_everywhere.succeeded(),
+            Err(_) => self.state.failed_everywhere.failed(),
+        }
+        result
+    }
+
+    fn count_full(&mut self, counters: Arc<FullCounters>) {
+        for output in &mut self.outputs {
+            output.count_full(counters.clone());
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use super::{
+    expand_path_template, BatchQueue, BatchSender, Batches, Batching, Sink, SinkOptions,
+    SinkOutput, SinkUrl,
+};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 6379;
+/// commands pipelined in one write, replies are read after all of them are sent
+const BATCH_SIZE: usize = 500;
+const TIMEOUT: Duration = Duration::from_secs(30);
+const PARAMS: &[&str] = &["mode", "maxlen"];
+
+/// how lines are sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// XADD to a stream, with the line, file and labels as fields
+    Stream,
+    /// PUBLISH to a channel, the message is the line
+    Publish,
+}
+
+#[derive(Debug, Clone)]
+struct Target {
+    addr: String,
+    user: Option<String>,
+    password: Option<String>,
+    /// stream or channel name, may have {dir}, {file} and {stem}
+    name: String,
+    mode: Mode,
+    /// trim streams to about this many entries
+    maxlen: Option<u64>,
+}
+
+/// redis://[user:password@]host[:port]/NAME[?mode=stream|publish][&maxlen=N]
+fn parse_url(url: &str) -> io::Result<Target> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let parsed = SinkUrl::parse(url, "redis://")?;
+    parsed.check_params(PARAMS)?;
+    if parsed.path.is_empty() {
+        return Err(invalid(format!(
+            "{} has no stream or channel name, like redis://localhost/logs",
+            url
+        )));
+    }
+
+    let mode = match parsed.param("mode") {
+        None | Some("stream") => Mode::Stream,
+        Some("publish") => Mode::Publish,
+        Some(mode) => {
+            return Err(invalid(format!(
+                "{} isn't a valid redis mode, expected one of: stream, publish",
+                mode
+            )))
+        }
+    };
+    let maxlen = match parsed.param("maxlen") {
+        Some(v) => Some(
+            v.parse::<u64>()
+                .map_err(|_| invalid(format!("maxlen {} isn't a number", v)))?,
+        ),
+        None => None,
+    };
+    if maxlen.is_some() && mode == Mode::Publish {
+        return Err(invalid("maxlen only applies to mode=stream".to_string()));
+    }
+
+    Ok(Target {
+        addr: parsed.addr(DEFAULT_PORT),
+        user: parsed.user.clone(),
+        password: parsed.password.clone(),
+        name: parsed.path,
+        mode,
+        maxlen,
+    })
+}
+
+/// a command in the redis protocol (RESP)
+fn encode_command(args: &[&[u8]]) -> Vec<u8> {
+    let mut command = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        command.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        command.extend_from_slice(arg);
+        command.extend_from_slice(b"\r\n");
+    }
+    command
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// read a reply, Ok(Err(message)) for error replies
+fn read_reply<R: BufRead>(reader: &mut R) -> io::Result<Result<(), String>> {
+    let mut header = String::new();
+    if reader.read_line(&mut header)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "redis closed the connection",
+        ));
+    }
+    let header = header.trim_end();
+    let (kind, rest) = header.split_at(header.len().min(1));
+    let len = || {
+        rest.parse::<i64>()
+            .map_err(|_| protocol_error(format!("invalid redis reply {}", header)))
+    };
+
+    match kind {
+        "+" | ":" => Ok(Ok(())),
+        "-" => Ok(Err(rest.to_string())),
+        "$" => {
+            let len = len()?;
+            if len >= 0 {
+                // the value and its \r\n
+                let mut value = vec![0; len as usize + 2];
+                reader.read_exact(&mut value)?;
+            }
+            Ok(Ok(()))
+        }
+        "*" => {
+            let mut result = Ok(());
+            for _ in 0..len()?.max(0) {
+                if let Err(err) = read_reply(reader)? {
+                    result = Err(err);
+                }
+            }
+            Ok(result)
+        }
+        _ => Err(protocol_error(format!("invalid redis reply {}", header))),
+    }
+}
+
+#[derive(Debug)]
+struct Connection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Connection {
+    fn open(target: &Target) -> io::Result<Connection> {
+        let stream = TcpStream::connect(&target.addr)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+
+        if let Some(password) = &target.password {
+            let auth = match &target.user {
+                Some(user) => encode_command(&[b"AUTH", user.as_bytes(), password.as_bytes()]),
+                None => encode_command(&[b"AUTH", password.as_bytes()]),
+            };
+            if let Some(err) = connection.send(&[auth])?.into_iter().next() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("redis AUTH failed: {}", err),
+                ));
+            }
+        }
+
+        Ok(connection)
+    }
+
+    /// send pipelined commands, returns the error replies
+    fn send(&mut self, commands: &[Vec<u8>]) -> io::Result<Vec<String>> {
+        self.writer.write_all(&commands.concat())?;
+        let mut errors = vec![];
+        for _ in commands {
+            if let Err(err) = read_reply(&mut self.reader)? {
+                errors.push(err);
+            }
+        }
+        Ok(errors)
+    }
+}
+
+/// sends batches of commands over its own connection, reconnecting after errors
+fn send_batches(target: &Target, batches: &Batches<Vec<u8>>) {
+    let mut connection: Option<Connection> = None;
+    batches.send_each(|batch| {
+        if connection.is_none() {
+            connection = Some(Connection::open(target)?);
+        }
+        match connection.as_mut().unwrap().send(batch) {
+            Ok(errors) => {
+                if let Some(err) = errors.first() {
+                    eprintln!(
+                        "Error: redis rejected {} of {} lines: {}",
+                        errors.len(),
+                        batch.len(),
+                        err
+                    );
+                }
+                Ok(())
+            }
+            Err(err) => {
+                connection = None;
+                Err(err)
+            }
+        }
+    });
+}
+
+/// sends lines to a redis stream with XADD or a channel with PUBLISH,
+/// commands are pipelined over `concurrency` connections
+#[derive(Debug)]
+pub struct RedisSink {
+    target: Target,
+    labels: Vec<(String, String)>,
+    sender: BatchSender<Vec<u8>>,
+}
+
+impl RedisSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<RedisSink> {
+        let target = parse_url(url)?;
+        // fail at startup if redis can't be reached
+        Connection::open(&target)?;
+
+        let batching = Batching {
+            size: BATCH_SIZE,
+            wait: None,
+            max_bytes: usize::MAX,
+        };
+        let sender = {
+            let target = target.clone();
+            let destination = format!("redis {}", target.addr);
+            BatchSender::start("redis", destination, batching, options, move |batches| {
+                send_batches(&target, batches)
+            })
+        };
+
+        Ok(RedisSink {
+            target,
+            labels: options.labels.clone(),
+            sender,
+        })
+    }
+}
+
+impl Sink for RedisSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(RedisOutput {
+            name: expand_path_template(&self.target.name, rel_path, "/"),
+            file: rel_path.display().to_string(),
+            mode: self.target.mode,
+            maxlen: self.target.maxlen.map(|maxlen| maxlen.to_string()),
+            labels: self.labels.clone(),
+            commands: self.sender.queue(),
+        })
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.sender.close()
+    }
+
+    fn failures(&self) -> u64 {
+        self.sender.failures()
+    }
+
+    fn errors(&self) -> u64 {
+        self.sender.errors()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        Connection::open(&self.target).map(|_| ())
+    }
+}
+
+#[derive(Debug)]
+struct RedisOutput {
+    /// stream or channel
+    name: String,
+    file: String,
+    mode: Mode,
+    maxlen: Option<String>,
+    labels: Vec<(String, String)>,
+    commands: BatchQueue<Vec<u8>>,
+}
+
+impl SinkOutput for RedisOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let command = match self.mode {
+            Mode::Publish => encode_command(&[b"PUBLISH", self.name.as_bytes(), line.as_bytes()]),
+            Mode::Stream => {
+                let mut args: Vec<&[u8]> = vec![b"XADD", self.name.as_bytes()];
+                if let Some(maxlen) = &self.maxlen {
+                    args.extend_from_slice(&[b"MAXLEN", b"~", maxlen.as_bytes()]);
+                }
+                args.extend_from_slice(&[
+                    b"*",
+                    b"line",
+                    line.as_bytes(),
+                    b"file",
+                    self.file.as_bytes(),
+                ]);
+                for (name, value) in &self.labels {
+                    args.push(name.as_bytes());
+                    args.push(value.as_bytes());
+                }
+                encode_command(&args)
+            }
+        };
+        let size = command.len();
+        self.commands.send(command, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_command, read_reply};
+
+    #[test]
+    fn commands_are_resp_arrays_of_bulk_strings() {
+        assert_eq!(
+            encode_command(&[b"PUBLISH", b"logs", b"a b\r\n"]),
+            b"*3\r\n$7\r\nPUBLISH\r\n$4\r\nlogs\r\n$5\r\na b\r\n\r\n"
+        );
+        assert_eq!(
+            encode_command(&[b"PING", b""]),
+            b"*2\r\n$4\r\nPING\r\n$0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn replies_are_read_whole() {
+        let replies = b"+OK\r\n:3\r\n$5\r\n1-0\r\n\r\n$-1\r\n*2\r\n$1\r\na\r\n-ERR nested\r\n\
+                        -WRONGTYPE not a stream\r\n";
+        let mut reader = &replies[..];
+        for _ in 0..4 {
+            assert_eq!(read_reply(&mut reader).unwrap(), Ok(()));
+        }
+        assert_eq!(
+            read_reply(&mut reader).unwrap(),
+            Err("ERR nested".to_string())
+        );
+        assert_eq!(
+            read_reply(&mut reader).unwrap(),
+            Err("WRONGTYPE not a stream".to_string())
+        );
+        assert!(reader.is_empty());
+        assert!(read_reply(&mut reader).is_err());
+        assert!(read_reply(&mut &b"?\r\n"[..]).is_err());
+    }
+}
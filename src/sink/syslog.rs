@@ -0,0 +1,373 @@
+use super::tls::{self, TlsClient, TlsStream};
+use super::{expand_path_template, Failures, Sink, SinkOptions, SinkOutput, SinkUrl};
+use crate::enrich::hostname;
+use chrono::{SecondsFormat, Utc};
+use std::io::{self, Write};
+use std::net::UdpSocket;
+use std::path::Path;
+use std::sync::Arc;
+
+const DEFAULT_PORT: u16 = 514;
+/// syslog over TLS, RFC 5425
+const DEFAULT_TLS_PORT: u16 = 6514;
+/// example enterprise number from RFC 5424, for labels as structured data
+const DEFAULT_SD_ID: &str = "loggen@32473";
+const PARAMS: &[&str] = &[
+    "facility", "severity", "hostname", "app_name", "procid", "msgid", "sd_id",
+];
+const FACILITIES: &[&str] = &[
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv",
+    "ftp", "ntp", "audit", "alert", "clock", "local0", "local1", "local2", "local3", "local4",
+    "local5", "local6", "local7",
+];
+const SEVERITIES: &[&str] = &[
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+/// other names of levels found in lines, for severity=auto
+const LEVEL_ALIASES: &[(&str, u8)] = &[
+    ("emergency", 0),
+    ("panic", 0),
+    ("fatal", 2),
+    ("critical", 2),
+    ("error", 3),
+    ("warn", 4),
+    ("information", 6),
+    ("trace", 7),
+];
+/// words at the start of a line where severity=auto looks for a level
+const LEVEL_WORDS: usize = 8;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Severity {
+    Fixed(u8),
+    /// from the level in the line, info if it has none
+    Auto,
+}
+
+/// the header fields, templates may have {dir}, {file} and {stem}
+#[derive(Debug, Clone)]
+struct Target {
+    addr: String,
+    facility: u8,
+    severity: Severity,
+    hostname: String,
+    app_name: String,
+    procid: String,
+    msgid: String,
+    sd_id: String,
+}
+
+/// a facility or severity by name or number
+fn code(kind: &str, v: &str, names: &[&str]) -> io::Result<u8> {
+    match v.parse::<u8>() {
+        Ok(code) if (code as usize) < names.len() => Ok(code),
+        _ => names
+            .iter()
+            .position(|name| *name == v)
+            .map(|code| code as u8)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "unknown syslog {} {}, supported: {} or 0 to {}",
+                    kind,
+                    v,
+                    names.join(", "),
+                    names.len() - 1
+                ))
+            }),
+    }
+}
+
+/// an SD-NAME: 1 to 32 printable ascii characters but =, ], " and space
+fn is_sd_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| (33..=126).contains(&b) && !b"=]\"".contains(&b))
+}
+
+/// syslog://host[:port][?facility=&severity=&hostname=&app_name=&procid=&msgid=&sd_id=],
+/// syslog+tls:// takes the certificates too
+fn parse_url(parsed: &SinkUrl) -> io::Result<Target> {
+    let facility = code(
+        "facility",
+        parsed.param("facility").unwrap_or("user"),
+        FACILITIES,
+    )?;
+    let severity = match parsed.param("severity").unwrap_or("info") {
+        "auto" => Severity::Auto,
+        v => Severity::Fixed(code("severity", v, SEVERITIES)?),
+    };
+    let sd_id = parsed.param("sd_id").unwrap_or(DEFAULT_SD_ID).to_string();
+    if !is_sd_name(&sd_id) {
+        return Err(invalid(format!(
+            "sd_id {} should be 1 to 32 printable characters without =, ], \" or spaces",
+            sd_id
+        )));
+    }
+    let param = |key: &str, default: &str| parsed.param(key).unwrap_or(default).to_string();
+
+    Ok(Target {
+        addr: parsed.addr(DEFAULT_PORT),
+        facility,
+        severity,
+        hostname: param("hostname", &hostname()),
+        app_name: param("app_name", "{stem}"),
+        procid: param("procid", "-"),
+        msgid: param("msgid", "-"),
+        sd_id,
+    })
+}
+
+/// a header field: printable ascii without spaces, at most `max` long, - if empty
+fn header_field(v: &str, max: usize) -> String {
+    let field: String = v
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max)
+        .collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+/// the level of a line in its first words, like ERROR, [warn] or level=debug
+fn line_severity(line: &str) -> Option<u8> {
+    line.split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|word| !word.is_empty())
+        .take(LEVEL_WORDS)
+        .find_map(|word| {
+            let word = word.to_ascii_lowercase();
+            SEVERITIES
+                .iter()
+                .position(|name| *name == word)
+                .map(|code| code as u8)
+                .or_else(|| {
+                    LEVEL_ALIASES
+                        .iter()
+                        .find(|(alias, _)| *alias == word)
+                        .map(|(_, code)| *code)
+                })
+        })
+}
+
+/// labels as one SD-ELEMENT, values escaped as RFC 5424 asks
+fn structured_data(sd_id: &str, labels: &[(String, String)], rel_path: &Path) -> String {
+    if labels.is_empty() {
+        return "-".to_string();
+    }
+
+    let mut data = format!("[{}", sd_id);
+    for (name, value) in labels {
+        let value = expand_path_template(value, rel_path, "/")
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace(']', "\\]");
+        data.push_str(&format!(" {}=\"{}\"", name, value));
+    }
+    data.push(']');
+    data
+}
+
+/// sends every line as an RFC 5424 message in its own UDP datagram, or
+/// octet counted over a TLS connection of each output with syslog+tls://, with
+/// the header fields set from the url instead of wrapping the raw line
+#[derive(Debug)]
+pub struct SyslogSink {
+    target: Target,
+    labels: Vec<(String, String)>,
+    /// None for UDP
+    tls: Option<Arc<TlsClient>>,
+    failures: Arc<Failures>,
+}
+
+impl SyslogSink {
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<SyslogSink> {
+        let (target, tls) = if url.starts_with("syslog+tls://") {
+            let parsed = SinkUrl::parse(url, "syslog+tls://")?;
+            parsed.check_params(&[PARAMS, tls::PARAMS].concat())?;
+            let client = TlsClient::new(&parsed, DEFAULT_TLS_PORT, options.tls_rotate)?;
+            let mut target = parse_url(&parsed)?;
+            target.addr = client.addr().to_string();
+            (target, Some(Arc::new(client)))
+        } else {
+            let parsed = SinkUrl::parse(url, "syslog://")?;
+            parsed.check_params(PARAMS)?;
+            (parse_url(&parsed)?, None)
+        };
+        if let Some((name, _)) = options.labels.iter().find(|(name, _)| !is_sd_name(name)) {
+            return Err(invalid(format!(
+                "label {} can't be a syslog structured data name",
+                name
+            )));
+        }
+        // fail at startup if the address doesn't resolve, a TLS server may be
+        // down for now
+        if tls.is_none() {
+            connect(&target.addr)?;
+        }
+
+        Ok(SyslogSink {
+            target,
+            labels: options.labels.clone(),
+            tls,
+            failures: Arc::new(Failures::default()),
+        })
+    }
+}
+
+fn connect(addr: &str) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(if addr.starts_with('[') {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    })?;
+    socket.connect(addr)?;
+    Ok(socket)
+}
+
+impl Sink for SyslogSink {
+    fn output(&self, rel_path: &Path) -> Box<dyn SinkOutput> {
+        let target = &self.target;
+        let field =
+            |template: &str, max| header_field(&expand_path_template(template, rel_path, "/"), max);
+        Box::new(SyslogOutput {
+            addr: target.addr.clone(),
+            transport: match &self.tls {
+                Some(client) => Transport::Tls {
+                    client: client.clone(),
+                    stream: None,
+                },
+                None => Transport::Udp(None),
+            },
+            facility: target.facility,
+            severity: target.severity,
+            header: format!(
+                "{} {} {} {} {}",
+                field(&target.hostname, 255),
+                field(&target.app_name, 48),
+                field(&target.procid, 128),
+                field(&target.msgid, 32),
+                structured_data(&target.sd_id, &self.labels, rel_path)
+            ),
+            failures: self.failures.clone(),
+        })
+    }
+
+    fn failures(&self) -> u64 {
+        self.failures.get()
+    }
+
+    fn errors(&self) -> u64 {
+        self.failures.total()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        if let Some(client) = &self.tls {
+            tls::close(client.connect(client.current()?.0)?);
+        }
+        Ok(())
+    }
+}
+
+/// connected on the first line and again after errors
+#[derive(Debug)]
+enum Transport {
+    Udp(Option<UdpSocket>),
+    /// with the generation of the certificates it was opened with, replaced
+    /// when they rotate
+    Tls {
+        client: Arc<TlsClient>,
+        stream: Option<(Box<TlsStream>, u64)>,
+    },
+}
+
+impl Transport {
+    fn send(&mut self, addr: &str, message: &str) -> io::Result<()> {
+        match self {
+            Transport::Udp(socket) => {
+                if socket.is_none() {
+                    *socket = Some(connect(addr)?);
+                }
+                // refused datagrams are reported on the next send of a connected socket
+                socket
+                    .as_ref()
+                    .unwrap()
+                    .send(message.as_bytes())
+                    .map(|_| ())
+            }
+            Transport::Tls { client, stream } => {
+                let (config, generation) = client.current()?;
+                if stream
+                    .as_ref()
+                    .is_some_and(|(_, opened)| *opened != generation)
+                {
+                    // a new handshake with the next client certificate
+                    tls::close(*stream.take().unwrap().0);
+                }
+                if stream.is_none() {
+                    *stream = Some((Box::new(client.connect(config)?), generation));
+                }
+                // octet counting framing, RFC 5425 section 4.3
+                let tls_stream = &mut stream.as_mut().unwrap().0;
+                write!(tls_stream, "{} {}", message.len(), message)?;
+                tls_stream.flush()
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        match self {
+            Transport::Udp(socket) => *socket = None,
+            Transport::Tls { stream, .. } => *stream = None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SyslogOutput {
+    addr: String,
+    transport: Transport,
+    facility: u8,
+    severity: Severity,
+    /// HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA
+    header: String,
+    failures: Arc<Failures>,
+}
+
+impl SinkOutput for SyslogOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let severity = match self.severity {
+            Severity::Fixed(severity) => severity,
+            Severity::Auto => line_severity(line).unwrap_or(6),
+        };
+        let message = format!(
+            "<{}>1 {} {} {}",
+            self.facility as u16 * 8 + severity as u16,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.header,
+            line
+        );
+
+        match self.transport.send(&self.addr, &message) {
+            Ok(()) => {
+                self.failures.succeeded();
+                Ok(())
+            }
+            Err(err) => {
+                self.failures.failed();
+                self.transport.disconnect();
+                Err(io::Error::new(
+                    err.kind(),
+                    format!("sending to syslog {}: {}", self.addr, err),
+                ))
+            }
+        }
+    }
+}
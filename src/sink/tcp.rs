@@ -0,0 +1,230 @@
+use super::{Failures, Sink, SinkOptions, SinkOutput, SinkUrl};
+use crate::backpressure::{self, FullCounters, FullQueue, WhenFull};
+use crate::parse::parse_kv_list;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// a send blocked this long fails, so a stalled server doesn't stall generators forever
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// when a connection is closed and a new one opened, see --reconnect-every
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reconnect {
+    /// after this many lines
+    pub lines: Option<u64>,
+    /// after it was open this long
+    pub every: Option<Duration>,
+}
+
+impl Reconnect {
+    /// parse "lines=1000", "secs=30" or both like "lines=1000,secs=30"
+    pub fn parse(v: &str) -> Result<Reconnect, String> {
+        let mut reconnect = Reconnect::default();
+        for (key, value) in parse_kv_list(v)? {
+            match key.as_str() {
+                "lines" => match value.parse::<u64>() {
+                    Ok(lines) if lines > 0 => reconnect.lines = Some(lines),
+                    _ => return Err(format!("lines={} isn't a number greater than 0", value)),
+                },
+                "secs" => match value.parse::<f64>() {
+                    Ok(secs) if secs > 0.0 => reconnect.every = Some(Duration::from_secs_f64(secs)),
+                    _ => return Err(format!("secs={} isn't a number greater than 0", value)),
+                },
+                _ => {
+                    return Err(format!(
+                        "unknown reconnect option {}, use lines=COUNT or secs=SECONDS",
+                        key
+                    ))
+                }
+            }
+        }
+        if reconnect.lines.is_none() && reconnect.every.is_none() {
+            return Err(format!("{} needs lines=COUNT or secs=SECONDS", v));
+        }
+        Ok(reconnect)
+    }
+
+    fn is_due(&self, lines: u64, opened: Instant) -> bool {
+        self.lines.is_some_and(|limit| lines >= limit)
+            || self.every.is_some_and(|every| opened.elapsed() >= every)
+    }
+}
+
+pub fn is_reconnect(v: String) -> Result<(), String> {
+    Reconnect::parse(&v).map(|_| ())
+}
+
+/// sends lines as they are, newline terminated, on connections of each generated
+/// file, like many hosts shipping to the same collector
+#[derive(Debug)]
+pub struct TcpSink {
+    addr: SocketAddr,
+    failures: Arc<Failures>,
+    block_on_full: Option<WhenFull>,
+    /// connections of each output, see --connections
+    connections: usize,
+    reconnect: Reconnect,
+}
+
+impl TcpSink {
+    /// tcp://host:port, the host can be an IPv6 address like tcp://[::1]:5140
+    pub fn new(url: &str, options: &SinkOptions) -> io::Result<TcpSink> {
+        let parsed = SinkUrl::parse(url, "tcp://")?;
+        parsed.check_params(&[])?;
+        if parsed.port.is_none() {
+            return Err(invalid(format!("{} needs a port", url)));
+        }
+        // fail at startup if the address doesn't resolve, the server may be down for now
+        let addr = parsed
+            .addr(0)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| invalid(format!("{} doesn't resolve to an address", url)))?;
+
+        Ok(TcpSink {
+            addr,
+            failures: Arc::new(Failures::default()),
+            block_on_full: options.block_on_full,
+            connections: options.connections.max(1),
+            reconnect: options.reconnect,
+        })
+    }
+}
+
+fn connect(addr: &SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(addr, CONNECT_TIMEOUT)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+impl Sink for TcpSink {
+    fn output(&self, _rel_path: &Path) -> Box<dyn SinkOutput> {
+        Box::new(TcpOutput {
+            addr: self.addr,
+            connections: (0..self.connections).map(|_| None).collect(),
+            next: 0,
+            reconnect: self.reconnect,
+            failures: self.failures.clone(),
+            block_on_full: self.block_on_full,
+            full_counters: Arc::new(FullCounters::default()),
+        })
+    }
+
+    fn failures(&self) -> u64 {
+        self.failures.get()
+    }
+
+    fn errors(&self) -> u64 {
+        self.failures.total()
+    }
+
+    fn probe(&self) -> io::Result<()> {
+        connect(&self.addr).map(|_| ())
+    }
+}
+
+/// an open connection of an output
+#[derive(Debug)]
+struct Connection {
+    stream: TcpStream,
+    /// lines the server didn't take yet, with --block-on-full
+    full: Option<FullQueue>,
+    opened: Instant,
+    lines: u64,
+}
+
+#[derive(Debug)]
+struct TcpOutput {
+    addr: SocketAddr,
+    /// lines go to each in turn, connected on their first line and again after
+    /// errors and --reconnect-every
+    connections: Vec<Option<Connection>>,
+    next: usize,
+    reconnect: Reconnect,
+    failures: Arc<Failures>,
+    block_on_full: Option<WhenFull>,
+    full_counters: Arc<FullCounters>,
+}
+
+impl TcpOutput {
+    fn connect(&self) -> io::Result<Connection> {
+        let stream = connect(&self.addr)?;
+        let mut full = None;
+        if let Some(when_full) = self.block_on_full {
+            backpressure::set_nonblocking(&stream)?;
+            full = Some(
+                FullQueue::new(when_full, self.full_counters.clone()).with_timeout(WRITE_TIMEOUT),
+            );
+        }
+        Ok(Connection {
+            stream,
+            full,
+            opened: Instant::now(),
+            lines: 0,
+        })
+    }
+
+    fn send(&mut self, index: usize, line: &str) -> io::Result<()> {
+        if self.connections[index].is_none() {
+            self.connections[index] = Some(self.connect()?);
+        }
+        let connection = self.connections[index].as_mut().unwrap();
+        if let Some(full) = &mut connection.full {
+            let mut terminated = line.as_bytes().to_vec();
+            if !line.ends_with('\n') {
+                terminated.push(b'\n');
+            }
+            full.write(&mut connection.stream, &terminated)?;
+        } else {
+            connection.stream.write_all(line.as_bytes())?;
+            if !line.ends_with('\n') {
+                connection.stream.write_all(b"\n")?;
+            }
+        }
+
+        connection.lines += 1;
+        if self.reconnect.is_due(connection.lines, connection.opened) {
+            // the next line on it opens a new connection, from a new source port,
+            // queued lines that still don't fit are counted as dropped
+            if let Some(full) = &mut connection.full {
+                full.drain(&mut connection.stream).ok();
+            }
+            self.connections[index] = None;
+        }
+        Ok(())
+    }
+}
+
+impl SinkOutput for TcpOutput {
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let index = self.next;
+        self.next = (self.next + 1) % self.connections.len();
+        match self.send(index, line) {
+            Ok(()) => {
+                self.failures.succeeded();
+                Ok(())
+            }
+            Err(err) => {
+                self.failures.failed();
+                self.connections[index] = None;
+                Err(io::Error::new(
+                    err.kind(),
+                    format!("sending to tcp {}: {}", self.addr, err),
+                ))
+            }
+        }
+    }
+
+    fn count_full(&mut self, counters: Arc<FullCounters>) {
+        self.full_counters = counters;
+    }
+}
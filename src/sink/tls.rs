@@ -0,0 +1,234 @@
+use super::SinkUrl;
+use crate::parse::{parse_duration, parse_kv_list};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::convert::TryFrom;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// a send blocked this long fails, so a stalled server doesn't stall generators forever
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// url parameters for the certificates
+pub const PARAMS: &[&str] = &["ca", "cert", "key", "server_name"];
+
+pub type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// parse "every=10m", see --tls-rotate-certs
+pub fn parse_rotate(v: &str) -> Result<Duration, String> {
+    let mut every = None;
+    for (key, value) in parse_kv_list(v)? {
+        match key.as_str() {
+            "every" => match parse_duration(&value)? {
+                duration if duration.is_zero() => {
+                    return Err(format!("every={} should be more than 0", value))
+                }
+                duration => every = Some(duration),
+            },
+            _ => {
+                return Err(format!(
+                    "unknown certificate rotation option {}, use every=DURATION",
+                    key
+                ))
+            }
+        }
+    }
+    every.ok_or_else(|| format!("{} needs every=DURATION", v))
+}
+
+pub fn is_rotate(v: String) -> Result<(), String> {
+    parse_rotate(&v).map(|_| ())
+}
+
+/// the files of the ca and of the client certificates, read again each time
+/// the certificates rotate so replaced files are picked up
+#[derive(Debug, Clone)]
+struct CertFiles {
+    /// the system's roots if None
+    ca: Option<String>,
+    /// certificate chain and key files, used in turn
+    clients: Vec<(String, String)>,
+}
+
+impl CertFiles {
+    /// ca=PATH, cert=PATH[,PATH...] and key=PATH[,PATH...], one key for every
+    /// cert or one for each
+    fn from_url(parsed: &SinkUrl) -> io::Result<CertFiles> {
+        let list = |key: &str| -> Vec<String> {
+            parsed
+                .param(key)
+                .map(|v| v.split(',').map(|path| path.to_string()).collect())
+                .unwrap_or_default()
+        };
+        let (certs, keys) = (list("cert"), list("key"));
+        let clients = match (certs.len(), keys.len()) {
+            (0, 0) => vec![],
+            (0, _) => return Err(invalid("key= needs a cert= to go with".to_string())),
+            (_, 1) => certs
+                .into_iter()
+                .map(|cert| (cert, keys[0].clone()))
+                .collect(),
+            (certs_len, keys_len) if certs_len == keys_len => certs.into_iter().zip(keys).collect(),
+            _ => {
+                return Err(invalid(format!(
+                    "{} client certificates need one key or one for each, not {}",
+                    certs.len(),
+                    keys.len()
+                )))
+            }
+        };
+        Ok(CertFiles {
+            ca: parsed.param("ca").map(|path| path.to_string()),
+            clients,
+        })
+    }
+
+    /// a new client config, with the client certificate at `index`, without
+    /// the sessions of earlier ones so connections do a full handshake
+    fn config(&self, index: usize) -> io::Result<Arc<ClientConfig>> {
+        let pem_error = |path: &str, err: rustls::pki_types::pem::Error| {
+            invalid(format!("reading {}: {}", path, err))
+        };
+        let mut roots = RootCertStore::empty();
+        match &self.ca {
+            Some(path) => {
+                for cert in
+                    CertificateDer::pem_file_iter(path).map_err(|err| pem_error(path, err))?
+                {
+                    roots
+                        .add(cert.map_err(|err| pem_error(path, err))?)
+                        .map_err(|err| invalid(format!("ca certificate in {}: {}", path, err)))?;
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+
+        let builder =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|err| invalid(err.to_string()))?
+                .with_root_certificates(roots);
+        let config = match self.clients.get(index) {
+            Some((cert_path, key_path)) => {
+                let chain = CertificateDer::pem_file_iter(cert_path)
+                    .map_err(|err| pem_error(cert_path, err))?
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| pem_error(cert_path, err))?;
+                let key = PrivateKeyDer::from_pem_file(key_path)
+                    .map_err(|err| pem_error(key_path, err))?;
+                builder
+                    .with_client_auth_cert(chain, key)
+                    .map_err(|err| invalid(format!("client certificate {}: {}", cert_path, err)))?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(Arc::new(config))
+    }
+}
+
+/// the config new connections use, a new one with the next client
+/// certificate every --tls-rotate-certs
+#[derive(Debug)]
+struct Current {
+    config: Arc<ClientConfig>,
+    /// connections made before a rotation are replaced
+    generation: u64,
+    index: usize,
+    since: Instant,
+}
+
+/// where and how a sink connects over TLS, shared by its outputs
+#[derive(Debug)]
+pub struct TlsClient {
+    host: String,
+    addr: String,
+    server_name: ServerName<'static>,
+    files: CertFiles,
+    rotate: Option<Duration>,
+    current: Mutex<Current>,
+}
+
+impl TlsClient {
+    /// the address, certificates and server name from the url, fails if the
+    /// certificates can't be read
+    pub fn new(
+        parsed: &SinkUrl,
+        default_port: u16,
+        rotate: Option<Duration>,
+    ) -> io::Result<TlsClient> {
+        let files = CertFiles::from_url(parsed)?;
+        let name = parsed
+            .param("server_name")
+            .unwrap_or_else(|| parsed.host.trim_start_matches('[').trim_end_matches(']'));
+        let server_name = ServerName::try_from(name.to_string())
+            .map_err(|err| invalid(format!("server name {}: {}", name, err)))?;
+        Ok(TlsClient {
+            host: parsed.host.clone(),
+            addr: parsed.addr(default_port),
+            server_name,
+            current: Mutex::new(Current {
+                config: files.config(0)?,
+                generation: 0,
+                index: 0,
+                since: Instant::now(),
+            }),
+            files,
+            rotate,
+        })
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// the config for new connections and its generation, rotating to the next
+    /// client certificate if it's time
+    pub fn current(&self) -> io::Result<(Arc<ClientConfig>, u64)> {
+        let mut current = self.current.lock().unwrap();
+        if self
+            .rotate
+            .is_some_and(|every| current.since.elapsed() >= every)
+        {
+            let index = (current.index + 1) % self.files.clients.len().max(1);
+            // try again on the next line if the files are being replaced
+            current.config = self.files.config(index)?;
+            current.index = index;
+            current.generation += 1;
+            current.since = Instant::now();
+        }
+        Ok((current.config.clone(), current.generation))
+    }
+
+    /// a connection that finished its handshake with `config`
+    pub fn connect(&self, config: Arc<ClientConfig>) -> io::Result<TlsStream> {
+        let addr = self
+            .addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| invalid(format!("{} doesn't resolve to an address", self.host)))?;
+        let socket = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        socket.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        socket.set_read_timeout(Some(WRITE_TIMEOUT))?;
+        socket.set_nodelay(true)?;
+        let connection =
+            ClientConnection::new(config, self.server_name.clone()).map_err(io::Error::other)?;
+        let mut stream = StreamOwned::new(connection, socket);
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+        Ok(stream)
+    }
+}
+
+/// tell the server the connection ends, so it doesn't see a truncated stream
+pub fn close(mut stream: TlsStream) {
+    stream.conn.send_close_notify();
+    stream.conn.complete_io(&mut stream.sock).ok();
+}
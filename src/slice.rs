@@ -0,0 +1,183 @@
+use crate::source::Source;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// part of each sample file replayed each pass, see --head, --tail and
+/// --lines-range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slice {
+    /// lines `first` to `last`, counted from 1, both included
+    Range { first: u64, last: Option<u64> },
+    /// the last lines
+    Tail(u64),
+}
+
+impl Slice {
+    /// parse "1000..5000", "1000.." or "..5000"
+    pub fn parse_range(v: &str) -> Result<Slice, String> {
+        let (first, last) = v
+            .split_once("..")
+            .ok_or_else(|| format!("{} should be FIRST..LAST, like 1000..5000", v))?;
+        let line = |value: &str| match value.parse::<u64>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(format!("{} isn't a line number, they start at 1", value)),
+        };
+        let first = if first.is_empty() { 1 } else { line(first)? };
+        let last = if last.is_empty() {
+            None
+        } else {
+            Some(line(last)?)
+        };
+        if last.is_some_and(|last| last < first) {
+            return Err(format!("{} ends before it starts", v));
+        }
+        Ok(Slice::Range { first, last })
+    }
+
+    pub fn head(lines: u64) -> Slice {
+        Slice::Range {
+            first: 1,
+            last: Some(lines),
+        }
+    }
+}
+
+impl fmt::Display for Slice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Slice::Range { first, last: None } => write!(f, "lines {}..", first),
+            Slice::Range {
+                first,
+                last: Some(last),
+            } => write!(f, "lines {}..{}", first, last),
+            Slice::Tail(lines) => write!(f, "the last {} lines", lines),
+        }
+    }
+}
+
+pub fn is_lines_range(v: String) -> Result<(), String> {
+    Slice::parse_range(&v).map(|_| ())
+}
+
+/// parse a --head or --tail line count, like 5000 or 10K
+pub fn parse_line_count(v: &str) -> Result<u64, String> {
+    match crate::parse::parse_count(v)? {
+        0 => Err("the line count should be greater than 0".to_string()),
+        lines => Ok(lines),
+    }
+}
+
+pub fn is_line_count(v: String) -> Result<(), String> {
+    parse_line_count(&v).map(|_| ())
+}
+
+/// a source that only gives the lines or records of its slice each pass
+#[derive(Debug)]
+pub struct SliceSource {
+    inner: Box<dyn Source>,
+    slice: Slice,
+    path: PathBuf,
+    /// lines or records read from `inner` this pass
+    position: u64,
+    /// lines or records in the sample, counted on the first read for Tail
+    total: Option<u64>,
+    /// a line was given since the last rewind
+    gave: bool,
+    warned: bool,
+}
+
+impl SliceSource {
+    pub fn new(inner: Box<dyn Source>, slice: Slice, path: PathBuf) -> SliceSource {
+        SliceSource {
+            inner,
+            slice,
+            path,
+            position: 0,
+            total: None,
+            gave: false,
+            warned: false,
+        }
+    }
+
+    /// first and last position to give, counted from 1
+    fn bounds<T>(
+        &mut self,
+        read: fn(&mut dyn Source) -> io::Result<Option<T>>,
+    ) -> io::Result<(u64, Option<u64>)> {
+        match self.slice {
+            Slice::Range { first, last } => Ok((first, last)),
+            Slice::Tail(lines) => {
+                let total = match self.total {
+                    Some(total) => total,
+                    None => {
+                        // counted once, samples don't change while replayed
+                        let mut total = 0;
+                        while read(self.inner.as_mut())?.is_some() {
+                            total += 1;
+                        }
+                        self.inner.rewind()?;
+                        self.position = 0;
+                        self.total = Some(total);
+                        total
+                    }
+                };
+                Ok((total.saturating_sub(lines) + 1, None))
+            }
+        }
+    }
+
+    /// the next line or record of the slice with `read`
+    fn next<T>(
+        &mut self,
+        read: fn(&mut dyn Source) -> io::Result<Option<T>>,
+    ) -> io::Result<Option<T>> {
+        let (first, last) = self.bounds(read)?;
+        loop {
+            if last.is_some_and(|last| self.position >= last) {
+                return Ok(None);
+            }
+            let item = match read(self.inner.as_mut())? {
+                Some(item) => item,
+                None => return Ok(None),
+            };
+            self.position += 1;
+            if self.position >= first {
+                self.gave = true;
+                return Ok(Some(item));
+            }
+        }
+    }
+}
+
+impl Source for SliceSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        self.next(|source| source.read_line())
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.next(|source| source.read_record())
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        if !self.gave && !self.warned {
+            self.warned = true;
+            eprintln!("Warning: {:?} has no {}", self.path, self.slice);
+        }
+        self.position = 0;
+        self.gave = false;
+        self.inner.rewind()
+    }
+
+    fn close(&mut self) {
+        self.inner.close()
+    }
+
+    fn line_number(&self) -> Option<u64> {
+        Some(self.position)
+    }
+
+    fn is_stream(&self) -> bool {
+        self.inner.is_stream()
+    }
+}
@@ -0,0 +1,79 @@
+use crate::parse::{parse_duration, parse_kv_list};
+use rand::Rng;
+use std::time::Duration;
+
+/// how writes are slowed down to emulate a slow disk, see --slow-write
+#[derive(Debug, Clone)]
+pub struct SlowWrite {
+    pub delay: Duration,
+    /// the delay varies up to this much either way
+    pub jitter: Duration,
+    /// one write in this many is delayed
+    pub every: u64,
+}
+
+impl SlowWrite {
+    /// parse "delay=5ms,jitter=2ms,every=100", times without unit are in
+    /// milliseconds
+    pub fn parse(v: &str) -> Result<SlowWrite, String> {
+        let mut delay = None;
+        let mut jitter = Duration::ZERO;
+        let mut every = 1;
+        let duration = |value: &str| match value.parse::<u64>() {
+            Ok(ms) => Ok(Duration::from_millis(ms)),
+            Err(_) => parse_duration(value),
+        };
+
+        for (key, value) in parse_kv_list(v)? {
+            match key.as_str() {
+                "delay" => delay = Some(duration(&value)?),
+                "jitter" => jitter = duration(&value)?,
+                "every" => match value.parse::<u64>() {
+                    Ok(n) if n > 0 => every = n,
+                    _ => return Err(format!("{} isn't a valid write count", value)),
+                },
+                _ => return Err(format!("unknown slow write option {}", key)),
+            }
+        }
+
+        Ok(SlowWrite {
+            delay: delay.ok_or_else(|| "slow write needs delay=ms".to_string())?,
+            jitter,
+            every,
+        })
+    }
+}
+
+pub fn is_slow_write(v: String) -> Result<(), String> {
+    SlowWrite::parse(&v).map(|_| ())
+}
+
+/// counts the writes of one output and says which ones are delayed
+#[derive(Debug)]
+pub struct Staller {
+    config: SlowWrite,
+    writes: u64,
+}
+
+impl Staller {
+    pub fn new(config: SlowWrite) -> Staller {
+        Staller { config, writes: 0 }
+    }
+
+    /// how long the next write pauses, None if it isn't delayed
+    pub fn next_pause(&mut self) -> Option<Duration> {
+        self.writes += 1;
+        if !self.writes.is_multiple_of(self.config.every) {
+            return None;
+        }
+
+        let delay = self.config.delay.as_secs_f64();
+        let jitter = self.config.jitter.as_secs_f64();
+        let pause = if jitter > 0.0 {
+            delay + rand::thread_rng().gen_range(-jitter..=jitter)
+        } else {
+            delay
+        };
+        Some(Duration::from_secs_f64(pause.max(0.0)))
+    }
+}
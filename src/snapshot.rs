@@ -0,0 +1,119 @@
+use crate::manifest::{self, Segment};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// files of an output tree by relative path, see `loggen snapshot`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub files: BTreeMap<String, Segment>,
+}
+
+pub fn take(out_dir: &Path) -> io::Result<Snapshot> {
+    let mut files = BTreeMap::new();
+    for entry in WalkDir::new(out_dir) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let rel_path = entry.path().strip_prefix(out_dir).unwrap_or(entry.path());
+        files.insert(
+            rel_path.display().to_string(),
+            manifest::segment_of(entry.path())?,
+        );
+    }
+
+    Ok(Snapshot { files })
+}
+
+pub fn load(path: &Path) -> io::Result<Snapshot> {
+    serde_json::from_str(&fs::read_to_string(path)?).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} isn't a snapshot: {}", path.display(), err),
+        )
+    })
+}
+
+/// how a file changed between two snapshots
+#[derive(Debug, Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum Change {
+    Added {
+        path: String,
+        lines: u64,
+        bytes: u64,
+    },
+    Removed {
+        path: String,
+        lines: u64,
+        bytes: u64,
+    },
+    /// lines and bytes are the difference, negative if the file shrank
+    Changed {
+        path: String,
+        lines: i64,
+        bytes: i64,
+    },
+}
+
+impl Change {
+    fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Changed { path, .. } => path,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Change::Added { path, lines, bytes } => {
+                format!("+ {} ({} lines, {} bytes)", path, lines, bytes)
+            }
+            Change::Removed { path, lines, bytes } => {
+                format!("- {} ({} lines, {} bytes)", path, lines, bytes)
+            }
+            Change::Changed { path, lines, bytes } => {
+                format!("~ {} ({:+} lines, {:+} bytes)", path, lines, bytes)
+            }
+        }
+    }
+}
+
+/// changes from `before` to `after`, in path order
+pub fn diff(before: &Snapshot, after: &Snapshot) -> Vec<Change> {
+    let mut changes = vec![];
+    for (path, old) in &before.files {
+        match after.files.get(path) {
+            None => changes.push(Change::Removed {
+                path: path.clone(),
+                lines: old.lines,
+                bytes: old.bytes,
+            }),
+            Some(new) if new != old => changes.push(Change::Changed {
+                path: path.clone(),
+                lines: new.lines as i64 - old.lines as i64,
+                bytes: new.bytes as i64 - old.bytes as i64,
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for (path, new) in &after.files {
+        if !before.files.contains_key(path) {
+            changes.push(Change::Added {
+                path: path.clone(),
+                lines: new.lines,
+                bytes: new.bytes,
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    changes
+}
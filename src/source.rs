@@ -0,0 +1,378 @@
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+/// what to generate from: a directory tree of samples or a source url
+#[derive(Debug, Clone, Copy)]
+pub enum Input<'a> {
+    Dir(&'a str),
+    Url(&'a str),
+    /// built in generator, see synth::FORMATS
+    Format(&'a str),
+}
+
+/// open a non directory input, returns the source and the output path
+/// relative to the output base directory, `message_column` is the column that
+/// has the line for table inputs
+pub fn open_url(url: &str, message_column: &str) -> io::Result<(Box<dyn Source>, PathBuf)> {
+    if url.starts_with("kafka://") {
+        return open_kafka(url);
+    } else if url.starts_with("sqlite:") {
+        return open_sqlite(url, message_column);
+    } else if url.starts_with("parquet:") {
+        return open_parquet(url, message_column);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "unsupported input {}, supported: kafka://, sqlite:, parquet:",
+            url
+        ),
+    ))
+}
+
+#[cfg(not(all(feature = "kafka", feature = "sqlite", feature = "parquet")))]
+fn not_built_with(feature: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "loggen was built without {0} support, build it with --features {0}",
+            feature
+        ),
+    )
+}
+
+#[cfg(any(feature = "kafka", feature = "sqlite", feature = "parquet"))]
+fn invalid_url(err: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+/// output file named after the input file, with a .log extension
+#[cfg(any(feature = "sqlite", feature = "parquet"))]
+fn rel_path_for(path: &std::path::Path) -> PathBuf {
+    PathBuf::from(path.file_name().unwrap_or_default()).with_extension("log")
+}
+
+#[cfg(feature = "kafka")]
+fn open_kafka(url: &str) -> io::Result<(Box<dyn Source>, PathBuf)> {
+    let (brokers, topic) = crate::kafka::parse_url(url).map_err(invalid_url)?;
+    let rel_path = PathBuf::from(format!("{}.log", topic));
+    let source = crate::kafka::KafkaSource::new(brokers, topic)?;
+    Ok((Box::new(source), rel_path))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn open_kafka(_url: &str) -> io::Result<(Box<dyn Source>, PathBuf)> {
+    Err(not_built_with("kafka"))
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(url: &str, message_column: &str) -> io::Result<(Box<dyn Source>, PathBuf)> {
+    let (path, table) = crate::sqlite::parse_url(url).map_err(invalid_url)?;
+    let rel_path = rel_path_for(&path);
+    let source = crate::sqlite::SqliteSource::new(path, &table, message_column)?;
+    Ok((Box::new(source), rel_path))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(_url: &str, _message_column: &str) -> io::Result<(Box<dyn Source>, PathBuf)> {
+    Err(not_built_with("sqlite"))
+}
+
+#[cfg(feature = "parquet")]
+fn open_parquet(url: &str, message_column: &str) -> io::Result<(Box<dyn Source>, PathBuf)> {
+    let path = crate::parquet::parse_url(url).map_err(invalid_url)?;
+    let rel_path = rel_path_for(&path);
+    let source = crate::parquet::ParquetSource::new(path, message_column)?;
+    Ok((Box::new(source), rel_path))
+}
+
+#[cfg(not(feature = "parquet"))]
+fn open_parquet(_url: &str, _message_column: &str) -> io::Result<(Box<dyn Source>, PathBuf)> {
+    Err(not_built_with("parquet"))
+}
+
+/// where sample lines come from
+pub trait Source: Debug + Send {
+    /// next line including its line ending, None when the end was reached
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+
+    /// next binary record without its length prefix, None when the end was
+    /// reached, only for sources of framed samples, see --records
+    fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "this input can't be read as binary records",
+        ))
+    }
+
+    /// start again from the first line
+    fn rewind(&mut self) -> io::Result<()>;
+
+    /// release any file descriptors, the source is reopened on next read
+    fn close(&mut self) {}
+
+    /// number of the last line read in the sample, from 1, for sources that
+    /// skip lines, the others are counted as they're read
+    fn line_number(&self) -> Option<u64> {
+        None
+    }
+
+    /// lines written at the start of every output file, when it's created and
+    /// again after rotations, like the directives of W3C extended logs
+    fn header(&mut self) -> Option<String> {
+        None
+    }
+
+    /// true for sources that never end, like pipes, for them None from
+    /// read_line means no line is available yet and they aren't wrapped
+    fn is_stream(&self) -> bool {
+        false
+    }
+}
+
+pub fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut buf = String::new();
+    match reader.read_line(&mut buf) {
+        Ok(len) => {
+            if len == 0 {
+                Ok(None)
+            } else {
+                Ok(Some(buf))
+            }
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// sample file, opened lazily and reopened at the same offset after close
+#[derive(Debug)]
+pub struct FileSource {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    offset: u64,
+    /// reader buffer size, the std default if None
+    capacity: Option<usize>,
+}
+
+impl FileSource {
+    pub fn new(path: PathBuf) -> FileSource {
+        FileSource::with_capacity(path, None)
+    }
+
+    pub fn with_capacity(path: PathBuf, capacity: Option<usize>) -> FileSource {
+        FileSource {
+            path,
+            reader: None,
+            offset: 0,
+            capacity,
+        }
+    }
+
+    fn reader(&mut self) -> io::Result<&mut BufReader<File>> {
+        if self.reader.is_none() {
+            let file = File::open(&self.path)?;
+            let mut reader = match self.capacity {
+                Some(capacity) => BufReader::with_capacity(capacity, file),
+                None => BufReader::new(file),
+            };
+            reader.seek(SeekFrom::Start(self.offset))?;
+            self.reader = Some(reader);
+        }
+
+        Ok(self.reader.as_mut().unwrap())
+    }
+}
+
+impl Source for FileSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let line = read_line(self.reader()?)?;
+        if let Some(line) = &line {
+            self.offset += line.len() as u64;
+        }
+
+        Ok(line)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        match &mut self.reader {
+            Some(reader) => reader.seek(SeekFrom::Start(0)).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn close(&mut self) {
+        self.reader = None;
+    }
+}
+
+/// sample file served from a memory map, lines are read without syscalls and
+/// a rewind is an offset reset
+#[derive(Debug)]
+pub struct MmapSource {
+    path: PathBuf,
+    // None for empty files, they can't be mapped
+    map: Option<Option<memmap2::Mmap>>,
+    offset: usize,
+}
+
+impl MmapSource {
+    pub fn new(path: PathBuf) -> MmapSource {
+        MmapSource {
+            path,
+            map: None,
+            offset: 0,
+        }
+    }
+
+    fn map(&mut self) -> io::Result<&[u8]> {
+        if self.map.is_none() {
+            let file = File::open(&self.path)?;
+            let map = if file.metadata()?.len() == 0 {
+                None
+            } else {
+                // safety: samples aren't expected to change while loggen runs,
+                // if one is truncated reads past its end fault
+                Some(unsafe { memmap2::Mmap::map(&file)? })
+            };
+            self.map = Some(map);
+        }
+
+        Ok(match &self.map {
+            Some(Some(map)) => &map[..],
+            _ => &[],
+        })
+    }
+}
+
+impl Source for MmapSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let offset = self.offset;
+        let data = &self.map()?[offset..];
+        if data.is_empty() {
+            return Ok(None);
+        }
+
+        let len = data
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|pos| pos + 1)
+            .unwrap_or(data.len());
+        let line = String::from_utf8(data[..len].to_vec()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            )
+        })?;
+        self.offset += len;
+        Ok(Some(line))
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+/// bytes read from a pipe at a time
+const PIPE_CHUNK: usize = 8192;
+
+/// named pipe in the input tree, read without blocking as lines arrive, it's
+/// never wrapped and keeps waiting for a new writer when one disconnects
+#[derive(Debug)]
+pub struct PipeSource {
+    path: PathBuf,
+    file: Option<File>,
+    /// bytes of a line that hasn't arrived complete yet
+    pending: Vec<u8>,
+    /// a writer sent data since the last disconnect
+    connected: bool,
+}
+
+impl PipeSource {
+    pub fn new(path: PathBuf) -> PipeSource {
+        PipeSource {
+            path,
+            file: None,
+            pending: vec![],
+            connected: false,
+        }
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            // non blocking so opening doesn't wait for a writer and reads
+            // don't stall the other files of the worker
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)?;
+            self.file = Some(file);
+        }
+
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    fn take_line(&mut self, len: usize) -> io::Result<String> {
+        let line: Vec<u8> = self.pending.drain(..len).collect();
+        String::from_utf8(line).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            )
+        })
+    }
+}
+
+impl Source for PipeSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut chunk = [0; PIPE_CHUNK];
+        loop {
+            if let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+                return self.take_line(pos + 1).map(Some);
+            }
+
+            match self.file()?.read(&mut chunk) {
+                Ok(0) => {
+                    // no writer, the next one to open the pipe continues the stream
+                    if self.connected {
+                        self.connected = false;
+                        eprintln!(
+                            "{}: writer disconnected, waiting for a new one",
+                            self.path.display()
+                        );
+                        if !self.pending.is_empty() {
+                            self.pending.push(b'\n');
+                            let len = self.pending.len();
+                            return self.take_line(len).map(Some);
+                        }
+                    }
+                    return Ok(None);
+                }
+                Ok(len) => {
+                    self.connected = true;
+                    self.pending.extend_from_slice(&chunk[..len]);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        // kept open, closing the read end would make a connected writer fail with EPIPE
+    }
+
+    fn is_stream(&self) -> bool {
+        true
+    }
+}
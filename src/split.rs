@@ -0,0 +1,108 @@
+use crate::parse::{parse_kv_list, parse_size};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitStrategy {
+    /// split exactly at the limit (on a character boundary)
+    Hard,
+    /// split at the last whitespace before the limit, hard if there's none
+    Word,
+}
+
+/// split lines longer than `max` bytes into several lines, all but the last
+/// end with `marker` so the pieces can be told apart from whole lines
+#[derive(Debug, Clone)]
+pub struct SplitConfig {
+    pub max: usize,
+    pub strategy: SplitStrategy,
+    pub marker: String,
+}
+
+impl SplitConfig {
+    /// parse "max=16KB strategy=word marker=\", options can also be separated by commas
+    pub fn parse(v: &str) -> Result<SplitConfig, String> {
+        let mut config = SplitConfig {
+            max: 0,
+            strategy: SplitStrategy::Hard,
+            marker: "\\".to_string(),
+        };
+        let normalized = v.split_whitespace().collect::<Vec<_>>().join(",");
+        for (key, value) in parse_kv_list(&normalized)? {
+            match key.as_str() {
+                "max" => config.max = parse_size(&value)? as usize,
+                "strategy" => {
+                    config.strategy = match value.as_str() {
+                        "hard" => SplitStrategy::Hard,
+                        "word" => SplitStrategy::Word,
+                        _ => {
+                            return Err(format!(
+                                "unknown split strategy {}, use hard or word",
+                                value
+                            ))
+                        }
+                    }
+                }
+                "marker" => config.marker = value,
+                _ => return Err(format!("unknown split option {}", key)),
+            }
+        }
+
+        if config.max == 0 {
+            return Err("split needs max=SIZE".to_string());
+        }
+        if config.marker.len() >= config.max {
+            return Err(format!(
+                "split marker {:?} must be shorter than max={}",
+                config.marker, config.max
+            ));
+        }
+
+        Ok(config)
+    }
+
+    /// byte index where the next piece of `text` ends, leaving room for the marker
+    fn cut(&self, text: &str) -> usize {
+        let mut end = self.max - self.marker.len();
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        if self.strategy == SplitStrategy::Word {
+            if let Some(pos) = text[..end].rfind(char::is_whitespace) {
+                if pos > 0 {
+                    // the whitespace stays at the end of this piece
+                    return pos + text[pos..].chars().next().unwrap().len_utf8();
+                }
+            }
+        }
+
+        // a character wider than the room left still has to go somewhere
+        if end == 0 {
+            text.chars().next().map(char::len_utf8).unwrap_or(0)
+        } else {
+            end
+        }
+    }
+
+    /// the pieces to write for `line`, each with the line's ending
+    pub fn split(&self, line: &str) -> Vec<String> {
+        let content = line.trim_end_matches(['\r', '\n']);
+        if content.len() <= self.max {
+            return vec![line.to_string()];
+        }
+
+        let ending = &line[content.len()..];
+        let mut pieces = vec![];
+        let mut rest = content;
+        while rest.len() > self.max {
+            let end = self.cut(rest);
+            pieces.push(format!("{}{}{}", &rest[..end], self.marker, ending));
+            rest = &rest[end..];
+        }
+        pieces.push(format!("{}{}", rest, ending));
+        pieces
+    }
+}
+
+pub fn is_split_config(v: String) -> Result<(), String> {
+    SplitConfig::parse(&v).map(|_| ())
+}
@@ -0,0 +1,115 @@
+use crate::source::Source;
+use rusqlite::Connection;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+const BATCH_SIZE: i64 = 1000;
+
+/// parse "sqlite:events.db?table=events" into (path, table)
+pub fn parse_url(url: &str) -> Result<(PathBuf, String), String> {
+    let rest = match url.strip_prefix("sqlite:") {
+        Some(rest) => rest,
+        None => return Err(format!("{} isn't a sqlite: url", url)),
+    };
+
+    match rest.find("?table=") {
+        Some(pos) if pos > 0 => Ok((
+            PathBuf::from(&rest[..pos]),
+            rest[pos + "?table=".len()..].to_string(),
+        )),
+        _ => Err(format!("{} should be sqlite:path?table=name", url)),
+    }
+}
+
+fn quote_identifier(name: &str) -> Result<String, String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Ok(format!("\"{}\"", name))
+    } else {
+        Err(format!("{} isn't a valid table or column name", name))
+    }
+}
+
+fn to_io_error(err: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("sqlite: {}", err))
+}
+
+/// reads a column of a table in rowid order, a batch of rows at a time
+pub struct SqliteSource {
+    connection: Connection,
+    query: String,
+    last_rowid: i64,
+    pending: VecDeque<String>,
+}
+
+impl fmt::Debug for SqliteSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteSource")
+            .field("query", &self.query)
+            .field("last_rowid", &self.last_rowid)
+            .finish()
+    }
+}
+
+impl SqliteSource {
+    pub fn new(path: PathBuf, table: &str, column: &str) -> io::Result<SqliteSource> {
+        let invalid = |err| io::Error::new(io::ErrorKind::InvalidInput, err);
+        let query = format!(
+            "SELECT rowid, {} FROM {} WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            quote_identifier(column).map_err(invalid)?,
+            quote_identifier(table).map_err(invalid)?
+        );
+        let connection =
+            Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(to_io_error)?;
+
+        // report a wrong table or column at startup
+        connection.prepare(&query).map_err(to_io_error)?;
+
+        Ok(SqliteSource {
+            connection,
+            query,
+            last_rowid: i64::MIN,
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn fetch(&mut self) -> rusqlite::Result<()> {
+        let mut statement = self.connection.prepare_cached(&self.query)?;
+        let mut rows = statement.query((self.last_rowid, BATCH_SIZE))?;
+        while let Some(row) = rows.next()? {
+            self.last_rowid = row.get(0)?;
+            let value: rusqlite::types::Value = row.get(1)?;
+            let mut line = match value {
+                rusqlite::types::Value::Null => String::new(),
+                rusqlite::types::Value::Integer(v) => v.to_string(),
+                rusqlite::types::Value::Real(v) => v.to_string(),
+                rusqlite::types::Value::Text(v) => v,
+                rusqlite::types::Value::Blob(v) => String::from_utf8_lossy(&v).to_string(),
+            };
+            if !line.ends_with('\n') {
+                line.push('\n');
+            }
+            self.pending.push_back(line);
+        }
+
+        Ok(())
+    }
+}
+
+impl Source for SqliteSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if self.pending.is_empty() {
+            self.fetch().map_err(to_io_error)?;
+        }
+
+        Ok(self.pending.pop_front())
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.last_rowid = i64::MIN;
+        self.pending.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use crate::parse::unknown_value;
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// the last part of the wait is spent spinning, sleeps can overshoot by more
+const SPIN: Duration = Duration::from_millis(2);
+/// longest sleep with ntp-tolerant, so clock adjustments and stop requests are noticed
+const MAX_STEP: Duration = Duration::from_millis(500);
+
+/// how the wait for --start-at follows the wall clock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    /// read the wall clock once and wait on the monotonic clock
+    Monotonic,
+    /// read the wall clock again on every step so steps and slews made by NTP
+    /// while waiting move the start with it
+    NtpTolerant,
+}
+
+impl SyncMode {
+    pub const NAMES: &'static [&'static str] = &["monotonic", "ntp-tolerant"];
+}
+
+impl FromStr for SyncMode {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<SyncMode, String> {
+        match v {
+            "monotonic" => Ok(SyncMode::Monotonic),
+            "ntp-tolerant" => Ok(SyncMode::NtpTolerant),
+            _ => Err(unknown_value("sync mode", v, SyncMode::NAMES)),
+        }
+    }
+}
+
+/// when to start writing, see --start-at
+#[derive(Debug, Clone)]
+pub struct StartAt {
+    pub at: DateTime<Utc>,
+    pub sync: SyncMode,
+}
+
+pub fn parse_start_at(v: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(v)
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|err| {
+            format!(
+                "{} isn't an RFC 3339 time like 2024-05-01T12:00:00Z: {}",
+                v, err
+            )
+        })
+}
+
+pub fn is_start_at(v: String) -> Result<(), String> {
+    parse_start_at(&v).map(|_| ())
+}
+
+/// time left until `at` by the wall clock, None if it passed
+fn until(at: DateTime<Utc>) -> Option<Duration> {
+    (at - Utc::now())
+        .to_std()
+        .ok()
+        .filter(|left| !left.is_zero())
+}
+
+impl StartAt {
+    /// block until the start time, returns early when stopped
+    pub fn wait(&self, stop: &AtomicBool) {
+        let left = match until(self.at) {
+            Some(left) => left,
+            None => {
+                let late = Utc::now() - self.at;
+                eprintln!(
+                    "Warning: start time {} passed {}ms ago, starting now",
+                    self.at.to_rfc3339(),
+                    late.num_milliseconds()
+                );
+                return;
+            }
+        };
+        println!(
+            "waiting {:.3}s to start at {}",
+            left.as_secs_f64(),
+            self.at.to_rfc3339()
+        );
+
+        match self.sync {
+            SyncMode::Monotonic => {
+                let deadline = Instant::now() + left;
+                while !stop.load(Ordering::Relaxed) {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        break;
+                    } else if left > SPIN {
+                        sleep((left - SPIN).min(MAX_STEP));
+                    } else {
+                        std::hint::spin_loop();
+                    }
+                }
+            }
+            SyncMode::NtpTolerant => {
+                while !stop.load(Ordering::Relaxed) {
+                    match until(self.at) {
+                        None => break,
+                        // sleep half of what's left so an adjustment is never overslept
+                        Some(left) if left > SPIN => {
+                            sleep(((left - SPIN) / 2).clamp(Duration::from_micros(100), MAX_STEP))
+                        }
+                        Some(_) => std::hint::spin_loop(),
+                    }
+                }
+            }
+        }
+
+        if !stop.load(Ordering::Relaxed) {
+            let late = Utc::now() - self.at;
+            println!(
+                "started {:.3}ms after {}",
+                late.num_microseconds().unwrap_or(0) as f64 / 1000.0,
+                self.at.to_rfc3339()
+            );
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use crate::backpressure::FullCounters;
+use crate::pacer::Pacer;
+use crate::profile::IoCounters;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// counters for a single generated file, updated by the worker that owns it
+#[derive(Debug)]
+pub struct FileStats {
+    pub path_in: PathBuf,
+    pub rel_path: PathBuf,
+    pub size: u64,
+    offset: AtomicU64,
+    lines: AtomicU64,
+    bytes: AtomicU64,
+    wraps: AtomicU64,
+    errors: AtomicU64,
+    /// see --profile-io
+    pub io: IoCounters,
+    created: Instant,
+    /// when the current pass started and the lines written before it, for
+    /// --warn-fast-wrap
+    pass_started_ms: AtomicU64,
+    pass_lines: AtomicU64,
+    last_pass_ms: AtomicU64,
+    /// passes shorter than --warn-fast-wrap
+    fast_wraps: AtomicU64,
+    /// lines dropped by --throttle
+    throttled: AtomicU64,
+    /// worker that read the file last, usize::MAX before the first read
+    worker: AtomicUsize,
+    /// how late the last read was, for --state-dir
+    behind_ms: AtomicU64,
+    last_error: Mutex<Option<(DateTime<Utc>, String)>>,
+    /// changes asked for this file while running
+    pub control: FileControl,
+    /// lines dropped and time blocked on a full pipe or socket, see --block-on-full
+    pub full: Arc<FullCounters>,
+}
+
+/// how long a paused file waits before it's looked at again
+const PAUSED_POLL: Duration = Duration::from_millis(100);
+
+/// pauses, rates and rotations of a single file asked for by `loggen shell`
+#[derive(Debug, Default)]
+pub struct FileControl {
+    paused: AtomicBool,
+    /// lines per second of this file alone, on top of --rate and --group-rate
+    pacer: Mutex<Option<Arc<Pacer>>>,
+    rotate: AtomicBool,
+}
+
+impl FileControl {
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// limit the file to `rate` lines per second, None removes the limit
+    pub fn set_rate(&self, rate: Option<f64>) {
+        *self.pacer.lock().unwrap() = rate.map(|rate| Arc::new(Pacer::new(Some(rate))));
+    }
+
+    pub fn rate(&self) -> Option<f64> {
+        self.pacer
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|pacer| pacer.rate())
+    }
+
+    /// take a slot to write if not paused, otherwise how long until it may
+    pub fn wait(&self) -> Option<Duration> {
+        if self.is_paused() {
+            return Some(PAUSED_POLL);
+        }
+        self.pacer
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|pacer| pacer.try_take().err())
+    }
+
+    /// give back the slot taken by the last wait
+    pub fn refund(&self) {
+        if let Some(pacer) = self.pacer.lock().unwrap().as_ref() {
+            pacer.refund();
+        }
+    }
+
+    pub fn request_rotate(&self) {
+        self.rotate.store(true, Ordering::Relaxed);
+    }
+
+    /// true once after each request
+    pub fn take_rotate(&self) -> bool {
+        self.rotate.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// how long a pass over the sample took and how many lines it wrote
+#[derive(Debug, Clone, Copy)]
+pub struct PassTime {
+    pub duration: Duration,
+    pub lines: u64,
+}
+
+impl FileStats {
+    pub fn new(path_in: PathBuf, rel_path: PathBuf, size: u64) -> FileStats {
+        FileStats {
+            path_in,
+            rel_path,
+            size,
+            offset: AtomicU64::new(0),
+            lines: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            wraps: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            io: IoCounters::default(),
+            created: Instant::now(),
+            pass_started_ms: AtomicU64::new(0),
+            pass_lines: AtomicU64::new(0),
+            last_pass_ms: AtomicU64::new(0),
+            fast_wraps: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            worker: AtomicUsize::new(usize::MAX),
+            behind_ms: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+            control: FileControl::default(),
+            full: Arc::new(FullCounters::default()),
+        }
+    }
+
+    pub fn read(&self, len: u64) {
+        self.offset.fetch_add(len, Ordering::Relaxed);
+    }
+
+    pub fn written(&self, len: u64) {
+        self.lines.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(len, Ordering::Relaxed);
+    }
+
+    /// a whole pass copied at once
+    pub fn written_pass(&self, lines: u64, bytes: u64) {
+        self.lines.fetch_add(lines, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// the pass that just ended, the first one is counted from registration
+    pub fn wrapped(&self) -> PassTime {
+        self.offset.store(0, Ordering::Relaxed);
+        self.wraps.fetch_add(1, Ordering::Relaxed);
+
+        let now_ms = self.created.elapsed().as_millis() as u64;
+        let started_ms = self.pass_started_ms.swap(now_ms, Ordering::Relaxed);
+        let lines = self.lines.load(Ordering::Relaxed);
+        let pass_ms = now_ms.saturating_sub(started_ms);
+        self.last_pass_ms.store(pass_ms, Ordering::Relaxed);
+        PassTime {
+            duration: Duration::from_millis(pass_ms),
+            lines: lines - self.pass_lines.swap(lines, Ordering::Relaxed),
+        }
+    }
+
+    pub fn fast_wrap(&self) {
+        self.fast_wraps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn throttled(&self) {
+        self.throttled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// count the error and print `message`
+    pub fn error(&self, message: String) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        eprintln!("{}", message);
+        *self.last_error.lock().unwrap() = Some((Utc::now(), message));
+    }
+
+    pub fn last_error(&self) -> Option<(DateTime<Utc>, String)> {
+        self.last_error.lock().unwrap().clone()
+    }
+
+    /// `worker` is reading the file `behind` after it was due
+    pub fn scheduled(&self, worker: usize, behind: Duration) {
+        self.worker.store(worker, Ordering::Relaxed);
+        self.behind_ms
+            .store(behind.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn worker(&self) -> Option<usize> {
+        Some(self.worker.load(Ordering::Relaxed)).filter(|worker| *worker != usize::MAX)
+    }
+
+    pub fn behind_ms(&self) -> u64 {
+        self.behind_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> FileStatus {
+        let offset = self.offset.load(Ordering::Relaxed);
+        FileStatus {
+            path_in: self.path_in.display().to_string(),
+            path_out: self.rel_path.display().to_string(),
+            size: self.size,
+            offset,
+            progress: if self.size == 0 {
+                0.0
+            } else {
+                offset as f64 / self.size as f64
+            },
+            lines: self.lines.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            wraps: self.wraps.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            last_pass_ms: self.last_pass_ms.load(Ordering::Relaxed),
+            fast_wraps: self.fast_wraps.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            dropped_on_full: self.full.dropped_lines(),
+            blocked_on_full_ms: self.full.blocked_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileStatus {
+    pub path_in: String,
+    pub path_out: String,
+    pub size: u64,
+    pub offset: u64,
+    pub progress: f64,
+    pub lines: u64,
+    pub bytes: u64,
+    pub wraps: u64,
+    pub errors: u64,
+    /// how long the last pass over the sample took, 0 before the first wrap
+    pub last_pass_ms: u64,
+    pub fast_wraps: u64,
+    pub throttled: u64,
+    /// lines dropped because the pipe or socket was full
+    pub dropped_on_full: u64,
+    /// time waiting for the pipe or socket to take lines
+    pub blocked_on_full_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub ready: bool,
+    pub uptime_secs: u64,
+    pub files: Vec<FileStatus>,
+}
+
+/// stats for every generated file, shared between workers and reporters
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    ready: AtomicBool,
+    files: Mutex<Vec<Arc<FileStats>>>,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats {
+            started: Instant::now(),
+            ready: AtomicBool::new(false),
+            files: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn register(&self, file_stats: FileStats) -> Arc<FileStats> {
+        let file_stats = Arc::new(file_stats);
+        self.files.lock().unwrap().push(file_stats.clone());
+        file_stats
+    }
+
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn files(&self) -> Vec<Arc<FileStats>> {
+        self.files.lock().unwrap().clone()
+    }
+
+    /// lines written to all files
+    pub fn lines(&self) -> u64 {
+        self.files
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|file_stats| file_stats.lines.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// errors of all files
+    pub fn errors(&self) -> u64 {
+        self.files
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|file_stats| file_stats.errors.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            ready: self.is_ready(),
+            uptime_secs: self.uptime().as_secs(),
+            files: self
+                .files
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|file_stats| file_stats.status())
+                .collect(),
+        }
+    }
+}
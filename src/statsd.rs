@@ -0,0 +1,177 @@
+use crate::stats::Stats;
+use std::collections::HashMap;
+use std::io;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// metrics per datagram are kept under this so they aren't fragmented
+const MAX_DATAGRAM: usize = 1432;
+/// counters pushed for every file, with the name of their FileStatus field
+const COUNTERS: &[&str] = &[
+    "lines",
+    "bytes",
+    "errors",
+    "wraps",
+    "fast_wraps",
+    "throttled",
+    "dropped_on_full",
+    "blocked_on_full_ms",
+];
+
+/// a tag value without the characters that separate tags and metric fields
+fn tag_value(v: &str) -> String {
+    v.chars()
+        .map(|c| {
+            if matches!(c, ',' | '|' | '#' | '\n') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// pushes the counters of every file as DogStatsD counters tagged with the file
+#[derive(Debug)]
+struct Statsd {
+    addr: String,
+    socket: UdpSocket,
+    prefix: String,
+    /// what was pushed last by file and counter, only the increase is sent
+    pushed: HashMap<(String, &'static str), u64>,
+}
+
+impl Statsd {
+    fn connect(addr: &str, prefix: &str) -> io::Result<Statsd> {
+        let socket = UdpSocket::bind(if addr.starts_with('[') {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        })?;
+        socket.connect(addr)?;
+        Ok(Statsd {
+            addr: addr.to_string(),
+            socket,
+            prefix: prefix.to_string(),
+            pushed: HashMap::new(),
+        })
+    }
+
+    fn push(&mut self, stats: &Stats) -> io::Result<()> {
+        let mut metrics = vec![];
+        for status in stats.status().files {
+            let values = [
+                status.lines,
+                status.bytes,
+                status.errors,
+                status.wraps,
+                status.fast_wraps,
+                status.throttled,
+                status.dropped_on_full,
+                status.blocked_on_full_ms,
+            ];
+            for (name, value) in COUNTERS.iter().zip(values) {
+                let pushed = self
+                    .pushed
+                    .entry((status.path_out.clone(), name))
+                    .or_insert(0);
+                if value > *pushed {
+                    metrics.push(format!(
+                        "{}.{}:{}|c|#file:{},sample:{}",
+                        self.prefix,
+                        name,
+                        value - *pushed,
+                        tag_value(&status.path_out),
+                        tag_value(&status.path_in)
+                    ));
+                    *pushed = value;
+                }
+            }
+        }
+
+        let mut datagram = String::new();
+        for metric in metrics {
+            if !datagram.is_empty() && datagram.len() + 1 + metric.len() > MAX_DATAGRAM {
+                self.send(&datagram)?;
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(&metric);
+        }
+        if !datagram.is_empty() {
+            self.send(&datagram)?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, datagram: &str) -> io::Result<()> {
+        self.socket
+            .send(datagram.as_bytes())
+            .map(|_| ())
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("sending to statsd {}: {}", self.addr, err),
+                )
+            })
+    }
+}
+
+/// the thread pushing to statsd, see --statsd
+#[derive(Debug)]
+pub struct Pusher {
+    done: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Pusher {
+    /// push what was written since the last push and stop, call once the
+    /// workers finished so their last lines are counted
+    pub fn finish(self) {
+        self.done.store(true, Ordering::Relaxed);
+        if self.handle.join().is_err() {
+            eprintln!("Error in statsd thread");
+        }
+    }
+}
+
+/// push the counters of every file in `stats` to the statsd server at `addr`
+/// every `interval` from a background thread
+pub fn start(
+    addr: &str,
+    prefix: &str,
+    interval: Duration,
+    stats: Arc<Stats>,
+) -> io::Result<Pusher> {
+    let mut statsd = Statsd::connect(addr, prefix)?;
+    let done = Arc::new(AtomicBool::new(false));
+    let thread_done = done.clone();
+
+    let handle = thread::spawn(move || {
+        let mut next = Instant::now() + interval;
+        loop {
+            let finishing = thread_done.load(Ordering::Relaxed);
+            if finishing || Instant::now() >= next {
+                // a server that isn't listening yet shouldn't stop the pushes
+                if let Err(err) = statsd.push(&stats) {
+                    eprintln!("Error: {}", err);
+                }
+                next += interval;
+            }
+            if finishing {
+                return;
+            }
+            sleep(
+                next.saturating_duration_since(Instant::now())
+                    .min(Duration::from_millis(100)),
+            );
+        }
+    });
+
+    Ok(Pusher { done, handle })
+}
@@ -0,0 +1,103 @@
+use crate::stats::Stats;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// how long a client has to send its request before it's dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// for the request line and headers together
+const MAX_HEADER_BYTES: u64 = 16 * 1024;
+
+/// serve /healthz, /readyz and /status on `addr` from a background thread
+pub fn serve(addr: &str, stats: Arc<Stats>) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    println!(
+        "status endpoint listening on http://{}",
+        listener.local_addr()?
+    );
+
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    // a slow client must not hold up the probes of the others
+                    let stats = stats.clone();
+                    thread::spawn(move || {
+                        handle(stream, &stats)
+                            .map_err(|err| eprintln!("Error in status endpoint: {:?}", err))
+                            .ok();
+                    });
+                }
+                Err(error) => {
+                    eprintln!("Error accepting status connection: {:?}", error);
+                }
+            }
+        }
+    }))
+}
+
+fn handle(stream: TcpStream, stats: &Stats) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?).take(MAX_HEADER_BYTES);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // consume headers, we don't use them but some clients wait until they are read
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    // the limit ran out before the empty line that ends the headers
+    if reader.limit() == 0 && !header.ends_with('\n') {
+        let body = format!("request headers are over {} bytes\n", MAX_HEADER_BYTES);
+        return respond(stream, 431, "text/plain", &body);
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let path = path.split('?').next().unwrap_or("");
+
+    let (code, content_type, body) = match (method, path) {
+        ("GET", "/healthz") => (200, "text/plain", "ok\n".to_string()),
+        ("GET", "/readyz") => {
+            if stats.is_ready() {
+                (200, "text/plain", "ready\n".to_string())
+            } else {
+                (503, "text/plain", "starting\n".to_string())
+            }
+        }
+        ("GET", "/status") => match serde_json::to_string(&stats.status()) {
+            Ok(json) => (200, "application/json", json),
+            Err(err) => (500, "text/plain", format!("{}\n", err)),
+        },
+        ("GET", _) => (404, "text/plain", "not found\n".to_string()),
+        _ => (405, "text/plain", "method not allowed\n".to_string()),
+    };
+
+    respond(stream, code, content_type, &body)
+}
+
+fn respond(mut stream: TcpStream, code: u16, content_type: &str, body: &str) -> io::Result<()> {
+    let reason = match code {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        reason,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,143 @@
+use crate::fd_budget::Lru;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// files of one worker and when each is read next, workers with nothing due
+/// take overdue files from the others, see --no-work-stealing
+#[derive(Debug)]
+pub struct WorkQueue<T> {
+    /// None for slots of items taken by other workers and for the item the
+    /// owner is working on
+    items: Vec<Option<T>>,
+    /// slots of taken items that can be reused
+    free: Vec<usize>,
+    schedule: BinaryHeap<Reverse<(Instant, usize)>>,
+    lru: Lru,
+    /// items of the worker, including the one it's working on
+    owned: usize,
+}
+
+impl<T> WorkQueue<T> {
+    pub fn new(max_open_items: usize) -> WorkQueue<T> {
+        WorkQueue {
+            items: vec![],
+            free: vec![],
+            schedule: BinaryHeap::new(),
+            lru: Lru::new(max_open_items),
+            owned: 0,
+        }
+    }
+
+    /// add `item` to be read at `at`
+    pub fn add(&mut self, item: T, at: Instant) {
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.items[index] = Some(item);
+                index
+            }
+            None => {
+                self.items.push(Some(item));
+                self.items.len() - 1
+            }
+        };
+        self.schedule.push(Reverse((at, index)));
+        self.owned += 1;
+    }
+
+    /// the worker has no items left, it won't get more than it finds or takes
+    pub fn is_empty(&self) -> bool {
+        self.owned == 0
+    }
+
+    /// when the next item is due and its slot
+    pub fn next(&self) -> Option<(Instant, usize)> {
+        self.schedule
+            .peek()
+            .map(|Reverse((deadline, index))| (*deadline, *index))
+    }
+
+    /// move the next item to `at`
+    pub fn postpone(&mut self, at: Instant) {
+        if let Some(Reverse((_, index))) = self.schedule.pop() {
+            self.schedule.push(Reverse((at, index)));
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.items[index].as_ref()
+    }
+
+    /// take the next item to work on it, with the item that must be closed
+    /// to stay within the open files budget if any
+    pub fn take(&mut self) -> Option<(usize, T, Option<&mut T>)> {
+        let Reverse((_, index)) = self.schedule.pop()?;
+        let item = self.items[index].take()?;
+        let evicted = self
+            .lru
+            .touch(index)
+            .and_then(move |evicted| self.items[evicted].as_mut());
+        Some((index, item, evicted))
+    }
+
+    /// put back an item taken with `take`, to be read again at `at`
+    pub fn put_back(&mut self, index: usize, item: T, at: Instant) {
+        self.items[index] = Some(item);
+        self.schedule.push(Reverse((at, index)));
+    }
+
+    pub fn items_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.items.iter_mut().flatten()
+    }
+
+    /// the most overdue half of the items at least `behind` late
+    fn steal(&mut self, now: Instant, behind: Duration) -> Vec<T> {
+        let mut late = vec![];
+        while let Some(Reverse((deadline, index))) = self.schedule.peek().copied() {
+            if deadline + behind > now {
+                break;
+            }
+            self.schedule.pop();
+            late.push((deadline, index));
+        }
+        for (deadline, index) in late.split_off(late.len().div_ceil(2)) {
+            self.schedule.push(Reverse((deadline, index)));
+        }
+
+        let stolen: Vec<T> = late
+            .into_iter()
+            .filter_map(|(_, index)| {
+                self.lru.remove(index);
+                self.free.push(index);
+                self.items[index].take()
+            })
+            .collect();
+        self.owned -= stolen.len();
+        stolen
+    }
+
+    /// every item, the queue is left empty
+    pub fn drain(&mut self) -> Vec<T> {
+        self.schedule.clear();
+        self.free.clear();
+        self.owned = 0;
+        self.items.drain(..).flatten().collect()
+    }
+}
+
+/// items at least `behind` late from the first other queue that has some,
+/// queues locked by their owner are skipped
+pub fn steal<T>(me: usize, queues: &[Mutex<WorkQueue<T>>], behind: Duration) -> Vec<T> {
+    let now = Instant::now();
+    for offset in 1..queues.len() {
+        if let Ok(mut queue) = queues[(me + offset) % queues.len()].try_lock() {
+            let stolen = queue.steal(now, behind);
+            if !stolen.is_empty() {
+                return stolen;
+            }
+        }
+    }
+
+    vec![]
+}
@@ -0,0 +1,187 @@
+use crate::timestamp::Timestamps;
+use chrono::Utc;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+const LEVELS: &[(&str, f64)] = &[
+    ("DEBUG", 0.1),
+    ("INFO", 0.7),
+    ("WARN", 0.12),
+    ("ERROR", 0.07),
+    ("FATAL", 0.01),
+];
+const SERVICES: &[&str] = &["api", "auth", "billing", "checkout", "search", "worker"];
+const METHODS: &[&str] = &["GET", "GET", "GET", "POST", "PUT", "DELETE"];
+const PATHS: &[&str] = &[
+    "/",
+    "/login",
+    "/api/v1/items",
+    "/api/v1/orders",
+    "/cart",
+    "/search",
+];
+const STATUSES: &[u16] = &[200, 200, 200, 200, 201, 204, 301, 400, 404, 500, 503];
+/// some with characters that have to be escaped in XML or quoted in kv pairs
+const MESSAGES: &[&str] = &[
+    "request completed",
+    "user logged in",
+    "cache miss for key session:42",
+    "query took >500ms",
+    "retrying connection to db-01 & db-02",
+    "payment declined: \"insufficient funds\"",
+    "config reloaded",
+    "rate limit reached for <anonymous>",
+    "job finished in 3 steps",
+    "unexpected token '}' at offset 17",
+];
+
+/// a value built in generators can put in a field, see --synth-fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Timestamp,
+    Level,
+    Host,
+    Service,
+    User,
+    Ip,
+    Method,
+    Path,
+    Status,
+    DurationMs,
+    Bytes,
+    TraceId,
+    Message,
+}
+
+impl FieldKind {
+    pub const NAMES: &'static [&'static str] = &[
+        "timestamp",
+        "level",
+        "host",
+        "service",
+        "user",
+        "ip",
+        "method",
+        "path",
+        "status",
+        "duration_ms",
+        "bytes",
+        "trace_id",
+        "message",
+    ];
+    const ALL: &'static [FieldKind] = &[
+        FieldKind::Timestamp,
+        FieldKind::Level,
+        FieldKind::Host,
+        FieldKind::Service,
+        FieldKind::User,
+        FieldKind::Ip,
+        FieldKind::Method,
+        FieldKind::Path,
+        FieldKind::Status,
+        FieldKind::DurationMs,
+        FieldKind::Bytes,
+        FieldKind::TraceId,
+        FieldKind::Message,
+    ];
+
+    fn parse(v: &str) -> Result<FieldKind, String> {
+        FieldKind::NAMES
+            .iter()
+            .position(|name| *name == v)
+            .map(|i| FieldKind::ALL[i])
+            .ok_or_else(|| {
+                format!(
+                    "unknown field {}, supported: {}",
+                    v,
+                    FieldKind::NAMES.join(", ")
+                )
+            })
+    }
+
+    fn value(&self, rng: &mut StdRng, timestamps: &Timestamps) -> String {
+        match self {
+            FieldKind::Timestamp => timestamps.iso8601_millis(Utc::now()),
+            FieldKind::Level => LEVELS
+                .choose_weighted(rng, |(_, weight)| *weight)
+                .unwrap()
+                .0
+                .to_string(),
+            FieldKind::Host => format!("web-{:02}", rng.gen_range(1..=12)),
+            FieldKind::Service => SERVICES.choose(rng).unwrap().to_string(),
+            FieldKind::User => format!("user-{:05}", rng.gen_range(0..10000)),
+            FieldKind::Ip => format!(
+                "10.{}.{}.{}",
+                rng.gen_range(0..=255),
+                rng.gen_range(0..=255),
+                rng.gen_range(1..255)
+            ),
+            FieldKind::Method => METHODS.choose(rng).unwrap().to_string(),
+            FieldKind::Path => PATHS.choose(rng).unwrap().to_string(),
+            FieldKind::Status => STATUSES.choose(rng).unwrap().to_string(),
+            FieldKind::DurationMs => rng.gen_range(1..2000).to_string(),
+            FieldKind::Bytes => rng.gen_range(0..65536).to_string(),
+            FieldKind::TraceId => format!("{:032x}", rng.gen::<u128>()),
+            FieldKind::Message => MESSAGES.choose(rng).unwrap().to_string(),
+        }
+    }
+}
+
+/// fields of the lines of --format kv and xml-event, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSet {
+    fields: Vec<(String, FieldKind)>,
+}
+
+impl Default for FieldSet {
+    fn default() -> FieldSet {
+        FieldSet::parse("timestamp,level,host,service,message").unwrap()
+    }
+}
+
+impl FieldSet {
+    /// parse "timestamp,level,msg=message", a field is named after its kind
+    /// unless it's given as NAME=KIND
+    pub fn parse(v: &str) -> Result<FieldSet, String> {
+        let mut fields: Vec<(String, FieldKind)> = vec![];
+        for field in v.split(',').map(|field| field.trim()) {
+            let (name, kind) = match field.split_once('=') {
+                Some((name, kind)) => (name.trim(), kind.trim()),
+                None => (field, field),
+            };
+            if !is_name(name) {
+                return Err(format!(
+                    "{} isn't a valid field name, use letters, digits, _, - and .",
+                    name
+                ));
+            }
+            if fields.iter().any(|(other, _)| other == name) {
+                return Err(format!("field {} is repeated", name));
+            }
+            fields.push((name.to_string(), FieldKind::parse(kind)?));
+        }
+
+        Ok(FieldSet { fields })
+    }
+
+    /// a (name, value) for each field
+    pub fn values(&self, rng: &mut StdRng, timestamps: &Timestamps) -> Vec<(&str, String)> {
+        self.fields
+            .iter()
+            .map(|(name, kind)| (name.as_str(), kind.value(rng, timestamps)))
+            .collect()
+    }
+}
+
+/// valid as a kv key and as an XML element name
+fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+pub fn is_field_set(v: String) -> Result<(), String> {
+    FieldSet::parse(&v).map(|_| ())
+}
@@ -0,0 +1,38 @@
+use super::fields::FieldSet;
+use super::LineGenerator;
+use crate::timestamp::Timestamps;
+use rand::rngs::StdRng;
+
+/// `key=value` pairs separated by spaces, values with spaces, quotes or `=`
+/// are double quoted with `"` and `\` escaped
+#[derive(Debug)]
+pub struct Kv {
+    fields: FieldSet,
+    timestamps: Timestamps,
+}
+
+impl Kv {
+    pub fn new(fields: FieldSet, timestamps: Timestamps) -> Kv {
+        Kv { fields, timestamps }
+    }
+}
+
+fn quote(value: &str) -> String {
+    if !value.is_empty() && !value.contains([' ', '"', '=', '\\']) {
+        return value.to_string();
+    }
+
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+impl LineGenerator for Kv {
+    fn line(&mut self, rng: &mut StdRng, _n: u64) -> String {
+        self.fields
+            .values(rng, &self.timestamps)
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, quote(value)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
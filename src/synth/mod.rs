@@ -0,0 +1,129 @@
+use crate::source::Source;
+use crate::timestamp::Timestamps;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fmt::Debug;
+use std::io;
+use std::path::PathBuf;
+
+mod fields;
+mod kv;
+mod sessions;
+mod unicode_stress;
+mod w3c;
+mod xml_event;
+
+pub use fields::{is_field_set, FieldSet};
+pub use sessions::{is_session_config, SessionConfig};
+
+/// built in formats for --format
+pub const FORMATS: &[&str] = &[
+    "unicode-stress",
+    "sessions",
+    "xml-event",
+    "kv",
+    "w3c-extended",
+];
+
+/// lines generated before the source reaches its end and wraps
+const LINES_PER_PASS: u64 = 1000;
+/// every pass generates the same lines so wrap strategies behave as with samples
+const SEED: u64 = 0x6c6f_6767_656e;
+
+/// produces synthetic lines, `n` is the line number in the current pass
+pub trait LineGenerator: Debug + Send {
+    fn line(&mut self, rng: &mut StdRng, n: u64) -> String;
+
+    /// a new pass starts, forget any state kept between lines
+    fn reset(&mut self) {}
+
+    /// lines every output file starts with, see Source::header
+    fn header(&mut self) -> Option<String> {
+        None
+    }
+}
+
+/// options of the built in formats
+#[derive(Debug, Clone, Default)]
+pub struct SynthOptions {
+    pub sessions: SessionConfig,
+    /// fields of kv and xml-event lines
+    pub fields: FieldSet,
+    /// zone of the timestamps in generated lines
+    pub timestamps: Timestamps,
+}
+
+/// open a built in generator, returns the source and the output path relative
+/// to the output base directory
+pub fn open(format: &str, options: &SynthOptions) -> io::Result<(Box<dyn Source>, PathBuf)> {
+    let generator: Box<dyn LineGenerator> = match format {
+        "unicode-stress" => Box::new(unicode_stress::UnicodeStress),
+        "sessions" => Box::new(sessions::Sessions::new(
+            options.sessions.clone(),
+            options.timestamps.clone(),
+        )),
+        "xml-event" => Box::new(xml_event::XmlEvent::new(
+            options.fields.clone(),
+            options.timestamps.clone(),
+        )),
+        "kv" => Box::new(kv::Kv::new(
+            options.fields.clone(),
+            options.timestamps.clone(),
+        )),
+        "w3c-extended" => Box::new(w3c::W3cExtended::new(options.timestamps.clone())),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unknown format {}, supported: {}",
+                    format,
+                    FORMATS.join(", ")
+                ),
+            ))
+        }
+    };
+
+    let rel_path = PathBuf::from(format!("{}.log", format));
+    Ok((Box::new(SynthSource::new(generator)), rel_path))
+}
+
+#[derive(Debug)]
+struct SynthSource {
+    generator: Box<dyn LineGenerator>,
+    rng: StdRng,
+    line: u64,
+}
+
+impl SynthSource {
+    fn new(generator: Box<dyn LineGenerator>) -> SynthSource {
+        SynthSource {
+            generator,
+            rng: StdRng::seed_from_u64(SEED),
+            line: 0,
+        }
+    }
+}
+
+impl Source for SynthSource {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if self.line == LINES_PER_PASS {
+            return Ok(None);
+        }
+
+        let mut line = self.generator.line(&mut self.rng, self.line);
+        line.push('\n');
+        self.line += 1;
+        Ok(Some(line))
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.rng = StdRng::seed_from_u64(SEED);
+        self.line = 0;
+        self.generator.reset();
+        Ok(())
+    }
+
+    fn header(&mut self) -> Option<String> {
+        self.generator.header()
+    }
+}
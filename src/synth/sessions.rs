@@ -0,0 +1,229 @@
+use super::LineGenerator;
+use crate::parse::{parse_duration, parse_kv_list};
+use crate::timestamp::Timestamps;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+/// distinct users sessions are started for
+const USERS: u32 = 10000;
+const ACTIONS: &[(&str, &str)] = &[
+    ("search", "/search?q="),
+    ("view_item", "/items/"),
+    ("add_to_cart", "/cart/items/"),
+    ("remove_from_cart", "/cart/items/"),
+    ("view_cart", "/cart"),
+    ("checkout", "/checkout"),
+    ("update_profile", "/profile"),
+];
+const QUERIES: &[&str] = &["shoes", "laptop", "coffee", "headphones", "desk", "lamp"];
+
+/// how sessions are generated, see --sessions
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// sessions open at the same time
+    pub concurrency: usize,
+    /// actions per session between login and logout, inclusive
+    pub actions: (u32, u32),
+    /// time between two events of a session, inclusive
+    pub think: (Duration, Duration),
+}
+
+impl Default for SessionConfig {
+    fn default() -> SessionConfig {
+        SessionConfig {
+            concurrency: 10,
+            actions: (3, 10),
+            think: (Duration::from_millis(500), Duration::from_secs(5)),
+        }
+    }
+}
+
+/// "MIN..MAX" or a single value for both
+fn parse_range<T: PartialOrd + Copy>(
+    v: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<(T, T), String> {
+    let (min, max) = match v.split_once("..") {
+        Some((min, max)) => (parse(min)?, parse(max)?),
+        None => {
+            let value = parse(v)?;
+            (value, value)
+        }
+    };
+
+    if min > max {
+        return Err(format!("{} isn't a valid range, MIN is bigger than MAX", v));
+    }
+    Ok((min, max))
+}
+
+impl SessionConfig {
+    /// parse "concurrency=50,actions=3..10,think=500ms..5s"
+    pub fn parse(v: &str) -> Result<SessionConfig, String> {
+        let mut config = SessionConfig::default();
+        for (key, value) in parse_kv_list(v)? {
+            match key.as_str() {
+                "concurrency" => {
+                    config.concurrency = match value.parse::<usize>() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("{} isn't a number greater than 0", value)),
+                    }
+                }
+                "actions" => {
+                    config.actions = parse_range(&value, |v| {
+                        v.parse::<u32>()
+                            .map_err(|_| format!("{} isn't a valid action count", v))
+                    })?
+                }
+                "think" => config.think = parse_range(&value, parse_duration)?,
+                _ => return Err(format!("unknown sessions option {}", key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+pub fn is_session_config(v: String) -> Result<(), String> {
+    SessionConfig::parse(&v).map(|_| ())
+}
+
+#[derive(Debug)]
+struct Session {
+    id: String,
+    user: u32,
+    ip: String,
+    started: ChronoDuration,
+    actions_left: u32,
+    logged_in: bool,
+}
+
+/// concurrent user sessions (login, actions, logout) with think times between
+/// their events, lines are ordered by the simulated time of each event, which
+/// starts when the pass starts
+#[derive(Debug)]
+pub struct Sessions {
+    config: SessionConfig,
+    timestamps: Timestamps,
+    epoch: DateTime<Utc>,
+    /// slots by the simulated time of their next event
+    next: BinaryHeap<Reverse<(ChronoDuration, usize)>>,
+    slots: Vec<Option<Session>>,
+}
+
+impl Sessions {
+    pub fn new(config: SessionConfig, timestamps: Timestamps) -> Sessions {
+        Sessions {
+            config,
+            timestamps,
+            epoch: Utc::now(),
+            next: BinaryHeap::new(),
+            slots: vec![],
+        }
+    }
+
+    fn think(&self, rng: &mut StdRng) -> ChronoDuration {
+        let (min, max) = self.config.think;
+        let millis = rng.gen_range(min.as_millis()..=max.as_millis());
+        ChronoDuration::milliseconds(millis as i64)
+    }
+
+    fn start(&self, rng: &mut StdRng, at: ChronoDuration) -> Session {
+        let (min, max) = self.config.actions;
+        Session {
+            id: format!("{:016x}", rng.gen::<u64>()),
+            user: rng.gen_range(0..USERS),
+            ip: format!(
+                "10.{}.{}.{}",
+                rng.gen_range(0..=255),
+                rng.gen_range(0..=255),
+                rng.gen_range(1..255)
+            ),
+            started: at,
+            actions_left: rng.gen_range(min..=max),
+            logged_in: false,
+        }
+    }
+
+    /// event text for the next step of `session` and whether it logged out
+    fn step(session: &mut Session, rng: &mut StdRng, at: ChronoDuration) -> (String, bool) {
+        if !session.logged_in {
+            session.logged_in = true;
+            return ("event=login status=success".to_string(), false);
+        }
+
+        if session.actions_left == 0 {
+            let duration = (at - session.started).num_milliseconds();
+            return (format!("event=logout duration_ms={}", duration), true);
+        }
+
+        session.actions_left -= 1;
+        let (action, path) = ACTIONS.choose(rng).unwrap();
+        let path = match *action {
+            "search" => format!("{}{}", path, QUERIES.choose(rng).unwrap()),
+            "view_item" | "add_to_cart" | "remove_from_cart" => {
+                format!("{}{}", path, rng.gen_range(1000..10000))
+            }
+            _ => path.to_string(),
+        };
+        let status = if rng.gen_bool(0.97) { 200 } else { 500 };
+        (
+            format!(
+                "event=action action={} path={} status={}",
+                action, path, status
+            ),
+            false,
+        )
+    }
+}
+
+impl LineGenerator for Sessions {
+    fn line(&mut self, rng: &mut StdRng, _n: u64) -> String {
+        if self.slots.is_empty() {
+            // sessions start spread over the first think time
+            for slot in 0..self.config.concurrency {
+                let at = self.think(rng);
+                self.slots.push(Some(self.start(rng, at)));
+                self.next.push(Reverse((at, slot)));
+            }
+        }
+
+        let Reverse((at, slot)) = self.next.pop().unwrap();
+        let session = match &mut self.slots[slot] {
+            Some(session) => session,
+            None => {
+                let session = self.start(rng, at);
+                self.slots[slot].insert(session)
+            }
+        };
+
+        let (event, ended) = Sessions::step(session, rng, at);
+        let line = format!(
+            "{} session={} user=user-{:05} ip={} {}",
+            self.timestamps.iso8601_millis(self.epoch + at),
+            session.id,
+            session.user,
+            session.ip,
+            event
+        );
+
+        if ended {
+            self.slots[slot] = None;
+        }
+        // a new session takes the slot of one that ended after a think time
+        let next = at + self.think(rng);
+        self.next.push(Reverse((next, slot)));
+        line
+    }
+
+    fn reset(&mut self) {
+        self.epoch = Utc::now();
+        self.next.clear();
+        self.slots.clear();
+    }
+}
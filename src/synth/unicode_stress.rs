@@ -0,0 +1,152 @@
+use super::LineGenerator;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+const KINDS: &[&str] = &[
+    "combining",
+    "rtl",
+    "surrogate-adjacent",
+    "zero-width",
+    "long-grapheme",
+    "mixed",
+];
+
+const LATIN: &[&str] = &["error", "user", "login", "request", "payment", "cache"];
+const ARABIC: &[&str] = &["مرحبا", "خطأ", "المستخدم", "طلب", "الدفع"];
+const HEBREW: &[&str] = &["שלום", "שגיאה", "משתמש", "בקשה", "תשלום"];
+/// bidi controls: RLO, LRO, RLE, PDF, RLI, LRI, FSI, PDI, RLM, LRM, ALM
+const BIDI: &[char] = &[
+    '\u{202E}', '\u{202D}', '\u{202B}', '\u{202C}', '\u{2067}', '\u{2066}', '\u{2068}', '\u{2069}',
+    '\u{200F}', '\u{200E}', '\u{061C}',
+];
+/// zero width space, non joiner, joiner, word joiner, BOM, soft hyphen,
+/// mongolian vowel separator and line/paragraph separators
+const ZERO_WIDTH: &[char] = &[
+    '\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}', '\u{00AD}', '\u{180E}', '\u{2028}',
+    '\u{2029}',
+];
+/// valid scalar values around the surrogate range (which can't be in a
+/// string), the BMP end, noncharacters and the first and last supplementary
+/// code points, including a 4 byte emoji so naive UTF-16 code breaks it in two
+const SURROGATE_ADJACENT: &[char] = &[
+    '\u{D7FF}',
+    '\u{E000}',
+    '\u{F8FF}',
+    '\u{FDD0}',
+    '\u{FFFD}',
+    '\u{FFFE}',
+    '\u{FFFF}',
+    '\u{10000}',
+    '\u{1F600}',
+    '\u{1D11E}',
+    '\u{10FFFD}',
+    '\u{10FFFF}',
+];
+/// emoji sequences that are a single grapheme made of many code points
+const EMOJI_CLUSTERS: &[&str] = &[
+    "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}",
+    "\u{1F3F4}\u{E0067}\u{E0062}\u{E0073}\u{E0063}\u{E0074}\u{E007F}",
+    "\u{1F9D1}\u{1F3FD}\u{200D}\u{1F91D}\u{200D}\u{1F9D1}\u{1F3FF}",
+    "\u{1F1FA}\u{1F1F3}",
+    "\u{1F44B}\u{1F3FE}",
+    "\u{2764}\u{FE0F}\u{200D}\u{1F525}",
+];
+
+/// lines to break parsers and UIs that assume one code point per visible
+/// character, left to right text or no invisible characters
+#[derive(Debug)]
+pub struct UnicodeStress;
+
+fn combining_marks(rng: &mut StdRng, out: &mut String, count: usize) {
+    for _ in 0..count {
+        // combining diacritical marks block
+        out.push(std::char::from_u32(rng.gen_range(0x300..0x370)).unwrap());
+    }
+}
+
+fn combining(rng: &mut StdRng) -> String {
+    let mut out = String::new();
+    for c in LATIN.choose(rng).unwrap().chars() {
+        out.push(c);
+        let count = rng.gen_range(1..8);
+        combining_marks(rng, &mut out, count);
+    }
+    out
+}
+
+fn rtl(rng: &mut StdRng) -> String {
+    let mut out = String::new();
+    out.push_str(ARABIC.choose(rng).unwrap());
+    out.push(' ');
+    out.push(*BIDI.choose(rng).unwrap());
+    out.push_str(LATIN.choose(rng).unwrap());
+    out.push(' ');
+    out.push_str(HEBREW.choose(rng).unwrap());
+    // unbalanced on purpose sometimes, the isolate or embedding leaks to the end of line
+    if rng.gen_bool(0.5) {
+        out.push('\u{2069}');
+    }
+    out.push_str(" 123");
+    out
+}
+
+fn surrogate_adjacent(rng: &mut StdRng) -> String {
+    (0..rng.gen_range(4..16))
+        .map(|_| *SURROGATE_ADJACENT.choose(rng).unwrap())
+        .collect()
+}
+
+fn zero_width(rng: &mut StdRng) -> String {
+    let mut out = String::new();
+    for c in LATIN.choose(rng).unwrap().chars() {
+        out.push(c);
+        if rng.gen_bool(0.6) {
+            out.push(*ZERO_WIDTH.choose(rng).unwrap());
+        }
+    }
+    out
+}
+
+fn long_grapheme(rng: &mut StdRng) -> String {
+    let mut out = String::new();
+    if rng.gen_bool(0.5) {
+        out.push('a');
+        let count = rng.gen_range(50..300);
+        combining_marks(rng, &mut out, count);
+    } else {
+        for _ in 0..rng.gen_range(1..20) {
+            out.push_str(EMOJI_CLUSTERS.choose(rng).unwrap());
+        }
+    }
+    out
+}
+
+impl LineGenerator for UnicodeStress {
+    fn line(&mut self, rng: &mut StdRng, n: u64) -> String {
+        let kind = KINDS[n as usize % KINDS.len()];
+        let text = match kind {
+            "combining" => combining(rng),
+            "rtl" => rtl(rng),
+            "surrogate-adjacent" => surrogate_adjacent(rng),
+            "zero-width" => zero_width(rng),
+            "long-grapheme" => long_grapheme(rng),
+            _ => [
+                combining(rng),
+                rtl(rng),
+                surrogate_adjacent(rng),
+                zero_width(rng),
+                long_grapheme(rng),
+            ]
+            .join(" "),
+        };
+
+        format!(
+            "seq={} kind={} user={} msg={}",
+            n,
+            kind,
+            zero_width(rng),
+            text
+        )
+    }
+}
@@ -0,0 +1,93 @@
+use super::LineGenerator;
+use crate::timestamp::Timestamps;
+use chrono::Utc;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// fields of each line, in the order of the #Fields directive
+const FIELDS: &str = "date time s-ip cs-method cs-uri-stem cs-uri-query s-port cs-username c-ip cs(User-Agent) cs(Referer) sc-status sc-substatus sc-win32-status time-taken";
+const REQUESTS: &[(&str, &str, &str)] = &[
+    ("GET", "/", "-"),
+    ("GET", "/default.aspx", "-"),
+    ("GET", "/products/list.aspx", "category=shoes&page=2"),
+    ("GET", "/images/logo.png", "-"),
+    ("GET", "/search", "q=coffee+mug"),
+    ("POST", "/api/orders", "-"),
+    ("POST", "/account/login.aspx", "ReturnUrl=%2Faccount"),
+    ("HEAD", "/health", "-"),
+];
+const USER_AGENTS: &[&str] = &[
+    "Mozilla/5.0+(Windows+NT+10.0;+Win64;+x64)+AppleWebKit/537.36+(KHTML,+like+Gecko)+Chrome/124.0+Safari/537.36",
+    "Mozilla/5.0+(Macintosh;+Intel+Mac+OS+X+14_4)+AppleWebKit/605.1.15+(KHTML,+like+Gecko)+Version/17.4+Safari/605.1.15",
+    "curl/8.5.0",
+    "-",
+];
+const REFERERS: &[&str] = &[
+    "-",
+    "https://www.example.com/",
+    "https://www.example.com/search",
+];
+/// status, substatus and win32 status, mostly successes
+const STATUSES: &[(u16, u16, u32)] = &[
+    (200, 0, 0),
+    (200, 0, 0),
+    (200, 0, 0),
+    (200, 0, 0),
+    (304, 0, 0),
+    (302, 0, 0),
+    (401, 2, 5),
+    (404, 0, 2),
+    (500, 0, 64),
+];
+
+/// IIS access logs in the W3C Extended Log Format, the #Software, #Version,
+/// #Date and #Fields directives start every output file
+#[derive(Debug)]
+pub struct W3cExtended {
+    timestamps: Timestamps,
+}
+
+impl W3cExtended {
+    pub fn new(timestamps: Timestamps) -> W3cExtended {
+        W3cExtended { timestamps }
+    }
+}
+
+impl LineGenerator for W3cExtended {
+    fn line(&mut self, rng: &mut StdRng, _n: u64) -> String {
+        let (method, stem, query) = REQUESTS.choose(rng).unwrap();
+        let (status, substatus, win32) = STATUSES.choose(rng).unwrap();
+        let user = if *status == 401 || rng.gen_bool(0.8) {
+            "-".to_string()
+        } else {
+            format!("CONTOSO\\user{:03}", rng.gen_range(0..1000))
+        };
+        format!(
+            "{} 10.0.0.{} {} {} {} {} {} 192.168.{}.{} {} {} {} {} {} {}",
+            self.timestamps.format(Utc::now(), "%Y-%m-%d %H:%M:%S"),
+            rng.gen_range(1..5),
+            method,
+            stem,
+            query,
+            if rng.gen_bool(0.9) { 443 } else { 80 },
+            user,
+            rng.gen_range(0..=255),
+            rng.gen_range(1..255),
+            USER_AGENTS.choose(rng).unwrap(),
+            REFERERS.choose(rng).unwrap(),
+            status,
+            substatus,
+            win32,
+            rng.gen_range(1..2000)
+        )
+    }
+
+    fn header(&mut self) -> Option<String> {
+        Some(format!(
+            "#Software: Microsoft Internet Information Services 10.0\n#Version: 1.0\n#Date: {}\n#Fields: {}\n",
+            self.timestamps.format(Utc::now(), "%Y-%m-%d %H:%M:%S"),
+            FIELDS
+        ))
+    }
+}
@@ -0,0 +1,43 @@
+use super::fields::FieldSet;
+use super::LineGenerator;
+use crate::timestamp::Timestamps;
+use rand::rngs::StdRng;
+
+/// one `<event>` element per line with an element for each field
+#[derive(Debug)]
+pub struct XmlEvent {
+    fields: FieldSet,
+    timestamps: Timestamps,
+}
+
+impl XmlEvent {
+    pub fn new(fields: FieldSet, timestamps: Timestamps) -> XmlEvent {
+        XmlEvent { fields, timestamps }
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl LineGenerator for XmlEvent {
+    fn line(&mut self, rng: &mut StdRng, _n: u64) -> String {
+        let mut line = "<event>".to_string();
+        for (name, value) in self.fields.values(rng, &self.timestamps) {
+            line.push_str(&format!("<{}>{}</{}>", name, escape(&value), name));
+        }
+        line.push_str("</event>");
+        line
+    }
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Path,
+    File,
+    LineNumber,
+}
+
+/// where the tag goes, see --tag-position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagPosition {
+    Append,
+    Prepend,
+}
+
+impl TagPosition {
+    pub const NAMES: &'static [&'static str] = &["append", "prepend"];
+
+    pub fn parse(v: &str) -> TagPosition {
+        match v {
+            "prepend" => TagPosition::Prepend,
+            _ => TagPosition::Append,
+        }
+    }
+}
+
+/// provenance added to each line read from a sample, for example
+/// '# src={path}:{lineno}', see --tag-lines
+///
+/// placeholders:
+/// * {path}: path of the sample the line was read from
+/// * {file}: path of the sample relative to the input directory
+/// * {lineno}: number of the line in the sample, starting at 1
+#[derive(Debug, Clone)]
+pub struct LineTag {
+    parts: Vec<Part>,
+    position: TagPosition,
+}
+
+impl LineTag {
+    pub fn parse(template: &str, position: TagPosition) -> Result<LineTag, String> {
+        let mut parts = vec![];
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(format!("unclosed placeholder in {}", template)),
+            };
+
+            parts.push(match &rest[start + 1..end] {
+                "path" => Part::Path,
+                "file" => Part::File,
+                "lineno" => Part::LineNumber,
+                placeholder => {
+                    return Err(format!(
+                        "unknown placeholder {{{}}}, use {{path}}, {{file}} or {{lineno}}",
+                        placeholder
+                    ))
+                }
+            });
+
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if template.contains(['\r', '\n']) {
+            return Err(format!("{:?} must fit in a line", template));
+        }
+
+        Ok(LineTag { parts, position })
+    }
+
+    /// the tag of the lines of one sample, with everything but the line number rendered
+    pub fn for_file(&self, path: &Path, rel_path: &Path) -> FileTag {
+        let mut parts = vec![String::new()];
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => parts.last_mut().unwrap().push_str(text),
+                Part::Path => parts
+                    .last_mut()
+                    .unwrap()
+                    .push_str(&path.display().to_string()),
+                Part::File => parts
+                    .last_mut()
+                    .unwrap()
+                    .push_str(&rel_path.display().to_string()),
+                Part::LineNumber => parts.push(String::new()),
+            }
+        }
+
+        FileTag {
+            parts,
+            position: self.position,
+        }
+    }
+}
+
+pub fn is_line_tag(v: String) -> Result<(), String> {
+    LineTag::parse(&v, TagPosition::Append).map(|_| ())
+}
+
+/// tags the lines of one sample
+#[derive(Debug)]
+pub struct FileTag {
+    /// text between line numbers
+    parts: Vec<String>,
+    position: TagPosition,
+}
+
+impl FileTag {
+    fn render(&self, lineno: u64) -> String {
+        self.parts.join(&lineno.to_string())
+    }
+
+    /// `line` tagged as line `lineno` of the sample, separated by a space and
+    /// keeping its ending
+    pub fn tag(&self, line: &str, lineno: u64) -> String {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        match self.position {
+            TagPosition::Append => format!("{} {}{}", content, self.render(lineno), ending),
+            TagPosition::Prepend => format!("{} {}", self.render(lineno), line),
+        }
+    }
+}
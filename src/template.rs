@@ -0,0 +1,220 @@
+use crate::cardinality::Cardinality;
+use crate::parse::is_valid_date_format;
+use crate::timestamp::Timestamps;
+use crate::values::ValueLists;
+use chrono::Utc;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// counters shared by every file so `{{counter name=orders}}` is unique across outputs
+#[derive(Debug, Clone, Default)]
+pub struct Counters {
+    values: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl Counters {
+    /// counters that continue from `values`, see --resume-output
+    pub fn starting_at(values: HashMap<String, u64>) -> Counters {
+        Counters {
+            values: Arc::new(Mutex::new(values)),
+        }
+    }
+
+    pub fn values(&self) -> HashMap<String, u64> {
+        self.values.lock().unwrap().clone()
+    }
+
+    fn next(&self, name: &str) -> u64 {
+        let mut values = self.values.lock().unwrap();
+        let value = values.entry(name.to_string()).or_insert(0);
+        *value += 1;
+        *value
+    }
+}
+
+#[derive(Debug)]
+enum Part<'a> {
+    Text(&'a str),
+    Call {
+        source: &'a str,
+        name: &'a str,
+        args: Vec<(&'a str, &'a str)>,
+    },
+}
+
+/// split a line in text and `{{name key=value ...}}` calls
+fn parse(line: &str) -> Vec<Part<'_>> {
+    let mut parts = vec![];
+    let mut rest = line;
+
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        if start > 0 {
+            parts.push(Part::Text(&rest[..start]));
+        }
+
+        let source = &rest[start + 2..end];
+        let mut words = source.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args = words
+            .map(|word| match word.find('=') {
+                Some(pos) => (&word[..pos], &word[pos + 1..]),
+                None => (word, ""),
+            })
+            .collect();
+        parts.push(Part::Call { source, name, args });
+
+        rest = &rest[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+
+    parts
+}
+
+fn arg<'a>(args: &[(&'a str, &'a str)], key: &str) -> Option<&'a str> {
+    args.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+fn arg_num(args: &[(&str, &str)], key: &str, default: u64) -> u64 {
+    arg(args, key)
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(default)
+}
+
+pub fn random_hex(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len)
+        .map(|_| std::char::from_digit(rng.gen_range(0..16), 16).unwrap())
+        .collect()
+}
+
+/// an id that is kept for `uses` renders before a new one is generated
+#[derive(Debug)]
+struct StickyId {
+    id: String,
+    remaining: u64,
+}
+
+/// state shared by the templates of all files
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    pub counters: Counters,
+    /// fields rendered with `{{name}}` that have a controlled number of values
+    pub fields: Arc<Vec<Cardinality>>,
+    /// files read by `{{choice file=...}}` and `{{cycle file=...}}`
+    pub values: ValueLists,
+    /// zone and locale of `{{timestamp}}`
+    pub timestamps: Timestamps,
+}
+
+/// renders template lines for one output file, ids generated by
+/// `{{session_id}}` and `{{trace_id}}` are shared by all lines of the file that
+/// use the same placeholder so consecutive lines correlate
+#[derive(Debug)]
+pub struct TemplateState {
+    context: TemplateContext,
+    sticky: HashMap<String, StickyId>,
+    /// uses of each `{{cycle ...}}` placeholder in the file
+    cycles: HashMap<String, u64>,
+}
+
+impl TemplateState {
+    pub fn new(context: TemplateContext) -> TemplateState {
+        TemplateState {
+            context,
+            sticky: HashMap::new(),
+            cycles: HashMap::new(),
+        }
+    }
+
+    fn sticky_id(&mut self, source: &str, uses: u64, len: usize) -> String {
+        let entry = self
+            .sticky
+            .entry(source.to_string())
+            .or_insert_with(|| StickyId {
+                id: String::new(),
+                remaining: 0,
+            });
+
+        if entry.remaining == 0 {
+            entry.id = random_hex(len);
+            entry.remaining = uses.max(1);
+        }
+
+        entry.remaining -= 1;
+        entry.id.clone()
+    }
+
+    fn call(&mut self, source: &str, name: &str, args: &[(&str, &str)]) -> Option<String> {
+        match name {
+            "timestamp" => {
+                let now = Utc::now();
+                match arg(args, "format") {
+                    Some(format) if is_valid_date_format(format) => {
+                        Some(self.context.timestamps.format(now, format))
+                    }
+                    Some(_) => None,
+                    None => Some(self.context.timestamps.rfc3339(now)),
+                }
+            }
+            "session_id" => Some(self.sticky_id(source, arg_num(args, "persist", 1), 16)),
+            "trace_id" => Some(self.sticky_id(source, arg_num(args, "new_every", 1), 32)),
+            "counter" => Some(
+                self.context
+                    .counters
+                    .next(arg(args, "name").unwrap_or("default"))
+                    .to_string(),
+            ),
+            "choice" | "cycle" => {
+                let weighted = matches!(arg(args, "weighted"), Some("") | Some("true"));
+                let list = self.context.values.get(arg(args, "file")?, weighted)?;
+                if name == "choice" {
+                    return Some(list.choice().to_string());
+                }
+
+                let uses = self.cycles.entry(source.to_string()).or_insert(0);
+                let value = list.cycle(*uses).to_string();
+                *uses += 1;
+                Some(value)
+            }
+            _ => self
+                .context
+                .fields
+                .iter()
+                .find(|field| field.field == name)
+                .map(|field| field.value()),
+        }
+    }
+
+    /// replace known calls in `line`, unknown ones are written as they are
+    pub fn render(&mut self, line: &str) -> String {
+        if !line.contains("{{") {
+            return line.to_string();
+        }
+
+        let mut result = String::with_capacity(line.len());
+        for part in parse(line) {
+            match part {
+                Part::Text(text) => result.push_str(text),
+                Part::Call { source, name, args } => match self.call(source, name, &args) {
+                    Some(value) => result.push_str(&value),
+                    None => {
+                        result.push_str("{{");
+                        result.push_str(source);
+                        result.push_str("}}");
+                    }
+                },
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,117 @@
+use crate::parse::{parse_duration, parse_percentage};
+use crate::route::Match;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// a --throttle rule: `MATCH:PCT%` keeps that share of the matching lines and
+/// drops the rest, with `:delay=DURATION` the rest is written that late instead
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    matcher: Match,
+    keep: f64,
+    delay: Option<Duration>,
+}
+
+impl Throttle {
+    /// parse "level=DEBUG:10%" or "/cache miss/:50%:delay=5s"
+    pub fn parse(v: &str) -> Result<Throttle, String> {
+        let (rule, delay) = match v.rsplit_once(':') {
+            Some((rule, delay)) if delay.starts_with("delay=") => {
+                let delay = parse_duration(&delay["delay=".len()..])?;
+                if delay.is_zero() {
+                    return Err(format!("{} has a 0 delay", v));
+                }
+                (rule, Some(delay))
+            }
+            _ => (v, None),
+        };
+        let (rule, keep) = rule
+            .rsplit_once(':')
+            .filter(|(_, keep)| keep.ends_with('%'))
+            .ok_or_else(|| {
+                format!(
+                    "{} should be MATCH:PCT%[:delay=DURATION], like level=DEBUG:10%",
+                    v
+                )
+            })?;
+
+        Ok(Throttle {
+            matcher: Match::parse(rule.trim())?,
+            keep: parse_percentage(keep)?,
+            delay,
+        })
+    }
+}
+
+pub fn is_throttle(v: String) -> Result<(), String> {
+    Throttle::parse(&v).map(|_| ())
+}
+
+/// what happens to a line after the throttle rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttled {
+    Write,
+    Dropped,
+    Delayed,
+}
+
+/// applies the throttle rules to the lines of one generated file, the first
+/// rule a line matches decides
+#[derive(Debug)]
+pub struct Throttler {
+    rules: Arc<Vec<Throttle>>,
+    /// delayed lines by when they're due, in the order they were delayed
+    delayed: BinaryHeap<Reverse<(Instant, u64, String)>>,
+    delayed_count: u64,
+}
+
+impl Throttler {
+    pub fn new(rules: Arc<Vec<Throttle>>) -> Throttler {
+        Throttler {
+            rules,
+            delayed: BinaryHeap::new(),
+            delayed_count: 0,
+        }
+    }
+
+    pub fn check(&mut self, line: &str) -> Throttled {
+        let mut json = None;
+        let rule = match self
+            .rules
+            .iter()
+            .find(|rule| rule.matcher.matches(line, &mut json))
+        {
+            Some(rule) => rule,
+            None => return Throttled::Write,
+        };
+        if rule.keep >= 1.0 || (rule.keep > 0.0 && rand::thread_rng().gen_bool(rule.keep)) {
+            return Throttled::Write;
+        }
+
+        match rule.delay {
+            Some(delay) => {
+                self.delayed_count += 1;
+                self.delayed.push(Reverse((
+                    Instant::now() + delay,
+                    self.delayed_count,
+                    line.to_string(),
+                )));
+                Throttled::Delayed
+            }
+            None => Throttled::Dropped,
+        }
+    }
+
+    /// next delayed line whose delay has elapsed
+    pub fn next_due(&mut self) -> Option<String> {
+        match self.delayed.peek() {
+            Some(Reverse((due, _, _))) if *due <= Instant::now() => {
+                self.delayed.pop().map(|Reverse((_, _, line))| line)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,531 @@
+use crate::parse::{parse_kv_list, parse_percentage, unknown_value};
+use chrono::{DateTime, Datelike, FixedOffset, Local, SecondsFormat, TimeZone, Timelike, Utc};
+use rand::Rng;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+/// where named zones are looked up, TZDIR comes first
+const ZONEINFO_DIRS: &[&str] = &[
+    "/usr/share/zoneinfo",
+    "/usr/lib/zoneinfo",
+    "/usr/share/lib/zoneinfo",
+];
+
+/// time zone of rendered timestamps, see --timestamp-tz
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum TimestampTz {
+    #[default]
+    Utc,
+    /// the zone of the machine
+    Local,
+    /// like +05:30, without daylight saving time
+    Fixed(FixedOffset),
+    /// a zone of the tz database like Europe/Berlin, offsets change with
+    /// daylight saving time
+    Zone(String),
+}
+
+impl TimestampTz {
+    pub const NAMES: &'static [&'static str] = &["utc", "local", "+HH:MM", "Area/City"];
+
+    /// named zones are rendered as the local zone with TZ set to them, call
+    /// before starting threads
+    pub fn activate(&self) {
+        if let TimestampTz::Zone(name) = self {
+            std::env::set_var("TZ", name);
+        }
+    }
+}
+
+/// +05:30, -0800 or +05
+fn parse_offset(v: &str) -> Option<FixedOffset> {
+    let sign = match v.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = v[1..].replace(':', "");
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn is_zone(name: &str) -> bool {
+    if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+        return false;
+    }
+    std::env::var("TZDIR")
+        .ok()
+        .into_iter()
+        .chain(ZONEINFO_DIRS.iter().map(|dir| dir.to_string()))
+        .any(|dir| Path::new(&dir).join(name).is_file())
+}
+
+impl FromStr for TimestampTz {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<TimestampTz, String> {
+        match v {
+            "utc" | "UTC" | "Z" => Ok(TimestampTz::Utc),
+            "local" => Ok(TimestampTz::Local),
+            _ if v.starts_with('+') || v.starts_with('-') => parse_offset(v)
+                .map(TimestampTz::Fixed)
+                .ok_or_else(|| format!("{} isn't an offset, use for example +05:30", v)),
+            _ if is_zone(v) => Ok(TimestampTz::Zone(v.to_string())),
+            _ => Err(unknown_value("time zone", v, TimestampTz::NAMES)),
+        }
+    }
+}
+
+pub fn is_timestamp_tz(v: String) -> Result<(), String> {
+    v.parse::<TimestampTz>().map(|_| ())
+}
+
+/// month and day names of rendered timestamps, see --timestamp-locale
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TimestampLocale {
+    #[default]
+    En,
+    De,
+    Es,
+    Fr,
+    It,
+    Nl,
+    Pt,
+}
+
+/// months, abbreviated months, days from monday and abbreviated days
+type Names = (
+    [&'static str; 12],
+    [&'static str; 12],
+    [&'static str; 7],
+    [&'static str; 7],
+);
+
+const DE: Names = (
+    [
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ],
+    [
+        "Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez",
+    ],
+    [
+        "Montag",
+        "Dienstag",
+        "Mittwoch",
+        "Donnerstag",
+        "Freitag",
+        "Samstag",
+        "Sonntag",
+    ],
+    ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+);
+const ES: Names = (
+    [
+        "enero",
+        "febrero",
+        "marzo",
+        "abril",
+        "mayo",
+        "junio",
+        "julio",
+        "agosto",
+        "septiembre",
+        "octubre",
+        "noviembre",
+        "diciembre",
+    ],
+    [
+        "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic",
+    ],
+    [
+        "lunes",
+        "martes",
+        "miércoles",
+        "jueves",
+        "viernes",
+        "sábado",
+        "domingo",
+    ],
+    ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+);
+const FR: Names = (
+    [
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ],
+    [
+        "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.", "nov.",
+        "déc.",
+    ],
+    [
+        "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+    ],
+    ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."],
+);
+const IT: Names = (
+    [
+        "gennaio",
+        "febbraio",
+        "marzo",
+        "aprile",
+        "maggio",
+        "giugno",
+        "luglio",
+        "agosto",
+        "settembre",
+        "ottobre",
+        "novembre",
+        "dicembre",
+    ],
+    [
+        "gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic",
+    ],
+    [
+        "lunedì",
+        "martedì",
+        "mercoledì",
+        "giovedì",
+        "venerdì",
+        "sabato",
+        "domenica",
+    ],
+    ["lun", "mar", "mer", "gio", "ven", "sab", "dom"],
+);
+const NL: Names = (
+    [
+        "januari",
+        "februari",
+        "maart",
+        "april",
+        "mei",
+        "juni",
+        "juli",
+        "augustus",
+        "september",
+        "oktober",
+        "november",
+        "december",
+    ],
+    [
+        "jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec",
+    ],
+    [
+        "maandag",
+        "dinsdag",
+        "woensdag",
+        "donderdag",
+        "vrijdag",
+        "zaterdag",
+        "zondag",
+    ],
+    ["ma", "di", "wo", "do", "vr", "za", "zo"],
+);
+const PT: Names = (
+    [
+        "janeiro",
+        "fevereiro",
+        "março",
+        "abril",
+        "maio",
+        "junho",
+        "julho",
+        "agosto",
+        "setembro",
+        "outubro",
+        "novembro",
+        "dezembro",
+    ],
+    [
+        "jan", "fev", "mar", "abr", "mai", "jun", "jul", "ago", "set", "out", "nov", "dez",
+    ],
+    [
+        "segunda-feira",
+        "terça-feira",
+        "quarta-feira",
+        "quinta-feira",
+        "sexta-feira",
+        "sábado",
+        "domingo",
+    ],
+    ["seg", "ter", "qua", "qui", "sex", "sáb", "dom"],
+);
+
+impl TimestampLocale {
+    pub const NAMES: &'static [&'static str] = &["en", "de", "es", "fr", "it", "nl", "pt"];
+
+    fn names(&self) -> Option<&'static Names> {
+        match self {
+            // chrono's own names
+            TimestampLocale::En => None,
+            TimestampLocale::De => Some(&DE),
+            TimestampLocale::Es => Some(&ES),
+            TimestampLocale::Fr => Some(&FR),
+            TimestampLocale::It => Some(&IT),
+            TimestampLocale::Nl => Some(&NL),
+            TimestampLocale::Pt => Some(&PT),
+        }
+    }
+
+    /// `format` with %B, %b (or %h), %A and %a replaced by the names of
+    /// `month0` and `weekday` (from monday) in this locale
+    fn localize(&self, format: &str, month0: usize, weekday: usize) -> String {
+        let (months, short_months, days, short_days) = match self.names() {
+            Some(names) => names,
+            None => return format.to_string(),
+        };
+
+        let mut localized = String::with_capacity(format.len() + 16);
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                localized.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('B') => localized.push_str(months[month0]),
+                Some('b') | Some('h') => localized.push_str(short_months[month0]),
+                Some('A') => localized.push_str(days[weekday]),
+                Some('a') => localized.push_str(short_days[weekday]),
+                Some(other) => {
+                    localized.push('%');
+                    localized.push(other);
+                }
+                None => localized.push('%'),
+            }
+        }
+        localized
+    }
+}
+
+impl FromStr for TimestampLocale {
+    type Err = String;
+
+    /// a language like de or a locale like de_DE.UTF-8
+    fn from_str(v: &str) -> Result<TimestampLocale, String> {
+        let language = v
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Ok(TimestampLocale::En),
+            "de" => Ok(TimestampLocale::De),
+            "es" => Ok(TimestampLocale::Es),
+            "fr" => Ok(TimestampLocale::Fr),
+            "it" => Ok(TimestampLocale::It),
+            "nl" => Ok(TimestampLocale::Nl),
+            "pt" => Ok(TimestampLocale::Pt),
+            _ => Err(unknown_value("locale", v, TimestampLocale::NAMES)),
+        }
+    }
+}
+
+pub fn is_timestamp_locale(v: String) -> Result<(), String> {
+    v.parse::<TimestampLocale>().map(|_| ())
+}
+
+/// 1900-01-01T00:00:00Z, the earliest --timestamp-fuzz past timestamp
+const FUZZ_PAST_SECS: i64 = -2_208_988_800;
+/// 2038-01-19T03:14:08Z, one second after 32 bit time runs out
+const FUZZ_FUTURE_SECS: i64 = 1 << 31;
+/// 9999-12-31T23:59:59Z, the last second with a four digit year
+const FUZZ_LAST_SECS: i64 = 253_402_300_799;
+
+/// kinds of extreme timestamps, see --timestamp-fuzz
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuzzKind {
+    /// a random time between 1900 and 1970, negative unix time
+    Past,
+    /// a random time between 2038, after 32 bit time, and the year 9999
+    Future,
+    /// 1970-01-01T00:00:00Z
+    Epoch,
+    /// the current time with second 60 in a minute that never has a leap second
+    Leap,
+}
+
+impl FuzzKind {
+    pub const NAMES: &'static [&'static str] = &["past", "future", "epoch", "leap"];
+
+    fn parse(v: &str) -> Result<FuzzKind, String> {
+        match v {
+            "past" => Ok(FuzzKind::Past),
+            "future" => Ok(FuzzKind::Future),
+            "epoch" => Ok(FuzzKind::Epoch),
+            "leap" => Ok(FuzzKind::Leap),
+            _ => Err(unknown_value("timestamp fuzz kind", v, FuzzKind::NAMES)),
+        }
+    }
+
+    /// the timestamp of this kind to emit instead of `time`
+    fn apply(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let mut rng = rand::thread_rng();
+        let secs = match self {
+            FuzzKind::Past => rng.gen_range(FUZZ_PAST_SECS..0),
+            FuzzKind::Future => rng.gen_range(FUZZ_FUTURE_SECS..=FUZZ_LAST_SECS),
+            FuzzKind::Epoch => 0,
+            FuzzKind::Leap => {
+                // the last minute of the day is where real leap seconds go
+                let minute = if time.hour() == 23 && time.minute() == 59 {
+                    58
+                } else {
+                    time.minute()
+                };
+                let leap = time.date_naive().and_hms_nano_opt(
+                    time.hour(),
+                    minute,
+                    59,
+                    1_000_000_000 + time.nanosecond() % 1_000_000_000,
+                );
+                return leap.map_or(time, |leap| Utc.from_utc_datetime(&leap));
+            }
+        };
+        Utc.timestamp_opt(secs, 0).single().unwrap_or(time)
+    }
+}
+
+/// how often each kind of extreme timestamp replaces the current time, see
+/// --timestamp-fuzz
+#[derive(Debug, Clone)]
+pub struct TimestampFuzz {
+    kinds: Vec<(FuzzKind, f64)>,
+}
+
+impl TimestampFuzz {
+    /// parse "KIND=PCT,..." like "past=1%,epoch=0.1%,leap=0.5%"
+    pub fn parse(v: &str) -> Result<TimestampFuzz, String> {
+        let mut kinds = vec![];
+        for (key, value) in parse_kv_list(v)? {
+            kinds.push((FuzzKind::parse(&key)?, parse_percentage(&value)?));
+        }
+        if kinds.is_empty() {
+            return Err(format!(
+                "{} needs at least one KIND=PCT, kinds: {}",
+                v,
+                FuzzKind::NAMES.join(", ")
+            ));
+        }
+        if kinds.iter().map(|(_, rate)| rate).sum::<f64>() > 1.0 {
+            return Err(format!("{} adds up to more than 100%", v));
+        }
+
+        Ok(TimestampFuzz { kinds })
+    }
+
+    /// `time` or, as often as asked, an extreme timestamp instead
+    pub fn apply(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let mut roll = rand::thread_rng().gen::<f64>();
+        for (kind, rate) in &self.kinds {
+            if roll < *rate {
+                return kind.apply(time);
+            }
+            roll -= rate;
+        }
+        time
+    }
+}
+
+pub fn is_timestamp_fuzz(v: String) -> Result<(), String> {
+    TimestampFuzz::parse(&v).map(|_| ())
+}
+
+/// how generated timestamps are rendered
+#[derive(Debug, Clone, Default)]
+pub struct Timestamps {
+    pub tz: TimestampTz,
+    pub locale: TimestampLocale,
+    /// extreme timestamps now and then
+    pub fuzz: Option<TimestampFuzz>,
+}
+
+impl Timestamps {
+    fn fuzzed(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        match &self.fuzz {
+            Some(fuzz) => fuzz.apply(time),
+            None => time,
+        }
+    }
+
+    /// `time` in the zone and locale with a strftime `format`
+    pub fn format(&self, time: DateTime<Utc>, format: &str) -> String {
+        let time = self.fuzzed(time);
+        match &self.tz {
+            TimestampTz::Utc => self.render(&time, format),
+            TimestampTz::Local | TimestampTz::Zone(_) => {
+                self.render(&time.with_timezone(&Local), format)
+            }
+            TimestampTz::Fixed(offset) => self.render(&time.with_timezone(offset), format),
+        }
+    }
+
+    fn render<Tz: TimeZone>(&self, time: &DateTime<Tz>, format: &str) -> String
+    where
+        Tz::Offset: Display,
+    {
+        let format = self.locale.localize(
+            format,
+            time.month0() as usize,
+            time.weekday().num_days_from_monday() as usize,
+        );
+        time.format(&format).to_string()
+    }
+
+    /// `time` in the zone as RFC 3339
+    pub fn rfc3339(&self, time: DateTime<Utc>) -> String {
+        let time = self.fuzzed(time);
+        match &self.tz {
+            TimestampTz::Utc => time.to_rfc3339(),
+            TimestampTz::Local | TimestampTz::Zone(_) => time.with_timezone(&Local).to_rfc3339(),
+            TimestampTz::Fixed(offset) => time.with_timezone(offset).to_rfc3339(),
+        }
+    }
+
+    /// `time` in the zone as ISO 8601 with milliseconds, Z for UTC
+    pub fn iso8601_millis(&self, time: DateTime<Utc>) -> String {
+        let time = self.fuzzed(time);
+        match &self.tz {
+            TimestampTz::Utc => time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            TimestampTz::Local | TimestampTz::Zone(_) => time
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            TimestampTz::Fixed(offset) => time
+                .with_timezone(offset)
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::parse::unknown_value;
+use std::fs::{File, FileTimes};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// how output file modification and access times are set
+#[derive(Debug, Clone)]
+pub enum TouchPolicy {
+    /// whatever the OS sets on write
+    Natural,
+    /// the write time moved back by a fixed amount, for backfilling historical logs
+    Backdate(Duration),
+    /// the time the file had when loggen first opened it (now if it didn't exist)
+    Freeze,
+}
+
+impl TouchPolicy {
+    pub const NAMES: &'static [&'static str] = &["natural", "backdate", "freeze"];
+
+    /// policy named `v`, backdate moves times back by `backdate_by`
+    pub fn parse(v: &str, backdate_by: Duration) -> Result<TouchPolicy, String> {
+        match v {
+            "natural" => Ok(TouchPolicy::Natural),
+            "backdate" => Ok(TouchPolicy::Backdate(backdate_by)),
+            "freeze" => Ok(TouchPolicy::Freeze),
+            _ => Err(unknown_value("touch policy", v, TouchPolicy::NAMES)),
+        }
+    }
+}
+
+/// applies a touch policy to one output path
+#[derive(Debug)]
+pub struct Toucher {
+    policy: TouchPolicy,
+    frozen: Option<SystemTime>,
+}
+
+impl Toucher {
+    pub fn new(policy: TouchPolicy) -> Toucher {
+        Toucher {
+            policy,
+            frozen: None,
+        }
+    }
+
+    /// remember the time to freeze at, call before `path` is first opened
+    pub fn opening(&mut self, path: &Path) {
+        if let (TouchPolicy::Freeze, None) = (&self.policy, self.frozen) {
+            self.frozen = Some(
+                path.metadata()
+                    .and_then(|m| m.modified())
+                    .unwrap_or_else(|_| SystemTime::now()),
+            );
+        }
+    }
+
+    /// the output path changed (a new partition), the next one is frozen on its own time
+    pub fn reset(&mut self) {
+        self.frozen = None;
+    }
+
+    /// set the times of `file` after writing to it
+    pub fn touch(&self, file: &File) -> io::Result<()> {
+        let time = match (&self.policy, self.frozen) {
+            (TouchPolicy::Natural, _) => return Ok(()),
+            // backdating past 1970 stops there
+            (TouchPolicy::Backdate(by), _) => SystemTime::now()
+                .checked_sub(*by)
+                .map_or(UNIX_EPOCH, |time| time.max(UNIX_EPOCH)),
+            (TouchPolicy::Freeze, Some(frozen)) => frozen,
+            (TouchPolicy::Freeze, None) => return Ok(()),
+        };
+
+        file.set_times(FileTimes::new().set_modified(time).set_accessed(time))
+    }
+
+    pub fn is_natural(&self) -> bool {
+        matches!(self.policy, TouchPolicy::Natural)
+    }
+}
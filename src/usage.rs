@@ -0,0 +1,225 @@
+use crate::limits;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// cpu time and context switches of a thread or of the whole process
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub user: Duration,
+    pub system: Duration,
+    pub voluntary: u64,
+    pub involuntary: u64,
+}
+
+impl Sample {
+    fn add(&mut self, other: &Sample) {
+        self.user += other.user;
+        self.system += other.system;
+        self.voluntary += other.voluntary;
+        self.involuntary += other.involuntary;
+    }
+
+    fn from_rusage(usage: &libc::rusage) -> Sample {
+        let time = |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+        Sample {
+            user: time(usage.ru_utime),
+            system: time(usage.ru_stime),
+            voluntary: usage.ru_nvcsw as u64,
+            involuntary: usage.ru_nivcsw as u64,
+        }
+    }
+}
+
+fn rusage(who: libc::c_int) -> libc::rusage {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrusage(who, &mut usage) };
+    usage
+}
+
+/// the calling thread, its id is used to read its use from /proc while it runs
+#[cfg(target_os = "linux")]
+fn thread_id() -> Option<i64> {
+    Some(unsafe { libc::syscall(libc::SYS_gettid) } as i64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_id() -> Option<i64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn own_thread_sample() -> Option<Sample> {
+    Some(Sample::from_rusage(&rusage(libc::RUSAGE_THREAD)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn own_thread_sample() -> Option<Sample> {
+    None
+}
+
+/// use of another running thread of this process
+fn thread_sample(tid: i64) -> Option<Sample> {
+    let stat = fs::read_to_string(format!("/proc/self/task/{}/stat", tid)).ok()?;
+    // the name in parenthesis can have spaces, utime and stime are the 14th
+    // and 15th fields counting from the pid
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace().skip(11);
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+    let mut time = || -> Option<Duration> {
+        let ticks_used = fields.next()?.parse::<u64>().ok()?;
+        Some(Duration::from_secs_f64(ticks_used as f64 / ticks))
+    };
+    let (user, system) = (time()?, time()?);
+
+    let status = fs::read_to_string(format!("/proc/self/task/{}/status", tid)).ok()?;
+    let switches = |name: &str| -> u64 {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(0)
+    };
+    Some(Sample {
+        user,
+        system,
+        voluntary: switches("voluntary_ctxt_switches:"),
+        involuntary: switches("nonvoluntary_ctxt_switches:"),
+    })
+}
+
+/// open file descriptors of this process
+fn open_fds() -> Option<usize> {
+    // the directory being read is open too
+    fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|entries| entries.count().saturating_sub(1))
+}
+
+#[derive(Debug)]
+struct ThreadUsage {
+    name: String,
+    tid: Option<i64>,
+    /// what it used when it ended, None while it runs
+    ended: Option<Sample>,
+}
+
+/// worker threads whose use is reported, see --resource-report
+#[derive(Debug, Default)]
+pub struct Usage {
+    threads: Mutex<Vec<ThreadUsage>>,
+}
+
+/// records the use of its thread when dropped at the end of the thread
+#[derive(Debug)]
+pub struct Entered {
+    usage: Arc<Usage>,
+    index: usize,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let sample = own_thread_sample().unwrap_or_default();
+        self.usage.threads.lock().unwrap()[self.index].ended = Some(sample);
+    }
+}
+
+impl Usage {
+    /// start counting the calling thread as `name`, threads with the same name,
+    /// like a worker started again after a rebalance, are summed
+    pub fn enter(usage: &Arc<Usage>, name: String) -> Entered {
+        let mut threads = usage.threads.lock().unwrap();
+        threads.push(ThreadUsage {
+            name,
+            tid: thread_id(),
+            ended: None,
+        });
+        Entered {
+            usage: usage.clone(),
+            index: threads.len() - 1,
+        }
+    }
+
+    /// use by thread name, in the order they were first entered
+    fn threads(&self) -> Vec<(String, Sample)> {
+        let mut totals: Vec<(String, Sample)> = vec![];
+        for thread in self.threads.lock().unwrap().iter() {
+            let sample = thread
+                .ended
+                .or_else(|| thread.tid.and_then(thread_sample))
+                .unwrap_or_default();
+            match totals.iter_mut().find(|(name, _)| *name == thread.name) {
+                Some((_, total)) => total.add(&sample),
+                None => totals.push((thread.name.clone(), sample)),
+            }
+        }
+        totals
+    }
+
+    /// print the use of the whole process and of each thread, `elapsed` is
+    /// how long loggen has been running
+    pub fn report(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let usage = rusage(libc::RUSAGE_SELF);
+        let process = Sample::from_rusage(&usage);
+        let rss = limits::memory_used()
+            .map(|bytes| format!("{:.1}MB", bytes as f64 / (1024.0 * 1024.0)))
+            .unwrap_or_else(|| "?".to_string());
+        // ru_maxrss is in kilobytes on linux and in bytes on macOS
+        let peak_kb = if cfg!(target_os = "macos") {
+            usage.ru_maxrss as f64 / 1024.0
+        } else {
+            usage.ru_maxrss as f64
+        };
+        println!(
+            "resources after {:.1}s: rss {} (peak {:.1}MB), {} open fds",
+            secs,
+            rss,
+            peak_kb / 1024.0,
+            open_fds().map_or("?".to_string(), |fds| fds.to_string())
+        );
+
+        println!(
+            "{:>10} {:>10} {:>7} {:>11} {:>11}  thread",
+            "user s", "system s", "cpu%", "voluntary", "involuntary"
+        );
+        for (name, sample) in self.threads() {
+            print_row(&sample, secs, &name);
+        }
+        print_row(&process, secs, "process");
+    }
+}
+
+fn print_row(sample: &Sample, secs: f64, name: &str) {
+    let user = sample.user.as_secs_f64();
+    let system = sample.system.as_secs_f64();
+    println!(
+        "{:>10.2} {:>10.2} {:>6.1}% {:>11} {:>11}  {}",
+        user,
+        system,
+        (user + system) * 100.0 / secs,
+        sample.voluntary,
+        sample.involuntary,
+        name
+    );
+}
+
+/// print the report every `interval` until `stop` is set
+pub fn watch(
+    usage: Arc<Usage>,
+    interval: Duration,
+    started: Instant,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut next = Instant::now() + interval;
+        while !stop.load(Ordering::Relaxed) {
+            sleep(Duration::from_millis(100));
+            if Instant::now() >= next {
+                usage.report(started.elapsed());
+                next += interval;
+            }
+        }
+    })
+}
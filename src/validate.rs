@@ -0,0 +1,283 @@
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Utc};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// lines used to detect the timestamp format
+const DETECT_LINES: u64 = 100;
+/// timestamps are only looked for at the start of lines
+const DETECT_PREFIX: usize = 256;
+/// bytes checked for control characters to tell binary files apart
+pub const BINARY_SAMPLE: usize = 8192;
+
+/// timestamp formats by name, chrono format and the width of the text they match
+const DATE_FORMATS: &[(&str, &str, usize)] = &[
+    ("iso8601", "%Y-%m-%dT%H:%M:%S", 19),
+    ("iso8601-space", "%Y-%m-%d %H:%M:%S", 19),
+    ("common-log", "%d/%b/%Y:%H:%M:%S %z", 26),
+    ("rfc2822", "%a, %d %b %Y %H:%M:%S %z", 31),
+    // syslog has no year, one is added to parse it
+    ("rfc3164", "%Y %b %e %H:%M:%S", 15),
+];
+
+#[derive(Debug, Default)]
+struct FileReport {
+    lines: u64,
+    bytes: u64,
+    max_line: usize,
+    long_lines: u64,
+    first_long_line: Option<u64>,
+    first_invalid_utf8: Option<u64>,
+    binary: bool,
+    formats: HashMap<&'static str, u64>,
+    detect_lines: u64,
+}
+
+/// strftime format of a timestamp format returned by `detect_format`
+pub fn strftime_of(name: &str) -> Option<&'static str> {
+    match name {
+        // rfc3164 is parsed with a year added in front
+        "rfc3164" => Some("%b %e %H:%M:%S"),
+        "epoch-seconds" => Some("%s"),
+        "epoch-millis" => Some("%s%3f"),
+        _ => DATE_FORMATS
+            .iter()
+            .find(|(format_name, _, _)| *format_name == name)
+            .map(|(_, format, _)| *format),
+    }
+}
+
+fn is_epoch(token: &str, digits: usize) -> bool {
+    // 2001-09-09 to 2286-11-20 in seconds, same range in milliseconds
+    token.len() == digits && token.chars().all(|c| c.is_ascii_digit()) && !token.starts_with('0')
+}
+
+fn parses(format: &str, text: &str) -> bool {
+    if format.contains("%z") {
+        DateTime::parse_from_str(text, format).is_ok()
+    } else {
+        NaiveDateTime::parse_from_str(text, format).is_ok()
+    }
+}
+
+/// name of the first timestamp format found near the start of `line`
+pub fn detect_format(line: &str) -> Option<&'static str> {
+    find_timestamp(line).map(|(name, _)| name)
+}
+
+/// the first timestamp near the start of `line`, times without an offset are
+/// taken as UTC and syslog times as this year
+pub fn parse_timestamp(line: &str) -> Option<DateTime<Utc>> {
+    let (name, text) = find_timestamp(line)?;
+    match name {
+        "epoch-seconds" => Utc.timestamp_opt(text.parse().ok()?, 0).single(),
+        "epoch-millis" => Utc.timestamp_millis_opt(text.parse().ok()?).single(),
+        "rfc3164" => {
+            let text = format!("{} {}", Utc::now().year(), text);
+            let format = DATE_FORMATS.iter().find(|(n, _, _)| *n == name)?.1;
+            NaiveDateTime::parse_from_str(&text, format)
+                .ok()
+                .map(|time| time.and_utc())
+        }
+        _ => {
+            let format = strftime_of(name)?;
+            if format.contains("%z") {
+                DateTime::parse_from_str(text, format)
+                    .ok()
+                    .map(|time| time.with_timezone(&Utc))
+            } else {
+                NaiveDateTime::parse_from_str(text, format)
+                    .ok()
+                    .map(|time| time.and_utc())
+            }
+        }
+    }
+}
+
+/// format name and text of the first timestamp near the start of `line`
+fn find_timestamp(line: &str) -> Option<(&'static str, &str)> {
+    let mut prefix_end = line.len().min(DETECT_PREFIX);
+    while !line.is_char_boundary(prefix_end) {
+        prefix_end -= 1;
+    }
+    let prefix = &line[..prefix_end];
+
+    let mut previous = None;
+    for (start, c) in prefix.char_indices() {
+        let boundary = previous.is_none_or(|p: char| !p.is_ascii_alphanumeric());
+        previous = Some(c);
+        if !boundary {
+            continue;
+        }
+
+        let rest = &prefix[start..];
+        for (name, format, width) in DATE_FORMATS {
+            if let Some(text) = rest.get(..*width) {
+                let matched = if *name == "rfc3164" {
+                    parses(format, &format!("2000 {}", text))
+                } else {
+                    parses(format, text)
+                };
+                if matched {
+                    return Some((name, text));
+                }
+            }
+        }
+
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let token = &rest[..digits];
+        if is_epoch(token, 13) {
+            return Some(("epoch-millis", token));
+        } else if is_epoch(token, 10) {
+            return Some(("epoch-seconds", token));
+        }
+    }
+
+    None
+}
+
+pub fn is_binary(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return true;
+    }
+
+    let control = sample
+        .iter()
+        .filter(|b| **b < 0x20 && !matches!(**b, b'\t' | b'\n' | b'\r' | 0x1b | 0x0c))
+        .count();
+    control * 10 > sample.len() * 3
+}
+
+fn scan(path: &Path, max_line_length: usize) -> io::Result<FileReport> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let sample = reader.fill_buf()?;
+    let mut report = FileReport {
+        binary: is_binary(&sample[..sample.len().min(BINARY_SAMPLE)]),
+        ..FileReport::default()
+    };
+    if report.binary {
+        return Ok(report);
+    }
+
+    let mut buf = vec![];
+    loop {
+        buf.clear();
+        let len = reader.read_until(b'\n', &mut buf)?;
+        if len == 0 {
+            break;
+        }
+
+        report.lines += 1;
+        report.bytes += len as u64;
+        report.max_line = report.max_line.max(len);
+        if len > max_line_length {
+            report.long_lines += 1;
+            report.first_long_line.get_or_insert(report.lines);
+        }
+
+        match std::str::from_utf8(&buf) {
+            Ok(line) => {
+                if report.detect_lines < DETECT_LINES {
+                    report.detect_lines += 1;
+                    if let Some(name) = detect_format(line) {
+                        *report.formats.entry(name).or_insert(0) += 1;
+                    }
+                }
+            }
+            Err(_) => {
+                report.first_invalid_utf8.get_or_insert(report.lines);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+impl FileReport {
+    fn problems(&self, max_line_length: usize) -> Vec<String> {
+        let mut problems = vec![];
+        if self.lines == 0 {
+            problems.push("empty file".to_string());
+        }
+        if let Some(line) = self.first_invalid_utf8 {
+            problems.push(format!("not UTF-8 (first at line {})", line));
+        }
+        if let Some(line) = self.first_long_line {
+            problems.push(format!(
+                "{} lines longer than {} bytes (first at line {})",
+                self.long_lines, max_line_length, line
+            ));
+        }
+        problems
+    }
+
+    fn timestamps(&self) -> String {
+        match self
+            .formats
+            .iter()
+            .max_by_key(|(name, count)| (**count, **name))
+        {
+            Some((name, count)) => format!(
+                "{} ({}% of first {} lines)",
+                name,
+                count * 100 / self.detect_lines,
+                self.detect_lines
+            ),
+            None => "none detected".to_string(),
+        }
+    }
+}
+
+/// check every file under `in_dir`, prints a line per file and returns how many
+/// have problems
+pub fn validate(in_dir: &Path, max_line_length: usize) -> io::Result<usize> {
+    let mut files = 0;
+    let mut failed = 0;
+
+    for entry in WalkDir::new(in_dir)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        files += 1;
+        let rel_path = path.strip_prefix(in_dir).unwrap_or(path).display();
+        let report = match scan(path, max_line_length) {
+            Ok(report) => report,
+            Err(err) => {
+                failed += 1;
+                println!("FAIL {}: {}", rel_path, err);
+                continue;
+            }
+        };
+
+        let problems = report.problems(max_line_length);
+        let summary = format!(
+            "{} lines, {} bytes, longest line {} bytes, timestamps: {}",
+            report.lines,
+            report.bytes,
+            report.max_line,
+            report.timestamps()
+        );
+        if report.binary {
+            failed += 1;
+            println!("FAIL {}: binary file", rel_path);
+        } else if problems.is_empty() {
+            println!("OK   {}: {}", rel_path, summary);
+        } else {
+            failed += 1;
+            println!("FAIL {}: {}; {}", rel_path, problems.join(", "), summary);
+        }
+    }
+
+    println!("{} files checked, {} with problems", files, failed);
+    Ok(failed)
+}
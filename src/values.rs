@@ -0,0 +1,101 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+/// values read from a file for `{{choice file=...}}` and `{{cycle file=...}}`,
+/// one per line, blank lines and lines starting with # are skipped
+#[derive(Debug)]
+pub struct ValueList {
+    values: Vec<String>,
+    /// running total of the weights, the last one is the total
+    cumulative: Vec<u64>,
+}
+
+impl ValueList {
+    /// with `weighted` the last word of each line is its weight, "GET /api 30"
+    pub fn parse(content: &str, weighted: bool) -> Result<ValueList, String> {
+        let mut values = vec![];
+        let mut cumulative = vec![];
+        let mut total = 0;
+
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (value, weight) = if weighted {
+                let split = line.rfind(char::is_whitespace).ok_or_else(|| {
+                    format!("line {} has no weight after the value: {}", n + 1, line)
+                })?;
+                let weight = line[split + 1..]
+                    .parse::<u64>()
+                    .map_err(|_| format!("line {} has an invalid weight: {}", n + 1, line))?;
+                (line[..split].trim_end(), weight)
+            } else {
+                (line, 1)
+            };
+
+            // values with weight 0 are never used
+            if weight > 0 {
+                total += weight;
+                values.push(value.to_string());
+                cumulative.push(total);
+            }
+        }
+
+        if values.is_empty() {
+            return Err("no values".to_string());
+        }
+        Ok(ValueList { values, cumulative })
+    }
+
+    fn at(&self, position: u64) -> &str {
+        let index = self.cumulative.partition_point(|&total| total <= position);
+        &self.values[index]
+    }
+
+    /// random value, likely in proportion to its weight
+    pub fn choice(&self) -> &str {
+        let total = *self.cumulative.last().unwrap();
+        self.at(rand::thread_rng().gen_range(0..total))
+    }
+
+    /// value for the `n`th use, in file order, each value repeated as many
+    /// times as its weight
+    pub fn cycle(&self, n: u64) -> &str {
+        self.at(n % self.cumulative.last().unwrap())
+    }
+}
+
+/// list read from (path, weighted), None if it couldn't be read
+type Lists = HashMap<(String, bool), Option<Arc<ValueList>>>;
+
+/// value files read on first use and shared by every file, files that can't be
+/// read are reported once
+#[derive(Debug, Clone, Default)]
+pub struct ValueLists {
+    lists: Arc<Mutex<Lists>>,
+}
+
+impl ValueLists {
+    pub fn get(&self, path: &str, weighted: bool) -> Option<Arc<ValueList>> {
+        let mut lists = self.lists.lock().unwrap();
+        lists
+            .entry((path.to_string(), weighted))
+            .or_insert_with(|| {
+                let list = fs::read_to_string(path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|content| ValueList::parse(&content, weighted));
+                match list {
+                    Ok(list) => Some(Arc::new(list)),
+                    Err(err) => {
+                        eprintln!("Error reading template values from {}: {}", path, err);
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+}
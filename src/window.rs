@@ -0,0 +1,82 @@
+use crate::validate::parse_timestamp;
+use chrono::{DateTime, Utc};
+
+/// part of the samples to replay by the timestamps in their lines, see
+/// --since and --until
+#[derive(Debug, Clone, Default)]
+pub struct Window {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// parse a --since or --until time in any of the formats found in samples
+pub fn parse_time(v: &str) -> Result<DateTime<Utc>, String> {
+    parse_timestamp(v.trim())
+        .ok_or_else(|| format!("{} isn't a time, use for example 2024-03-01T10:00:00", v))
+}
+
+pub fn is_time(v: String) -> Result<(), String> {
+    parse_time(&v).map(|_| ())
+}
+
+/// what to do with a sample line
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Keep,
+    Skip,
+    /// past --until, the rest of the sample is skipped
+    End,
+}
+
+/// where a sample is in the window
+#[derive(Debug)]
+pub struct WindowState {
+    window: Window,
+    /// lines without a timestamp, like stack traces, go with the line before
+    inside: bool,
+    /// a line was kept since the sample was rewound
+    kept: bool,
+    /// a whole pass had no lines in the window
+    empty: bool,
+}
+
+impl WindowState {
+    pub fn new(window: Window) -> WindowState {
+        WindowState {
+            inside: window.since.is_none(),
+            window,
+            kept: false,
+            empty: false,
+        }
+    }
+
+    pub fn check(&mut self, line: &str) -> Verdict {
+        if let Some(time) = parse_timestamp(line) {
+            if self.window.until.is_some_and(|until| time > until) {
+                return Verdict::End;
+            }
+            self.inside = self.window.since.is_none_or(|since| time >= since);
+        }
+
+        if self.inside {
+            self.kept = true;
+            Verdict::Keep
+        } else {
+            Verdict::Skip
+        }
+    }
+
+    /// start over with the sample, true the first time a pass kept no lines
+    pub fn rewound(&mut self) -> bool {
+        let newly_empty = !self.kept && !self.empty;
+        self.empty |= !self.kept;
+        self.inside = self.window.since.is_none();
+        self.kept = false;
+        newly_empty
+    }
+
+    /// no lines of the sample are in the window, it isn't read again
+    pub fn is_empty(&self) -> bool {
+        self.empty
+    }
+}
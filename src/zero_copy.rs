@@ -0,0 +1,113 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+/// bytes asked for per copy_file_range call
+const CHUNK: u64 = 64 * 1024 * 1024;
+/// buffer of the fallback copy through user space
+const BUF_SIZE: usize = 1024 * 1024;
+
+/// size of a sample copied whole, see GenOptions::zero_copy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pass {
+    pub bytes: u64,
+    pub lines: u64,
+}
+
+/// bytes and lines of the sample at `path`, a last line without a newline counts
+pub fn measure(path: &Path) -> io::Result<Pass> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; BUF_SIZE];
+    let mut pass = Pass { bytes: 0, lines: 0 };
+    let mut last = b'\n';
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        pass.bytes += len as u64;
+        pass.lines += buf[..len].iter().filter(|b| **b == b'\n').count() as u64;
+        last = buf[len - 1];
+    }
+    if last != b'\n' {
+        pass.lines += 1;
+    }
+
+    Ok(pass)
+}
+
+/// copy `len` bytes from the start of `src` to the end of `dst`, in the kernel
+/// with copy_file_range where the filesystems support it, `dst` must not be
+/// opened for appending
+pub fn append(src: &File, dst: &File, len: u64) -> io::Result<()> {
+    let mut off_in = 0;
+    let mut off_out = dst.metadata()?.len();
+    while off_in < len {
+        let chunk = (len - off_in).min(CHUNK);
+        let copied = match copy_range(src, off_in, dst, off_out, chunk) {
+            Ok(0) => break,
+            Ok(copied) => copied,
+            // across filesystems or unsupported by one of them
+            Err(_) => copy_through_buffer(src, off_in, dst, off_out, chunk)?,
+        };
+        off_in += copied;
+        off_out += copied;
+    }
+
+    Ok(())
+}
+
+/// true for errors of outputs that can't be written at an offset, like pipes
+pub fn is_unsupported(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::ESPIPE) | Some(libc::EINVAL))
+}
+
+#[cfg(target_os = "linux")]
+fn copy_range(src: &File, off_in: u64, dst: &File, off_out: u64, len: u64) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut off_in = off_in as libc::loff_t;
+    let mut off_out = off_out as libc::loff_t;
+    let copied = unsafe {
+        libc::copy_file_range(
+            src.as_raw_fd(),
+            &mut off_in,
+            dst.as_raw_fd(),
+            &mut off_out,
+            len as usize,
+            0,
+        )
+    };
+    if copied < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(copied as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_range(src: &File, off_in: u64, dst: &File, off_out: u64, len: u64) -> io::Result<u64> {
+    copy_through_buffer(src, off_in, dst, off_out, len)
+}
+
+fn copy_through_buffer(
+    src: &File,
+    off_in: u64,
+    dst: &File,
+    off_out: u64,
+    len: u64,
+) -> io::Result<u64> {
+    let mut buf = vec![0; BUF_SIZE.min(len as usize)];
+    let mut copied = 0;
+    while copied < len {
+        let want = buf.len().min((len - copied) as usize);
+        let read = src.read_at(&mut buf[..want], off_in + copied)?;
+        if read == 0 {
+            break;
+        }
+        dst.write_all_at(&buf[..read], off_out + copied)?;
+        copied += read as u64;
+    }
+
+    Ok(copied)
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+/// an empty directory for the test, removed first if an earlier run left it
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("loggen-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn shell_rate_is_honored_without_interval() {
+    let dir = test_dir("shell-rate");
+    let data = dir.join("data");
+    let out = dir.join("out");
+    fs::create_dir_all(&data).unwrap();
+    fs::write(data.join("a.log"), "l1\nl2\nl3\n").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_loggen"))
+        .arg("shell")
+        .arg("-i")
+        .arg(&data)
+        .arg("-o")
+        .arg(&out)
+        .args(["-t", "0", "-p", "1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"rate 2\n").unwrap();
+    sleep(Duration::from_secs(3));
+    stdin.write_all(b"quit\n").unwrap();
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+
+    // 2 lines per second for 3 seconds, whole passes of 3 lines would be many more
+    let lines = fs::read_to_string(out.join("a.log"))
+        .unwrap()
+        .lines()
+        .count();
+    assert!(
+        (4..=8).contains(&lines),
+        "wrote {} lines at rate 2 for 3s",
+        lines
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shell_reads_options_from_config() {
+    let dir = test_dir("shell-config");
+    let data = dir.join("data");
+    let out = dir.join("out");
+    fs::create_dir_all(&data).unwrap();
+    fs::write(data.join("a.log"), "l1\nl2\nl3\n").unwrap();
+    let config = dir.join("loggen.json");
+    fs::write(
+        &config,
+        format!(
+            r#"{{"in-base-dir":{:?},"out-base-dir":{:?},"interval":500,"parallelism":1}}"#,
+            data, out
+        ),
+    )
+    .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_loggen"))
+        .arg("shell")
+        .arg("--config")
+        .arg(&config)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    sleep(Duration::from_secs(2));
+    stdin.write_all(b"quit\n").unwrap();
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+
+    // a line every 500ms for 2 seconds, without the config's interval it
+    // would be whole passes
+    let lines = fs::read_to_string(out.join("a.log"))
+        .unwrap()
+        .lines()
+        .count();
+    assert!(
+        (2..=6).contains(&lines),
+        "wrote {} lines with interval 500 for 2s",
+        lines
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,49 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// an empty directory for the test, removed first if an earlier run left it
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("loggen-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn fifo_outputs_get_lines_instead_of_copies() {
+    let dir = test_dir("zero-copy-fifo");
+    let data = dir.join("data");
+    let out = dir.join("out");
+    fs::create_dir_all(&data).unwrap();
+    fs::create_dir_all(&out).unwrap();
+    fs::write(data.join("a.log"), "l1\nl2\nl3\n").unwrap();
+    let status = Command::new("mkfifo")
+        .arg(out.join("a.log"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // no interval, so whole passes would be copied to a regular file
+    let mut child = Command::new(env!("CARGO_BIN_EXE_loggen"))
+        .arg("-i")
+        .arg(&data)
+        .arg("-o")
+        .arg(&out)
+        .args(["-t", "0", "-p", "1"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let lines: Vec<String> = BufReader::new(File::open(out.join("a.log")).unwrap())
+        .lines()
+        .take(9)
+        .map(|line| line.unwrap())
+        .collect();
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(lines, ["l1", "l2", "l3"].repeat(3));
+    fs::remove_dir_all(&dir).unwrap();
+}